@@ -0,0 +1,564 @@
+use super::classify;
+use super::errors::HttpError;
+use super::http_metrics::circuit_breakers::Handle;
+use super::metrics::{ConcreteLabels, HttpConcreteBreakers};
+use super::outcome::{self, Record, ResponseBody};
+use crate::Error;
+use futures::future;
+use linkerd2_stack::{layer, NewService};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+/// Configures circuit breakers for concrete backends.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Opens a circuit when the ratio of failed responses over a window
+    /// reaches this value. Zero disables the circuit breaker.
+    pub failure_rate: f64,
+
+    /// The minimum number of responses in a window before its failure rate is
+    /// considered.
+    pub min_requests: u32,
+    pub window: Duration,
+
+    /// How long an open circuit fails requests before admitting probes.
+    pub open_timeout: Duration,
+
+    /// The number of probe requests that must succeed for a half-open circuit
+    /// to close. At most this many probes are dispatched at once.
+    pub half_open_probes: u32,
+}
+
+/// Fails requests to a backend while its circuit is open.
+///
+/// A circuit is closed until the failure rate of its responses exceeds the
+/// configured threshold, at which point it opens and fails all requests. Once
+/// the open timeout elapses, the circuit is half-open: a limited number of
+/// probe requests are dispatched, and the circuit closes if they all succeed
+/// or opens again if any fail.
+///
+/// Backends that share a set of labels share a circuit.
+pub struct NewCircuitBreaker<N> {
+    config: Arc<Config>,
+    breakers: Arc<Mutex<HashMap<ConcreteLabels, Weak<Breaker>>>>,
+    metrics: HttpConcreteBreakers,
+    inner: N,
+}
+
+pub struct CircuitBreaker<S> {
+    breaker: Arc<Breaker>,
+    /// Set when the service is ready: either an attempt that may be
+    /// dispatched or `None` if the request is to be failed.
+    ///
+    /// If the service is dropped before it is called, dropping the attempt
+    /// releases its probe slot.
+    admitted: Option<Option<Arc<Attempt>>>,
+    inner: S,
+}
+
+struct Breaker {
+    config: Arc<Config>,
+    labels: ConcreteLabels,
+    metrics: Handle,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Incremented on each transition so that the outcomes of requests
+    /// admitted in a prior state are ignored.
+    generation: u64,
+    circuit: Circuit,
+}
+
+#[derive(Debug, PartialEq)]
+enum Circuit {
+    Closed {
+        window_start: Instant,
+        successes: u32,
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        in_flight: u32,
+        successes: u32,
+    },
+}
+
+/// A request admitted by a breaker.
+struct Attempt {
+    breaker: Arc<Breaker>,
+    generation: u64,
+    is_probe: bool,
+}
+
+// === impl NewCircuitBreaker ===
+
+impl<N> NewCircuitBreaker<N> {
+    pub fn layer(
+        config: Config,
+        metrics: HttpConcreteBreakers,
+    ) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        let config = Arc::new(config);
+        let breakers = Arc::new(Mutex::new(HashMap::new()));
+        layer::mk(move |inner| Self {
+            config: config.clone(),
+            breakers: breakers.clone(),
+            metrics: metrics.clone(),
+            inner,
+        })
+    }
+}
+
+impl<T, N> NewService<T> for NewCircuitBreaker<N>
+where
+    for<'t> &'t T: Into<ConcreteLabels>,
+    N: NewService<T>,
+{
+    type Service = CircuitBreaker<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let labels: ConcreteLabels = (&target).into();
+        let breaker = {
+            let mut breakers = self.breakers.lock().expect("circuit breakers poisoned");
+            match breakers.get(&labels).and_then(Weak::upgrade) {
+                Some(breaker) => breaker,
+                None => {
+                    // Drop breakers that are no longer referenced by any
+                    // service.
+                    breakers.retain(|_, b| b.strong_count() > 0);
+                    let metrics = self.metrics.get_handle(labels.clone());
+                    let breaker = Arc::new(Breaker::new(self.config.clone(), labels, metrics));
+                    breakers.insert(breaker.labels.clone(), Arc::downgrade(&breaker));
+                    breaker
+                }
+            }
+        };
+
+        CircuitBreaker {
+            breaker,
+            admitted: None,
+            inner: self.inner.new_service(target),
+        }
+    }
+}
+
+impl<N: Clone> Clone for NewCircuitBreaker<N> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            breakers: self.breakers.clone(),
+            metrics: self.metrics.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// === impl CircuitBreaker ===
+
+impl<A, B, S> tower::Service<http::Request<A>> for CircuitBreaker<S>
+where
+    S: tower::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = Error;
+    type Future = future::Either<
+        outcome::ResponseFuture<S::Future>,
+        future::Ready<Result<Self::Response, Error>>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // The admission decision is made before the inner service is polled
+        // so that requests are failed immediately, without waiting for an
+        // unavailable backend, while the circuit is open.
+        let breaker = &self.breaker;
+        match self.admitted.get_or_insert_with(|| breaker.admit()) {
+            Some(_) => self.inner.poll_ready(cx).map_err(Into::into),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let breaker = &self.breaker;
+        let admitted = self.admitted.take().expect("called before ready");
+        // The circuit may have changed state since the request was admitted
+        // (e.g. if other requests' failures opened it), in which case the
+        // request must be admitted by the circuit's current state.
+        let admitted = admitted.and_then(|attempt| {
+            if breaker.is_current(attempt.generation) {
+                return Some(attempt);
+            }
+            drop(attempt);
+            breaker.admit()
+        });
+        match admitted {
+            Some(attempt) => {
+                let classify = req.extensions().get::<classify::Response>().cloned();
+                let rsp = self.inner.call(req);
+                future::Either::Left(outcome::ResponseFuture::new(classify, attempt, rsp))
+            }
+            None => future::Either::Right(future::err(HttpError::circuit_open().into())),
+        }
+    }
+}
+
+// === impl Breaker ===
+
+impl Breaker {
+    fn new(config: Arc<Config>, labels: ConcreteLabels, metrics: Handle) -> Self {
+        Self {
+            config,
+            labels,
+            metrics,
+            state: Mutex::new(State {
+                generation: 0,
+                circuit: Circuit::closed(Instant::now()),
+            }),
+        }
+    }
+
+    /// Returns an attempt if a request may be dispatched to the backend.
+    fn admit(self: &Arc<Self>) -> Option<Arc<Attempt>> {
+        let mut state = self.state.lock().expect("circuit breaker state poisoned");
+        let now = Instant::now();
+
+        if let Circuit::Open { until } = state.circuit {
+            if now < until {
+                return None;
+            }
+            debug!(dst = %self.labels.dst, "Circuit breaker half-open");
+            self.transition(
+                &mut state,
+                Circuit::HalfOpen {
+                    in_flight: 0,
+                    successes: 0,
+                },
+            );
+            self.metrics.incr_half_opened();
+        }
+
+        let is_probe = match state.circuit {
+            Circuit::Closed { .. } => false,
+            Circuit::HalfOpen {
+                ref mut in_flight,
+                successes,
+            } => {
+                if *in_flight + successes >= self.config.half_open_probes.max(1) {
+                    return None;
+                }
+                *in_flight += 1;
+                true
+            }
+            Circuit::Open { .. } => unreachable!("open circuits are not admitted"),
+        };
+
+        Some(Arc::new(Attempt {
+            breaker: self.clone(),
+            generation: state.generation,
+            is_probe,
+        }))
+    }
+
+    /// Returns true if the circuit has not changed state since `generation`.
+    fn is_current(&self, generation: u64) -> bool {
+        match self.state.lock() {
+            Ok(state) => state.generation == generation,
+            Err(_) => false,
+        }
+    }
+
+    fn record(&self, generation: u64, is_failure: bool) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        if state.generation != generation {
+            return;
+        }
+
+        let now = Instant::now();
+        let config = &self.config;
+        match state.circuit {
+            Circuit::Closed {
+                ref mut window_start,
+                ref mut successes,
+                ref mut failures,
+            } => {
+                if now.saturating_duration_since(*window_start) >= config.window {
+                    *window_start = now;
+                    *successes = 0;
+                    *failures = 0;
+                }
+                if is_failure {
+                    *failures += 1;
+                } else {
+                    *successes += 1;
+                }
+
+                let responses = *successes + *failures;
+                let (successes, failures) = (*successes, *failures);
+                if config.failure_rate > 0.0
+                    && responses >= config.min_requests.max(1)
+                    && f64::from(failures) / f64::from(responses) >= config.failure_rate
+                {
+                    info!(
+                        dst = %self.labels.dst,
+                        window.successes = successes,
+                        window.failures = failures,
+                        open_timeout = ?config.open_timeout,
+                        "Circuit breaker opened",
+                    );
+                    self.open(&mut state, now);
+                }
+            }
+            Circuit::HalfOpen {
+                ref mut successes, ..
+            } => {
+                if is_failure {
+                    info!(dst = %self.labels.dst, "Circuit breaker reopened after a failed probe");
+                    self.open(&mut state, now);
+                    return;
+                }
+
+                *successes += 1;
+                if *successes >= config.half_open_probes.max(1) {
+                    info!(dst = %self.labels.dst, "Circuit breaker closed");
+                    self.transition(&mut state, Circuit::closed(now));
+                    self.metrics.incr_closed();
+                }
+            }
+            Circuit::Open { .. } => {}
+        }
+    }
+
+    /// Releases a probe once its outcome has been recorded or it was
+    /// canceled.
+    fn release_probe(&self, generation: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.generation != generation {
+                return;
+            }
+            if let Circuit::HalfOpen {
+                ref mut in_flight, ..
+            } = state.circuit
+            {
+                *in_flight = in_flight.saturating_sub(1);
+            }
+        }
+    }
+
+    fn open(&self, state: &mut State, now: Instant) {
+        let until = now + self.config.open_timeout;
+        self.transition(state, Circuit::Open { until });
+        self.metrics.incr_opened();
+    }
+
+    fn transition(&self, state: &mut State, circuit: Circuit) {
+        state.generation += 1;
+        state.circuit = circuit;
+    }
+}
+
+// === impl Circuit ===
+
+impl Circuit {
+    fn closed(now: Instant) -> Self {
+        Circuit::Closed {
+            window_start: now,
+            successes: 0,
+            failures: 0,
+        }
+    }
+}
+
+// === impl Attempt ===
+
+impl Record for Attempt {
+    fn record(&self, is_failure: bool) {
+        self.breaker.record(self.generation, is_failure);
+    }
+}
+
+impl Drop for Attempt {
+    fn drop(&mut self) {
+        if self.is_probe {
+            self.breaker.release_probe(self.generation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics::Direction, Addr};
+    use std::str::FromStr;
+    use tokio::time;
+
+    fn breaker() -> Arc<Breaker> {
+        let config = Config {
+            failure_rate: 0.5,
+            min_requests: 4,
+            window: Duration::from_secs(10),
+            open_timeout: Duration::from_secs(5),
+            half_open_probes: 2,
+        };
+        let labels = ConcreteLabels {
+            direction: Direction::Out,
+            dst: Addr::from_str("foo.ns.svc.cluster.local:80").unwrap(),
+        };
+        let metrics = HttpConcreteBreakers::default().get_handle(labels.clone());
+        Arc::new(Breaker::new(Arc::new(config), labels, metrics))
+    }
+
+    fn is_open(b: &Breaker) -> bool {
+        matches!(b.state.lock().unwrap().circuit, Circuit::Open { .. })
+    }
+
+    #[tokio::test]
+    async fn opens_on_failure_rate() {
+        time::pause();
+        let b = breaker();
+
+        // The failure rate is only considered once enough responses have been
+        // observed.
+        for _ in 0..3 {
+            b.admit().expect("must be admitted").record(true);
+        }
+        assert!(!is_open(&b));
+
+        // Responses in prior windows are not considered.
+        time::advance(Duration::from_secs(10)).await;
+        for _ in 0..3 {
+            b.admit().expect("must be admitted").record(false);
+        }
+        b.admit().expect("must be admitted").record(true);
+        assert!(!is_open(&b));
+
+        b.admit().expect("must be admitted").record(true);
+        b.admit().expect("must be admitted").record(true);
+        assert!(is_open(&b));
+        assert!(b.admit().is_none(), "open circuits fail requests");
+    }
+
+    #[tokio::test]
+    async fn half_open_probes() {
+        time::pause();
+        let b = breaker();
+
+        // A response to a request admitted before the circuit opened is
+        // ignored.
+        let stale = b.admit().expect("must be admitted");
+        for _ in 0..4 {
+            b.admit().expect("must be admitted").record(true);
+        }
+        assert!(is_open(&b));
+        stale.record(false);
+        assert!(is_open(&b));
+
+        // Once the timeout elapses, only a limited number of probes are
+        // admitted. A failed probe reopens the circuit.
+        time::advance(Duration::from_secs(5)).await;
+        let p0 = b.admit().expect("probe must be admitted");
+        let p1 = b.admit().expect("probe must be admitted");
+        assert!(b.admit().is_none());
+        p0.record(true);
+        drop(p0);
+        assert!(is_open(&b));
+        p1.record(false);
+        assert!(is_open(&b));
+
+        // Canceled probes release their slots. The circuit closes when all
+        // probes succeed.
+        time::advance(Duration::from_secs(5)).await;
+        drop(b.admit().expect("probe must be admitted"));
+        let p0 = b.admit().expect("probe must be admitted");
+        let p1 = b.admit().expect("probe must be admitted");
+        p0.record(false);
+        p1.record(false);
+        assert_eq!(
+            b.state.lock().unwrap().circuit,
+            Circuit::closed(Instant::now())
+        );
+    }
+
+    fn service(
+        b: &Arc<Breaker>,
+    ) -> CircuitBreaker<
+        impl tower::Service<http::Request<()>, Response = http::Response<()>, Error = Error>,
+    > {
+        CircuitBreaker {
+            breaker: b.clone(),
+            admitted: None,
+            inner: tower::service_fn(|_: http::Request<()>| {
+                future::ok::<_, Error>(http::Response::new(()))
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn rechecks_circuit_when_called() {
+        use tower::Service;
+        time::pause();
+        let b = breaker();
+
+        // The circuit opens after the requests are admitted.
+        let mut svc0 = service(&b);
+        future::poll_fn(|cx| svc0.poll_ready(cx)).await.unwrap();
+        let mut svc1 = service(&b);
+        future::poll_fn(|cx| svc1.poll_ready(cx)).await.unwrap();
+        for _ in 0..4 {
+            b.admit().expect("must be admitted").record(true);
+        }
+        let err = svc0
+            .call(http::Request::new(()))
+            .await
+            .err()
+            .expect("the circuit is open");
+        assert_eq!(
+            err.downcast_ref::<HttpError>().map(HttpError::status),
+            Some(http::StatusCode::SERVICE_UNAVAILABLE)
+        );
+
+        // Once the circuit is half-open, a request admitted while it was
+        // closed is dispatched as a probe.
+        time::advance(Duration::from_secs(5)).await;
+        let _p0 = b.admit().expect("probe must be admitted");
+        svc1.call(http::Request::new(()))
+            .await
+            .expect("probe must be dispatched");
+        assert_eq!(
+            b.state.lock().unwrap().circuit,
+            Circuit::HalfOpen {
+                in_flight: 1,
+                successes: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn uncalled_services_release_probes() {
+        use tower::Service;
+        time::pause();
+        let b = breaker();
+        for _ in 0..4 {
+            b.admit().expect("must be admitted").record(true);
+        }
+        time::advance(Duration::from_secs(5)).await;
+
+        let mut svc = service(&b);
+        future::poll_fn(|cx| svc.poll_ready(cx)).await.unwrap();
+        let _p0 = b.admit().expect("probe must be admitted");
+        assert!(b.admit().is_none());
+
+        drop(svc);
+        assert!(b.admit().is_some(), "the uncalled probe must be released");
+    }
+}
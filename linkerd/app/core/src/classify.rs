@@ -26,6 +26,9 @@ pub enum Eos {
     Default(http::StatusCode),
     Grpc(GrpcEos),
     Profile(Class),
+    /// Profile classes that match on the gRPC status, which is only known
+    /// once the response's trailers have been read.
+    ProfileGrpc(http::StatusCode, profiles::http::ResponseClasses),
     Error(&'static str),
 }
 
@@ -103,18 +106,22 @@ impl Default for Response {
 }
 
 impl Response {
-    fn match_class<B>(
-        rsp: &http::Response<B>,
+    fn match_class(
+        status: http::StatusCode,
+        grpc_status: Option<u32>,
         classes: &[profiles::http::ResponseClass],
     ) -> Option<Class> {
         for class in classes {
-            if class.is_match(rsp) {
+            if class.is_match_status(status, grpc_status) {
                 let result = if class.is_failure() {
                     SuccessOrFailure::Failure
                 } else {
                     SuccessOrFailure::Success
                 };
-                return Some(Class::Default(result));
+                return match grpc_status {
+                    Some(code) if class.is_grpc() => Some(Class::Grpc(result, code)),
+                    _ => Some(Class::Default(result)),
+                };
             }
         }
 
@@ -134,13 +141,22 @@ impl classify::ClassifyResponse for Response {
             Response::Grpc => grpc_class(rsp.headers())
                 .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
                 .unwrap_or(Eos::Grpc(GrpcEos::Open)),
-            Response::Profile(ref classes) => Self::match_class(rsp, classes.as_ref())
-                .map(Eos::Profile)
-                .unwrap_or_else(|| {
-                    grpc_class(rsp.headers())
-                        .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
-                        .unwrap_or_else(|| Eos::Default(rsp.status()))
-                }),
+            Response::Profile(classes) => {
+                let grpc_status = grpc_status(rsp.headers());
+                // Unless the response is trailers-only, gRPC status matches
+                // must wait for the end of the stream.
+                if grpc_status.is_none() && classes.iter().any(|c| c.is_grpc()) {
+                    return Eos::ProfileGrpc(rsp.status(), classes);
+                }
+
+                Self::match_class(rsp.status(), grpc_status, classes.as_ref())
+                    .map(Eos::Profile)
+                    .unwrap_or_else(|| {
+                        grpc_class(rsp.headers())
+                            .map(|c| Eos::Grpc(GrpcEos::NoBody(c)))
+                            .unwrap_or_else(|| Eos::Default(rsp.status()))
+                    })
+            }
         }
     }
 
@@ -173,6 +189,18 @@ impl classify::ClassifyEos for Eos {
                 .and_then(grpc_class)
                 .unwrap_or(Class::Grpc(SuccessOrFailure::Success, 0)),
             Eos::Profile(class) => class,
+            Eos::ProfileGrpc(status, classes) => {
+                let grpc_status = trailers.and_then(grpc_status);
+                Response::match_class(status, grpc_status, classes.as_ref())
+                    .or_else(|| trailers.and_then(grpc_class))
+                    .unwrap_or_else(|| {
+                        if status.is_server_error() {
+                            Class::Default(SuccessOrFailure::Failure)
+                        } else {
+                            Class::Default(SuccessOrFailure::Success)
+                        }
+                    })
+            }
             Eos::Error(msg) => Class::Stream(SuccessOrFailure::Failure, msg.into()),
         }
     }
@@ -182,22 +210,25 @@ impl classify::ClassifyEos for Eos {
     }
 }
 
-fn grpc_class(headers: &http::HeaderMap) -> Option<Class> {
+fn grpc_status(headers: &http::HeaderMap) -> Option<u32> {
     headers
         .get("grpc-status")
         .and_then(|v| v.to_str().ok())
         .and_then(|s| s.parse::<u32>().ok())
-        .map(|grpc_status| {
-            let ok = match grpc::Code::from_i32(grpc_status as i32) {
-                grpc::Code::Unknown
-                | grpc::Code::DeadlineExceeded
-                | grpc::Code::Internal
-                | grpc::Code::Unavailable
-                | grpc::Code::DataLoss => SuccessOrFailure::Failure,
-                _ => SuccessOrFailure::Success,
-            };
-            Class::Grpc(ok, grpc_status)
-        })
+}
+
+fn grpc_class(headers: &http::HeaderMap) -> Option<Class> {
+    grpc_status(headers).map(|grpc_status| {
+        let ok = match grpc::Code::from_i32(grpc_status as i32) {
+            grpc::Code::Unknown
+            | grpc::Code::DeadlineExceeded
+            | grpc::Code::Internal
+            | grpc::Code::Unavailable
+            | grpc::Code::DataLoss => SuccessOrFailure::Failure,
+            _ => SuccessOrFailure::Success,
+        };
+        Class::Grpc(ok, grpc_status)
+    })
 }
 
 fn h2_error(err: &Error) -> String {
@@ -225,6 +256,7 @@ impl Class {
 #[cfg(test)]
 mod tests {
     use super::{Class, SuccessOrFailure};
+    use crate::profiles;
     use http::{HeaderMap, Response, StatusCode};
     use linkerd2_http_classify::{ClassifyEos, ClassifyResponse};
 
@@ -316,4 +348,65 @@ mod tests {
             .eos(Some(&trailers));
         assert_eq!(class, Class::Grpc(SuccessOrFailure::Failure, 4));
     }
+
+    fn grpc_classes() -> profiles::http::ResponseClasses {
+        use profiles::http::{ResponseClass, ResponseMatch, Route};
+        let classes = vec![
+            // UNAVAILABLE
+            ResponseClass::new(true, ResponseMatch::GrpcStatus { min: 14, max: 14 }),
+            // NOT_FOUND
+            ResponseClass::new(false, ResponseMatch::GrpcStatus { min: 5, max: 5 }),
+        ];
+        Route::new(std::iter::empty(), classes)
+            .response_classes()
+            .clone()
+    }
+
+    #[test]
+    fn profile_grpc_status_trailer() {
+        let rsp = Response::builder().status(StatusCode::OK).body(()).unwrap();
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", 14.into());
+        let class = super::Response::Profile(grpc_classes())
+            .start(&rsp)
+            .eos(Some(&trailers));
+        assert_eq!(class, Class::Grpc(SuccessOrFailure::Failure, 14));
+
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", 5.into());
+        let class = super::Response::Profile(grpc_classes())
+            .start(&rsp)
+            .eos(Some(&trailers));
+        assert_eq!(class, Class::Grpc(SuccessOrFailure::Success, 5));
+    }
+
+    #[test]
+    fn profile_grpc_status_header() {
+        let rsp = Response::builder()
+            .header("grpc-status", "14")
+            .status(StatusCode::OK)
+            .body(())
+            .unwrap();
+        let class = super::Response::Profile(grpc_classes())
+            .start(&rsp)
+            .eos(None);
+        assert_eq!(class, Class::Grpc(SuccessOrFailure::Failure, 14));
+    }
+
+    #[test]
+    fn profile_grpc_status_falls_back() {
+        let rsp = Response::builder().status(StatusCode::OK).body(()).unwrap();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", 13.into());
+        let class = super::Response::Profile(grpc_classes())
+            .start(&rsp)
+            .eos(Some(&trailers));
+        assert_eq!(class, Class::Grpc(SuccessOrFailure::Failure, 13));
+
+        let class = super::Response::Profile(grpc_classes())
+            .start(&rsp)
+            .eos(None);
+        assert_eq!(class, Class::Default(SuccessOrFailure::Success));
+    }
 }
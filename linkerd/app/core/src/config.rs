@@ -1,3 +1,4 @@
+pub use crate::concurrency_limit::Aimd;
 pub use crate::exp_backoff::ExponentialBackoff;
 pub use crate::proxy::http::{h1, h2};
 pub use crate::transport::{BindTcp, DefaultOrigDstAddr, NoOrigDstAddr, OrigDstAddr};
@@ -26,6 +27,9 @@ pub struct ProxyConfig {
     pub cache_max_idle_age: Duration,
     pub dispatch_timeout: Duration,
     pub max_in_flight_requests: usize,
+    /// Adapts the in-flight request limit, up to `max_in_flight_requests`, to
+    /// the latency of responses.
+    pub adaptive_concurrency: Option<Aimd>,
     pub detect_protocol_timeout: Duration,
 }
 
@@ -33,8 +33,7 @@ impl fmt::Display for ControlAddr {
     }
 }
 
-type BalanceBody =
-    http::balance::PendingUntilFirstDataBody<tower::load::peak_ewma::Handle, hyper::Body>;
+type BalanceBody = http::balance::PendingUntilFirstDataBody<http::balance::Handle, hyper::Body>;
 
 type RspBody = linkerd2_http_metrics::requests::ResponseBody<BalanceBody, classify::Eos>;
 
@@ -62,6 +61,7 @@ impl Config {
             .push_timeout(self.connect.timeout)
             .push(self::client::layer())
             .push(reconnect::layer(backoff))
+            .push(http::balance::NewWeighted::layer())
             .push(self::resolve::layer(dns, backoff))
             .push_on_response(self::control::balance::layer())
             .into_new_service()
@@ -193,7 +193,7 @@ mod balance {
     const EWMA_DECAY: Duration = Duration::from_secs(10);
 
     pub fn layer<A, B>() -> http::balance::Layer<A, B> {
-        http::balance::layer(EWMA_DEFAULT_RTT, EWMA_DECAY)
+        http::balance::layer(EWMA_DEFAULT_RTT, EWMA_DECAY, Duration::from_secs(0), None)
     }
 }
 
@@ -238,6 +238,13 @@ mod client {
         }
     }
 
+    /// Control plane endpoints are weighted equally.
+    impl Into<http::balance::Weight> for &'_ Target {
+        fn into(self) -> http::balance::Weight {
+            http::balance::Weight::default()
+        }
+    }
+
     // === impl Layer ===
 
     pub fn layer<C, B>() -> impl svc::Layer<C, Service = Client<C, B>> + Copy
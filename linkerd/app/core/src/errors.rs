@@ -45,6 +45,9 @@ pub enum Reason {
     FailFast,
     GatewayLoop,
     NotFound,
+    FaultInjected,
+    RateLimited,
+    CircuitOpen,
     Unexpected,
 }
 
@@ -396,6 +399,9 @@ impl metrics::FmtLabels for Reason {
                 Reason::IdentityRequired => "identity required",
                 Reason::GatewayLoop => "gateway loop",
                 Reason::NotFound => "not found",
+                Reason::FaultInjected => "fault injected",
+                Reason::RateLimited => "rate limited",
+                Reason::CircuitOpen => "circuit open",
                 Reason::Io(_) => "i/o",
                 Reason::Unexpected => "unexpected",
             }
@@ -451,6 +457,36 @@ impl HttpError {
         }
     }
 
+    /// An error injected by a route's fault configuration.
+    pub fn fault(http: http::StatusCode, grpc: Code) -> Self {
+        Self {
+            message: "fault injected",
+            http,
+            grpc,
+            reason: Reason::FaultInjected,
+        }
+    }
+
+    /// A request rejected because its rate limit was exceeded.
+    pub fn rate_limited() -> Self {
+        Self {
+            message: "rate limit exceeded",
+            http: http::StatusCode::TOO_MANY_REQUESTS,
+            grpc: Code::ResourceExhausted,
+            reason: Reason::RateLimited,
+        }
+    }
+
+    /// A request rejected because its backend's circuit breaker is open.
+    pub fn circuit_open() -> Self {
+        Self {
+            message: "circuit breaker open",
+            http: http::StatusCode::SERVICE_UNAVAILABLE,
+            grpc: Code::Unavailable,
+            reason: Reason::CircuitOpen,
+        }
+    }
+
     pub fn status(&self) -> http::StatusCode {
         self.http
     }
@@ -0,0 +1,253 @@
+use super::dst::Route;
+use super::errors::HttpError;
+use crate::profiles::http::{Fault, FaultAbort, FaultDelay};
+use futures::ready;
+use linkerd2_error::Error;
+use linkerd2_stack::{layer, NewService, Proxy};
+use pin_project::pin_project;
+use rand::{thread_rng, Rng};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time;
+use tonic::Code;
+use tracing::debug;
+
+/// Injects a route's configured faults into its requests.
+#[derive(Clone, Debug)]
+pub struct NewFault<N> {
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct FaultProxy<P> {
+    fault: Option<Fault>,
+    inner: P,
+}
+
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+{
+    Inner(#[pin] P::Future),
+    Abort(Option<HttpError>),
+    Delay {
+        #[pin]
+        sleep: time::Sleep,
+        abort: Option<HttpError>,
+        proxy: P,
+        svc: S,
+        req: Option<Req>,
+    },
+}
+
+// === impl NewFault ===
+
+impl<N> NewFault<N> {
+    pub fn layer() -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(|inner| Self { inner })
+    }
+}
+
+impl<N: NewService<Route>> NewService<Route> for NewFault<N> {
+    type Service = FaultProxy<N::Service>;
+
+    fn new_service(&mut self, route: Route) -> Self::Service {
+        FaultProxy {
+            fault: route.route.fault().cloned(),
+            inner: self.inner.new_service(route),
+        }
+    }
+}
+
+// === impl FaultProxy ===
+
+impl<Req, P, S> Proxy<Req, S> for FaultProxy<P>
+where
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = ResponseFuture<P, S, Req>;
+
+    fn proxy(&self, svc: &mut S, req: Req) -> Self::Future {
+        let fault = match self.fault.as_ref() {
+            Some(fault) => fault,
+            None => return ResponseFuture::Inner(self.inner.proxy(svc, req)),
+        };
+
+        let abort = fault.abort.as_ref().and_then(sample_abort);
+        match fault.delay.as_ref().and_then(sample_delay) {
+            Some(delay) => {
+                debug!(?delay, "Injecting delay");
+                // The inner service is cloned so that it may be called once
+                // the delay elapses.
+                ResponseFuture::Delay {
+                    sleep: time::sleep(delay),
+                    abort,
+                    proxy: self.inner.clone(),
+                    svc: svc.clone(),
+                    req: Some(req),
+                }
+            }
+            None => match abort {
+                Some(error) => ResponseFuture::Abort(Some(error)),
+                None => ResponseFuture::Inner(self.inner.proxy(svc, req)),
+            },
+        }
+    }
+}
+
+fn is_sampled(percent: u8) -> bool {
+    percent > 0 && thread_rng().gen_range(0, 100) < percent
+}
+
+fn sample_delay(delay: &FaultDelay) -> Option<Duration> {
+    if !is_sampled(delay.percent) {
+        return None;
+    }
+    if delay.min >= delay.max {
+        return Some(delay.min);
+    }
+    Some(thread_rng().gen_range(delay.min, delay.max))
+}
+
+fn sample_abort(abort: &FaultAbort) -> Option<HttpError> {
+    if !is_sampled(abort.percent) {
+        return None;
+    }
+    let grpc = Code::from_i32(abort.grpc_status as i32);
+    debug!(http.status = %abort.http_status, grpc.status = ?grpc, "Injecting abort");
+    Some(HttpError::fault(abort.http_status, grpc))
+}
+
+// === impl ResponseFuture ===
+
+impl<P, S, Req> Future for ResponseFuture<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            let future = match self.as_mut().project() {
+                ResponseFutureProj::Inner(f) => return f.poll(cx).map_err(Into::into),
+                ResponseFutureProj::Abort(error) => {
+                    let error = error.take().expect("polled after ready");
+                    return Poll::Ready(Err(error.into()));
+                }
+                ResponseFutureProj::Delay {
+                    sleep,
+                    abort,
+                    proxy,
+                    svc,
+                    req,
+                } => {
+                    ready!(sleep.poll(cx));
+                    if let Some(error) = abort.take() {
+                        return Poll::Ready(Err(error.into()));
+                    }
+                    ready!(svc.poll_ready(cx)).map_err(Into::into)?;
+                    proxy.proxy(svc, req.take().expect("polled after ready"))
+                }
+            };
+            self.set(ResponseFuture::Inner(future));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::time::Instant;
+
+    /// A proxy that simply calls the inner service.
+    #[derive(Clone)]
+    struct Forward;
+
+    impl<S: tower::Service<()>> Proxy<(), S> for Forward
+    where
+        S::Error: Into<Error>,
+    {
+        type Request = ();
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = S::Future;
+
+        fn proxy(&self, svc: &mut S, req: ()) -> Self::Future {
+            svc.call(req)
+        }
+    }
+
+    fn ok() -> impl tower::Service<(), Response = (), Error = Error, Future = impl Send> + Clone {
+        tower::service_fn(|()| future::ok::<(), Error>(()))
+    }
+
+    fn proxy(fault: Fault) -> FaultProxy<Forward> {
+        FaultProxy {
+            fault: Some(fault),
+            inner: Forward,
+        }
+    }
+
+    #[tokio::test]
+    async fn abort() {
+        let fault = Fault {
+            abort: Some(FaultAbort {
+                percent: 100,
+                http_status: http::StatusCode::SERVICE_UNAVAILABLE,
+                grpc_status: 14,
+            }),
+            ..Fault::default()
+        };
+        let err = proxy(fault).proxy(&mut ok(), ()).await.unwrap_err();
+        let err = err
+            .downcast_ref::<HttpError>()
+            .expect("must be an HttpError");
+        assert_eq!(err.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn delay() {
+        let fault = Fault {
+            delay: Some(FaultDelay {
+                percent: 100,
+                min: Duration::from_millis(10),
+                max: Duration::from_millis(20),
+            }),
+            ..Fault::default()
+        };
+        let start = Instant::now();
+        proxy(fault).proxy(&mut ok(), ()).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn unsampled() {
+        let fault = Fault {
+            delay: Some(FaultDelay {
+                percent: 0,
+                min: Duration::from_secs(10),
+                max: Duration::from_secs(10),
+            }),
+            abort: Some(FaultAbort {
+                percent: 0,
+                http_status: http::StatusCode::SERVICE_UNAVAILABLE,
+                grpc_status: 14,
+            }),
+        };
+        proxy(fault).proxy(&mut ok(), ()).await.unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use super::dst::Route;
+use crate::profiles::http::HashKey;
+use crate::proxy::http::{
+    balance::{hash, RequestHash},
+    ClientHandle,
+};
+use linkerd2_stack::{layer, NewService, Proxy};
+
+/// Sets the hash of requests on routes with a `HashKey`, so that balancers
+/// dispatch requests with the same key to the same endpoint.
+#[derive(Clone, Debug)]
+pub struct NewHashKey<N> {
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct HashKeyProxy<P> {
+    hash_key: Option<HashKey>,
+    inner: P,
+}
+
+// === impl NewHashKey ===
+
+impl<N> NewHashKey<N> {
+    pub fn layer() -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(|inner| Self { inner })
+    }
+}
+
+impl<N: NewService<Route>> NewService<Route> for NewHashKey<N> {
+    type Service = HashKeyProxy<N::Service>;
+
+    fn new_service(&mut self, route: Route) -> Self::Service {
+        HashKeyProxy {
+            hash_key: route.route.hash_key().cloned(),
+            inner: self.inner.new_service(route),
+        }
+    }
+}
+
+// === impl HashKeyProxy ===
+
+impl<B, P, S> Proxy<http::Request<B>, S> for HashKeyProxy<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        if let Some(key) = self.hash_key.as_ref() {
+            // Requests without a value for the key are balanced by load.
+            if let Some(h) = hash_request(key, &req) {
+                req.extensions_mut().insert(RequestHash(h));
+            }
+        }
+        self.inner.proxy(svc, req)
+    }
+}
+
+fn hash_request<B>(key: &HashKey, req: &http::Request<B>) -> Option<u64> {
+    match key {
+        HashKey::SourceIp => req
+            .extensions()
+            .get::<ClientHandle>()
+            .map(|c| hash(&c.addr.ip())),
+        key => key.value(req).map(hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_key_value() {
+        let key = HashKey::Cookie("session".to_string());
+        let req = |cookie: &str| {
+            http::Request::builder()
+                .header(http::header::COOKIE, cookie)
+                .body(())
+                .unwrap()
+        };
+
+        let a = hash_request(&key, &req("session=a"));
+        assert!(a.is_some());
+        assert_eq!(hash_request(&key, &req("other=b; session=a")), a);
+        assert_ne!(hash_request(&key, &req("session=b")), a);
+        assert_eq!(hash_request(&key, &http::Request::new(())), None);
+    }
+}
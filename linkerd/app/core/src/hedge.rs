@@ -0,0 +1,271 @@
+use super::dst::Route;
+use super::retry::CloneRequest;
+use crate::proxy::http::balance::Dispatched;
+use linkerd2_metrics::Summary;
+use linkerd2_retry::{HedgePolicy, NewHedgeLayer, NewPolicy};
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower::retry::budget::Budget;
+use tracing::trace;
+
+/// Requests are not hedged until at least this many response latencies have
+/// been observed on a route.
+const MIN_LATENCIES: u64 = 20;
+
+/// Latencies are observed over a sliding window so that the hedge delay tracks
+/// recent behavior.
+const LATENCY_WINDOWS: u32 = 6;
+const LATENCY_LIFETIME: Duration = Duration::from_secs(60);
+
+/// How often the hedge delay is recomputed from the observed latencies.
+const DELAY_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub fn layer(latency_percentile: f64) -> NewHedgeLayer<NewHedge> {
+    NewHedgeLayer::new(NewHedge::new(latency_percentile))
+}
+
+/// Builds hedge policies for routes that are marked idempotent.
+#[derive(Clone, Debug)]
+pub struct NewHedge<C = ()> {
+    latency_percentile: f64,
+    _clone_request: PhantomData<C>,
+}
+
+/// Hedges requests that have not received a response after the route's
+/// `latency_percentile` response latency.
+///
+/// Hedged requests are dispatched to a different endpoint than the original
+/// request, if one is ready.
+pub struct Hedge<C = ()> {
+    budget: Arc<Budget>,
+    /// False when the budget is shared with the route's retry policy, which
+    /// already deposits into it for each response.
+    deposit: bool,
+    latencies: Arc<Latencies>,
+    _clone_request: PhantomData<C>,
+}
+
+/// A route's response latencies and the hedge delay derived from them.
+///
+/// Computing a percentile requires locking the latency histogram, so the
+/// delay is only recomputed periodically rather than for every request.
+struct Latencies {
+    summary: Summary,
+    percentile: f64,
+    /// The hedge delay, in milliseconds, or zero if too few latencies have
+    /// been observed.
+    delay_ms: AtomicU64,
+    /// When the delay should next be recomputed, in milliseconds since
+    /// `started`.
+    next_update_ms: AtomicU64,
+    started: Instant,
+}
+
+// === impl NewHedge ===
+
+impl NewHedge {
+    pub fn new(latency_percentile: f64) -> Self {
+        Self {
+            latency_percentile,
+            _clone_request: PhantomData,
+        }
+    }
+}
+
+impl<C> NewPolicy<Route> for NewHedge<C> {
+    type Policy = Hedge<C>;
+
+    fn new_policy(&self, route: &Route) -> Option<Self::Policy> {
+        let budget = route.route.hedges()?.budget().clone();
+        let deposit = match route.route.retries() {
+            Some(retries) => !Arc::ptr_eq(retries.budget(), &budget),
+            None => true,
+        };
+        Some(Hedge {
+            budget,
+            deposit,
+            latencies: Arc::new(Latencies::new(self.latency_percentile)),
+            _clone_request: self._clone_request,
+        })
+    }
+}
+
+// === impl Hedge ===
+
+impl<C, B> HedgePolicy<http::Request<B>> for Hedge<C>
+where
+    C: CloneRequest<http::Request<B>>,
+{
+    fn delay(&self) -> Option<Duration> {
+        self.latencies.delay()
+    }
+
+    fn clone_request(&self, req: &mut http::Request<B>) -> Option<http::Request<B>> {
+        let mut clone = C::clone_request(req)?;
+
+        // Both copies share a record of the endpoints they were dispatched
+        // to, so that balancers send them to different endpoints.
+        let dispatched = match req.extensions().get::<Dispatched>() {
+            Some(dispatched) => dispatched.clone(),
+            None => {
+                let dispatched = Dispatched::default();
+                req.extensions_mut().insert(dispatched.clone());
+                dispatched
+            }
+        };
+        clone.extensions_mut().insert(dispatched);
+
+        Some(clone)
+    }
+
+    fn can_hedge(&self, req: &http::Request<B>) -> bool {
+        if !C::can_retry(req) {
+            trace!("Request cannot be hedged");
+            return false;
+        }
+        // The hedged request's body is replayed from the original request's,
+        // so it may only be sent once the original body has been buffered.
+        if !C::is_complete(req) {
+            trace!("Request body has not been sent completely");
+            return false;
+        }
+        self.budget.withdraw().is_ok()
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        if self.deposit {
+            self.budget.deposit();
+        }
+        let _ = self.latencies.summary.record(latency.as_millis() as u64);
+    }
+}
+
+impl<C> Clone for Hedge<C> {
+    fn clone(&self) -> Self {
+        Self {
+            budget: self.budget.clone(),
+            deposit: self.deposit,
+            latencies: self.latencies.clone(),
+            _clone_request: self._clone_request,
+        }
+    }
+}
+
+// === impl Latencies ===
+
+impl Latencies {
+    fn new(percentile: f64) -> Self {
+        let summary = Summary::new_resizable(LATENCY_WINDOWS, LATENCY_LIFETIME, 2)
+            .expect("latency histogram must be valid");
+        Self {
+            summary,
+            percentile,
+            delay_ms: AtomicU64::new(0),
+            next_update_ms: AtomicU64::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        let now_ms = self.started.elapsed().as_millis() as u64;
+        let next_update_ms = self.next_update_ms.load(Ordering::Acquire);
+        // Only one caller recomputes the delay in each interval.
+        if now_ms >= next_update_ms
+            && self
+                .next_update_ms
+                .compare_exchange(
+                    next_update_ms,
+                    now_ms + DELAY_UPDATE_INTERVAL.as_millis() as u64,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+        {
+            let delay_ms = self
+                .summary
+                .quantile(self.percentile, MIN_LATENCIES)
+                .map(|ms| ms.max(1))
+                .unwrap_or(0);
+            trace!(delay_ms, "Updated hedge delay");
+            self.delay_ms.store(delay_ms, Ordering::Release);
+        }
+
+        match self.delay_ms.load(Ordering::Acquire) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::ReplayBody;
+    use hyper::body::HttpBody;
+    use tokio::time;
+
+    fn hedge() -> Hedge {
+        Hedge {
+            budget: Arc::new(Budget::new(Duration::from_secs(10), 10, 1.0)),
+            deposit: true,
+            latencies: Arc::new(Latencies::new(0.5)),
+            _clone_request: PhantomData,
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_delay() {
+        time::pause();
+        let hedge = hedge();
+        let policy = |h: &Hedge| HedgePolicy::<http::Request<ReplayBody<hyper::Body>>>::delay(h);
+
+        assert_eq!(policy(&hedge), None, "too few latencies were observed");
+        for _ in 0..MIN_LATENCIES {
+            hedge.latencies.summary.record(10).unwrap();
+        }
+        assert_eq!(policy(&hedge), None, "the delay must not be recomputed yet");
+
+        time::advance(DELAY_UPDATE_INTERVAL).await;
+        assert_eq!(policy(&hedge), Some(Duration::from_millis(10)));
+
+        for _ in 0..(MIN_LATENCIES * 2) {
+            hedge.latencies.summary.record(50).unwrap();
+        }
+        assert_eq!(policy(&hedge), Some(Duration::from_millis(10)));
+        time::advance(DELAY_UPDATE_INTERVAL).await;
+        assert_eq!(policy(&hedge), Some(Duration::from_millis(50)));
+    }
+
+    #[tokio::test]
+    async fn hedges_once_body_is_complete() {
+        let hedge = hedge();
+        let (mut tx, body) = hyper::Body::channel();
+        let mut req = http::Request::new(ReplayBody::new(body, 1024));
+        let clone = hedge
+            .clone_request(&mut req)
+            .expect("request must be cloned");
+
+        // Both copies are dispatched with the same record of endpoints.
+        assert!(req.extensions().get::<Dispatched>().is_some());
+        assert!(clone.extensions().get::<Dispatched>().is_some());
+
+        let mut body = req.into_body();
+        tx.send_data("hello".into()).await.unwrap();
+        assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello");
+        assert!(
+            !hedge.can_hedge(&clone),
+            "the original body is still being sent"
+        );
+
+        drop(tx);
+        assert!(body.data().await.is_none());
+        assert!(hedge.can_hedge(&clone));
+    }
+}
@@ -11,6 +11,7 @@
 
 pub use linkerd2_addr::{self as addr, Addr, NameAddr};
 pub use linkerd2_cache as cache;
+pub use linkerd2_concurrency_limit as concurrency_limit;
 pub use linkerd2_conditional::Conditional;
 pub use linkerd2_dns;
 pub use linkerd2_drain as drain;
@@ -28,14 +29,22 @@ pub use linkerd2_tracing as trace;
 
 mod addr_match;
 pub mod admin;
+pub mod circuit_breaker;
 pub mod classify;
 pub mod config;
 pub mod control;
 pub mod dns;
 pub mod dst;
 pub mod errors;
+pub mod fault;
 pub mod handle_time;
+pub mod hash_key;
+pub mod hedge;
+pub mod locality;
 pub mod metrics;
+pub mod mirror;
+pub mod outcome;
+pub mod outlier;
 pub mod proxy;
 pub mod retry;
 pub mod serve;
@@ -0,0 +1,407 @@
+use futures::prelude::*;
+use linkerd2_stack::{layer, NewService};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tokio::time;
+use tracing::debug;
+
+/// The destination label that names an endpoint's zone.
+pub const ZONE_LABEL: &str = "zone";
+
+/// Configures zone-aware load balancing.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The zone in which the proxy runs.
+    pub zone: String,
+
+    /// Endpoints in other zones only receive traffic when less than this ratio
+    /// of a balancer's local endpoints are healthy.
+    pub min_healthy: f64,
+
+    /// A local endpoint is unhealthy once it has not been ready for this long.
+    pub not_ready_timeout: Duration,
+}
+
+/// The zone of an endpoint, if it is known.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Zone(pub Option<String>);
+
+/// Wraps endpoint services so that load balancers prefer endpoints in the
+/// proxy's zone.
+///
+/// An endpoint in another zone is not ready while enough of its balancer's
+/// local endpoints are healthy, so the balancer only routes requests to local
+/// endpoints. A local endpoint is healthy until its inner service fails or has
+/// not been ready for the `not_ready_timeout`, so that local endpoints that are
+/// briefly busy do not cause traffic to spill over.
+/// Endpoints that share a `K`-typed key (i.e. that are in the same balancer)
+/// share a pool of local endpoints.
+pub struct NewLocality<K, N> {
+    config: Option<Arc<Config>>,
+    pools: Arc<Mutex<HashMap<K, Weak<Pool>>>>,
+    inner: N,
+}
+
+pub struct Locality<S> {
+    role: Option<Role>,
+    inner: S,
+}
+
+enum Role {
+    Local {
+        pool: Arc<Pool>,
+        healthy: bool,
+        timeout: Duration,
+        /// Set while the endpoint is not ready. Fires once the endpoint has
+        /// not been ready for the `timeout`.
+        not_ready: Option<Pin<Box<time::Sleep>>>,
+    },
+    Remote(Arc<Pool>),
+}
+
+/// Tracks the number of local endpoints in a balancer and how many are
+/// healthy.
+#[derive(Debug)]
+struct Pool {
+    min_healthy: f64,
+    local: AtomicUsize,
+    healthy: AtomicUsize,
+    remote_wakers: Mutex<Vec<Waker>>,
+}
+
+// === impl NewLocality ===
+
+impl<K, N> NewLocality<K, N> {
+    /// Locality is ignored when `config` is `None`.
+    pub fn layer(config: Option<Config>) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        let config = config.map(Arc::new);
+        let pools = Arc::new(Mutex::new(HashMap::new()));
+        layer::mk(move |inner| Self {
+            config: config.clone(),
+            pools: pools.clone(),
+            inner,
+        })
+    }
+}
+
+impl<T, K, N> NewService<T> for NewLocality<K, N>
+where
+    for<'t> &'t T: Into<K> + Into<Zone>,
+    K: Hash + Eq,
+    N: NewService<T>,
+{
+    type Service = Locality<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let role = self.config.as_ref().map(|config| {
+            let pool = {
+                let mut pools = self.pools.lock().expect("locality pools poisoned");
+                let key: K = (&target).into();
+                match pools.get(&key).and_then(Weak::upgrade) {
+                    Some(pool) => pool,
+                    None => {
+                        // Drop pools that are no longer referenced by any endpoint.
+                        pools.retain(|_, p| p.strong_count() > 0);
+                        let pool = Arc::new(Pool::new(config.min_healthy));
+                        pools.insert(key, Arc::downgrade(&pool));
+                        pool
+                    }
+                }
+            };
+
+            let Zone(zone) = (&target).into();
+            if zone.as_ref() == Some(&config.zone) {
+                Role::local(pool, config.not_ready_timeout)
+            } else {
+                Role::Remote(pool)
+            }
+        });
+
+        Locality {
+            role,
+            inner: self.inner.new_service(target),
+        }
+    }
+}
+
+impl<K, N: Clone> Clone for NewLocality<K, N> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            pools: self.pools.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// === impl Locality ===
+
+impl<Req, S> tower::Service<Req> for Locality<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        match self.role.as_mut() {
+            None => self.inner.poll_ready(cx),
+            Some(Role::Remote(pool)) => {
+                // Remote endpoints do not become ready until the balancer
+                // spills over from its local endpoints.
+                if !pool.spill(cx) {
+                    return Poll::Pending;
+                }
+                self.inner.poll_ready(cx)
+            }
+            Some(Role::Local {
+                pool,
+                healthy,
+                timeout,
+                not_ready,
+            }) => {
+                let poll = self.inner.poll_ready(cx);
+                let ready = match poll {
+                    Poll::Ready(Ok(())) => {
+                        *not_ready = None;
+                        true
+                    }
+                    Poll::Ready(Err(_)) => false,
+                    Poll::Pending => {
+                        let timeout = *timeout;
+                        let sleep = not_ready.get_or_insert_with(|| Box::pin(time::sleep(timeout)));
+                        *healthy && sleep.as_mut().poll(cx).is_pending()
+                    }
+                };
+                if ready != *healthy {
+                    *healthy = ready;
+                    pool.set_healthy(ready);
+                }
+                poll
+            }
+        }
+    }
+
+    #[inline]
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+// === impl Role ===
+
+impl Role {
+    fn local(pool: Arc<Pool>, timeout: Duration) -> Self {
+        // Endpoints are assumed to be healthy until they fail to become ready.
+        pool.local.fetch_add(1, Ordering::AcqRel);
+        pool.healthy.fetch_add(1, Ordering::AcqRel);
+        Role::Local {
+            pool,
+            healthy: true,
+            timeout,
+            not_ready: None,
+        }
+    }
+}
+
+impl Drop for Role {
+    fn drop(&mut self) {
+        if let Role::Local { pool, healthy, .. } = self {
+            pool.local.fetch_sub(1, Ordering::AcqRel);
+            if *healthy {
+                pool.healthy.fetch_sub(1, Ordering::AcqRel);
+            }
+            pool.wake_if_spilling();
+        }
+    }
+}
+
+// === impl Pool ===
+
+impl Pool {
+    fn new(min_healthy: f64) -> Self {
+        Self {
+            min_healthy,
+            local: AtomicUsize::new(0),
+            healthy: AtomicUsize::new(0),
+            remote_wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn is_spilling(&self) -> bool {
+        let local = self.local.load(Ordering::Acquire);
+        let healthy = self.healthy.load(Ordering::Acquire);
+        (healthy as f64) < self.min_healthy * (local as f64) || healthy == 0
+    }
+
+    /// Returns true if requests should spill over to remote endpoints.
+    /// Otherwise, the task is notified when the pool starts spilling over.
+    fn spill(&self, cx: &mut Context<'_>) -> bool {
+        if self.is_spilling() {
+            return true;
+        }
+
+        let mut wakers = self.remote_wakers.lock().expect("locality wakers poisoned");
+        if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        drop(wakers);
+
+        // Local endpoints may have become unhealthy while the waker was
+        // registered.
+        self.is_spilling()
+    }
+
+    fn set_healthy(&self, healthy: bool) {
+        if healthy {
+            self.healthy.fetch_add(1, Ordering::AcqRel);
+        } else {
+            self.healthy.fetch_sub(1, Ordering::AcqRel);
+            self.wake_if_spilling();
+        }
+    }
+
+    fn wake_if_spilling(&self) {
+        if !self.is_spilling() {
+            return;
+        }
+        let wakers =
+            std::mem::take(&mut *self.remote_wakers.lock().expect("locality wakers poisoned"));
+        if !wakers.is_empty() {
+            debug!(
+                local = self.local.load(Ordering::Acquire),
+                healthy = self.healthy.load(Ordering::Acquire),
+                "Spilling over to endpoints in other zones"
+            );
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker_ref;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn spills_when_local_endpoints_are_unhealthy() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let pool = Arc::new(Pool::new(0.5));
+        let a = Role::local(pool.clone(), TIMEOUT);
+        let _b = Role::local(pool.clone(), TIMEOUT);
+        let _c = Role::local(pool.clone(), TIMEOUT);
+        assert!(!pool.spill(&mut cx));
+
+        pool.set_healthy(false);
+        assert!(!pool.spill(&mut cx), "2 of 3 local endpoints are healthy");
+
+        pool.set_healthy(false);
+        assert!(pool.spill(&mut cx), "1 of 3 local endpoints is healthy");
+
+        pool.set_healthy(true);
+        assert!(!pool.spill(&mut cx));
+
+        drop(a);
+        assert!(!pool.spill(&mut cx), "the dropped endpoint was healthy");
+    }
+
+    #[test]
+    fn spills_without_local_endpoints() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let pool = Arc::new(Pool::new(0.5));
+        assert!(pool.spill(&mut cx));
+
+        let local = Role::local(pool.clone(), TIMEOUT);
+        assert!(!pool.spill(&mut cx));
+
+        drop(local);
+        assert!(pool.spill(&mut cx));
+    }
+
+    #[test]
+    fn remote_endpoints_are_not_ready_until_spilling() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let pool = Arc::new(Pool::new(1.0));
+        let local = Role::local(pool.clone(), TIMEOUT);
+        let mut remote = Locality {
+            role: Some(Role::Remote(pool.clone())),
+            inner: tower::service_fn(|()| futures::future::ok::<_, ()>(())),
+        };
+        assert!(tower::Service::poll_ready(&mut remote, &mut cx).is_pending());
+        assert_eq!(pool.remote_wakers.lock().unwrap().len(), 1);
+
+        pool.set_healthy(false);
+        assert!(pool.remote_wakers.lock().unwrap().is_empty());
+        assert!(tower::Service::poll_ready(&mut remote, &mut cx).is_ready());
+        drop(local);
+    }
+
+    #[tokio::test]
+    async fn local_endpoints_are_unhealthy_after_not_ready_timeout() {
+        time::pause();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let pool = Arc::new(Pool::new(1.0));
+        let ready = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut local = Locality {
+            role: Some(Role::local(pool.clone(), TIMEOUT)),
+            inner: Toggle(ready.clone()),
+        };
+        assert!(tower::Service::poll_ready(&mut local, &mut cx).is_ready());
+
+        // An endpoint that is briefly not ready remains healthy.
+        ready.store(false, Ordering::SeqCst);
+        assert!(tower::Service::poll_ready(&mut local, &mut cx).is_pending());
+        assert!(!pool.spill(&mut cx));
+        time::advance(TIMEOUT / 2).await;
+        assert!(tower::Service::poll_ready(&mut local, &mut cx).is_pending());
+        assert!(!pool.spill(&mut cx));
+
+        time::advance(TIMEOUT / 2).await;
+        assert!(tower::Service::poll_ready(&mut local, &mut cx).is_pending());
+        assert!(pool.spill(&mut cx), "the endpoint has not been ready");
+
+        // The timeout restarts once the endpoint is ready again.
+        ready.store(true, Ordering::SeqCst);
+        assert!(tower::Service::poll_ready(&mut local, &mut cx).is_ready());
+        assert!(!pool.spill(&mut cx));
+        ready.store(false, Ordering::SeqCst);
+        time::advance(TIMEOUT / 2).await;
+        assert!(tower::Service::poll_ready(&mut local, &mut cx).is_pending());
+        assert!(!pool.spill(&mut cx));
+    }
+
+    /// A service that is ready only while its flag is set.
+    struct Toggle(Arc<std::sync::atomic::AtomicBool>);
+
+    impl tower::Service<()> for Toggle {
+        type Response = ();
+        type Error = ();
+        type Future = future::Ready<Result<(), ()>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            if self.0.load(Ordering::SeqCst) {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        }
+
+        fn call(&mut self, (): ()) -> Self::Future {
+            future::ok(())
+        }
+    }
+}
@@ -9,16 +9,29 @@ use linkerd2_addr::Addr;
 use linkerd2_metrics::FmtLabels;
 pub use linkerd2_metrics::*;
 use std::fmt::{self, Write};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+metrics! {
+    request_concurrency_limit: Gauge {
+        "The maximum number of requests that may be in flight"
+    }
+}
+
 pub type ControlHttp = http_metrics::Requests<ControlLabels, Class>;
 
 pub type HttpEndpoint = http_metrics::Requests<EndpointLabels, Class>;
 
+pub type HttpEndpointEjections = http_metrics::Ejections<EndpointLabels>;
+
+pub type HttpConcreteBreakers = http_metrics::CircuitBreakers<ConcreteLabels>;
+
 pub type HttpRoute = http_metrics::Requests<RouteLabels, Class>;
 
 pub type HttpRouteRetry = http_metrics::Retries<RouteLabels>;
 
+pub type HttpRouteMirror = http_metrics::Mirrors<RouteLabels>;
+
 pub type Stack = stack_metrics::Registry<StackLabels>;
 
 #[derive(Clone)]
@@ -26,8 +39,12 @@ pub struct Proxy {
     pub http_route: HttpRoute,
     pub http_route_actual: HttpRoute,
     pub http_route_retry: HttpRouteRetry,
+    pub http_route_mirror: HttpRouteMirror,
     pub http_endpoint: HttpEndpoint,
+    pub http_endpoint_ejections: HttpEndpointEjections,
+    pub http_concrete_breakers: HttpConcreteBreakers,
     pub http_errors: errors::MetricsLayer,
+    pub concurrency_limit: Arc<Gauge>,
     pub stack: Stack,
     pub transport: transport::Metrics,
 }
@@ -39,6 +56,13 @@ pub struct Metrics {
     pub opencensus: opencensus::metrics::Registry,
 }
 
+/// Reports each direction's current in-flight request limit.
+#[derive(Clone, Debug, Default)]
+struct ConcurrencyLimitReport {
+    inbound: Arc<Gauge>,
+    outbound: Arc<Gauge>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ControlLabels {
     addr: Addr,
@@ -53,6 +77,12 @@ pub struct EndpointLabels {
     pub labels: Option<String>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConcreteLabels {
+    pub direction: Direction,
+    pub dst: Addr,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct StackLabels {
     pub direction: Direction,
@@ -115,6 +145,18 @@ impl Metrics {
             (m, r)
         };
 
+        let (http_endpoint_ejections, ejections_report) = {
+            let m = metrics::Ejections::<EndpointLabels>::default();
+            let r = m.clone().into_report(retain_idle);
+            (m, r)
+        };
+
+        let (http_concrete_breakers, breakers_report) = {
+            let m = metrics::CircuitBreakers::<ConcreteLabels>::default();
+            let r = m.clone().into_report(retain_idle);
+            (m, r)
+        };
+
         let (http_route, route_report) = {
             let m = metrics::Requests::<RouteLabels, Class>::default();
             let r = m.clone().into_report(retain_idle).with_prefix("route");
@@ -127,6 +169,12 @@ impl Metrics {
             (m, r)
         };
 
+        let (http_route_mirror, mirror_report) = {
+            let m = metrics::Mirrors::<RouteLabels>::default();
+            let r = m.clone().into_report(retain_idle).with_prefix("route");
+            (m, r)
+        };
+
         let (http_route_actual, actual_report) = {
             let m = metrics::Requests::<RouteLabels, Class>::default();
             let r = m
@@ -138,6 +186,8 @@ impl Metrics {
 
         let http_errors = errors::Metrics::default();
 
+        let concurrency_limit = ConcurrencyLimitReport::default();
+
         let stack = stack_metrics::Registry::default();
 
         let (transport, transport_report) = transport::metrics::new(retain_idle);
@@ -147,19 +197,27 @@ impl Metrics {
         let metrics = Metrics {
             inbound: Proxy {
                 http_endpoint: http_endpoint.clone(),
+                http_endpoint_ejections: http_endpoint_ejections.clone(),
+                http_concrete_breakers: http_concrete_breakers.clone(),
                 http_route: http_route.clone(),
                 http_route_actual: http_route_actual.clone(),
                 http_route_retry: http_route_retry.clone(),
+                http_route_mirror: http_route_mirror.clone(),
                 http_errors: http_errors.inbound(),
+                concurrency_limit: concurrency_limit.inbound.clone(),
                 stack: stack.clone(),
                 transport: transport.clone(),
             },
             outbound: Proxy {
                 http_endpoint,
+                http_endpoint_ejections,
+                http_concrete_breakers,
                 http_route,
                 http_route_retry,
+                http_route_mirror,
                 http_route_actual,
                 http_errors: http_errors.outbound(),
+                concurrency_limit: concurrency_limit.outbound.clone(),
                 stack: stack.clone(),
                 transport,
             },
@@ -169,10 +227,14 @@ impl Metrics {
 
         let report = (http_errors.report())
             .and_then(endpoint_report)
+            .and_then(ejections_report)
+            .and_then(breakers_report)
             .and_then(route_report)
             .and_then(retry_report)
+            .and_then(mirror_report)
             .and_then(actual_report)
             .and_then(control_report)
+            .and_then(concurrency_limit)
             .and_then(transport_report)
             .and_then(opencensus_report)
             .and_then(stack)
@@ -183,6 +245,17 @@ impl Metrics {
     }
 }
 
+// === impl ConcurrencyLimitReport ===
+
+impl FmtMetrics for ConcurrencyLimitReport {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        request_concurrency_limit.fmt_help(f)?;
+        request_concurrency_limit.fmt_metric_labeled(f, &*self.inbound, &Direction::In)?;
+        request_concurrency_limit.fmt_metric_labeled(f, &*self.outbound, &Direction::Out)?;
+        Ok(())
+    }
+}
+
 // === impl CtlLabels ===
 
 impl From<&'_ control::ControlAddr> for ControlLabels {
@@ -228,6 +301,15 @@ impl FmtLabels for RouteLabels {
     }
 }
 
+// === impl ConcreteLabels ===
+
+impl FmtLabels for ConcreteLabels {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.direction.fmt_labels(f)?;
+        write!(f, ",dst=\"{}\"", self.dst)
+    }
+}
+
 // === impl EndpointLabels ===
 
 impl FmtLabels for EndpointLabels {
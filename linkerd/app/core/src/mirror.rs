@@ -0,0 +1,572 @@
+use super::classify;
+use super::dst::Route;
+use super::http_metrics::mirrors::Handle;
+use super::metrics::HttpRouteMirror;
+use super::retry::{CloneRequest, ReplayBody};
+use crate::{profiles, Addr, Error};
+use futures::{future, prelude::*, ready};
+use hyper::body::HttpBody;
+use linkerd2_http_classify::{Classify, ClassifyEos, ClassifyResponse};
+use linkerd2_stack::{layer, NewService, Proxy};
+use pin_project::pin_project;
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tower::util::{Oneshot, ServiceExt};
+use tracing::{debug, debug_span, trace};
+use tracing_futures::Instrument;
+
+/// Sends a copy of each request on routes that have a mirror to the mirror's
+/// address.
+///
+/// This must be applied outside of retries, hedging, and fault injection so
+/// that each request is mirrored once, regardless of how it is handled. Copies
+/// are sent to the logical stack marked with a `MirrorTarget`, so that a
+/// `Mirror` service dispatches them to the mirror's address.
+///
+/// At most `max_in_flight` mirrored requests may be in flight on each route.
+/// Requests are not mirrored while a route is at this limit.
+#[derive(Clone, Debug)]
+pub struct NewMirrorProxy<N> {
+    metrics: HttpRouteMirror,
+    max_in_flight: usize,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct MirrorProxy<P> {
+    mirror: Option<RouteMirror>,
+    inner: P,
+}
+
+#[derive(Clone, Debug)]
+struct RouteMirror {
+    addr: Addr,
+    response_classes: profiles::http::ResponseClasses,
+    metrics: Handle,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: usize,
+}
+
+/// A request extension that indicates that a request is a copy that should be
+/// sent to a mirror.
+#[derive(Clone, Debug)]
+pub struct MirrorTarget(Addr);
+
+#[pin_project]
+pub struct ResponseFuture<F, S, A, B> {
+    #[pin]
+    inner: F,
+    mirror: Option<PendingMirror<S, A, B>>,
+}
+
+/// A copy of a request, which is sent once the original request completes.
+struct PendingMirror<S, A, B> {
+    addr: Addr,
+    req: http::Request<ReplayBody<A>>,
+    svc: S,
+    classify: classify::Response,
+    metrics: Handle,
+    in_flight: InFlight,
+    _body: PhantomData<fn() -> B>,
+}
+
+/// Decrements a route's count of in-flight mirrored requests when dropped.
+struct InFlight(Arc<AtomicUsize>);
+
+/// Builds `Mirror` services, which dispatch copies of requests to mirror
+/// services built by `M`.
+#[derive(Clone, Debug)]
+pub struct NewMirror<N, M> {
+    new_mirror: M,
+    inner: N,
+}
+
+/// Dispatches requests that are marked with a `MirrorTarget` to a mirror
+/// service for the target's address; all other requests are dispatched to the
+/// inner service.
+///
+/// Mirror services are dropped once the profile no longer has a route that is
+/// mirrored to their address.
+pub struct Mirror<T, S, M>
+where
+    M: NewService<(Option<Addr>, T)>,
+{
+    target: T,
+    profile: Option<Pin<Box<dyn Stream<Item = profiles::Profile> + Send + Sync>>>,
+    new_mirror: M,
+    mirrors: HashMap<Addr, M::Service>,
+    inner: S,
+}
+
+// === impl NewMirrorProxy ===
+
+impl<N> NewMirrorProxy<N> {
+    pub fn layer(
+        metrics: HttpRouteMirror,
+        max_in_flight: usize,
+    ) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self {
+            metrics: metrics.clone(),
+            max_in_flight,
+            inner,
+        })
+    }
+}
+
+impl<N: NewService<Route>> NewService<Route> for NewMirrorProxy<N> {
+    type Service = MirrorProxy<N::Service>;
+
+    fn new_service(&mut self, route: Route) -> Self::Service {
+        let mirror = route.route.mirror().cloned().map(|addr| RouteMirror {
+            addr,
+            response_classes: route.route.response_classes().clone(),
+            metrics: self.metrics.get_handle(&route),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: self.max_in_flight,
+        });
+        MirrorProxy {
+            mirror,
+            inner: self.inner.new_service(route),
+        }
+    }
+}
+
+// === impl MirrorProxy ===
+
+impl<A, B, P, S, RspB> Proxy<http::Request<ReplayBody<A>>, S> for MirrorProxy<P>
+where
+    A: HttpBody + Send + 'static,
+    A::Error: Into<Error>,
+    B: From<ReplayBody<A>> + Send + 'static,
+    P: Proxy<http::Request<ReplayBody<A>>, S, Request = http::Request<B>>,
+    S: tower::Service<http::Request<B>, Response = http::Response<RspB>> + Clone + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send,
+    RspB: HttpBody + Send + 'static,
+    RspB::Error: Into<Error>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = ResponseFuture<P::Future, S, A, B>;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<ReplayBody<A>>) -> Self::Future {
+        let mirror = self
+            .mirror
+            .as_ref()
+            .and_then(|mirror| mirror.pending(&req, svc));
+        ResponseFuture {
+            inner: self.inner.proxy(svc, req),
+            mirror,
+        }
+    }
+}
+
+// === impl RouteMirror ===
+
+impl RouteMirror {
+    /// Copies a request so that it may be mirrored once it completes.
+    fn pending<A: HttpBody, B, S: Clone>(
+        &self,
+        req: &http::Request<ReplayBody<A>>,
+        svc: &S,
+    ) -> Option<PendingMirror<S, A, B>> {
+        let mut mirror_req = match <() as CloneRequest<_>>::clone_request(req) {
+            Some(req) => req,
+            None => {
+                debug!(addr = %self.addr, "Request body is too large to be mirrored");
+                self.metrics.incr_body_too_large();
+                return None;
+            }
+        };
+
+        let in_flight = match InFlight::acquire(&self.in_flight, self.max_in_flight) {
+            Some(in_flight) => in_flight,
+            None => {
+                debug!(addr = %self.addr, "Too many mirrored requests in flight");
+                self.metrics.incr_in_flight_limit();
+                return None;
+            }
+        };
+
+        mirror_req
+            .extensions_mut()
+            .insert(MirrorTarget(self.addr.clone()));
+        Some(PendingMirror {
+            addr: self.addr.clone(),
+            classify: classify::Request::from(self.response_classes.clone()).classify(req),
+            req: mirror_req,
+            svc: svc.clone(),
+            metrics: self.metrics.clone(),
+            in_flight,
+            _body: PhantomData,
+        })
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<F, T, E, S, A, B, RspB> Future for ResponseFuture<F, S, A, B>
+where
+    F: Future<Output = Result<T, E>>,
+    A: HttpBody + Send + 'static,
+    A::Error: Into<Error>,
+    B: From<ReplayBody<A>> + Send + 'static,
+    S: tower::Service<http::Request<B>, Response = http::Response<RspB>> + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send,
+    RspB: HttpBody + Send + 'static,
+    RspB::Error: Into<Error>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let out = ready!(this.inner.poll(cx));
+        // The copy is only sent once the original request has completed, so
+        // that the mirror cannot impact the original request.
+        if let Some(mirror) = this.mirror.take() {
+            mirror.spawn();
+        }
+        Poll::Ready(out)
+    }
+}
+
+// === impl PendingMirror ===
+
+impl<S, A, B, RspB> PendingMirror<S, A, B>
+where
+    A: HttpBody + Send + 'static,
+    A::Error: Into<Error>,
+    B: From<ReplayBody<A>> + Send + 'static,
+    S: tower::Service<http::Request<B>, Response = http::Response<RspB>> + Send + 'static,
+    S::Error: Into<Error>,
+    S::Future: Send,
+    RspB: HttpBody + Send + 'static,
+    RspB::Error: Into<Error>,
+{
+    fn spawn(self) {
+        let Self {
+            addr,
+            req,
+            svc,
+            classify,
+            metrics,
+            in_flight,
+            ..
+        } = self;
+
+        // The copy's body is replayed from the original request's body, which
+        // must have been buffered completely.
+        if req.body().is_capped() {
+            debug!(%addr, "Request body is too large to be mirrored");
+            metrics.incr_body_too_large();
+            return;
+        }
+        if !req.body().is_complete() {
+            debug!(%addr, "Request body was not sent completely; not mirroring");
+            metrics.incr_body_streaming();
+            return;
+        }
+
+        tokio::spawn(
+            async move {
+                let rsp = svc.oneshot(req.map(B::from)).await.map_err(Into::into);
+                let class = match rsp {
+                    Ok(rsp) => {
+                        let eos = classify.start(&rsp);
+                        classify_body(rsp.into_body(), eos).await
+                    }
+                    Err(error) => {
+                        debug!(%error, "Mirrored request failed");
+                        classify.error(&error)
+                    }
+                };
+                let is_success = !class.is_failure();
+                trace!(is_success, "Mirrored request completed");
+                metrics.incr_result(is_success);
+                drop(in_flight);
+            }
+            .instrument(debug_span!("mirror", %addr)),
+        );
+    }
+}
+
+/// Reads a mirrored response's body, which is discarded, so that the response
+/// may be classified by its trailers.
+async fn classify_body<B>(body: B, eos: classify::Eos) -> classify::Class
+where
+    B: HttpBody,
+    B::Error: Into<Error>,
+{
+    let mut body = Box::pin(body);
+    while let Some(data) = body.data().await {
+        if let Err(e) = data {
+            return eos.error(&e.into());
+        }
+    }
+    match body.trailers().await {
+        Ok(trailers) => eos.eos(trailers.as_ref()),
+        Err(e) => eos.error(&e.into()),
+    }
+}
+
+// === impl InFlight ===
+
+impl InFlight {
+    fn acquire(in_flight: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        let prior = in_flight.fetch_add(1, Ordering::AcqRel);
+        let acquired = Self(in_flight.clone());
+        if prior >= max {
+            // Dropping the guard releases the slot.
+            return None;
+        }
+        Some(acquired)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// === impl NewMirror ===
+
+impl<N, M> NewMirror<N, M> {
+    pub fn layer(new_mirror: M) -> impl tower::layer::Layer<N, Service = Self> + Clone
+    where
+        M: Clone,
+    {
+        layer::mk(move |inner| Self {
+            new_mirror: new_mirror.clone(),
+            inner,
+        })
+    }
+}
+
+impl<T, N, M> NewService<T> for NewMirror<N, M>
+where
+    T: Clone,
+    for<'t> &'t T: Into<Option<profiles::Receiver>>,
+    N: NewService<T>,
+    M: NewService<(Option<Addr>, T)> + Clone,
+{
+    type Service = Mirror<T, N::Service, M>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let profile =
+            Into::<Option<profiles::Receiver>>::into(&target).map(profiles::stream_profile);
+        Mirror {
+            inner: self.inner.new_service(target.clone()),
+            target,
+            profile,
+            new_mirror: self.new_mirror.clone(),
+            mirrors: HashMap::new(),
+        }
+    }
+}
+
+// === impl Mirror ===
+
+impl<T, S, M, B> tower::Service<http::Request<B>> for Mirror<T, S, M>
+where
+    T: Clone,
+    S: tower::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+    M: NewService<(Option<Addr>, T)>,
+    M::Service: tower::Service<http::Request<B>, Response = S::Response> + Clone,
+    <M::Service as tower::Service<http::Request<B>>>::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<S::Future, Error>,
+        future::ErrInto<Oneshot<M::Service, http::Request<B>>, Error>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut update = None;
+        if let Some(profile) = self.profile.as_mut() {
+            while let Poll::Ready(Some(up)) = profile.as_mut().poll_next(cx) {
+                update = Some(up);
+            }
+        }
+
+        // Drop the mirrors that are no longer used by any of the profile's
+        // routes.
+        if let Some(profiles::Profile { http_routes, .. }) = update {
+            self.mirrors.retain(|addr, _| {
+                let is_used = http_routes.iter().any(|(_, r)| r.mirror() == Some(addr));
+                if !is_used {
+                    debug!(%addr, "Evicting mirror");
+                }
+                is_used
+            });
+        }
+
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let addr = match req.extensions_mut().remove::<MirrorTarget>() {
+            Some(MirrorTarget(addr)) => addr,
+            None => return future::Either::Left(self.inner.call(req).err_into()),
+        };
+
+        let mirror = {
+            let target = &self.target;
+            let new_mirror = &mut self.new_mirror;
+            self.mirrors
+                .entry(addr.clone())
+                .or_insert_with(|| new_mirror.new_service((Some(addr.clone()), target.clone())))
+                .clone()
+        };
+        trace!(%addr, "Dispatching mirrored request");
+        future::Either::Right(mirror.oneshot(req).err_into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{Direction, HttpRouteMirror};
+    use std::str::FromStr;
+    use tokio::sync::mpsc;
+    use tower::{layer::Layer, Service};
+
+    const MAX_IN_FLIGHT: usize = 100;
+
+    fn route_mirror(addr: &Addr) -> RouteMirror {
+        let route = Route {
+            route: Default::default(),
+            target: addr.clone(),
+            direction: Direction::Out,
+        };
+        RouteMirror {
+            addr: addr.clone(),
+            response_classes: Default::default(),
+            metrics: HttpRouteMirror::default().get_handle(&route),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: MAX_IN_FLIGHT,
+        }
+    }
+
+    async fn read_body<B>(body: B) -> String
+    where
+        B: HttpBody,
+        B::Error: std::fmt::Debug,
+    {
+        let bytes = hyper::body::to_bytes(body)
+            .await
+            .expect("body must be read");
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[derive(Clone)]
+    struct Logical(profiles::Receiver);
+
+    impl From<&'_ Logical> for Option<profiles::Receiver> {
+        fn from(Logical(rx): &Logical) -> Self {
+            Some(rx.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn mirrors_requests_once() {
+        let addr = Addr::from_str("shadow.ns.svc.cluster.local:8080").unwrap();
+        let mirror = MirrorProxy {
+            mirror: Some(route_mirror(&addr)),
+            inner: (),
+        };
+
+        // Records each request's body and whether it's a mirrored copy.
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut svc = tower::service_fn(move |req: http::Request<ReplayBody<hyper::Body>>| {
+            let tx = tx.clone();
+            async move {
+                let is_mirrored = req.extensions().get::<MirrorTarget>().is_some();
+                let body = read_body(req.into_body()).await;
+                tx.send((is_mirrored, body)).unwrap();
+                Ok::<_, Error>(http::Response::new(hyper::Body::empty()))
+            }
+        });
+
+        let req = http::Request::new(ReplayBody::new(hyper::Body::from("hello"), 1024));
+        mirror.proxy(&mut svc, req).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), (false, "hello".to_string()));
+        assert_eq!(rx.recv().await.unwrap(), (true, "hello".to_string()));
+
+        // Requests with bodies that are too large are not mirrored.
+        let req = http::Request::new(ReplayBody::new(hyper::Body::from(vec![b'a'; 2048]), 1024));
+        mirror.proxy(&mut svc, req).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), (false, "a".repeat(2048)));
+
+        // Requests are not mirrored while too many mirrored requests are in
+        // flight.
+        let in_flight = (0..MAX_IN_FLIGHT)
+            .map(|_| {
+                let in_flight = &mirror.mirror.as_ref().unwrap().in_flight;
+                InFlight::acquire(in_flight, MAX_IN_FLIGHT).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let req = http::Request::new(ReplayBody::new(hyper::Body::from("world"), 1024));
+        mirror.proxy(&mut svc, req).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), (false, "world".to_string()));
+        drop(in_flight);
+
+        drop((mirror, svc));
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dispatches_marked_requests_to_mirrors() {
+        let addr = Addr::from_str("shadow.ns.svc.cluster.local:8080").unwrap();
+        let mut route = profiles::http::Route::default();
+        route.set_mirror(addr.clone());
+        let profile = profiles::Profile {
+            http_routes: vec![(
+                profiles::http::RequestMatch::Path(regex::Regex::new(".*").unwrap()),
+                route,
+            )],
+            ..Default::default()
+        };
+        let (tx, rx) = tokio::sync::watch::channel(profile);
+
+        let new_mirror = |(addr, _): (Option<Addr>, Logical)| {
+            tower::service_fn(move |_: http::Request<()>| {
+                future::ok::<_, Error>(format!("mirror {}", addr.clone().unwrap()))
+            })
+        };
+        let new_inner = |_: Logical| {
+            tower::service_fn(|_: http::Request<()>| future::ok::<_, Error>("inner".to_string()))
+        };
+        let mut mirror = NewMirror::layer(new_mirror)
+            .layer(new_inner)
+            .new_service(Logical(rx));
+
+        future::poll_fn(|cx| mirror.poll_ready(cx)).await.unwrap();
+        let rsp = mirror.call(http::Request::new(())).await.unwrap();
+        assert_eq!(rsp, "inner");
+
+        let mut req = http::Request::new(());
+        req.extensions_mut().insert(MirrorTarget(addr.clone()));
+        future::poll_fn(|cx| mirror.poll_ready(cx)).await.unwrap();
+        let rsp = mirror.call(req).await.unwrap();
+        assert_eq!(rsp, format!("mirror {}", addr));
+        assert_eq!(mirror.mirrors.len(), 1);
+
+        // The mirror is dropped once no route uses it.
+        tx.send(profiles::Profile::default()).unwrap();
+        future::poll_fn(|cx| mirror.poll_ready(cx)).await.unwrap();
+        assert!(mirror.mirrors.is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+//! Reports whether each response succeeded or failed, as determined by its
+//! request's response classifier.
+
+use super::classify;
+use crate::Error;
+use futures::{ready, TryFuture};
+use http::HeaderMap;
+use hyper::body::HttpBody;
+use linkerd2_http_classify::{ClassifyEos, ClassifyResponse};
+use pin_project::{pin_project, pinned_drop};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// Records the outcome of a response.
+///
+/// Each response's outcome is recorded at most once. Responses whose bodies
+/// are dropped before they complete are classified without trailers; requests
+/// that are canceled before a response is received are not recorded.
+pub trait Record: Send + Sync {
+    fn record(&self, is_failure: bool);
+}
+
+#[pin_project]
+pub struct ResponseFuture<F> {
+    #[pin]
+    inner: F,
+    classify: Option<classify::Response>,
+    recorder: Option<Arc<dyn Record>>,
+}
+
+#[pin_project(PinnedDrop)]
+pub struct ResponseBody<B> {
+    #[pin]
+    inner: B,
+    classify: Option<classify::Eos>,
+    recorder: Option<Arc<dyn Record>>,
+}
+
+// === impl ResponseFuture ===
+
+impl<F> ResponseFuture<F> {
+    /// Records the outcome of a response, as classified by its request's
+    /// response classifier, if it has one.
+    pub fn new(classify: Option<classify::Response>, recorder: Arc<dyn Record>, inner: F) -> Self {
+        Self {
+            inner,
+            classify,
+            recorder: Some(recorder),
+        }
+    }
+}
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: TryFuture<Ok = http::Response<B>>,
+    F::Error: Into<Error>,
+{
+    type Output = Result<http::Response<ResponseBody<B>>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.try_poll(cx));
+        let recorder = this.recorder.take().expect("polled after ready");
+        Poll::Ready(match res {
+            Ok(rsp) => {
+                let classify = match this.classify.take() {
+                    Some(classify) => classify,
+                    None => {
+                        // Without a classifier, only server errors indicate
+                        // failure.
+                        recorder.record(rsp.status().is_server_error());
+                        let rsp = rsp.map(|inner| ResponseBody {
+                            inner,
+                            classify: None,
+                            recorder: None,
+                        });
+                        return Poll::Ready(Ok(rsp));
+                    }
+                };
+                let classify = classify.start(&rsp);
+                Ok(rsp.map(|inner| ResponseBody {
+                    inner,
+                    classify: Some(classify),
+                    recorder: Some(recorder),
+                }))
+            }
+            Err(e) => {
+                let e = e.into();
+                recorder.record(true);
+                Err(e)
+            }
+        })
+    }
+}
+
+// === impl ResponseBody ===
+
+impl<B> ResponseBody<B> {
+    fn record(self: Pin<&mut Self>, trailers: Option<&HeaderMap>) {
+        let this = self.project();
+        if let (Some(classify), Some(recorder)) = (this.classify.take(), this.recorder.take()) {
+            recorder.record(classify.eos(trailers).is_failure());
+        }
+    }
+
+    fn record_error(self: Pin<&mut Self>, error: &Error) {
+        let this = self.project();
+        if let (Some(classify), Some(recorder)) = (this.classify.take(), this.recorder.take()) {
+            recorder.record(classify.error(error).is_failure());
+        }
+    }
+}
+
+impl<B> HttpBody for ResponseBody<B>
+where
+    B: HttpBody,
+    B::Error: Into<Error>,
+{
+    type Data = B::Data;
+    type Error = Error;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        match ready!(self.as_mut().project().inner.poll_data(cx)) {
+            Some(Ok(data)) => Poll::Ready(Some(Ok(data))),
+            Some(Err(e)) => {
+                let e = e.into();
+                self.record_error(&e);
+                Poll::Ready(Some(Err(e)))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        match ready!(self.as_mut().project().inner.poll_trailers(cx)) {
+            Ok(trailers) => {
+                self.record(trailers.as_ref());
+                Poll::Ready(Ok(trailers))
+            }
+            Err(e) => {
+                let e = e.into();
+                self.record_error(&e);
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+#[pinned_drop]
+impl<B> PinnedDrop for ResponseBody<B> {
+    fn drop(self: Pin<&mut Self>) {
+        // Responses that are dropped before their trailers are read are
+        // classified without trailers.
+        self.record(None);
+    }
+}
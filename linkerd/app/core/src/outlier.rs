@@ -0,0 +1,515 @@
+use super::classify;
+use super::http_metrics::ejections::Handle;
+use super::metrics::{EndpointLabels, HttpEndpointEjections};
+use super::outcome::{self, Record, ResponseBody};
+use crate::Error;
+use futures::ready;
+use linkerd2_stack::{layer, NewService};
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{self, Instant};
+use tracing::debug;
+
+/// Configures outlier detection for balanced endpoints.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Ejects an endpoint after this many consecutive failures. Zero disables
+    /// consecutive-failure detection.
+    pub consecutive_failures: u32,
+
+    /// Ejects an endpoint when its success rate over a window falls below this
+    /// ratio. Zero disables success-rate detection.
+    pub min_success_rate: f64,
+
+    /// The minimum number of responses in a window before its success rate is
+    /// considered.
+    pub success_rate_min_requests: u32,
+    pub success_rate_window: Duration,
+
+    /// An endpoint is ejected for `base_ejection_time` multiplied by the
+    /// number of times it has been ejected, up to `max_ejection_time`. The
+    /// count is reset once an endpoint has not been ejected for
+    /// `max_ejection_time`.
+    pub base_ejection_time: Duration,
+    pub max_ejection_time: Duration,
+
+    /// The maximum percentage of a balancer's endpoints that may be ejected at
+    /// once. A single endpoint may always be ejected from a balancer with more
+    /// than one endpoint, unless this is zero.
+    pub max_ejection_percent: u8,
+}
+
+/// Wraps endpoint services so that endpoints that fail too often are ejected
+/// from their load balancer.
+///
+/// An ejected endpoint is never ready, so the balancer routes requests to other
+/// endpoints until the ejection expires. Endpoints that share a `K`-typed key
+/// (i.e. that are in the same balancer) share an ejection limit.
+pub struct NewOutlier<K, N> {
+    config: Arc<Config>,
+    pools: Arc<Mutex<HashMap<K, Weak<Pool>>>>,
+    metrics: HttpEndpointEjections,
+    inner: N,
+}
+
+pub struct Outlier<S> {
+    endpoint: Arc<Endpoint>,
+    sleep: Option<Pin<Box<time::Sleep>>>,
+    inner: S,
+}
+
+/// Tracks the number of endpoints in a balancer and how many are ejected.
+#[derive(Debug, Default)]
+struct Pool {
+    endpoints: AtomicUsize,
+    ejected: AtomicUsize,
+}
+
+struct Endpoint {
+    config: Arc<Config>,
+    pool: Arc<Pool>,
+    metrics: Handle,
+    state: Mutex<State>,
+}
+
+struct State {
+    consecutive_failures: u32,
+    window_start: Instant,
+    window_successes: u32,
+    window_failures: u32,
+    ejections: u32,
+    ejected_until: Option<Instant>,
+    /// When the endpoint was last restored after an ejection.
+    restored_at: Option<Instant>,
+}
+
+// === impl NewOutlier ===
+
+impl<K, N> NewOutlier<K, N> {
+    pub fn layer(
+        config: Config,
+        metrics: HttpEndpointEjections,
+    ) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        let config = Arc::new(config);
+        let pools = Arc::new(Mutex::new(HashMap::new()));
+        layer::mk(move |inner| Self {
+            config: config.clone(),
+            pools: pools.clone(),
+            metrics: metrics.clone(),
+            inner,
+        })
+    }
+}
+
+impl<T, K, N> NewService<T> for NewOutlier<K, N>
+where
+    for<'t> &'t T: Into<K> + Into<EndpointLabels>,
+    K: Hash + Eq,
+    N: NewService<T>,
+{
+    type Service = Outlier<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let pool = {
+            let mut pools = self.pools.lock().expect("outlier pools poisoned");
+            let key: K = (&target).into();
+            match pools.get(&key).and_then(Weak::upgrade) {
+                Some(pool) => pool,
+                None => {
+                    // Drop pools that are no longer referenced by any endpoint.
+                    pools.retain(|_, p| p.strong_count() > 0);
+                    let pool = Arc::new(Pool::default());
+                    pools.insert(key, Arc::downgrade(&pool));
+                    pool
+                }
+            }
+        };
+
+        let labels: EndpointLabels = (&target).into();
+        let metrics = self.metrics.get_handle(labels);
+        Outlier {
+            endpoint: Arc::new(Endpoint::new(self.config.clone(), pool, metrics)),
+            sleep: None,
+            inner: self.inner.new_service(target),
+        }
+    }
+}
+
+impl<K, N: Clone> Clone for NewOutlier<K, N> {
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            pools: self.pools.clone(),
+            metrics: self.metrics.clone(),
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// === impl Outlier ===
+
+impl<A, B, S> tower::Service<http::Request<A>> for Outlier<S>
+where
+    S: tower::Service<http::Request<A>, Response = http::Response<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = http::Response<ResponseBody<B>>;
+    type Error = Error;
+    type Future = outcome::ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // An ejected endpoint does not become ready until its ejection
+        // expires, so the balancer sends requests to other endpoints.
+        if let Some(until) = self.endpoint.ejected_until() {
+            let sleep = self
+                .sleep
+                .get_or_insert_with(|| Box::pin(time::sleep_until(until)));
+            ready!(sleep.as_mut().poll(cx));
+            self.sleep = None;
+            self.endpoint.restore();
+        }
+
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let classify = req.extensions().get::<classify::Response>().cloned();
+        outcome::ResponseFuture::new(classify, self.endpoint.clone(), self.inner.call(req))
+    }
+}
+
+// === impl Endpoint ===
+
+impl Endpoint {
+    fn new(config: Arc<Config>, pool: Arc<Pool>, metrics: Handle) -> Self {
+        pool.endpoints.fetch_add(1, Ordering::AcqRel);
+        Self {
+            config,
+            pool,
+            metrics,
+            state: Mutex::new(State {
+                consecutive_failures: 0,
+                window_start: Instant::now(),
+                window_successes: 0,
+                window_failures: 0,
+                ejections: 0,
+                ejected_until: None,
+                restored_at: None,
+            }),
+        }
+    }
+
+    fn ejected_until(&self) -> Option<Instant> {
+        self.state.lock().ok()?.ejected_until
+    }
+
+    /// Restores an ejected endpoint once its ejection has expired.
+    fn restore(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            if state.ejected_until.take().is_some() {
+                debug!("Restoring ejected endpoint");
+                self.pool.ejected.fetch_sub(1, Ordering::AcqRel);
+                let now = Instant::now();
+                state.restored_at = Some(now);
+                state.reset_window(now);
+            }
+        }
+    }
+}
+
+impl Record for Endpoint {
+    /// Records the outcome of a response, ejecting the endpoint if it is an
+    /// outlier.
+    fn record(&self, is_failure: bool) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        // Responses to requests that were dispatched before the endpoint was
+        // ejected are ignored.
+        if state.ejected_until.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+        if now.saturating_duration_since(state.window_start) >= self.config.success_rate_window {
+            state.reset_window(now);
+        }
+        if is_failure {
+            state.consecutive_failures += 1;
+            state.window_failures += 1;
+        } else {
+            state.consecutive_failures = 0;
+            state.window_successes += 1;
+        }
+
+        let config = &self.config;
+        let consecutive = config.consecutive_failures > 0
+            && state.consecutive_failures >= config.consecutive_failures;
+        let responses = state.window_successes + state.window_failures;
+        let success_rate = config.min_success_rate > 0.0
+            && responses > 0
+            && responses >= config.success_rate_min_requests
+            && f64::from(state.window_successes) / f64::from(responses) < config.min_success_rate;
+        if !consecutive && !success_rate {
+            return;
+        }
+
+        if !self.pool.try_eject(config.max_ejection_percent) {
+            debug!(
+                consecutive_failures = state.consecutive_failures,
+                "Outlier not ejected; too many endpoints are already ejected",
+            );
+            return;
+        }
+
+        // Endpoints that have stayed healthy since they were last restored
+        // are ejected as if for the first time.
+        if let Some(restored_at) = state.restored_at.take() {
+            if now.saturating_duration_since(restored_at) >= config.max_ejection_time {
+                state.ejections = 0;
+            }
+        }
+        state.ejections += 1;
+        let ejection_time = config
+            .base_ejection_time
+            .checked_mul(state.ejections)
+            .unwrap_or(config.max_ejection_time)
+            .min(config.max_ejection_time);
+        debug!(
+            consecutive_failures = state.consecutive_failures,
+            window.successes = state.window_successes,
+            window.failures = state.window_failures,
+            ejections = state.ejections,
+            ?ejection_time,
+            "Ejecting outlier",
+        );
+        state.ejected_until = Some(now + ejection_time);
+        state.consecutive_failures = 0;
+        state.reset_window(now);
+        self.metrics.incr_ejected();
+    }
+}
+
+impl Drop for Endpoint {
+    fn drop(&mut self) {
+        self.pool.endpoints.fetch_sub(1, Ordering::AcqRel);
+        let is_ejected = self
+            .state
+            .get_mut()
+            .map(|s| s.ejected_until.is_some())
+            .unwrap_or(false);
+        if is_ejected {
+            self.pool.ejected.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+// === impl State ===
+
+impl State {
+    fn reset_window(&mut self, now: Instant) {
+        self.window_start = now;
+        self.window_successes = 0;
+        self.window_failures = 0;
+    }
+}
+
+// === impl Pool ===
+
+impl Pool {
+    /// Reserves an ejection if doing so does not exceed the maximum percentage
+    /// of ejected endpoints.
+    fn try_eject(&self, max_percent: u8) -> bool {
+        let endpoints = self.endpoints.load(Ordering::Acquire);
+        if endpoints < 2 || max_percent == 0 {
+            return false;
+        }
+        let max = (endpoints * max_percent as usize / 100).max(1);
+        self.ejected
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |ejected| {
+                if ejected < max {
+                    Some(ejected + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_metrics::Ejections;
+
+    fn config() -> Config {
+        Config {
+            consecutive_failures: 3,
+            min_success_rate: 0.0,
+            success_rate_min_requests: 10,
+            success_rate_window: Duration::from_secs(10),
+            base_ejection_time: Duration::from_secs(10),
+            max_ejection_time: Duration::from_secs(25),
+            max_ejection_percent: 50,
+        }
+    }
+
+    fn endpoints(config: Config, n: usize) -> Vec<Arc<Endpoint>> {
+        let config = Arc::new(config);
+        let pool = Arc::new(Pool::default());
+        let metrics = Ejections::<()>::default();
+        (0..n)
+            .map(|_| {
+                let endpoint = Endpoint::new(config.clone(), pool.clone(), metrics.get_handle(()));
+                Arc::new(endpoint)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn ejects_after_consecutive_failures() {
+        time::pause();
+        let eps = endpoints(config(), 2);
+
+        eps[0].record(true);
+        eps[0].record(true);
+        eps[0].record(false);
+        eps[0].record(true);
+        eps[0].record(true);
+        assert_eq!(eps[0].ejected_until(), None);
+
+        eps[0].record(true);
+        assert_eq!(
+            eps[0].ejected_until(),
+            Some(Instant::now() + Duration::from_secs(10))
+        );
+    }
+
+    #[tokio::test]
+    async fn ejects_low_success_rate() {
+        time::pause();
+        let eps = endpoints(
+            Config {
+                consecutive_failures: 0,
+                min_success_rate: 0.9,
+                ..config()
+            },
+            2,
+        );
+
+        for _ in 0..3 {
+            eps[0].record(true);
+            eps[0].record(true);
+            eps[0].record(false);
+        }
+        // The success rate is only considered once enough responses have been
+        // observed.
+        assert_eq!(eps[0].ejected_until(), None);
+
+        // Responses in prior windows are not considered.
+        time::advance(Duration::from_secs(10)).await;
+        for _ in 0..9 {
+            eps[0].record(true);
+        }
+        assert_eq!(eps[0].ejected_until(), None);
+        eps[0].record(false);
+        assert!(eps[0].ejected_until().is_some());
+    }
+
+    #[tokio::test]
+    async fn limits_ejected_endpoints() {
+        time::pause();
+        let eps = endpoints(config(), 4);
+
+        for ep in eps.iter() {
+            for _ in 0..3 {
+                ep.record(true);
+            }
+        }
+        let ejected = eps.iter().filter(|ep| ep.ejected_until().is_some());
+        assert_eq!(ejected.count(), 2);
+
+        // Endpoints are not ejected from single-endpoint balancers.
+        let eps = endpoints(config(), 1);
+        for _ in 0..3 {
+            eps[0].record(true);
+        }
+        assert_eq!(eps[0].ejected_until(), None);
+    }
+
+    #[tokio::test]
+    async fn ejection_time_grows() {
+        time::pause();
+        let eps = endpoints(config(), 2);
+
+        for expected in &[10, 20, 25] {
+            for _ in 0..3 {
+                eps[0].record(true);
+            }
+            let until = eps[0].ejected_until().expect("must be ejected");
+            assert_eq!(until - Instant::now(), Duration::from_secs(*expected));
+            time::advance(until - Instant::now()).await;
+            eps[0].restore();
+        }
+    }
+
+    #[tokio::test]
+    async fn ejection_time_resets_after_healthy_interval() {
+        time::pause();
+        let eps = endpoints(config(), 2);
+        let eject = |expected: u64| {
+            for _ in 0..3 {
+                eps[0].record(true);
+            }
+            let until = eps[0].ejected_until().expect("must be ejected");
+            assert_eq!(until - Instant::now(), Duration::from_secs(expected));
+            until
+        };
+
+        let until = eject(10);
+        time::advance(until - Instant::now()).await;
+        eps[0].restore();
+
+        // The endpoint has not been healthy for `max_ejection_time`.
+        time::advance(Duration::from_secs(24)).await;
+        let until = eject(20);
+        time::advance(until - Instant::now()).await;
+        eps[0].restore();
+
+        time::advance(Duration::from_secs(25)).await;
+        eject(10);
+    }
+
+    #[tokio::test]
+    async fn ejected_endpoints_are_not_ready() {
+        time::pause();
+        let mut eps = endpoints(config(), 2);
+        let mut svc = Outlier {
+            endpoint: eps.remove(0),
+            sleep: None,
+            inner: tower::service_fn(|_: http::Request<()>| {
+                futures::future::ok::<_, Error>(http::Response::new(hyper::Body::empty()))
+            }),
+        };
+        for _ in 0..3 {
+            svc.endpoint.record(true);
+        }
+
+        let mut ready = futures::future::poll_fn(|cx| tower::Service::poll_ready(&mut svc, cx));
+        assert!(futures::poll!(&mut ready).is_pending());
+        time::advance(Duration::from_secs(10)).await;
+        ready.await.expect("must become ready");
+        assert_eq!(svc.endpoint.ejected_until(), None);
+    }
+}
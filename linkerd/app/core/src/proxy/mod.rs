@@ -4,6 +4,7 @@ pub use linkerd2_proxy_api_resolve as api_resolve;
 pub use linkerd2_proxy_core as core;
 pub use linkerd2_proxy_discover as discover;
 pub use linkerd2_proxy_dns_resolve as dns_resolve;
+pub use linkerd2_proxy_file_resolve as file_resolve;
 pub use linkerd2_proxy_http as http;
 pub use linkerd2_proxy_identity as identity;
 pub use linkerd2_proxy_resolve as resolve;
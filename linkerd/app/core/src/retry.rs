@@ -4,26 +4,99 @@ use super::dst::Route;
 use super::http_metrics::retries::Handle;
 use super::metrics::HttpRouteRetry;
 use super::transport::tls;
-use crate::profiles;
-use futures::future;
+use crate::{
+    exp_backoff::ExponentialBackoff,
+    profiles,
+    proxy::http::{balance::RequestHash, timeout},
+};
+use futures::ready;
 use hyper::body::HttpBody;
 use linkerd2_http_classify::{Classify, ClassifyEos, ClassifyResponse};
+use linkerd2_http_retry::PeekTrailers;
+pub use linkerd2_http_retry::{PeekTrailersBody, ReplayBody};
 use linkerd2_retry::NewRetryLayer;
-use std::marker::PhantomData;
-use std::sync::Arc;
+use linkerd2_stack::{layer, NewService, Proxy};
+use pin_project::pin_project;
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::time;
 use tower::retry::budget::Budget;
+use tracing::trace;
 
-pub fn layer(metrics: HttpRouteRetry) -> NewRetryLayer<NewRetry> {
-    NewRetryLayer::new(NewRetry::new(metrics))
+pub fn layer(metrics: HttpRouteRetry, backoff: ExponentialBackoff) -> NewRetryLayer<NewRetry> {
+    NewRetryLayer::new(NewRetry::new(metrics, backoff))
 }
 
 pub trait CloneRequest<Req> {
     fn clone_request(req: &Req) -> Option<Req>;
+
+    /// Returns false if a request can no longer be retried, e.g. because its
+    /// body could not be buffered.
+    fn can_retry(_: &Req) -> bool {
+        true
+    }
+
+    /// Returns false if a copy of a request cannot be sent until the original
+    /// request has been sent completely, e.g. because its body is still being
+    /// buffered.
+    fn is_complete(_: &Req) -> bool {
+        true
+    }
+}
+
+/// Wraps request bodies in a `ReplayBody` so that requests on retryable routes
+/// may be retried (or hedged, or mirrored).
+///
+/// Bodies are only buffered on routes that have a retry or hedge policy or a
+/// mirror.
+#[derive(Clone, Debug)]
+pub struct NewReplayBody<N> {
+    max_bytes: usize,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplayBodyProxy<P> {
+    max_bytes: usize,
+    inner: P,
+}
+
+/// Reads ahead in responses on retryable routes so that they may be classified
+/// by their trailers (e.g. a gRPC status) before a retry decision is made.
+///
+/// Only the first data frame of each response is read ahead, so responses that
+/// stream data are not held back until they complete.
+#[derive(Clone, Debug)]
+pub struct NewPeekTrailers<N> {
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct PeekTrailersProxy<P> {
+    peek: bool,
+    inner: P,
+}
+
+#[pin_project(project = PeekTrailersFutureProj)]
+pub enum PeekTrailersFuture<F, B: HttpBody> {
+    Inner {
+        #[pin]
+        future: F,
+        peek: bool,
+    },
+    Peek(#[pin] PeekTrailers<B>),
 }
 
 #[derive(Clone, Debug)]
 pub struct NewRetry<C = ()> {
     metrics: HttpRouteRetry,
+    backoff: ExponentialBackoff,
     _clone_request: PhantomData<C>,
 }
 
@@ -31,13 +104,26 @@ pub struct Retry<C = ()> {
     metrics: Handle,
     budget: Arc<Budget>,
     response_classes: profiles::http::ResponseClasses,
+    backoff: ExponentialBackoff,
+    timeout: Option<Duration>,
+    /// The number of times the request has been retried.
+    retries: u32,
     _clone_request: PhantomData<C>,
 }
 
+/// Waits before a request is retried.
+#[pin_project]
+pub struct Delay<P> {
+    #[pin]
+    sleep: time::Sleep,
+    policy: Option<P>,
+}
+
 impl NewRetry {
-    pub fn new(metrics: HttpRouteRetry) -> Self {
+    pub fn new(metrics: HttpRouteRetry, backoff: ExponentialBackoff) -> Self {
         Self {
             metrics,
+            backoff,
             _clone_request: PhantomData,
         }
     }
@@ -45,6 +131,7 @@ impl NewRetry {
     pub fn clone_requests_via<C>(self) -> NewRetry<C> {
         NewRetry {
             metrics: self.metrics,
+            backoff: self.backoff,
             _clone_request: PhantomData,
         }
     }
@@ -61,29 +148,39 @@ impl<C> linkerd2_retry::NewPolicy<Route> for NewRetry<C> {
             metrics,
             budget: retries.budget().clone(),
             response_classes: route.route.response_classes().clone(),
+            backoff: self.backoff,
+            timeout: route.route.timeout(),
+            retries: 0,
             _clone_request: self._clone_request,
         })
     }
 }
 
-impl<C, A, B, E> linkerd2_retry::Policy<http::Request<A>, http::Response<B>, E> for Retry<C>
+impl<C, A, B, E> linkerd2_retry::Policy<http::Request<A>, http::Response<PeekTrailersBody<B>>, E>
+    for Retry<C>
 where
     C: CloneRequest<http::Request<A>>,
+    B: HttpBody,
 {
-    type Future = future::Ready<Self>;
+    type Future = Delay<Self>;
 
     fn retry(
         &self,
         req: &http::Request<A>,
-        result: Result<&http::Response<B>, &E>,
+        result: Result<&http::Response<PeekTrailersBody<B>>, &E>,
     ) -> Option<Self::Future> {
-        let retryable = match result {
-            Err(_) => false,
-            Ok(rsp) => classify::Request::from(self.response_classes.clone())
-                .classify(req)
-                .start(rsp)
-                .eos(None)
-                .is_failure(),
+        let (retryable, retry_after) = match result {
+            Err(_) => (false, None),
+            Ok(rsp) => {
+                // Responses that end quickly, like most gRPC failures, are
+                // classified by their trailers.
+                let is_failure = classify::Request::from(self.response_classes.clone())
+                    .classify(req)
+                    .start(rsp)
+                    .eos(rsp.body().peek_trailers())
+                    .is_failure();
+                (is_failure, retry_after(rsp))
+            }
         };
 
         if !retryable {
@@ -91,13 +188,49 @@ where
             return None;
         }
 
+        if !C::can_retry(req) {
+            trace!("Request cannot be retried");
+            return None;
+        }
+
+        // A retry is pointless once the request has timed out, either by the
+        // route's timeout or by the request's deadline.
+        let remaining = req
+            .extensions()
+            .get::<timeout::Deadline>()
+            .map(|timeout::Deadline(at)| at.saturating_duration_since(time::Instant::now()));
+        let limit = match (self.timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        };
+        let delay = match retry_delay(&self.backoff, self.retries, retry_after, limit) {
+            Some(delay) => delay,
+            None => {
+                trace!(
+                    ?retry_after,
+                    ?limit,
+                    "Retry-After exceeds the request's timeout"
+                );
+                return None;
+            }
+        };
+
         let withdrew = self.budget.withdraw().is_ok();
         self.metrics.incr_retryable(withdrew);
         if !withdrew {
             return None;
         }
 
-        Some(future::ready(self.clone()))
+        trace!(?delay, retries = self.retries, "Retrying");
+
+        let policy = Self {
+            retries: self.retries.saturating_add(1),
+            ..self.clone()
+        };
+        Some(Delay {
+            sleep: time::sleep(delay),
+            policy: Some(policy),
+        })
     }
 
     fn clone_request(&self, req: &http::Request<A>) -> Option<http::Request<A>> {
@@ -111,18 +244,84 @@ impl<C> Clone for Retry<C> {
             metrics: self.metrics.clone(),
             budget: self.budget.clone(),
             response_classes: self.response_classes.clone(),
+            backoff: self.backoff,
+            timeout: self.timeout,
+            retries: self.retries,
             _clone_request: self._clone_request,
         }
     }
 }
 
-impl<B: Default + HttpBody> CloneRequest<http::Request<B>> for () {
-    fn clone_request(req: &http::Request<B>) -> Option<http::Request<B>> {
-        if !req.body().is_end_stream() {
+/// Returns the delay requested by a `Retry-After` header on a 429 or 503
+/// response.
+///
+/// The header may either specify a number of seconds or an HTTP date.
+fn retry_after<B>(rsp: &http::Response<B>) -> Option<Duration> {
+    match rsp.status() {
+        http::StatusCode::TOO_MANY_REQUESTS | http::StatusCode::SERVICE_UNAVAILABLE => {}
+        _ => return None,
+    }
+
+    let value = rsp
+        .headers()
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = httpdate::parse_http_date(value).ok()?;
+    // Dates in the past indicate that the request may be retried immediately.
+    Some(
+        at.duration_since(SystemTime::now())
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+    )
+}
+
+/// Returns how long to wait before a request is retried, or `None` if it
+/// should not be retried.
+///
+/// Servers may indicate how long to wait before retrying; otherwise, delays
+/// back off based on the number of prior retries. Requests are not retried if
+/// the server asks to wait longer than `limit`, the time remaining before the
+/// request times out, and backoff delays are capped by it.
+fn retry_delay(
+    backoff: &ExponentialBackoff,
+    retries: u32,
+    retry_after: Option<Duration>,
+    limit: Option<Duration>,
+) -> Option<Duration> {
+    match (retry_after, limit) {
+        (Some(retry_after), Some(limit)) if retry_after >= limit => None,
+        (Some(retry_after), _) => Some(retry_after),
+        (None, limit) => {
+            let delay = backoff.delay(retries);
+            Some(limit.map_or(delay, |limit| delay.min(limit)))
+        }
+    }
+}
+
+// === impl Delay ===
+
+impl<P> Future for Delay<P> {
+    type Output = P;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        ready!(this.sleep.poll(cx));
+        Poll::Ready(this.policy.take().expect("polled after ready"))
+    }
+}
+
+impl<B: HttpBody> CloneRequest<http::Request<ReplayBody<B>>> for () {
+    fn clone_request(req: &http::Request<ReplayBody<B>>) -> Option<http::Request<ReplayBody<B>>> {
+        if req.body().is_capped() {
             return None;
         }
 
-        let mut clone = http::Request::new(B::default());
+        let mut clone = http::Request::new(req.body().clone());
         *clone.method_mut() = req.method().clone();
         *clone.uri_mut() = req.uri().clone();
         *clone.headers_mut() = req.headers().clone();
@@ -132,6 +331,17 @@ impl<B: Default + HttpBody> CloneRequest<http::Request<B>> for () {
             clone.extensions_mut().insert(ext.clone());
         }
 
+        // Copies carry the time remaining before the original request's
+        // deadline.
+        if let Some(ext) = req.extensions().get::<timeout::Deadline>() {
+            clone.extensions_mut().insert(*ext);
+        }
+
+        // Copies are balanced by the same hash as the original request.
+        if let Some(ext) = req.extensions().get::<RequestHash>() {
+            clone.extensions_mut().insert(*ext);
+        }
+
         // // Count retries toward the request's total handle time.
         // if let Some(ext) = req.extensions().get::<handle_time::Tracker>() {
         //     clone.extensions_mut().insert(ext.clone());
@@ -139,4 +349,193 @@ impl<B: Default + HttpBody> CloneRequest<http::Request<B>> for () {
 
         Some(clone)
     }
+
+    fn can_retry(req: &http::Request<ReplayBody<B>>) -> bool {
+        !req.body().is_capped()
+    }
+
+    fn is_complete(req: &http::Request<ReplayBody<B>>) -> bool {
+        req.body().is_complete()
+    }
+}
+
+// === impl NewPeekTrailers ===
+
+impl<N> NewPeekTrailers<N> {
+    pub fn layer() -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(|inner| Self { inner })
+    }
+}
+
+impl<N: NewService<Route>> NewService<Route> for NewPeekTrailers<N> {
+    type Service = PeekTrailersProxy<N::Service>;
+
+    fn new_service(&mut self, route: Route) -> Self::Service {
+        PeekTrailersProxy {
+            peek: route.route.retries().is_some(),
+            inner: self.inner.new_service(route),
+        }
+    }
+}
+
+// === impl PeekTrailersProxy ===
+
+impl<Req, B, P, S> Proxy<Req, S> for PeekTrailersProxy<P>
+where
+    B: HttpBody,
+    P: Proxy<Req, S, Response = http::Response<B>>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = http::Response<PeekTrailersBody<B>>;
+    type Error = P::Error;
+    type Future = PeekTrailersFuture<P::Future, B>;
+
+    fn proxy(&self, svc: &mut S, req: Req) -> Self::Future {
+        PeekTrailersFuture::Inner {
+            future: self.inner.proxy(svc, req),
+            peek: self.peek,
+        }
+    }
+}
+
+impl<F, B, E> Future for PeekTrailersFuture<F, B>
+where
+    F: Future<Output = Result<http::Response<B>, E>>,
+    B: HttpBody,
+{
+    type Output = Result<http::Response<PeekTrailersBody<B>>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match self.as_mut().project() {
+                PeekTrailersFutureProj::Inner { future, peek } => {
+                    let rsp = ready!(future.poll(cx))?;
+                    if !*peek {
+                        return Poll::Ready(Ok(rsp.map(PeekTrailersBody::new)));
+                    }
+                    self.set(PeekTrailersFuture::Peek(PeekTrailersBody::peek_response(
+                        rsp,
+                    )));
+                }
+                PeekTrailersFutureProj::Peek(future) => return future.poll(cx).map(Ok),
+            }
+        }
+    }
+}
+
+// === impl NewReplayBody ===
+
+impl<N> NewReplayBody<N> {
+    pub fn layer(max_bytes: usize) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self { max_bytes, inner })
+    }
+}
+
+impl<N: NewService<Route>> NewService<Route> for NewReplayBody<N> {
+    type Service = ReplayBodyProxy<N::Service>;
+
+    fn new_service(&mut self, route: Route) -> Self::Service {
+        // Bodies on routes without retries, hedging, or mirroring are never
+        // replayed, so they are wrapped without buffering any data.
+        let max_bytes = if route.route.retries().is_some()
+            || route.route.hedges().is_some()
+            || route.route.mirror().is_some()
+        {
+            self.max_bytes
+        } else {
+            0
+        };
+        ReplayBodyProxy {
+            max_bytes,
+            inner: self.inner.new_service(route),
+        }
+    }
+}
+
+// === impl ReplayBodyProxy ===
+
+impl<A, P, S> Proxy<http::Request<A>, S> for ReplayBodyProxy<P>
+where
+    A: HttpBody,
+    P: Proxy<http::Request<ReplayBody<A>>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<A>) -> Self::Future {
+        let max_bytes = self.max_bytes;
+        self.inner
+            .proxy(svc, req.map(|body| ReplayBody::new(body, max_bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rsp(status: u16, retry_after: &str) -> http::Response<()> {
+        http::Response::builder()
+            .status(status)
+            .header(http::header::RETRY_AFTER, retry_after)
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        assert_eq!(retry_after(&rsp(429, "3")), Some(Duration::from_secs(3)));
+        assert_eq!(retry_after(&rsp(503, " 0 ")), Some(Duration::from_secs(0)));
+        assert_eq!(retry_after(&rsp(503, "soon")), None);
+    }
+
+    #[test]
+    fn retry_after_date() {
+        let past = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(60));
+        assert_eq!(retry_after(&rsp(503, &past)), Some(Duration::from_secs(0)));
+
+        let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let delay = retry_after(&rsp(503, &future)).expect("must parse date");
+        assert!(delay > Duration::from_secs(50) && delay <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn retry_after_limited_by_timeout() {
+        let backoff =
+            ExponentialBackoff::new(Duration::from_millis(10), Duration::from_millis(500), 0.0)
+                .unwrap();
+        let retry_after = Some(Duration::from_secs(3));
+
+        // Servers may ask to wait longer than the maximum backoff.
+        assert_eq!(retry_delay(&backoff, 0, retry_after, None), retry_after);
+        assert_eq!(
+            retry_delay(&backoff, 0, retry_after, Some(Duration::from_secs(10))),
+            retry_after
+        );
+
+        // Requests that would time out before the retry are not retried.
+        assert_eq!(
+            retry_delay(&backoff, 0, retry_after, Some(Duration::from_secs(1))),
+            None
+        );
+
+        // Backoffs are capped by the time remaining.
+        assert_eq!(
+            retry_delay(&backoff, 0, None, None),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            retry_delay(&backoff, 8, None, Some(Duration::from_millis(100))),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn retry_after_ignored_for_other_statuses() {
+        assert_eq!(retry_after(&rsp(500, "3")), None);
+        assert_eq!(retry_after(&rsp(200, "3")), None);
+    }
 }
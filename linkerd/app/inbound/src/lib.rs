@@ -34,6 +34,7 @@ use tracing::debug_span;
 mod allow_discovery;
 pub mod endpoint;
 mod prevent_loop;
+pub mod rate_limit;
 mod require_identity_for_ports;
 
 #[derive(Clone, Debug)]
@@ -43,6 +44,7 @@ pub struct Config {
     pub require_identity_for_inbound_ports: RequireIdentityForPorts,
     pub disable_protocol_detection_for_ports: SkipByPort,
     pub profile_idle_timeout: Duration,
+    pub rate_limit: Option<rate_limit::Config>,
 }
 
 #[derive(Clone, Debug)]
@@ -223,6 +225,9 @@ impl Config {
                     // Sets the route as a request extension so that it can be used
                     // by tap.
                     .push_http_insert_target()
+                    // Rejects requests that exceed the configured rate limit
+                    // before they are recorded in per-route metrics.
+                    .push(rate_limit::NewRateLimit::layer(self.rate_limit.clone()))
                     // Records per-route metrics.
                     .push(metrics.http_route.to_layer::<classify::Response, _>())
                     // Sets the per-route response classifier as a request
@@ -304,6 +309,7 @@ impl Config {
             server: ServerConfig { h2_settings, .. },
             dispatch_timeout,
             max_in_flight_requests,
+            adaptive_concurrency,
             detect_protocol_timeout,
             cache_max_idle_age,
             ..
@@ -349,7 +355,11 @@ impl Config {
                     // Downgrades the protocol if upgraded by an outbound proxy.
                     .push(orig_proto::Downgrade::layer())
                     // Limits the number of in-flight requests.
-                    .push(svc::ConcurrencyLimit::layer(max_in_flight_requests))
+                    .push(svc::ConcurrencyLimit::adaptive_layer(
+                        max_in_flight_requests,
+                        adaptive_concurrency,
+                        metrics.concurrency_limit.clone(),
+                    ))
                     // Eagerly fail requests when the proxy is out of capacity for a
                     // dispatch_timeout.
                     .push(svc::FailFast::layer("HTTP Server", dispatch_timeout))
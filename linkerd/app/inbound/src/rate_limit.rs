@@ -0,0 +1,275 @@
+use crate::endpoint::TcpAccept;
+use futures::{future, prelude::*};
+use linkerd2_app_core::{
+    dst,
+    errors::HttpError,
+    metrics::RouteLabels,
+    proxy::identity,
+    svc::{layer, stack::Proxy, NewService},
+    Error,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// Configures token-bucket rate limiting for inbound requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Determines which requests share a bucket.
+    pub key: Key,
+
+    /// The number of requests per second that each bucket admits.
+    pub rate: f64,
+
+    /// The number of requests that each bucket may admit at once after it
+    /// has been idle.
+    pub burst: u32,
+}
+
+/// Determines which requests share a token bucket.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// Requests to the same route share a bucket.
+    Route,
+    /// Requests from the same client identity share a bucket. Clients without
+    /// an identity share a single bucket.
+    Client,
+    /// Requests to the same route from the same client identity share a
+    /// bucket.
+    RouteAndClient,
+}
+
+/// Rejects requests once their token bucket is empty.
+#[derive(Clone, Debug)]
+pub struct NewRateLimit<N> {
+    limiter: Option<Arc<Limiter>>,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitProxy<P> {
+    route: dst::Route,
+    labels: Arc<RouteLabels>,
+    limiter: Option<Arc<Limiter>>,
+    inner: P,
+}
+
+#[derive(Debug)]
+struct Limiter {
+    config: Config,
+    buckets: Mutex<Buckets>,
+}
+
+/// Routes are identified by their labels, which are much cheaper to hash and
+/// compare than the routes themselves.
+type BucketKey = (Option<Arc<RouteLabels>>, Option<identity::Name>);
+
+#[derive(Debug)]
+struct Buckets {
+    by_key: HashMap<BucketKey, Bucket>,
+    swept_at: Instant,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// How often buckets that have refilled are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+
+// === impl NewRateLimit ===
+
+impl<N> NewRateLimit<N> {
+    /// Requests are not limited when `config` is `None`.
+    pub fn layer(config: Option<Config>) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        let limiter = config.map(|config| Arc::new(Limiter::new(config, Instant::now())));
+        layer::mk(move |inner| Self {
+            limiter: limiter.clone(),
+            inner,
+        })
+    }
+}
+
+impl<N: NewService<dst::Route>> NewService<dst::Route> for NewRateLimit<N> {
+    type Service = RateLimitProxy<N::Service>;
+
+    fn new_service(&mut self, route: dst::Route) -> Self::Service {
+        RateLimitProxy {
+            labels: Arc::new(RouteLabels::from(&route)),
+            route: route.clone(),
+            limiter: self.limiter.clone(),
+            inner: self.inner.new_service(route),
+        }
+    }
+}
+
+// === impl RateLimitProxy ===
+
+impl<B, P, S> Proxy<http::Request<B>, S> for RateLimitProxy<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = future::Either<
+        future::ErrInto<P::Future, Error>,
+        future::Ready<Result<P::Response, Error>>,
+    >;
+
+    fn proxy(&self, svc: &mut S, req: http::Request<B>) -> Self::Future {
+        if let Some(limiter) = self.limiter.as_ref() {
+            let client = req
+                .extensions()
+                .get::<TcpAccept>()
+                .and_then(|accept| accept.peer_id.value().cloned());
+            let key = limiter.key(&self.labels, client);
+            if !limiter.acquire(key, Instant::now()) {
+                debug!(route = %self.route, "Rate limited");
+                return future::Either::Right(future::err(HttpError::rate_limited().into()));
+            }
+        }
+
+        future::Either::Left(self.inner.proxy(svc, req).err_into())
+    }
+}
+
+// === impl Limiter ===
+
+impl Limiter {
+    fn new(config: Config, now: Instant) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                swept_at: now,
+            }),
+        }
+    }
+
+    fn key(&self, route: &Arc<RouteLabels>, client: Option<identity::Name>) -> BucketKey {
+        match self.config.key {
+            Key::Route => (Some(route.clone()), None),
+            Key::Client => (None, client),
+            Key::RouteAndClient => (Some(route.clone()), client),
+        }
+    }
+
+    /// Takes a token from the key's bucket, returning false if it is empty.
+    fn acquire(&self, key: BucketKey, now: Instant) -> bool {
+        let Config { rate, burst, .. } = self.config;
+        let burst = f64::from(burst.max(1));
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+
+        // Buckets that have refilled are equivalent to new buckets, so they
+        // are dropped rather than retained for every key ever seen. They are
+        // swept periodically so that the cost is amortized across requests.
+        if now.saturating_duration_since(buckets.swept_at) >= SWEEP_INTERVAL {
+            buckets
+                .by_key
+                .retain(|_, b| b.refill(rate, burst, now) < burst);
+            buckets.swept_at = now;
+        }
+        let bucket = buckets.by_key.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+
+        if bucket.refill(rate, burst, now) < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+// === impl Bucket ===
+
+impl Bucket {
+    /// Adds the tokens accrued since the bucket was last updated and returns
+    /// the number of available tokens.
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * rate).min(burst);
+        self.updated_at = now;
+        self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_app_core::{metrics::Direction, profiles, Addr};
+    use std::str::FromStr;
+
+    fn limiter(key: Key, now: Instant) -> Limiter {
+        let config = Config {
+            key,
+            rate: 10.0,
+            burst: 2,
+        };
+        Limiter::new(config, now)
+    }
+
+    fn route(target: &str) -> Arc<RouteLabels> {
+        let route = dst::Route {
+            target: Addr::from_str(target).unwrap(),
+            route: profiles::http::Route::default(),
+            direction: Direction::In,
+        };
+        Arc::new(RouteLabels::from(&route))
+    }
+
+    #[test]
+    fn refills_at_rate() {
+        let now = Instant::now();
+        let limiter = limiter(Key::Route, now);
+        let key = limiter.key(&route("a.example.com:80"), None);
+
+        assert!(limiter.acquire(key.clone(), now));
+        assert!(limiter.acquire(key.clone(), now));
+        assert!(!limiter.acquire(key.clone(), now), "burst exhausted");
+
+        let now = now + Duration::from_millis(100);
+        assert!(limiter.acquire(key.clone(), now));
+        assert!(!limiter.acquire(key.clone(), now));
+
+        // Tokens do not accrue beyond the burst.
+        let now = now + Duration::from_secs(10);
+        assert!(limiter.acquire(key.clone(), now));
+        assert!(limiter.acquire(key.clone(), now));
+        assert!(!limiter.acquire(key, now));
+    }
+
+    #[test]
+    fn keys_by_route_and_client() {
+        let now = Instant::now();
+        let limiter = limiter(Key::RouteAndClient, now);
+        let a = route("a.example.com:80");
+        let b = route("b.example.com:80");
+        let foo = identity::Name::from_str("foo.ns.serviceaccount.identity.linkerd.cluster.local")
+            .unwrap();
+
+        for _ in 0..2 {
+            assert!(limiter.acquire(limiter.key(&a, Some(foo.clone())), now));
+        }
+        assert!(!limiter.acquire(limiter.key(&a, Some(foo.clone())), now));
+        assert!(limiter.acquire(limiter.key(&a, None), now));
+        assert!(limiter.acquire(limiter.key(&b, Some(foo)), now));
+
+        // Full buckets are only dropped once the sweep interval elapses.
+        let now = now + Duration::from_secs(1);
+        assert!(limiter.acquire(limiter.key(&b, None), now));
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 4);
+
+        let now = now + SWEEP_INTERVAL;
+        assert!(limiter.acquire(limiter.key(&b, None), now));
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 1);
+    }
+}
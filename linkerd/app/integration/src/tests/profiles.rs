@@ -209,7 +209,7 @@ async fn does_not_retry_if_earlier_response_class_is_success() {
 }
 
 #[tokio::test]
-async fn does_not_retry_if_request_has_body() {
+async fn retry_if_request_has_body() {
     profile_test! {
         routes: [
             controller::route()
@@ -224,6 +224,28 @@ async fn does_not_retry_if_request_has_body() {
                 .body("req has a body".into())
                 .unwrap();
             let res = client.request_body(req).await;
+            assert_eq!(res.status(), 200);
+        }
+    }
+}
+
+#[tokio::test]
+async fn does_not_retry_if_request_body_is_too_large() {
+    profile_test! {
+        routes: [
+            controller::route()
+                .request_any()
+                .response_failure(500..600)
+                .retryable(true)
+        ],
+        budget: Some(controller::retry_budget(Duration::from_secs(10), 0.1, 1)),
+        with_client: |client: client::Client| async move {
+            // Larger than the default body buffer limit (64KB).
+            let req = client.request_builder("/0.5")
+                .method("POST")
+                .body(vec![b'x'; 1024 * 100].into())
+                .unwrap();
+            let res = client.request_body(req).await;
             assert_eq!(res.status(), 533);
         }
     }
@@ -1,9 +1,9 @@
 use super::{Concrete, Endpoint, Logical};
-use crate::{resolve, stack_labels};
+use crate::{resolve, stack_labels, Config};
 use linkerd2_app_core::{
-    classify,
+    circuit_breaker, classify,
     config::ProxyConfig,
-    metrics, profiles,
+    fault, hash_key, hedge, locality, metrics, mirror, outlier, profiles,
     proxy::{api_resolve::Metadata, core::Resolve, http},
     retry, svc,
     transport::tls::ReasonForNoPeerName,
@@ -12,7 +12,7 @@ use linkerd2_app_core::{
 use tracing::debug_span;
 
 pub fn stack<B, E, ESvc, R>(
-    config: &ProxyConfig,
+    config: &Config,
     endpoint: E,
     resolve: R,
     metrics: metrics::Proxy,
@@ -43,10 +43,10 @@ where
         cache_max_idle_age,
         dispatch_timeout,
         ..
-    } = config.clone();
+    } = config.proxy.clone();
     let watchdog = cache_max_idle_age * 2;
 
-    svc::stack(endpoint.clone())
+    let concrete = svc::stack(endpoint.clone())
         .check_new_service::<Endpoint, http::Request<http::BoxBody>>()
         .push_on_response(
             svc::layers()
@@ -59,6 +59,17 @@ where
                 .push(http::BoxRequest::layer()),
         )
         .check_new_service::<Endpoint, http::Request<_>>()
+        // Ejects endpoints that fail too often from the balancer.
+        .push(outlier::NewOutlier::<Concrete, _>::layer(
+            config.outlier.clone(),
+            metrics.http_endpoint_ejections.clone(),
+        ))
+        // Prefers endpoints in the proxy's zone.
+        .push(locality::NewLocality::<Concrete, _>::layer(
+            config.locality.clone(),
+        ))
+        // Annotates endpoints with their weights for the balancer.
+        .push(http::balance::NewWeighted::layer())
         .push(resolve::layer(resolve, watchdog))
         .check_service::<Concrete>()
         .push_on_response(
@@ -66,6 +77,7 @@ where
                 .push(http::balance::layer(
                     crate::EWMA_DEFAULT_RTT,
                     crate::EWMA_DECAY,
+                    config.balance_slow_start,
                 ))
                 .push(svc::layer::mk(svc::SpawnReady::new))
                 // If the balancer has been empty/unavailable for 10s, eagerly fail
@@ -75,6 +87,12 @@ where
         )
         .into_new_service()
         .check_new_service::<Concrete, http::Request<_>>()
+        // Fails requests to backends that have failed too many requests.
+        .push(circuit_breaker::NewCircuitBreaker::layer(
+            config.circuit_breaker.clone(),
+            metrics.http_concrete_breakers.clone(),
+        ))
+        .check_new_service::<Concrete, http::Request<_>>()
         .instrument(|c: &Concrete| match c.resolve.as_ref() {
             None => debug_span!("concrete"),
             Some(addr) => debug_span!("concrete", %addr),
@@ -85,7 +103,24 @@ where
         // concrete address.
         .push_map_target(Concrete::from)
         .check_new_service::<(Option<Addr>, Logical), http::Request<_>>()
-        .push(profiles::split::layer())
+        .into_inner();
+
+    // Mirrored requests are dispatched to a concrete stack for the mirror's
+    // address. The stack is buffered so that it may be shared by all mirrored
+    // requests.
+    let new_mirror = svc::stack(concrete.clone())
+        .push_on_response(
+            svc::layers()
+                .push(svc::FailFast::layer("HTTP Mirror", dispatch_timeout))
+                .push_spawn_buffer(buffer_capacity),
+        )
+        .into_inner();
+
+    svc::stack(concrete)
+        .push(profiles::split::http_layer())
+        .check_new_service::<Logical, http::Request<_>>()
+        // Sends a copy of requests on mirrored routes to the route's mirror.
+        .push(mirror::NewMirror::layer(new_mirror))
         .check_new_service::<Logical, http::Request<_>>()
         // Drives concrete stacks to readiness and makes the split
         // cloneable, as required by the retry middleware.
@@ -97,15 +132,43 @@ where
         .check_new_service::<Logical, http::Request<_>>()
         .push(profiles::http::route_request::layer(
             svc::proxies()
+                // Sets each attempt's deadline headers to the time remaining
+                // before the request's timeout.
+                .push(http::NewDeadlineHeaders::layer(
+                    config.deadline_header.clone(),
+                ))
                 .push(
                     metrics
                         .http_route_actual
                         .to_layer::<classify::Response, _>(),
                 )
+                // Reads ahead in responses so that retries may be decided by
+                // their trailers.
+                .push(retry::NewPeekTrailers::layer())
                 // Sets an optional retry policy.
-                .push(retry::layer(metrics.http_route_retry))
-                // Sets an optional request timeout.
-                .push(http::MakeTimeoutLayer::default())
+                .push(retry::layer(metrics.http_route_retry, config.retry_backoff))
+                // Sends a second copy of slow requests on idempotent routes.
+                .push(hedge::layer(config.hedge_latency_percentile))
+                // Sets the hash of requests on routes that are balanced by
+                // consistent hashing.
+                .push(hash_key::NewHashKey::layer())
+                // Injects the route's faults, if any.
+                .push(fault::NewFault::layer())
+                // Sends a copy of each request on mirrored routes to the
+                // route's mirror, once, regardless of faults, hedges, or
+                // retries.
+                .push(mirror::NewMirrorProxy::layer(
+                    metrics.http_route_mirror,
+                    config.max_in_flight_mirrors,
+                ))
+                // Buffers request bodies so that they may be retried, hedged,
+                // or mirrored.
+                .push(retry::NewReplayBody::layer(config.max_buffered_body_bytes))
+                // Sets an optional request timeout, bounded by the request's
+                // deadline.
+                .push(http::MakeTimeoutLayer::with_deadline_header(
+                    config.deadline_header.clone(),
+                ))
                 // Records per-route metrics.
                 .push(metrics.http_route.to_layer::<classify::Response, _>())
                 // Sets the per-route response classifier as a request
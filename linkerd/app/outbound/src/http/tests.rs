@@ -88,7 +88,7 @@ where
 
     let (tap, _) = tap::new();
     let router = super::logical::stack(
-        &cfg.proxy,
+        &cfg,
         super::endpoint::stack(
             &cfg.proxy.connect,
             connect,
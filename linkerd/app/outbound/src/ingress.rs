@@ -57,6 +57,7 @@ where
                 server: ServerConfig { h2_settings, .. },
                 dispatch_timeout,
                 max_in_flight_requests,
+                adaptive_concurrency,
                 detect_protocol_timeout,
                 buffer_capacity,
                 cache_max_idle_age,
@@ -94,7 +95,11 @@ where
             svc::layers()
                 .push(http::BoxRequest::layer())
                 // Limits the number of in-flight requests.
-                .push(svc::ConcurrencyLimit::layer(max_in_flight_requests))
+                .push(svc::ConcurrencyLimit::adaptive_layer(
+                    max_in_flight_requests,
+                    adaptive_concurrency,
+                    metrics.concurrency_limit.clone(),
+                ))
                 // Eagerly fail requests when the proxy is out of capacity for a
                 // dispatch_timeout.
                 .push(svc::FailFast::layer("Server", dispatch_timeout))
@@ -14,7 +14,10 @@ pub mod tcp;
 #[cfg(test)]
 mod test_util;
 
-use linkerd2_app_core::{config::ProxyConfig, metrics, AddrMatch};
+use linkerd2_app_core::{
+    circuit_breaker, config::ProxyConfig, exp_backoff::ExponentialBackoff, locality, metrics,
+    outlier, proxy::http::header::HeaderName, AddrMatch,
+};
 use std::{collections::HashMap, time::Duration};
 
 const EWMA_DEFAULT_RTT: Duration = Duration::from_millis(30);
@@ -24,6 +27,39 @@ const EWMA_DECAY: Duration = Duration::from_secs(10);
 pub struct Config {
     pub proxy: ProxyConfig,
     pub allow_discovery: AddrMatch,
+
+    /// The maximum number of bytes of a request body that are buffered so that
+    /// the request may be retried.
+    pub max_buffered_body_bytes: usize,
+
+    /// The maximum number of mirrored requests that may be in flight on each
+    /// route. Requests are not mirrored while a route is at this limit.
+    pub max_in_flight_mirrors: usize,
+
+    /// Determines how long to wait before a request is retried.
+    pub retry_backoff: ExponentialBackoff,
+
+    /// The response latency percentile after which requests on idempotent
+    /// routes are hedged.
+    pub hedge_latency_percentile: f64,
+
+    /// Determines when endpoints are ejected from HTTP load balancers.
+    pub outlier: outlier::Config,
+
+    /// Determines when requests to an HTTP backend are failed because too
+    /// many of its responses have failed.
+    pub circuit_breaker: circuit_breaker::Config,
+
+    /// When set, request deadlines are also read from (and written to) this
+    /// header, in addition to `grpc-timeout`.
+    pub deadline_header: Option<HeaderName>,
+
+    /// When set, load balancers prefer endpoints in the proxy's zone.
+    pub locality: Option<locality::Config>,
+
+    /// The window over which the share of traffic dispatched to a newly
+    /// discovered endpoint ramps up to its full share.
+    pub balance_slow_start: Duration,
 }
 
 fn stack_labels(proto: &'static str, name: &'static str) -> metrics::StackLabels {
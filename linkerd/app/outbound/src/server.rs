@@ -48,8 +48,7 @@ where
     P::Future: Send,
     P::Error: Send,
 {
-    let tcp_balance =
-        tcp::balance::stack(&config.proxy, tcp_connect.clone(), resolve, drain.clone());
+    let tcp_balance = tcp::balance::stack(config, tcp_connect.clone(), resolve, drain.clone());
     let accept = accept_stack(
         config,
         profiles,
@@ -137,6 +136,7 @@ where
         server: ServerConfig { h2_settings, .. },
         dispatch_timeout,
         max_in_flight_requests,
+        adaptive_concurrency,
         detect_protocol_timeout,
         buffer_capacity,
         cache_max_idle_age,
@@ -158,7 +158,11 @@ where
             svc::layers()
                 .push(http::BoxRequest::layer())
                 // Limits the number of in-flight requests.
-                .push(svc::ConcurrencyLimit::layer(max_in_flight_requests))
+                .push(svc::ConcurrencyLimit::adaptive_layer(
+                    max_in_flight_requests,
+                    adaptive_concurrency,
+                    metrics.concurrency_limit.clone(),
+                ))
                 // Eagerly fail requests when the proxy is out of capacity for a
                 // dispatch_timeout.
                 .push(svc::FailFast::layer("Server", dispatch_timeout))
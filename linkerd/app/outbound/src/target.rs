@@ -1,6 +1,8 @@
 use linkerd2_app_core::{
-    metrics, profiles,
-    proxy::{api_resolve::Metadata, identity, resolve::map_endpoint::MapEndpoint},
+    locality, metrics, profiles,
+    proxy::{
+        api_resolve::Metadata, http::balance::Weight, identity, resolve::map_endpoint::MapEndpoint,
+    },
     transport::{self, listen, tls},
     Addr, Conditional,
 };
@@ -22,7 +24,7 @@ pub struct Logical<P> {
     pub protocol: P,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Concrete<P> {
     pub resolve: Option<Addr>,
     pub logical: Logical<P>,
@@ -164,12 +166,36 @@ impl<P: std::fmt::Debug> std::fmt::Debug for Logical<P> {
 
 // === impl Concrete ===
 
+impl<P> Concrete<P> {
+    /// Returns true when the target's profile balances connections by
+    /// consistent hashing on the client's IP address.
+    pub fn hashes_source_ip(&self) -> bool {
+        self.logical
+            .profile
+            .as_ref()
+            .map(|p| p.borrow().hash_key == Some(profiles::http::HashKey::SourceIp))
+            .unwrap_or(false)
+    }
+}
+
 impl<P> From<(Option<Addr>, Logical<P>)> for Concrete<P> {
     fn from((resolve, logical): (Option<Addr>, Logical<P>)) -> Self {
         Self { resolve, logical }
     }
 }
 
+impl<P> Into<metrics::ConcreteLabels> for &'_ Concrete<P> {
+    fn into(self) -> metrics::ConcreteLabels {
+        metrics::ConcreteLabels {
+            direction: metrics::Direction::Out,
+            dst: self
+                .resolve
+                .clone()
+                .unwrap_or_else(|| self.logical.orig_dst.into()),
+        }
+    }
+}
+
 /// Produces an address to be used if resolution is rejected.
 impl<P> Into<SocketAddr> for &'_ Concrete<P> {
     fn into(self) -> SocketAddr {
@@ -256,6 +282,24 @@ impl<P> Into<metrics::EndpointLabels> for &'_ Endpoint<P> {
     }
 }
 
+impl<P> Into<locality::Zone> for &'_ Endpoint<P> {
+    fn into(self) -> locality::Zone {
+        locality::Zone(self.metadata.labels().get(locality::ZONE_LABEL).cloned())
+    }
+}
+
+impl<P> Into<Weight> for &'_ Endpoint<P> {
+    fn into(self) -> Weight {
+        Weight(self.metadata.weight())
+    }
+}
+
+impl<P: Clone> Into<Concrete<P>> for &'_ Endpoint<P> {
+    fn into(self) -> Concrete<P> {
+        self.concrete.clone()
+    }
+}
+
 impl<P: std::hash::Hash> std::hash::Hash for Endpoint<P> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.addr.hash(state);
@@ -1,8 +1,7 @@
 use super::{Concrete, Endpoint};
-use crate::resolve;
+use crate::{resolve, Config};
 use linkerd2_app_core::{
-    config::ProxyConfig,
-    drain,
+    drain, locality,
     proxy::{api_resolve::Metadata, core::Resolve, tcp},
     svc,
     transport::io,
@@ -12,7 +11,7 @@ use tracing::debug_span;
 
 /// Constructs a TCP load balancer.
 pub fn stack<I, C, R>(
-    config: &ProxyConfig,
+    config: &Config,
     connect: C,
     resolve: R,
     drain: drain::Watch,
@@ -22,7 +21,7 @@ pub fn stack<I, C, R>(
                   + svc::Service<io::PrefixedIo<I>, Response = (), Error = Error, Future = impl Send>,
 > + Clone
 where
-    I: io::AsyncRead + io::AsyncWrite + std::fmt::Debug + Send + Unpin + 'static,
+    I: io::AsyncRead + io::AsyncWrite + io::PeerAddr + std::fmt::Debug + Send + Unpin + 'static,
     C: svc::Service<Endpoint> + Clone + Send + 'static,
     C::Response: io::AsyncRead + io::AsyncWrite + Send + Unpin,
     C::Error: Into<Error>,
@@ -31,20 +30,47 @@ where
     R::Resolution: Send,
     R::Future: Send,
 {
-    svc::stack(connect)
+    let resolved = svc::stack(connect)
         .push_make_thunk()
         .instrument(
             |t: &Endpoint| debug_span!("endpoint", peer.addr = %t.addr, peer.id = ?t.identity),
         )
-        .push(resolve::layer(resolve, config.cache_max_idle_age * 2))
+        // Prefers endpoints in the proxy's zone.
+        .push(locality::NewLocality::<Concrete, _>::layer(
+            config.locality.clone(),
+        ))
+        // Annotates endpoints with their weights for the balancer.
+        .push(tcp::balance::NewWeighted::layer())
+        .push(resolve::layer(resolve, config.proxy.cache_max_idle_age * 2))
+        .into_inner();
+
+    // Forwards each client's connections to a single endpoint when the
+    // target's profile hashes on the client's IP address.
+    let hash_forward = svc::stack(resolved.clone())
+        .push_on_response(
+            svc::layers()
+                .push(tcp::balance::hash_forward_layer(
+                    crate::EWMA_DEFAULT_RTT,
+                    crate::EWMA_DECAY,
+                    config.balance_slow_start,
+                ))
+                .push(drain::Retain::layer(drain.clone())),
+        )
+        .into_new_service()
+        .into_inner();
+
+    svc::stack(resolved)
         .push_on_response(
             svc::layers()
                 .push(tcp::balance::layer(
                     crate::EWMA_DEFAULT_RTT,
                     crate::EWMA_DECAY,
+                    config.balance_slow_start,
                 ))
                 .push(tcp::Forward::layer())
                 .push(drain::Retain::layer(drain)),
         )
         .into_new_service()
+        .push_switch(|c: &Concrete| !c.hashes_source_ip(), hash_forward)
+        .into_inner()
 }
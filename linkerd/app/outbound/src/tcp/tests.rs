@@ -70,7 +70,7 @@ async fn plaintext_tcp() {
 
     // Build the outbound TCP balancer stack.
     let (_, drain) = drain::channel();
-    let forward = super::balance::stack(&cfg.proxy, connect, resolver, drain).new_service(concrete);
+    let forward = super::balance::stack(&cfg, connect, resolver, drain).new_service(concrete);
 
     forward
         .oneshot(client_io)
@@ -153,7 +153,7 @@ async fn tls_when_hinted() {
 
     // Build the outbound TCP balancer stack.
     let (_, drain) = drain::channel();
-    let mut balance = super::balance::stack(&cfg.proxy, connect, resolver, drain);
+    let mut balance = super::balance::stack(&cfg, connect, resolver, drain);
 
     let plain = balance
         .new_service(plain_concrete)
@@ -2,7 +2,7 @@ use crate::Config;
 pub use futures::prelude::*;
 pub use ipnet::IpNet;
 use linkerd2_app_core::{
-    config, exp_backoff,
+    circuit_breaker, config, exp_backoff, outlier,
     proxy::http::{h1, h2},
     transport::BindTcp,
     IpMatch,
@@ -40,7 +40,36 @@ pub fn default_config(orig_dst: SocketAddr) -> Config {
             cache_max_idle_age: Duration::from_secs(60),
             dispatch_timeout: Duration::from_secs(3),
             max_in_flight_requests: 10_000,
+            adaptive_concurrency: None,
             detect_protocol_timeout: Duration::from_secs(3),
         },
+        max_buffered_body_bytes: 64 * 1024,
+        max_in_flight_mirrors: 100,
+        retry_backoff: exp_backoff::ExponentialBackoff::new(
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+            0.1,
+        )
+        .unwrap(),
+        hedge_latency_percentile: 0.9,
+        outlier: outlier::Config {
+            consecutive_failures: 5,
+            min_success_rate: 0.0,
+            success_rate_min_requests: 20,
+            success_rate_window: Duration::from_secs(10),
+            base_ejection_time: Duration::from_secs(1),
+            max_ejection_time: Duration::from_secs(10),
+            max_ejection_percent: 50,
+        },
+        circuit_breaker: circuit_breaker::Config {
+            failure_rate: 0.0,
+            min_requests: 20,
+            window: Duration::from_secs(10),
+            open_timeout: Duration::from_secs(5),
+            half_open_probes: 1,
+        },
+        deadline_header: None,
+        locality: None,
+        balance_slow_start: Duration::from_secs(0),
     }
 }
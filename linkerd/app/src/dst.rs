@@ -2,31 +2,58 @@ use linkerd2_app_core::{
     control, dns,
     exp_backoff::{ExponentialBackoff, ExponentialBackoffStream},
     is_discovery_rejected, metrics, profiles,
-    proxy::{api_resolve as api, identity, resolve::recover},
+    proxy::{api_resolve as api, file_resolve, identity, resolve::recover},
+    svc,
     transport::tls,
     Error, Recover,
 };
+use std::{path::PathBuf, time::Duration};
 use tonic::body::BoxBody;
 
 #[derive(Clone, Debug)]
 pub struct Config {
-    pub control: control::Config,
+    /// The destination service. When it is not configured, profiles and
+    /// endpoints are only discovered from local files.
+    pub control: Option<control::Config>,
     pub context: String,
+
+    /// A directory of profiles that override the destination service's.
+    pub profile_dir: Option<PathBuf>,
+    pub profile_dir_interval: Duration,
+
+    /// A file of endpoints that override the destination service's.
+    pub endpoints_file: Option<PathBuf>,
+    pub endpoints_file_interval: Duration,
+
+    /// A directory in which the destination service's responses are saved,
+    /// to be served when it doesn't respond before `snapshot_timeout`.
+    pub snapshot_dir: Option<PathBuf>,
+    pub snapshot_timeout: Duration,
+    /// How long a snapshot is kept after it was last written.
+    pub snapshot_max_age: Duration,
 }
 
 /// Handles to destination service clients.
 pub struct Dst {
     /// The address of the destination service, used for logging.
-    pub addr: control::ControlAddr,
+    pub addr: Option<control::ControlAddr>,
 
-    /// Resolves profiles.
-    pub profiles: profiles::Client<control::Client<BoxBody>, BackoffUnlessInvalidArgument>,
+    /// Resolves profiles, preferring those in the profile directory.
+    pub profiles: svc::Either<profiles::file::Overrides<ProfilesClient>, profiles::file::Profiles>,
 
-    /// Resolves endpoints.
-    pub resolve:
-        recover::Resolve<BackoffUnlessInvalidArgument, api::Resolve<control::Client<BoxBody>>>,
+    /// Resolves endpoints, preferring those in the endpoints file.
+    pub resolve: svc::Either<file_resolve::Overrides<ResolveClient>, file_resolve::FileResolve>,
+
+    /// Reports destinations that are served from snapshots.
+    pub snapshots: api::snapshot::Report,
 }
 
+type ProfilesClient = profiles::Client<control::Client<BoxBody>, BackoffUnlessInvalidArgument>;
+
+type ResolveClient = api::snapshot::Resolve<
+    recover::Resolve<BackoffUnlessInvalidArgument, api::Resolve<control::Client<BoxBody>>>,
+>;
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct BackoffUnlessInvalidArgument(ExponentialBackoff);
 
@@ -39,14 +66,72 @@ impl Config {
         metrics: metrics::ControlHttp,
         identity: tls::Conditional<identity::Local>,
     ) -> Result<Dst, Error> {
-        let addr = self.control.addr.clone();
-        let backoff = BackoffUnlessInvalidArgument(self.control.connect.backoff);
-        let svc = self.control.build(dns, metrics, identity);
+        let local_profiles = match self.profile_dir {
+            Some(dir) => {
+                tracing::info!(dir = %dir.display(), "Loading profiles");
+                Some(profiles::file::Profiles::watch(
+                    dir,
+                    self.profile_dir_interval,
+                )?)
+            }
+            None => None,
+        };
+        let local_resolve = match self.endpoints_file {
+            Some(file) => {
+                tracing::info!(file = %file.display(), "Loading endpoints");
+                Some(file_resolve::FileResolve::watch(
+                    file,
+                    self.endpoints_file_interval,
+                )?)
+            }
+            None => None,
+        };
+
+        let control = match self.control {
+            Some(control) => control,
+            None => {
+                // Without a destination service, all discovery is local.
+                let profiles = local_profiles.ok_or("no profile directory configured")?;
+                let resolve = local_resolve.ok_or("no endpoints file configured")?;
+                return Ok(Dst {
+                    addr: None,
+                    profiles: svc::Either::B(profiles),
+                    resolve: svc::Either::B(resolve),
+                    snapshots: api::snapshot::Report::disabled(),
+                });
+            }
+        };
+
+        let snapshots = match self.snapshot_dir {
+            Some(dir) => {
+                tracing::info!(dir = %dir.display(), "Saving snapshots");
+                Some(api::snapshot::Snapshots::new(dir, self.snapshot_max_age)?)
+            }
+            None => None,
+        };
+
+        let addr = control.addr.clone();
+        let backoff = BackoffUnlessInvalidArgument(control.connect.backoff);
+        let svc = control.build(dns, metrics, identity);
+
+        let profiles = profiles::Client::new(svc.clone(), backoff, self.context.clone())
+            .with_snapshots(snapshots.clone(), self.snapshot_timeout);
+        let resolve = api::snapshot::Resolve::new(
+            snapshots.clone(),
+            self.snapshot_timeout,
+            recover::Resolve::new(
+                backoff,
+                api::Resolve::new(svc, self.context).with_snapshots(snapshots.clone()),
+            ),
+        );
 
         Ok(Dst {
-            addr,
-            profiles: profiles::Client::new(svc.clone(), backoff, self.context.clone()),
-            resolve: recover::Resolve::new(backoff, api::Resolve::new(svc, self.context)),
+            addr: Some(addr),
+            profiles: svc::Either::A(profiles::file::Overrides::new(local_profiles, profiles)),
+            resolve: svc::Either::A(file_resolve::Overrides::new(local_resolve, resolve)),
+            snapshots: snapshots
+                .map(|s| s.report())
+                .unwrap_or_else(api::snapshot::Report::disabled),
         })
     }
 }
@@ -1,8 +1,9 @@
 use crate::core::{
-    addr,
+    addr, circuit_breaker,
     config::*,
     control::{Config as ControlConfig, ControlAddr},
-    proxy::http::{h1, h2},
+    locality, outlier, profiles,
+    proxy::http::{h1, h2, header::HeaderName},
     transport::{tls, BindTcp},
     Addr, AddrMatch, NameMatch,
 };
@@ -31,6 +32,15 @@ pub enum EnvError {
     NoDestinationAddress,
 }
 
+/// How the local identity's certificates are provisioned.
+#[derive(Clone, Debug)]
+pub enum IdentitySource {
+    /// Certificates are issued by the Identity service.
+    Service(ControlAddr, identity::certify::Config),
+    /// Certificates are issued by an external agent and read from files.
+    Files(identity::file::Config),
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub enum ParseError {
     NotADuration,
@@ -43,6 +53,8 @@ pub enum ParseError {
     NameError,
     InvalidTokenSource,
     InvalidTrustAnchors,
+    InvalidRateLimitKey,
+    InvalidHeaderName,
 }
 
 // Environment variables to look at when loading the configuration
@@ -91,6 +103,116 @@ const ENV_OUTBOUND_MAX_IDLE_CONNS_PER_ENDPOINT: &str =
 pub const ENV_INBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_INBOUND_MAX_IN_FLIGHT";
 pub const ENV_OUTBOUND_MAX_IN_FLIGHT: &str = "LINKERD2_PROXY_OUTBOUND_MAX_IN_FLIGHT";
 
+/// Configures a response latency above which the in-flight request limit is
+/// decreased. When set, the limit adapts to response latency instead of being
+/// fixed at the `MAX_IN_FLIGHT` value, which becomes its upper bound.
+pub const ENV_INBOUND_ADAPTIVE_CONCURRENCY_LATENCY: &str =
+    "LINKERD2_PROXY_INBOUND_ADAPTIVE_CONCURRENCY_LATENCY";
+pub const ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_LATENCY: &str =
+    "LINKERD2_PROXY_OUTBOUND_ADAPTIVE_CONCURRENCY_LATENCY";
+
+/// Configures the lower bound of an adaptive in-flight request limit.
+pub const ENV_INBOUND_ADAPTIVE_CONCURRENCY_MIN: &str =
+    "LINKERD2_PROXY_INBOUND_ADAPTIVE_CONCURRENCY_MIN";
+pub const ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN: &str =
+    "LINKERD2_PROXY_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN";
+
+/// Configures the number of inbound requests per second admitted by each rate
+/// limit bucket. Requests are not rate limited unless this is set.
+pub const ENV_INBOUND_RATE_LIMIT: &str = "LINKERD2_PROXY_INBOUND_RATE_LIMIT";
+
+/// Configures the number of inbound requests that each rate limit bucket may
+/// admit at once. Defaults to one second's worth of requests.
+pub const ENV_INBOUND_RATE_LIMIT_BURST: &str = "LINKERD2_PROXY_INBOUND_RATE_LIMIT_BURST";
+
+/// Configures which inbound requests share a rate limit bucket: `route`,
+/// `client` (by the client's TLS identity), or `route+client`.
+pub const ENV_INBOUND_RATE_LIMIT_KEY: &str = "LINKERD2_PROXY_INBOUND_RATE_LIMIT_KEY";
+
+/// Configures the maximum number of bytes of an outbound request body that may
+/// be buffered so that the request can be retried.
+///
+/// Requests with larger bodies are not retried.
+pub const ENV_OUTBOUND_MAX_BUFFERED_BODY_BYTES: &str =
+    "LINKERD2_PROXY_OUTBOUND_MAX_BUFFERED_BODY_BYTES";
+
+/// Configures the maximum number of mirrored requests that may be in flight on
+/// each outbound route.
+///
+/// Requests are not mirrored while a route is at this limit, so that a slow
+/// mirror cannot hold an unbounded number of buffered requests.
+pub const ENV_OUTBOUND_MAX_IN_FLIGHT_MIRRORS: &str =
+    "LINKERD2_PROXY_OUTBOUND_MAX_IN_FLIGHT_MIRRORS";
+
+/// Configures outlier detection for outbound HTTP load balancers.
+///
+/// Endpoints are ejected after `CONSECUTIVE_FAILURES` consecutive failures
+/// (zero disables this check) or when their success rate over
+/// `SUCCESS_RATE_WINDOW` falls below `MIN_SUCCESS_RATE` (zero disables this
+/// check). At most `MAX_EJECTION_PERCENT` of a balancer's endpoints are ejected
+/// at once.
+pub const ENV_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES";
+pub const ENV_OUTBOUND_OUTLIER_MIN_SUCCESS_RATE: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_MIN_SUCCESS_RATE";
+pub const ENV_OUTBOUND_OUTLIER_SUCCESS_RATE_MIN_REQUESTS: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_SUCCESS_RATE_MIN_REQUESTS";
+pub const ENV_OUTBOUND_OUTLIER_SUCCESS_RATE_WINDOW: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_SUCCESS_RATE_WINDOW";
+pub const ENV_OUTBOUND_OUTLIER_BASE_EJECTION_TIME: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_BASE_EJECTION_TIME";
+pub const ENV_OUTBOUND_OUTLIER_MAX_EJECTION_TIME: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_MAX_EJECTION_TIME";
+pub const ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT: &str =
+    "LINKERD2_PROXY_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT";
+
+/// Configures circuit breakers for outbound HTTP backends.
+///
+/// A backend's circuit opens when the ratio of its failed responses over
+/// `WINDOW` reaches `FAILURE_RATE` (zero, the default, disables circuit
+/// breaking), once at least `MIN_REQUESTS` responses have been observed. An
+/// open circuit fails requests for `OPEN_TIMEOUT` and then admits
+/// `HALF_OPEN_PROBES` requests, closing if they all succeed.
+pub const ENV_OUTBOUND_CIRCUIT_BREAKER_FAILURE_RATE: &str =
+    "LINKERD2_PROXY_OUTBOUND_CIRCUIT_BREAKER_FAILURE_RATE";
+pub const ENV_OUTBOUND_CIRCUIT_BREAKER_MIN_REQUESTS: &str =
+    "LINKERD2_PROXY_OUTBOUND_CIRCUIT_BREAKER_MIN_REQUESTS";
+pub const ENV_OUTBOUND_CIRCUIT_BREAKER_WINDOW: &str =
+    "LINKERD2_PROXY_OUTBOUND_CIRCUIT_BREAKER_WINDOW";
+pub const ENV_OUTBOUND_CIRCUIT_BREAKER_OPEN_TIMEOUT: &str =
+    "LINKERD2_PROXY_OUTBOUND_CIRCUIT_BREAKER_OPEN_TIMEOUT";
+pub const ENV_OUTBOUND_CIRCUIT_BREAKER_HALF_OPEN_PROBES: &str =
+    "LINKERD2_PROXY_OUTBOUND_CIRCUIT_BREAKER_HALF_OPEN_PROBES";
+
+/// Configures the response latency percentile, between 0 and 1, after which a
+/// request on an idempotent route is hedged.
+pub const ENV_OUTBOUND_HEDGE_LATENCY_PERCENTILE: &str =
+    "LINKERD2_PROXY_OUTBOUND_HEDGE_LATENCY_PERCENTILE";
+
+/// Configures a header that carries an outbound request's deadline as a number
+/// of milliseconds, in addition to the gRPC `grpc-timeout` header. Requests
+/// time out when their deadline elapses, and the header is rewritten with the
+/// time remaining.
+pub const ENV_OUTBOUND_DEADLINE_HEADER: &str = "LINKERD2_PROXY_OUTBOUND_DEADLINE_HEADER";
+
+/// Configures the zone in which the proxy runs. When set, outbound load
+/// balancers prefer endpoints whose `zone` label matches.
+pub const ENV_ZONE: &str = "LINKERD2_PROXY_ZONE";
+
+/// Configures the minimum ratio of a load balancer's local endpoints that must
+/// be healthy before traffic spills over to endpoints in other zones.
+pub const ENV_OUTBOUND_ZONE_MIN_HEALTHY: &str = "LINKERD2_PROXY_OUTBOUND_ZONE_MIN_HEALTHY";
+
+/// Configures how long a load balancer's local endpoint may be not ready before
+/// it is considered unhealthy.
+pub const ENV_OUTBOUND_ZONE_NOT_READY_TIMEOUT: &str =
+    "LINKERD2_PROXY_OUTBOUND_ZONE_NOT_READY_TIMEOUT";
+
+/// Configures the window over which the share of traffic that outbound load
+/// balancers dispatch to a newly discovered endpoint ramps up to its full share.
+/// Zero disables slow-start.
+pub const ENV_OUTBOUND_BALANCE_SLOW_START: &str = "LINKERD2_PROXY_OUTBOUND_BALANCE_SLOW_START";
+
 pub const ENV_TRACE_ATTRIBUTES_PATH: &str = "LINKERD2_PROXY_TRACE_ATTRIBUTES_PATH";
 
 /// Constrains which destination names may be used for profile/route discovery.
@@ -130,11 +252,42 @@ pub const ENV_INBOUND_PORTS_REQUIRE_IDENTITY: &str =
 pub const ENV_IDENTITY_DISABLED: &str = "LINKERD2_PROXY_IDENTITY_DISABLED";
 pub const ENV_IDENTITY_DIR: &str = "LINKERD2_PROXY_IDENTITY_DIR";
 pub const ENV_IDENTITY_TRUST_ANCHORS: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS";
+
+/// The local identity: either a DNS-like name or a SPIFFE ID, e.g.
+/// `spiffe://cluster.local/ns/default/sa/web`. A proxy with a SPIFFE ID only
+/// terminates TLS for clients that indicate its trust domain via SNI, and its
+/// certificate must be an X.509-SVID for the ID.
 pub const ENV_IDENTITY_IDENTITY_LOCAL_NAME: &str = "LINKERD2_PROXY_IDENTITY_LOCAL_NAME";
 pub const ENV_IDENTITY_TOKEN_FILE: &str = "LINKERD2_PROXY_IDENTITY_TOKEN_FILE";
 pub const ENV_IDENTITY_MIN_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MIN_REFRESH";
 pub const ENV_IDENTITY_MAX_REFRESH: &str = "LINKERD2_PROXY_IDENTITY_MAX_REFRESH";
 
+/// Constrains meshed connections to TLS 1.3. By default, TLS 1.3 is preferred
+/// but TLS 1.2 is accepted.
+pub const ENV_IDENTITY_REQUIRE_TLS13: &str = "LINKERD2_PROXY_IDENTITY_REQUIRE_TLS13";
+
+/// Configures a file holding the local identity's PEM-encoded certificate
+/// chain, leaf first. When set, along with `ENV_IDENTITY_KEY_FILE`,
+/// certificates are read from files that are maintained by an external agent
+/// (e.g. cert-manager or Vault Agent) instead of from the Identity service.
+pub const ENV_IDENTITY_CRT_FILE: &str = "LINKERD2_PROXY_IDENTITY_CRT_FILE";
+
+/// Configures a file holding the local identity's PEM-encoded PKCS#8 private
+/// key.
+pub const ENV_IDENTITY_KEY_FILE: &str = "LINKERD2_PROXY_IDENTITY_KEY_FILE";
+
+/// Configures a file holding a PEM-encoded bundle of trust anchors, which is
+/// used in place of `ENV_IDENTITY_TRUST_ANCHORS`. The trust anchors are
+/// reloaded when the file changes, so that roots may be rotated without
+/// restarting the proxy: certificates issued by any root in the bundle are
+/// accepted, so the new root is added to the bundle before the old root is
+/// removed.
+pub const ENV_IDENTITY_TRUST_ANCHORS_FILE: &str = "LINKERD2_PROXY_IDENTITY_TRUST_ANCHORS_FILE";
+
+/// Configures how often the identity's certificate, key, and trust anchors
+/// files are checked for changes.
+pub const ENV_IDENTITY_FILE_INTERVAL: &str = "LINKERD2_PROXY_IDENTITY_FILE_INTERVAL";
+
 pub const ENV_IDENTITY_SVC_BASE: &str = "LINKERD2_PROXY_IDENTITY_SVC";
 
 pub const ENV_DESTINATION_SVC_BASE: &str = "LINKERD2_PROXY_DESTINATION_SVC";
@@ -147,6 +300,42 @@ pub const ENV_DESTINATION_CONTEXT: &str = "LINKERD2_PROXY_DESTINATION_CONTEXT";
 pub const ENV_DESTINATION_PROFILE_INITIAL_TIMEOUT: &str =
     "LINKERD2_PROXY_DESTINATION_PROFILE_INITIAL_TIMEOUT";
 
+/// Configures a directory of `ServiceProfile` files (YAML or JSON). A file's
+/// profile is served in place of the destination service's profile for the
+/// same host.
+pub const ENV_DESTINATION_PROFILE_DIR: &str = "LINKERD2_PROXY_DESTINATION_PROFILE_DIR";
+
+/// Configures how often the profile directory is checked for changes.
+pub const ENV_DESTINATION_PROFILE_DIR_INTERVAL: &str =
+    "LINKERD2_PROXY_DESTINATION_PROFILE_DIR_INTERVAL";
+
+/// Configures a file (YAML or JSON) that maps destination authorities to
+/// their endpoints. A destination in the file is resolved from the file
+/// rather than the destination service.
+///
+/// When both the endpoints file and the profile directory are configured,
+/// the destination service may be omitted.
+pub const ENV_DESTINATION_ENDPOINTS_FILE: &str = "LINKERD2_PROXY_DESTINATION_ENDPOINTS_FILE";
+
+/// Configures how often the endpoints file is checked for changes.
+pub const ENV_DESTINATION_ENDPOINTS_FILE_INTERVAL: &str =
+    "LINKERD2_PROXY_DESTINATION_ENDPOINTS_FILE_INTERVAL";
+
+/// Configures a directory in which the endpoints and profiles received from
+/// the destination service are saved. When the destination service doesn't
+/// respond to a lookup, e.g. because the proxy restarted during a control
+/// plane outage, the destination's last-known-good endpoints and profile are
+/// loaded from this directory.
+pub const ENV_DESTINATION_SNAPSHOT_DIR: &str = "LINKERD2_PROXY_DESTINATION_SNAPSHOT_DIR";
+
+/// Configures how long a lookup waits for the destination service before
+/// falling back to a snapshot.
+pub const ENV_DESTINATION_SNAPSHOT_TIMEOUT: &str = "LINKERD2_PROXY_DESTINATION_SNAPSHOT_TIMEOUT";
+
+/// Configures how long a snapshot is kept after it was last written. Expired
+/// snapshots are removed when the proxy starts.
+pub const ENV_DESTINATION_SNAPSHOT_MAX_AGE: &str = "LINKERD2_PROXY_DESTINATION_SNAPSHOT_MAX_AGE";
+
 pub const ENV_TAP_DISABLED: &str = "LINKERD2_PROXY_TAP_DISABLED";
 pub const ENV_TAP_SVC_NAME: &str = "LINKERD2_PROXY_TAP_SVC_NAME";
 const ENV_RESOLV_CONF: &str = "LINKERD2_PROXY_RESOLV_CONF";
@@ -189,6 +378,11 @@ const DEFAULT_OUTBOUND_CONNECT_BACKOFF: ExponentialBackoff = ExponentialBackoff
     max: Duration::from_millis(500),
     jitter: 0.1,
 };
+const DEFAULT_OUTBOUND_RETRY_BACKOFF: ExponentialBackoff = ExponentialBackoff {
+    min: Duration::from_millis(25),
+    max: Duration::from_millis(500),
+    jitter: 0.5,
+};
 const DEFAULT_RESOLV_CONF: &str = "/etc/resolv.conf";
 
 const DEFAULT_INITIAL_STREAM_WINDOW_SIZE: u32 = 65_535; // Protocol default
@@ -221,6 +415,43 @@ const DEFAULT_OUTBOUND_MAX_IDLE_CONNS_PER_ENDPOINT: usize = std::usize::MAX;
 const DEFAULT_INBOUND_MAX_IN_FLIGHT: usize = 100_000;
 const DEFAULT_OUTBOUND_MAX_IN_FLIGHT: usize = 100_000;
 
+// Adaptive limits start at, and never fall below, this many in-flight requests.
+const DEFAULT_INBOUND_ADAPTIVE_CONCURRENCY_MIN: usize = 100;
+const DEFAULT_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN: usize = 100;
+
+// Request bodies are buffered in memory for as long as the request may be
+// retried, so this limit should stay small.
+const DEFAULT_OUTBOUND_MAX_BUFFERED_BODY_BYTES: usize = 64 * 1024;
+
+// Each mirrored request may hold a buffered body, so this is bounded by
+// `DEFAULT_OUTBOUND_MAX_BUFFERED_BODY_BYTES` to a few MB per route.
+const DEFAULT_OUTBOUND_MAX_IN_FLIGHT_MIRRORS: usize = 100;
+
+const DEFAULT_OUTBOUND_HEDGE_LATENCY_PERCENTILE: f64 = 0.95;
+
+const DEFAULT_OUTBOUND_ZONE_MIN_HEALTHY: f64 = 0.7;
+const DEFAULT_OUTBOUND_ZONE_NOT_READY_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Slow-start is disabled unless a window is configured.
+const DEFAULT_OUTBOUND_BALANCE_SLOW_START: Duration = Duration::from_secs(0);
+
+// Outlier detection ejects endpoints that fail consecutively by default;
+// success-rate detection must be enabled explicitly.
+const DEFAULT_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES: u32 = 5;
+const DEFAULT_OUTBOUND_OUTLIER_MIN_SUCCESS_RATE: f64 = 0.0;
+const DEFAULT_OUTBOUND_OUTLIER_SUCCESS_RATE_MIN_REQUESTS: u32 = 20;
+const DEFAULT_OUTBOUND_OUTLIER_SUCCESS_RATE_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_OUTLIER_BASE_EJECTION_TIME: Duration = Duration::from_secs(30);
+const DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_TIME: Duration = Duration::from_secs(300);
+const DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT: u8 = 50;
+
+// Circuit breaking must be enabled explicitly by setting a failure rate.
+const DEFAULT_OUTBOUND_CIRCUIT_BREAKER_FAILURE_RATE: f64 = 0.0;
+const DEFAULT_OUTBOUND_CIRCUIT_BREAKER_MIN_REQUESTS: u32 = 20;
+const DEFAULT_OUTBOUND_CIRCUIT_BREAKER_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_CIRCUIT_BREAKER_OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_OUTBOUND_CIRCUIT_BREAKER_HALF_OPEN_PROBES: u32 = 3;
+
 // This value should be large enough to admit requests without exerting
 // backpressure so that requests implicitly buffer in the executor; but it
 // should be small enough that callers can't force the proxy to consume an
@@ -233,12 +464,18 @@ const DEFAULT_BUFFER_CAPACITY: usize = 10_000;
 
 const DEFAULT_DESTINATION_PROFILE_SUFFIXES: &str = "svc.cluster.local.";
 const DEFAULT_DESTINATION_PROFILE_IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+const DEFAULT_DESTINATION_PROFILE_DIR_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_DESTINATION_ENDPOINTS_FILE_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_DESTINATION_SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_DESTINATION_SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24 * 7);
 
 const DEFAULT_IDENTITY_MIN_REFRESH: Duration = Duration::from_secs(10);
 const DEFAULT_IDENTITY_MAX_REFRESH: Duration = Duration::from_secs(60 * 60 * 24);
+const DEFAULT_IDENTITY_FILE_INTERVAL: Duration = Duration::from_secs(10);
 
 const INBOUND_CONNECT_BASE: &str = "INBOUND_CONNECT";
 const OUTBOUND_CONNECT_BASE: &str = "OUTBOUND_CONNECT";
+const OUTBOUND_RETRY_BASE: &str = "OUTBOUND_RETRY";
 
 /// Load a `App` by reading ENV variables.
 pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError> {
@@ -294,6 +531,33 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
 
     let inbound_max_in_flight = parse(strings, ENV_INBOUND_MAX_IN_FLIGHT, parse_number);
     let outbound_max_in_flight = parse(strings, ENV_OUTBOUND_MAX_IN_FLIGHT, parse_number);
+    let inbound_adaptive_concurrency = parse_adaptive_concurrency(
+        strings,
+        ENV_INBOUND_ADAPTIVE_CONCURRENCY_LATENCY,
+        ENV_INBOUND_ADAPTIVE_CONCURRENCY_MIN,
+        DEFAULT_INBOUND_ADAPTIVE_CONCURRENCY_MIN,
+    );
+    let outbound_adaptive_concurrency = parse_adaptive_concurrency(
+        strings,
+        ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_LATENCY,
+        ENV_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN,
+        DEFAULT_OUTBOUND_ADAPTIVE_CONCURRENCY_MIN,
+    );
+
+    let inbound_rate_limit = parse_rate_limit(strings);
+
+    let outbound_max_buffered_body_bytes =
+        parse(strings, ENV_OUTBOUND_MAX_BUFFERED_BODY_BYTES, parse_number);
+    let outbound_max_in_flight_mirrors =
+        parse(strings, ENV_OUTBOUND_MAX_IN_FLIGHT_MIRRORS, parse_number);
+    let outbound_outlier = parse_outlier(strings);
+    let outbound_circuit_breaker = parse_circuit_breaker(strings);
+    let outbound_hedge_latency_percentile =
+        parse(strings, ENV_OUTBOUND_HEDGE_LATENCY_PERCENTILE, parse_ratio);
+    let outbound_deadline_header = parse(strings, ENV_OUTBOUND_DEADLINE_HEADER, parse_header_name);
+    let outbound_locality = parse_locality(strings);
+    let outbound_balance_slow_start =
+        parse(strings, ENV_OUTBOUND_BALANCE_SLOW_START, parse_duration);
 
     let metrics_retain_idle = parse(strings, ENV_METRICS_RETAIN_IDLE, parse_duration);
 
@@ -341,6 +605,27 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         parse_dns_suffixes,
     );
     let dst_profile_networks = parse(strings, ENV_DESTINATION_PROFILE_NETWORKS, parse_networks);
+    let dst_profile_dir = parse(strings, ENV_DESTINATION_PROFILE_DIR, |s| {
+        Ok(PathBuf::from(s))
+    });
+    let dst_profile_dir_interval = parse(
+        strings,
+        ENV_DESTINATION_PROFILE_DIR_INTERVAL,
+        parse_duration,
+    );
+    let dst_endpoints_file = parse(strings, ENV_DESTINATION_ENDPOINTS_FILE, |s| {
+        Ok(PathBuf::from(s))
+    });
+    let dst_endpoints_file_interval = parse(
+        strings,
+        ENV_DESTINATION_ENDPOINTS_FILE_INTERVAL,
+        parse_duration,
+    );
+    let dst_snapshot_dir = parse(strings, ENV_DESTINATION_SNAPSHOT_DIR, |s| {
+        Ok(PathBuf::from(s))
+    });
+    let dst_snapshot_timeout = parse(strings, ENV_DESTINATION_SNAPSHOT_TIMEOUT, parse_duration);
+    let dst_snapshot_max_age = parse(strings, ENV_DESTINATION_SNAPSHOT_MAX_AGE, parse_duration);
 
     let initial_stream_window_size = parse(strings, ENV_INITIAL_STREAM_WINDOW_SIZE, parse_number);
     let initial_connection_window_size =
@@ -438,8 +723,26 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 dispatch_timeout,
                 max_in_flight_requests: outbound_max_in_flight?
                     .unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT),
+                adaptive_concurrency: outbound_adaptive_concurrency?,
                 detect_protocol_timeout,
             },
+            max_buffered_body_bytes: outbound_max_buffered_body_bytes?
+                .unwrap_or(DEFAULT_OUTBOUND_MAX_BUFFERED_BODY_BYTES),
+            max_in_flight_mirrors: outbound_max_in_flight_mirrors?
+                .unwrap_or(DEFAULT_OUTBOUND_MAX_IN_FLIGHT_MIRRORS),
+            retry_backoff: parse_backoff(
+                strings,
+                OUTBOUND_RETRY_BASE,
+                DEFAULT_OUTBOUND_RETRY_BACKOFF,
+            )?,
+            hedge_latency_percentile: outbound_hedge_latency_percentile?
+                .unwrap_or(DEFAULT_OUTBOUND_HEDGE_LATENCY_PERCENTILE),
+            outlier: outbound_outlier?,
+            circuit_breaker: outbound_circuit_breaker?,
+            deadline_header: outbound_deadline_header?,
+            locality: outbound_locality?,
+            balance_slow_start: outbound_balance_slow_start?
+                .unwrap_or(DEFAULT_OUTBOUND_BALANCE_SLOW_START),
         }
     };
 
@@ -532,29 +835,50 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
                 dispatch_timeout,
                 max_in_flight_requests: inbound_max_in_flight?
                     .unwrap_or(DEFAULT_INBOUND_MAX_IN_FLIGHT),
+                adaptive_concurrency: inbound_adaptive_concurrency?,
                 detect_protocol_timeout,
             },
             require_identity_for_inbound_ports: require_identity_for_inbound_ports.into(),
             profile_idle_timeout: dst_profile_idle_timeout?
                 .unwrap_or(DEFAULT_DESTINATION_PROFILE_IDLE_TIMEOUT),
             disable_protocol_detection_for_ports: inbound_opaque_ports.into(),
+            rate_limit: inbound_rate_limit?,
         }
     };
 
     let dst = {
-        let addr = dst_addr?.ok_or(EnvError::NoDestinationAddress)?;
-        let connect = if addr.addr.is_loopback() {
-            inbound.proxy.connect.clone()
-        } else {
-            outbound.proxy.connect.clone()
+        let profile_dir = dst_profile_dir?;
+        let endpoints_file = dst_endpoints_file?;
+        let control = match dst_addr? {
+            Some(addr) => {
+                let connect = if addr.addr.is_loopback() {
+                    inbound.proxy.connect.clone()
+                } else {
+                    outbound.proxy.connect.clone()
+                };
+                Some(ControlConfig {
+                    addr,
+                    connect,
+                    buffer_capacity,
+                })
+            }
+            // Without a destination service, profiles and endpoints must be
+            // configured locally.
+            None if profile_dir.is_some() && endpoints_file.is_some() => None,
+            None => return Err(EnvError::NoDestinationAddress),
         };
         super::dst::Config {
             context: dst_token?.unwrap_or_default(),
-            control: ControlConfig {
-                addr,
-                connect,
-                buffer_capacity,
-            },
+            profile_dir,
+            profile_dir_interval: dst_profile_dir_interval?
+                .unwrap_or(DEFAULT_DESTINATION_PROFILE_DIR_INTERVAL),
+            endpoints_file,
+            endpoints_file_interval: dst_endpoints_file_interval?
+                .unwrap_or(DEFAULT_DESTINATION_ENDPOINTS_FILE_INTERVAL),
+            snapshot_dir: dst_snapshot_dir?,
+            snapshot_timeout: dst_snapshot_timeout?.unwrap_or(DEFAULT_DESTINATION_SNAPSHOT_TIMEOUT),
+            snapshot_max_age: dst_snapshot_max_age?.unwrap_or(DEFAULT_DESTINATION_SNAPSHOT_MAX_AGE),
+            control,
         }
     };
 
@@ -617,21 +941,24 @@ pub fn parse_config<S: Strings>(strings: &S) -> Result<super::Config, EnvError>
         .unwrap_or(super::tap::Config::Disabled);
 
     let identity = identity_config?
-        .map(|(addr, certify)| {
-            // If the address doesn't have a server identity, then we're on localhost.
-            let connect = if addr.identity.is_none() {
-                inbound.proxy.connect.clone()
-            } else {
-                outbound.proxy.connect.clone()
-            };
-            identity::Config::Enabled {
-                certify,
-                control: ControlConfig {
-                    addr,
-                    connect,
-                    buffer_capacity: 1,
-                },
+        .map(|source| match source {
+            IdentitySource::Service(addr, certify) => {
+                // If the address doesn't have a server identity, then we're on localhost.
+                let connect = if addr.identity.is_none() {
+                    inbound.proxy.connect.clone()
+                } else {
+                    outbound.proxy.connect.clone()
+                };
+                identity::Config::Enabled {
+                    certify,
+                    control: ControlConfig {
+                        addr,
+                        connect,
+                        buffer_capacity: 1,
+                    },
+                }
             }
+            IdentitySource::Files(files) => identity::Config::Files(files),
         })
         .unwrap_or(identity::Config::Disabled);
 
@@ -760,25 +1087,35 @@ where
     s.parse().map_err(|_| ParseError::NotANumber)
 }
 
-fn parse_duration(s: &str) -> Result<Duration, ParseError> {
-    use regex::Regex;
-
-    let re = Regex::new(r"^\s*(\d+)(ms|s|m|h|d)?\s*$").expect("duration regex");
+fn parse_ratio(s: &str) -> Result<f64, ParseError> {
+    let p = parse_number::<f64>(s)?;
+    if !(0.0..=1.0).contains(&p) {
+        return Err(ParseError::NotANumber);
+    }
+    Ok(p)
+}
 
-    let cap = re.captures(s).ok_or(ParseError::NotADuration)?;
+fn parse_header_name(s: &str) -> Result<HeaderName, ParseError> {
+    s.parse().map_err(|_| ParseError::InvalidHeaderName)
+}
 
-    let magnitude = parse_number(&cap[1])?;
-    match cap.get(2).map(|m| m.as_str()) {
-        None if magnitude == 0 => Ok(Duration::from_secs(0)),
-        Some("ms") => Ok(Duration::from_millis(magnitude)),
-        Some("s") => Ok(Duration::from_secs(magnitude)),
-        Some("m") => Ok(Duration::from_secs(magnitude * 60)),
-        Some("h") => Ok(Duration::from_secs(magnitude * 60 * 60)),
-        Some("d") => Ok(Duration::from_secs(magnitude * 60 * 60 * 24)),
-        _ => Err(ParseError::NotADuration),
+fn parse_rate_limit_key(s: &str) -> Result<inbound::rate_limit::Key, ParseError> {
+    use inbound::rate_limit::Key;
+    match s {
+        "route" => Ok(Key::Route),
+        "client" => Ok(Key::Client),
+        "route+client" => Ok(Key::RouteAndClient),
+        _ => Err(ParseError::InvalidRateLimitKey),
     }
 }
 
+fn parse_duration(s: &str) -> Result<Duration, ParseError> {
+    profiles::parse_duration(s).map_err(|e| match e {
+        profiles::InvalidDuration::NotANumber => ParseError::NotANumber,
+        profiles::InvalidDuration::NotADuration => ParseError::NotADuration,
+    })
+}
+
 fn parse_socket_addr(s: &str) -> Result<SocketAddr, ParseError> {
     match parse_addr(s)? {
         Addr::Socket(a) => Ok(a),
@@ -917,6 +1254,167 @@ pub fn parse_backoff<S: Strings>(
     }
 }
 
+fn parse_outlier<S: Strings>(strings: &S) -> Result<outlier::Config, EnvError> {
+    let consecutive_failures = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES,
+        parse_number,
+    );
+    let min_success_rate = parse(strings, ENV_OUTBOUND_OUTLIER_MIN_SUCCESS_RATE, parse_ratio);
+    let success_rate_min_requests = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_SUCCESS_RATE_MIN_REQUESTS,
+        parse_number,
+    );
+    let success_rate_window = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_SUCCESS_RATE_WINDOW,
+        parse_duration,
+    );
+    let base_ejection_time = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_BASE_EJECTION_TIME,
+        parse_duration,
+    );
+    let max_ejection_time = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_MAX_EJECTION_TIME,
+        parse_duration,
+    );
+    let max_ejection_percent = parse(
+        strings,
+        ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT,
+        parse_number,
+    );
+
+    let max_ejection_percent =
+        max_ejection_percent?.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT);
+    if max_ejection_percent > 100 {
+        error!(
+            "{} must not exceed 100",
+            ENV_OUTBOUND_OUTLIER_MAX_EJECTION_PERCENT
+        );
+        return Err(EnvError::InvalidEnvVar);
+    }
+
+    Ok(outlier::Config {
+        consecutive_failures: consecutive_failures?
+            .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_CONSECUTIVE_FAILURES),
+        min_success_rate: min_success_rate?.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_MIN_SUCCESS_RATE),
+        success_rate_min_requests: success_rate_min_requests?
+            .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_SUCCESS_RATE_MIN_REQUESTS),
+        success_rate_window: success_rate_window?
+            .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_SUCCESS_RATE_WINDOW),
+        base_ejection_time: base_ejection_time?
+            .unwrap_or(DEFAULT_OUTBOUND_OUTLIER_BASE_EJECTION_TIME),
+        max_ejection_time: max_ejection_time?.unwrap_or(DEFAULT_OUTBOUND_OUTLIER_MAX_EJECTION_TIME),
+        max_ejection_percent,
+    })
+}
+
+fn parse_circuit_breaker<S: Strings>(strings: &S) -> Result<circuit_breaker::Config, EnvError> {
+    let failure_rate = parse(
+        strings,
+        ENV_OUTBOUND_CIRCUIT_BREAKER_FAILURE_RATE,
+        parse_ratio,
+    );
+    let min_requests = parse(
+        strings,
+        ENV_OUTBOUND_CIRCUIT_BREAKER_MIN_REQUESTS,
+        parse_number,
+    );
+    let window = parse(strings, ENV_OUTBOUND_CIRCUIT_BREAKER_WINDOW, parse_duration);
+    let open_timeout = parse(
+        strings,
+        ENV_OUTBOUND_CIRCUIT_BREAKER_OPEN_TIMEOUT,
+        parse_duration,
+    );
+    let half_open_probes = parse(
+        strings,
+        ENV_OUTBOUND_CIRCUIT_BREAKER_HALF_OPEN_PROBES,
+        parse_number,
+    );
+
+    Ok(circuit_breaker::Config {
+        failure_rate: failure_rate?.unwrap_or(DEFAULT_OUTBOUND_CIRCUIT_BREAKER_FAILURE_RATE),
+        min_requests: min_requests?.unwrap_or(DEFAULT_OUTBOUND_CIRCUIT_BREAKER_MIN_REQUESTS),
+        window: window?.unwrap_or(DEFAULT_OUTBOUND_CIRCUIT_BREAKER_WINDOW),
+        open_timeout: open_timeout?.unwrap_or(DEFAULT_OUTBOUND_CIRCUIT_BREAKER_OPEN_TIMEOUT),
+        half_open_probes: half_open_probes?
+            .unwrap_or(DEFAULT_OUTBOUND_CIRCUIT_BREAKER_HALF_OPEN_PROBES),
+    })
+}
+
+fn parse_locality<S: Strings>(strings: &S) -> Result<Option<locality::Config>, EnvError> {
+    let zone = strings.get(ENV_ZONE);
+    let min_healthy = parse(strings, ENV_OUTBOUND_ZONE_MIN_HEALTHY, parse_ratio)?
+        .unwrap_or(DEFAULT_OUTBOUND_ZONE_MIN_HEALTHY);
+    let not_ready_timeout = parse(strings, ENV_OUTBOUND_ZONE_NOT_READY_TIMEOUT, parse_duration)?
+        .unwrap_or(DEFAULT_OUTBOUND_ZONE_NOT_READY_TIMEOUT);
+    match zone? {
+        Some(zone) if !zone.is_empty() => Ok(Some(locality::Config {
+            zone,
+            min_healthy,
+            not_ready_timeout,
+        })),
+        _ => Ok(None),
+    }
+}
+
+fn parse_adaptive_concurrency<S: Strings>(
+    strings: &S,
+    latency_env: &str,
+    min_env: &str,
+    default_min: usize,
+) -> Result<Option<Aimd>, EnvError> {
+    let latency = parse(strings, latency_env, parse_duration);
+    let min = parse(strings, min_env, parse_number);
+    match (latency?, min?) {
+        (None, None) => Ok(None),
+        (Some(latency_threshold), min) => Ok(Some(Aimd {
+            min_limit: min.unwrap_or(default_min),
+            latency_threshold,
+            backoff_ratio: Aimd::DEFAULT_BACKOFF_RATIO,
+        })),
+        (None, Some(_)) => {
+            error!("{} must be specified when {} is set", latency_env, min_env);
+            Err(EnvError::InvalidEnvVar)
+        }
+    }
+}
+
+fn parse_rate_limit<S: Strings>(
+    strings: &S,
+) -> Result<Option<inbound::rate_limit::Config>, EnvError> {
+    let rate = parse(strings, ENV_INBOUND_RATE_LIMIT, parse_number::<f64>);
+    let burst = parse(strings, ENV_INBOUND_RATE_LIMIT_BURST, parse_number);
+    let key = parse(strings, ENV_INBOUND_RATE_LIMIT_KEY, parse_rate_limit_key);
+    let rate = match rate? {
+        Some(rate) if rate > 0.0 => rate,
+        Some(_) => {
+            error!("{} must be positive", ENV_INBOUND_RATE_LIMIT);
+            return Err(EnvError::InvalidEnvVar);
+        }
+        None => {
+            if burst?.is_some() || key?.is_some() {
+                error!(
+                    "{} must be specified when {} or {} is set",
+                    ENV_INBOUND_RATE_LIMIT,
+                    ENV_INBOUND_RATE_LIMIT_BURST,
+                    ENV_INBOUND_RATE_LIMIT_KEY
+                );
+                return Err(EnvError::InvalidEnvVar);
+            }
+            return Ok(None);
+        }
+    };
+    Ok(Some(inbound::rate_limit::Config {
+        key: key?.unwrap_or(inbound::rate_limit::Key::Route),
+        burst: burst?.unwrap_or_else(|| rate.ceil() as u32),
+        rate,
+    }))
+}
+
 pub fn parse_control_addr<S: Strings>(
     strings: &S,
     base: &str,
@@ -955,13 +1453,43 @@ pub fn parse_control_addr_disable_identity<S: Strings>(
     Ok(a.map(|addr| ControlAddr { addr, identity }))
 }
 
-pub fn parse_identity_config<S: Strings>(
-    strings: &S,
-) -> Result<Option<(ControlAddr, identity::certify::Config)>, EnvError> {
+pub fn parse_identity_config<S: Strings>(strings: &S) -> Result<Option<IdentitySource>, EnvError> {
     let control = parse_control_addr(strings, ENV_IDENTITY_SVC_BASE);
+    let require_tls13 = parse(strings, ENV_IDENTITY_REQUIRE_TLS13, parse_bool)?.unwrap_or(false);
     let ta = parse(strings, ENV_IDENTITY_TRUST_ANCHORS, |ref s| {
-        identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)
+        let ta = identity::TrustAnchors::from_pem(s).ok_or(ParseError::InvalidTrustAnchors)?;
+        Ok(if require_tls13 {
+            ta.require_tls13()
+        } else {
+            ta
+        })
     });
+    let file_interval = parse(strings, ENV_IDENTITY_FILE_INTERVAL, parse_duration)?
+        .unwrap_or(DEFAULT_IDENTITY_FILE_INTERVAL);
+    let ta_file = parse(strings, ENV_IDENTITY_TRUST_ANCHORS_FILE, |s| {
+        Ok(PathBuf::from(s))
+    })?
+    .map(|path| identity::trust_anchors::File {
+        path,
+        interval: file_interval,
+        require_tls13,
+    });
+    let ta = match ta_file.as_ref() {
+        None => ta,
+        Some(file) => match ta? {
+            Some(_) => {
+                error!(
+                    "{} must be unset when {} is set.",
+                    ENV_IDENTITY_TRUST_ANCHORS, ENV_IDENTITY_TRUST_ANCHORS_FILE
+                );
+                Err(EnvError::InvalidEnvVar)
+            }
+            None => file.load().map(Some).map_err(|e| {
+                error!("Could not read {}: {}", ENV_IDENTITY_TRUST_ANCHORS_FILE, e);
+                EnvError::InvalidEnvVar
+            }),
+        },
+    };
     let dir = parse(strings, ENV_IDENTITY_DIR, |ref s| Ok(PathBuf::from(s)));
     let tok = parse(strings, ENV_IDENTITY_TOKEN_FILE, |ref s| {
         identity::TokenSource::if_nonempty_file(s.to_string()).map_err(|e| {
@@ -972,12 +1500,28 @@ pub fn parse_identity_config<S: Strings>(
     let li = parse(strings, ENV_IDENTITY_IDENTITY_LOCAL_NAME, parse_identity);
     let min_refresh = parse(strings, ENV_IDENTITY_MIN_REFRESH, parse_duration);
     let max_refresh = parse(strings, ENV_IDENTITY_MAX_REFRESH, parse_duration);
+    let crt_file = parse(strings, ENV_IDENTITY_CRT_FILE, |s| Ok(PathBuf::from(s)));
+    let key_file = parse(strings, ENV_IDENTITY_KEY_FILE, |s| Ok(PathBuf::from(s)));
 
     let disabled = strings
         .get(ENV_IDENTITY_DISABLED)?
         .map(|d| !d.is_empty())
         .unwrap_or(false);
 
+    let files = match (crt_file?, key_file?) {
+        (None, None) => None,
+        (Some(crt), Some(key)) => Some((crt, key)),
+        (crt, _) => {
+            let (set, unset) = if crt.is_some() {
+                (ENV_IDENTITY_CRT_FILE, ENV_IDENTITY_KEY_FILE)
+            } else {
+                (ENV_IDENTITY_KEY_FILE, ENV_IDENTITY_CRT_FILE)
+            };
+            error!("{} must be set when {} is set.", unset, set);
+            return Err(EnvError::InvalidEnvVar);
+        }
+    };
+
     match (
         disabled,
         control?,
@@ -987,8 +1531,9 @@ pub fn parse_identity_config<S: Strings>(
         tok?,
         min_refresh?,
         max_refresh?,
+        files,
     ) {
-        (disabled, None, None, None, None, None, None, None) => {
+        (disabled, None, None, None, None, None, None, None, None) => {
             if !disabled {
                 error!(
                     "{} must be set or identity configuration must be specified.",
@@ -999,6 +1544,24 @@ pub fn parse_identity_config<S: Strings>(
                 Ok(None)
             }
         }
+        (
+            false,
+            None,
+            Some(trust_anchors),
+            None,
+            Some(local_name),
+            None,
+            _,
+            _,
+            Some((crt, key)),
+        ) => Ok(Some(IdentitySource::Files(identity::file::Config {
+            trust_anchors,
+            trust_anchors_file: ta_file,
+            local_name,
+            crt,
+            key,
+            interval: file_interval,
+        }))),
         (
             false,
             Some(control),
@@ -1008,6 +1571,7 @@ pub fn parse_identity_config<S: Strings>(
             Some(token),
             min_refresh,
             max_refresh,
+            None,
         ) => {
             let key = {
                 let mut p = dir.clone();
@@ -1045,12 +1609,13 @@ pub fn parse_identity_config<S: Strings>(
                     })
             };
 
-            Ok(Some((
+            Ok(Some(IdentitySource::Service(
                 control,
                 identity::certify::Config {
                     local_name,
                     token,
                     trust_anchors,
+                    trust_anchors_file: ta_file,
                     csr: csr?,
                     key: key?,
                     min_refresh: min_refresh.unwrap_or(DEFAULT_IDENTITY_MIN_REFRESH),
@@ -1058,7 +1623,7 @@ pub fn parse_identity_config<S: Strings>(
                 },
             )))
         }
-        (disabled, addr, trust_anchors, end_entity_dir, local_id, token, _minr, _maxr) => {
+        (disabled, addr, trust_anchors, end_entity_dir, local_id, token, _minr, _maxr, files) => {
             if disabled {
                 error!(
                     "{} must be unset when other identity variables are set.",
@@ -1067,6 +1632,32 @@ pub fn parse_identity_config<S: Strings>(
             }
             let s = format!("{0}_ADDR and {0}_NAME", ENV_IDENTITY_SVC_BASE);
             let svc_env: &str = &s.as_str();
+            if files.is_some() {
+                for (set, name) in &[
+                    (addr.is_some(), svc_env),
+                    (end_entity_dir.is_some(), ENV_IDENTITY_DIR),
+                    (token.is_some(), ENV_IDENTITY_TOKEN_FILE),
+                ] {
+                    if *set {
+                        error!(
+                            "{} must be unset when {} is set.",
+                            name, ENV_IDENTITY_CRT_FILE,
+                        );
+                    }
+                }
+                for (unset, name) in &[
+                    (trust_anchors.is_none(), ENV_IDENTITY_TRUST_ANCHORS),
+                    (local_id.is_none(), ENV_IDENTITY_IDENTITY_LOCAL_NAME),
+                ] {
+                    if *unset {
+                        error!(
+                            "{} must be set when {} is set.",
+                            name, ENV_IDENTITY_CRT_FILE,
+                        );
+                    }
+                }
+                return Err(EnvError::InvalidEnvVar);
+            }
             for (unset, name) in &[
                 (addr.is_none(), svc_env),
                 (trust_anchors.is_none(), ENV_IDENTITY_TRUST_ANCHORS),
@@ -1257,4 +1848,41 @@ mod tests {
             "names are coerced to lowercase"
         );
     }
+
+    #[test]
+    fn identities() {
+        fn p(s: &str) -> Result<String, ParseError> {
+            parse_identity(s).map(|n| n.to_string())
+        }
+
+        assert_eq!(
+            p("web.default.serviceaccount.identity.linkerd.cluster.local"),
+            Ok("web.default.serviceaccount.identity.linkerd.cluster.local".to_owned())
+        );
+        assert_eq!(
+            p("spiffe://cluster.local/ns/default/sa/web"),
+            Ok("spiffe://cluster.local/ns/default/sa/web".to_owned())
+        );
+        assert_eq!(
+            p("spiffe://cluster.local/ns/default/sa/web/"),
+            Err(ParseError::NameError),
+            "trailing slashes are not allowed in SPIFFE IDs"
+        );
+        assert_eq!(p("web.default."), Err(ParseError::NameError));
+    }
+
+    #[test]
+    fn parse_rate_limit_key_valid_and_invalid() {
+        use inbound::rate_limit::Key;
+        assert_eq!(parse_rate_limit_key("route"), Ok(Key::Route));
+        assert_eq!(parse_rate_limit_key("client"), Ok(Key::Client));
+        assert_eq!(
+            parse_rate_limit_key("route+client"),
+            Ok(Key::RouteAndClient)
+        );
+        assert_eq!(
+            parse_rate_limit_key("client+route"),
+            Err(ParseError::InvalidRateLimitKey)
+        );
+    }
 }
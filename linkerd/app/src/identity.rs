@@ -1,5 +1,6 @@
 pub use linkerd2_app_core::proxy::identity::{
-    certify, metrics, Crt, CrtKey, Csr, InvalidName, Key, Local, Name, TokenSource, TrustAnchors,
+    certify, file, metrics, trust_anchors, Crt, CrtKey, Csr, InvalidName, Key, Local, Name,
+    TokenSource, TrustAnchors,
 };
 use linkerd2_app_core::{
     control, dns,
@@ -21,6 +22,7 @@ pub enum Config {
         control: control::Config,
         certify: certify::Config,
     },
+    Files(file::Config),
 }
 
 // The Disabled case is extraordinarily rare.
@@ -28,7 +30,9 @@ pub enum Config {
 pub enum Identity {
     Disabled,
     Enabled {
-        addr: control::ControlAddr,
+        /// The Identity service's address, unless certificates are read from
+        /// files.
+        addr: Option<control::ControlAddr>,
         local: Local,
         task: Task,
     },
@@ -49,8 +53,10 @@ impl Config {
                 let (local, daemon) = Local::new(&certify);
 
                 let addr = control.addr.clone();
+                // The Identity service is verified with the current trust
+                // anchors, as they are reloaded.
                 let svc =
-                    control.build(dns, metrics, tls::Conditional::Some(certify.trust_anchors));
+                    control.build(dns, metrics, tls::Conditional::Some(local.trust_anchors()));
 
                 // Save to be spawned on an auxiliary runtime.
                 let task = {
@@ -61,7 +67,26 @@ impl Config {
                     })
                 };
 
-                Ok(Identity::Enabled { addr, local, task })
+                Ok(Identity::Enabled {
+                    addr: Some(addr),
+                    local,
+                    task,
+                })
+            }
+            Config::Files(files) => {
+                let (local, daemon) = Local::from_files(&files);
+
+                // Save to be spawned on an auxiliary runtime.
+                let task = Box::pin(async move {
+                    debug!(crt = %files.crt.display(), key = %files.key.display(), "running");
+                    daemon.run().await
+                });
+
+                Ok(Identity::Enabled {
+                    addr: None,
+                    local,
+                    task,
+                })
             }
         }
     }
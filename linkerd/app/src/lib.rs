@@ -56,7 +56,7 @@ pub struct Config {
 pub struct App {
     admin: admin::Admin,
     drain: drain::Signal,
-    dst: ControlAddr,
+    dst: Option<ControlAddr>,
     identity: identity::Identity,
     inbound_addr: SocketAddr,
     oc_collector: oc_collector::OcCollector,
@@ -110,6 +110,7 @@ impl Config {
             let dns = dns.resolver.clone();
             info_span!("dst").in_scope(|| dst.build(dns, metrics, identity.local()))
         }?;
+        let report = report.and_then(dst.snapshots.clone());
 
         let oc_collector = {
             let identity = identity.local();
@@ -146,7 +147,7 @@ impl Config {
             info!(listen.addr = %outbound_addr, ingress_mode);
 
             let outbound_http = outbound::http::logical::stack(
-                &outbound.proxy,
+                &outbound,
                 outbound::http::endpoint::stack(
                     &outbound.proxy.connect,
                     outbound::tcp::connect::stack(
@@ -278,8 +279,8 @@ impl App {
         }
     }
 
-    pub fn dst_addr(&self) -> &ControlAddr {
-        &self.dst
+    pub fn dst_addr(&self) -> Option<&ControlAddr> {
+        self.dst.as_ref()
     }
 
     pub fn local_identity(&self) -> Option<&identity::Local> {
@@ -292,7 +293,7 @@ impl App {
     pub fn identity_addr(&self) -> Option<&ControlAddr> {
         match self.identity {
             identity::Identity::Disabled => None,
-            identity::Identity::Enabled { ref addr, .. } => Some(addr),
+            identity::Identity::Enabled { ref addr, .. } => addr.as_ref(),
         }
     }
 
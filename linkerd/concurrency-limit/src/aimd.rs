@@ -0,0 +1,209 @@
+use linkerd2_metrics::Gauge;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+/// Configures a concurrency limit that adapts to observed latency by additive
+/// increase, multiplicative decrease (AIMD).
+#[derive(Clone, Debug)]
+pub struct Aimd {
+    /// The limit is never decreased below this value. The limit starts here.
+    pub min_limit: usize,
+
+    /// Responses that take longer than this decrease the limit.
+    pub latency_threshold: Duration,
+
+    /// The ratio by which the limit is multiplied when it decreases.
+    pub backoff_ratio: f64,
+}
+
+/// Resizes a semaphore as responses complete.
+///
+/// The limit increases by one permit for each timely response while at least
+/// half of the permits are in use, up to a maximum. It decreases by the
+/// backoff ratio when a response exceeds the latency threshold, at most once
+/// per threshold so that a burst of slow responses does not collapse the
+/// limit.
+#[derive(Debug)]
+pub(crate) struct Controller {
+    config: Aimd,
+    max_limit: usize,
+    semaphore: Arc<Semaphore>,
+    state: Mutex<State>,
+    gauge: Arc<Gauge>,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    /// Permits that must be retired (rather than released) to shrink the
+    /// semaphore to the limit.
+    debt: usize,
+    decreased_at: Option<Instant>,
+}
+
+// === impl Aimd ===
+
+impl Aimd {
+    pub const DEFAULT_BACKOFF_RATIO: f64 = 0.9;
+}
+
+// === impl Controller ===
+
+impl Controller {
+    pub(crate) fn new(config: Aimd, max_limit: usize, gauge: Arc<Gauge>) -> Self {
+        let limit = config.min_limit.min(max_limit).max(1);
+        gauge.set(limit as u64);
+        Self {
+            config,
+            max_limit,
+            semaphore: Arc::new(Semaphore::new(limit)),
+            state: Mutex::new(State {
+                limit,
+                debt: 0,
+                decreased_at: None,
+            }),
+            gauge,
+        }
+    }
+
+    pub(crate) fn semaphore(&self) -> Arc<Semaphore> {
+        self.semaphore.clone()
+    }
+
+    /// Releases a permit once its response completes, adjusting the limit by
+    /// the response's latency.
+    pub(crate) fn release(&self, permit: OwnedSemaphorePermit, latency: Duration) {
+        self.release_at(permit, latency, Instant::now())
+    }
+
+    fn release_at(&self, permit: OwnedSemaphorePermit, latency: Duration, now: Instant) {
+        let mut state = self.state.lock().expect("concurrency limit state poisoned");
+        let prior = state.limit;
+
+        if latency > self.config.latency_threshold {
+            let cooling = state
+                .decreased_at
+                .map(|t| now.saturating_duration_since(t) < self.config.latency_threshold)
+                .unwrap_or(false);
+            if !cooling {
+                let limit = (state.limit as f64 * self.config.backoff_ratio) as usize;
+                let limit = limit.max(self.config.min_limit).max(1);
+                state.debt += state.limit - limit;
+                state.limit = limit;
+                state.decreased_at = Some(now);
+            }
+        } else if state.limit < self.max_limit {
+            // Permits that are still outstanding, including this one.
+            let in_flight =
+                (state.limit + state.debt).saturating_sub(self.semaphore.available_permits());
+            if in_flight * 2 >= state.limit {
+                state.limit += 1;
+                if state.debt > 0 {
+                    state.debt -= 1;
+                } else {
+                    self.semaphore.add_permits(1);
+                }
+            }
+        }
+
+        if state.debt > 0 {
+            state.debt -= 1;
+            permit.forget();
+        } else {
+            drop(permit);
+        }
+        // Retire idle permits so that the limit applies immediately.
+        while state.debt > 0 {
+            match self.semaphore.try_acquire() {
+                Ok(idle) => {
+                    idle.forget();
+                    state.debt -= 1;
+                }
+                Err(_) => break,
+            }
+        }
+
+        if state.limit != prior {
+            debug!(limit = state.limit, ?latency, "Adjusted concurrency limit");
+            self.gauge.set(state.limit as u64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(min_limit: usize, max_limit: usize) -> Controller {
+        let config = Aimd {
+            min_limit,
+            latency_threshold: Duration::from_millis(100),
+            backoff_ratio: 0.5,
+        };
+        Controller::new(config, max_limit, Arc::new(Gauge::default()))
+    }
+
+    fn acquire(c: &Controller) -> OwnedSemaphorePermit {
+        c.semaphore()
+            .try_acquire_owned()
+            .expect("permit must be available")
+    }
+
+    #[test]
+    fn increases_while_busy() {
+        let c = controller(4, 5);
+        assert_eq!(c.gauge.value(), 4);
+
+        // Only one of four permits is in use, so the limit is not increased...
+        let p = acquire(&c);
+        c.release(p, Duration::from_millis(1));
+        assert_eq!(c.gauge.value(), 4);
+
+        // ...until at least half of the permits are in use.
+        let p0 = acquire(&c);
+        let p1 = acquire(&c);
+        c.release(p0, Duration::from_millis(1));
+        assert_eq!(c.gauge.value(), 5);
+        assert_eq!(c.semaphore.available_permits(), 4);
+
+        // The limit does not exceed the maximum.
+        let p0 = acquire(&c);
+        c.release(p0, Duration::from_millis(1));
+        c.release(p1, Duration::from_millis(1));
+        assert_eq!(c.gauge.value(), 5);
+        assert_eq!(c.semaphore.available_permits(), 5);
+    }
+
+    #[test]
+    fn decreases_when_slow() {
+        // Start above the minimum limit.
+        let mut c = controller(8, 100);
+        c.config.min_limit = 2;
+
+        let now = Instant::now();
+        let permits = (0..4).map(|_| acquire(&c)).collect::<Vec<_>>();
+        let mut permits = permits.into_iter();
+        c.release_at(permits.next().unwrap(), Duration::from_secs(1), now);
+        assert_eq!(c.gauge.value(), 4);
+        // Idle permits are retired immediately, so only one more permit may
+        // be acquired while three are outstanding.
+        assert_eq!(c.semaphore.available_permits(), 1);
+
+        // Slow responses do not decrease the limit again until the threshold
+        // has elapsed.
+        c.release_at(permits.next().unwrap(), Duration::from_secs(1), now);
+        assert_eq!(c.gauge.value(), 4);
+        c.release_at(
+            permits.next().unwrap(),
+            Duration::from_secs(1),
+            now + Duration::from_millis(200),
+        );
+        assert_eq!(c.gauge.value(), 2);
+        c.release(permits.next().unwrap(), Duration::from_secs(1));
+        assert_eq!(c.semaphore.available_permits(), 2);
+    }
+}
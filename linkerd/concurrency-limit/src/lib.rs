@@ -3,9 +3,16 @@
 //! Modified from tower-concurrency-limit so that the Layer holds a semaphore
 //! and, therefore, so that the limit applies across all services created by
 //! this layer.
+//!
+//! The limit may be fixed or it may adapt to the latency of responses.
 
 #![deny(warnings, rust_2018_idioms)]
 
+mod aimd;
+
+pub use self::aimd::Aimd;
+use self::aimd::Controller;
+use linkerd2_metrics::Gauge;
 use linkerd2_stack::layer;
 use pin_project::pin_project;
 use std::{
@@ -15,6 +22,7 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::Instant,
 };
 use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tower::Service;
@@ -25,6 +33,7 @@ use tracing::trace;
 pub struct ConcurrencyLimit<T> {
     inner: T,
     semaphore: Arc<Semaphore>,
+    controller: Option<Arc<Controller>>,
     state: State,
 }
 
@@ -42,19 +51,45 @@ pub struct ResponseFuture<T> {
     inner: T,
     // The permit is held until the future becomes ready.
     permit: Option<OwnedSemaphorePermit>,
+    // Adaptive limits are adjusted by the response's latency.
+    adapt: Option<(Arc<Controller>, Instant)>,
 }
 
 impl<S> ConcurrencyLimit<S> {
     /// Create a new concurrency-limiting layer.
     pub fn layer(limit: usize) -> impl layer::Layer<S, Service = Self> + Clone {
         let semaphore = Arc::new(Semaphore::new(limit));
-        layer::mk(move |inner| Self::new(inner, semaphore.clone()))
+        layer::mk(move |inner| Self::new(inner, semaphore.clone(), None))
+    }
+
+    /// Create a new concurrency-limiting layer that never allows more than
+    /// `max_limit` requests in flight.
+    ///
+    /// When `adaptive` is set, the limit adapts to the latency of responses
+    /// between its minimum and `max_limit`. The current limit is recorded in
+    /// `gauge`.
+    pub fn adaptive_layer(
+        max_limit: usize,
+        adaptive: Option<Aimd>,
+        gauge: Arc<Gauge>,
+    ) -> impl layer::Layer<S, Service = Self> + Clone {
+        let controller =
+            adaptive.map(|config| Arc::new(Controller::new(config, max_limit, gauge.clone())));
+        let semaphore = match controller.as_ref() {
+            Some(c) => c.semaphore(),
+            None => {
+                gauge.set(max_limit as u64);
+                Arc::new(Semaphore::new(max_limit))
+            }
+        };
+        layer::mk(move |inner| Self::new(inner, semaphore.clone(), controller.clone()))
     }
 
-    fn new(inner: S, semaphore: Arc<Semaphore>) -> Self {
+    fn new(inner: S, semaphore: Arc<Semaphore>, controller: Option<Arc<Controller>>) -> Self {
         ConcurrencyLimit {
             inner,
             semaphore,
+            controller,
             state: State::Empty,
         }
     }
@@ -95,10 +130,16 @@ where
             _ => panic!("max requests in-flight; poll_ready must be called first"),
         };
 
+        let adapt = self.controller.clone().map(|c| (c, Instant::now()));
+
         // Call the inner service
         let inner = self.inner.call(request);
 
-        ResponseFuture { inner, permit }
+        ResponseFuture {
+            inner,
+            permit,
+            adapt,
+        }
     }
 }
 
@@ -110,6 +151,7 @@ where
         ConcurrencyLimit {
             inner: self.inner.clone(),
             semaphore: self.semaphore.clone(),
+            controller: self.controller.clone(),
             state: State::Empty,
         }
     }
@@ -124,11 +166,14 @@ where
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
         let res = futures::ready!(this.inner.poll(cx));
-        let released = this.permit.take().is_some();
+        let permit = this.permit.take();
         debug_assert!(
-            released,
+            permit.is_some(),
             "Permit must be released when the future completes"
         );
+        if let (Some(permit), Some((controller, start))) = (permit, this.adapt.take()) {
+            controller.release(permit, start.elapsed());
+        }
         trace!("permit released");
         Poll::Ready(res)
     }
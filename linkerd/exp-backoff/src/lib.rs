@@ -65,6 +65,20 @@ impl ExponentialBackoff {
         Ok(ExponentialBackoff { min, max, jitter })
     }
 
+    /// Returns a jittered delay for an operation that has already been
+    /// attempted `iterations` times.
+    ///
+    /// This is useful when the caller tracks attempts itself rather than
+    /// using an `ExponentialBackoffStream`.
+    pub fn delay(&self, iterations: u32) -> Duration {
+        self.jittered(iterations, &mut thread_rng())
+    }
+
+    fn jittered<R: rand::Rng>(&self, iterations: u32, rng: &mut R) -> Duration {
+        let base = self.base(iterations);
+        base + self.jitter(base, rng)
+    }
+
     fn base(&self, iterations: u32) -> Duration {
         debug_assert!(
             self.min <= self.max,
@@ -116,10 +130,7 @@ impl Stream for ExponentialBackoffStream {
                 return Poll::Ready(None);
             }
 
-            let backoff = {
-                let base = this.backoff.base(*this.iterations);
-                base + this.backoff.jitter(base, &mut this.rng)
-            };
+            let backoff = this.backoff.jittered(*this.iterations, &mut this.rng);
             this.delay.as_mut().set(Some(time::sleep(backoff)));
         }
     }
@@ -161,6 +172,17 @@ mod tests {
             TestResult::from_bool(min <= delay && delay <= max)
         }
 
+        fn backoff_delay(min_ms: u64, max_ms: u64, jitter: f64, iterations: u32) -> TestResult {
+            let min = Duration::from_millis(min_ms);
+            let max = Duration::from_millis(max_ms);
+            let backoff = match ExponentialBackoff::new(min, max, jitter) {
+                Err(_) => return TestResult::discard(),
+                Ok(backoff) => backoff,
+            };
+            let delay = backoff.delay(iterations);
+            TestResult::from_bool(min <= delay && delay <= max)
+        }
+
         fn backoff_jitter(base_ms: u64, max_ms: u64, jitter: f64) -> TestResult {
             let base = Duration::from_millis(base_ms);
             let max = Duration::from_millis(max_ms);
@@ -0,0 +1,158 @@
+use super::{LastUpdate, Prefixed, Registry, Report};
+use linkerd2_metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics, Metric};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+#[derive(Debug)]
+pub struct CircuitBreakers<T>(Arc<Mutex<Registry<T, Metrics>>>)
+where
+    T: Hash + Eq;
+
+#[derive(Clone, Debug)]
+pub struct Handle(Arc<Mutex<Metrics>>);
+
+#[derive(Debug)]
+pub struct Metrics {
+    last_update: Instant,
+    opened: Counter,
+    half_opened: Counter,
+    closed: Counter,
+}
+
+/// The state a circuit breaker transitioned into.
+struct StateLabel(&'static str);
+
+// === impl CircuitBreakers ===
+
+impl<T: Hash + Eq> Default for CircuitBreakers<T> {
+    fn default() -> Self {
+        CircuitBreakers(Arc::new(Mutex::new(Registry::default())))
+    }
+}
+
+impl<T: Hash + Eq> CircuitBreakers<T> {
+    pub fn into_report(self, retain_idle: Duration) -> Report<T, Metrics> {
+        Report::new(retain_idle, self.0)
+    }
+
+    pub fn get_handle(&self, target: impl Into<T>) -> Handle {
+        let mut reg = self
+            .0
+            .lock()
+            .expect("circuit breaker metrics registry poisoned");
+        Handle(reg.entry(target.into()).or_default().clone())
+    }
+}
+
+impl<T: Hash + Eq> Clone for CircuitBreakers<T> {
+    fn clone(&self) -> Self {
+        CircuitBreakers(self.0.clone())
+    }
+}
+
+// === impl Handle ===
+
+impl Handle {
+    /// Records that a circuit opened, so that requests are failed.
+    pub fn incr_opened(&self) {
+        self.incr(|m| &mut m.opened);
+    }
+
+    /// Records that an open circuit began to admit probe requests.
+    pub fn incr_half_opened(&self) {
+        self.incr(|m| &mut m.half_opened);
+    }
+
+    /// Records that a circuit closed, so that all requests are admitted.
+    pub fn incr_closed(&self) {
+        self.incr(|m| &mut m.closed);
+    }
+
+    fn incr(&self, counter: impl FnOnce(&mut Metrics) -> &mut Counter) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            counter(&mut *m).incr();
+        }
+    }
+}
+
+// === impl Metrics ===
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            last_update: Instant::now(),
+            opened: Counter::default(),
+            half_opened: Counter::default(),
+            closed: Counter::default(),
+        }
+    }
+}
+
+impl LastUpdate for Metrics {
+    fn last_update(&self) -> Instant {
+        self.last_update
+    }
+}
+
+// === impl Report ===
+
+impl<T> Report<T, Metrics>
+where
+    T: FmtLabels + Hash + Eq,
+{
+    fn circuit_breaker_transitions_total(&self) -> Metric<'_, Prefixed<'_, &'static str>, Counter> {
+        Metric::new(
+            self.prefix_key("circuit_breaker_transitions_total"),
+            "Total count of times a circuit breaker changed state.",
+        )
+    }
+}
+
+impl<T> FmtMetrics for Report<T, Metrics>
+where
+    T: FmtLabels + Hash + Eq,
+{
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut registry = match self.registry.lock() {
+            Err(_) => return Ok(()),
+            Ok(r) => r,
+        };
+        trace!(
+            prefix = %self.prefix,
+            targets = %registry.len(),
+            "Formatting HTTP circuit breaker metrics",
+        );
+
+        if registry.is_empty() {
+            return Ok(());
+        }
+
+        let metric = self.circuit_breaker_transitions_total();
+        metric.fmt_help(f)?;
+        for (tgt, tm) in registry.iter() {
+            if let Ok(m) = tm.lock() {
+                for (state, counter) in &[
+                    ("open", &m.opened),
+                    ("half-open", &m.half_opened),
+                    ("closed", &m.closed),
+                ] {
+                    counter.fmt_metric_labeled(f, &metric.name, (tgt, StateLabel(*state)))?;
+                }
+            }
+        }
+
+        registry.retain_since(Instant::now() - self.retain_idle);
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for StateLabel {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "state=\"{}\"", self.0)
+    }
+}
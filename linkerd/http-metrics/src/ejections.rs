@@ -0,0 +1,122 @@
+use super::{LastUpdate, Prefixed, Registry, Report};
+use linkerd2_metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics, Metric};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+#[derive(Debug)]
+pub struct Ejections<T>(Arc<Mutex<Registry<T, Metrics>>>)
+where
+    T: Hash + Eq;
+
+#[derive(Clone, Debug)]
+pub struct Handle(Arc<Mutex<Metrics>>);
+
+#[derive(Debug)]
+pub struct Metrics {
+    last_update: Instant,
+    ejections: Counter,
+}
+
+// === impl Ejections ===
+
+impl<T: Hash + Eq> Default for Ejections<T> {
+    fn default() -> Self {
+        Ejections(Arc::new(Mutex::new(Registry::default())))
+    }
+}
+
+impl<T: Hash + Eq> Ejections<T> {
+    pub fn into_report(self, retain_idle: Duration) -> Report<T, Metrics> {
+        Report::new(retain_idle, self.0)
+    }
+
+    pub fn get_handle(&self, target: impl Into<T>) -> Handle {
+        let mut reg = self.0.lock().expect("ejection metrics registry poisoned");
+        Handle(reg.entry(target.into()).or_default().clone())
+    }
+}
+
+impl<T: Hash + Eq> Clone for Ejections<T> {
+    fn clone(&self) -> Self {
+        Ejections(self.0.clone())
+    }
+}
+
+// === impl Handle ===
+
+impl Handle {
+    /// Records that an endpoint was ejected from its load balancer.
+    pub fn incr_ejected(&self) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.ejections.incr();
+        }
+    }
+}
+
+// === impl Metrics ===
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            last_update: Instant::now(),
+            ejections: Counter::default(),
+        }
+    }
+}
+
+impl LastUpdate for Metrics {
+    fn last_update(&self) -> Instant {
+        self.last_update
+    }
+}
+
+// === impl Report ===
+
+impl<T> Report<T, Metrics>
+where
+    T: FmtLabels + Hash + Eq,
+{
+    fn outlier_ejections_total(&self) -> Metric<'_, Prefixed<'_, &'static str>, Counter> {
+        Metric::new(
+            self.prefix_key("outlier_ejections_total"),
+            "Total count of times an endpoint was ejected from its load balancer.",
+        )
+    }
+}
+
+impl<T> FmtMetrics for Report<T, Metrics>
+where
+    T: FmtLabels + Hash + Eq,
+{
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut registry = match self.registry.lock() {
+            Err(_) => return Ok(()),
+            Ok(r) => r,
+        };
+        trace!(
+            prefix = %self.prefix,
+            targets = %registry.len(),
+            "Formatting HTTP ejection metrics",
+        );
+
+        if registry.is_empty() {
+            return Ok(());
+        }
+
+        let metric = self.outlier_ejections_total();
+        metric.fmt_help(f)?;
+        for (tgt, tm) in registry.iter() {
+            if let Ok(m) = tm.lock() {
+                m.ejections.fmt_metric_labeled(f, &metric.name, tgt)?;
+            }
+        }
+
+        registry.retain_since(Instant::now() - self.retain_idle);
+
+        Ok(())
+    }
+}
@@ -1,12 +1,18 @@
 #![deny(warnings, rust_2018_idioms)]
 
-pub use self::{requests::Requests, retries::Retries};
+pub use self::{
+    circuit_breakers::CircuitBreakers, ejections::Ejections, mirrors::Mirrors, requests::Requests,
+    retries::Retries,
+};
 use linkerd2_metrics::{LastUpdate, Store};
 use std::fmt;
 use std::hash::Hash;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+pub mod circuit_breakers;
+pub mod ejections;
+pub mod mirrors;
 pub mod requests;
 pub mod retries;
 
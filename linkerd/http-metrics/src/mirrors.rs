@@ -0,0 +1,196 @@
+use super::{LastUpdate, Prefixed, Registry, Report};
+use linkerd2_metrics::{Counter, FmtLabels, FmtMetric, FmtMetrics, Metric};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+#[derive(Debug)]
+pub struct Mirrors<T>(Arc<Mutex<Registry<T, Metrics>>>)
+where
+    T: Hash + Eq;
+
+#[derive(Clone, Debug)]
+pub struct Handle(Arc<Mutex<Metrics>>);
+
+#[derive(Debug)]
+pub struct Metrics {
+    last_update: Instant,
+    success: Counter,
+    failure: Counter,
+    body_too_large: Counter,
+    body_streaming: Counter,
+    in_flight_limit: Counter,
+}
+
+enum Label {
+    Success,
+    Failure,
+    BodyTooLarge,
+    BodyStreaming,
+    InFlightLimit,
+}
+
+// === impl Mirrors ===
+
+impl<T: Hash + Eq> Default for Mirrors<T> {
+    fn default() -> Self {
+        Mirrors(Arc::new(Mutex::new(Registry::default())))
+    }
+}
+
+impl<T: Hash + Eq> Mirrors<T> {
+    pub fn into_report(self, retain_idle: Duration) -> Report<T, Metrics> {
+        Report::new(retain_idle, self.0)
+    }
+
+    pub fn get_handle(&self, target: impl Into<T>) -> Handle {
+        let mut reg = self.0.lock().expect("mirror metrics registry poisoned");
+        Handle(reg.entry(target.into()).or_default().clone())
+    }
+}
+
+impl<T: Hash + Eq> Clone for Mirrors<T> {
+    fn clone(&self) -> Self {
+        Mirrors(self.0.clone())
+    }
+}
+
+// === impl Handle ===
+
+impl Handle {
+    /// Records the outcome of a mirrored request.
+    pub fn incr_result(&self, is_success: bool) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            if is_success {
+                m.success.incr();
+            } else {
+                m.failure.incr();
+            }
+        }
+    }
+
+    /// Records that a request was not mirrored because its body could not be
+    /// buffered.
+    pub fn incr_body_too_large(&self) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.body_too_large.incr();
+        }
+    }
+
+    /// Records that a request was not mirrored because its body was still
+    /// being sent when its response completed.
+    pub fn incr_body_streaming(&self) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.body_streaming.incr();
+        }
+    }
+
+    /// Records that a request was not mirrored because too many mirrored
+    /// requests were already in flight.
+    pub fn incr_in_flight_limit(&self) {
+        if let Ok(mut m) = self.0.lock() {
+            m.last_update = Instant::now();
+            m.in_flight_limit.incr();
+        }
+    }
+}
+
+// === impl Metrics ===
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            last_update: Instant::now(),
+            success: Counter::default(),
+            failure: Counter::default(),
+            body_too_large: Counter::default(),
+            body_streaming: Counter::default(),
+            in_flight_limit: Counter::default(),
+        }
+    }
+}
+
+impl LastUpdate for Metrics {
+    fn last_update(&self) -> Instant {
+        self.last_update
+    }
+}
+
+// === impl Report ===
+
+impl<T> Report<T, Metrics>
+where
+    T: FmtLabels + Hash + Eq,
+{
+    fn mirror_total(&self) -> Metric<'_, Prefixed<'_, &'static str>, Counter> {
+        Metric::new(
+            self.prefix_key("mirror_total"),
+            "Total count of HTTP requests mirrored to a shadow backend.",
+        )
+    }
+}
+
+impl<T> FmtMetrics for Report<T, Metrics>
+where
+    T: FmtLabels + Hash + Eq,
+{
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut registry = match self.registry.lock() {
+            Err(_) => return Ok(()),
+            Ok(r) => r,
+        };
+        trace!(
+            prefix = %self.prefix,
+            targets = %registry.len(),
+            "Formatting HTTP mirror metrics",
+        );
+
+        if registry.is_empty() {
+            return Ok(());
+        }
+
+        let metric = self.mirror_total();
+        metric.fmt_help(f)?;
+        for (tgt, tm) in registry.iter() {
+            if let Ok(m) = tm.lock() {
+                m.success
+                    .fmt_metric_labeled(f, &metric.name, (tgt, Label::Success))?;
+                m.failure
+                    .fmt_metric_labeled(f, &metric.name, (tgt, Label::Failure))?;
+                m.body_too_large
+                    .fmt_metric_labeled(f, &metric.name, (tgt, Label::BodyTooLarge))?;
+                m.body_streaming.fmt_metric_labeled(
+                    f,
+                    &metric.name,
+                    (tgt, Label::BodyStreaming),
+                )?;
+                m.in_flight_limit.fmt_metric_labeled(
+                    f,
+                    &metric.name,
+                    (tgt, Label::InFlightLimit),
+                )?;
+            }
+        }
+
+        registry.retain_since(Instant::now() - self.retain_idle);
+
+        Ok(())
+    }
+}
+
+impl FmtLabels for Label {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Label::Success => write!(f, "classification=\"success\""),
+            Label::Failure => write!(f, "classification=\"failure\""),
+            Label::BodyTooLarge => write!(f, "skipped=\"body_too_large\""),
+            Label::BodyStreaming => write!(f, "skipped=\"body_streaming\""),
+            Label::InFlightLimit => write!(f, "skipped=\"in_flight_limit\""),
+        }
+    }
+}
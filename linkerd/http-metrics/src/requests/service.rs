@@ -358,6 +358,21 @@ where
     }
 }
 
+/// Wraps a body without recording metrics for it, e.g. for requests that are
+/// sent on a route's behalf rather than by a client.
+impl<B, C> From<B> for RequestBody<B, C>
+where
+    B: Body,
+    C: Hash + Eq,
+{
+    fn from(inner: B) -> Self {
+        Self {
+            metrics: None,
+            inner,
+        }
+    }
+}
+
 impl<B, C> Default for ResponseBody<B, C>
 where
     B: Body + Default,
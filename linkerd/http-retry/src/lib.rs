@@ -0,0 +1,482 @@
+#![deny(warnings, rust_2018_idioms)]
+
+use bytes::{Buf, Bytes};
+use futures::ready;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use linkerd2_error::Error;
+use parking_lot::Mutex;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+mod peek_trailers;
+
+pub use self::peek_trailers::{PeekTrailers, PeekTrailersBody};
+
+/// Wraps an HTTP body so that it may be replayed, e.g. when a request is
+/// retried.
+///
+/// Data is buffered as it is read from the inner body, up to a maximum number
+/// of bytes. A clone of a `ReplayBody` first yields the data that has already
+/// been buffered and then continues to read from the inner body. Only one clone
+/// may read the body at a time.
+///
+/// If the body grows beyond the limit, its buffer is discarded and the body is
+/// *capped*: the clone that is reading the body continues to stream it
+/// normally, but other clones may no longer be replayed.
+///
+/// Once the body has been read completely, any number of clones may replay it
+/// concurrently.
+pub struct ReplayBody<B> {
+    /// The state shared by all clones, owned by this clone while it is being
+    /// read.
+    state: Option<State<B>>,
+    shared: Arc<Shared<B>>,
+
+    /// The number of chunks this clone has yielded.
+    pos: usize,
+}
+
+/// Indicates that a `ReplayBody` exceeded its buffer limit before this clone
+/// could be replayed.
+#[derive(Debug)]
+pub struct Capped(());
+
+/// Indicates that a `ReplayBody` was polled while another clone was still
+/// reading the body.
+#[derive(Debug)]
+pub struct Busy(());
+
+struct Shared<B> {
+    state: Mutex<Option<State<B>>>,
+    /// Set once the body has been read completely (unless it was capped), so
+    /// that clones may replay it without acquiring the state.
+    complete: Mutex<Option<Arc<Complete>>>,
+    is_capped: AtomicBool,
+    is_empty: bool,
+    size_hint: SizeHint,
+}
+
+struct State<B> {
+    /// The inner body, until its trailers have been read.
+    body: Option<Pin<Box<B>>>,
+    is_data_done: bool,
+    trailers: Option<HeaderMap>,
+
+    /// The total number of chunks read from the inner body.
+    chunks: usize,
+    buf: Vec<Bytes>,
+    buf_bytes: usize,
+    max_bytes: usize,
+}
+
+struct Complete {
+    buf: Vec<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+// === impl ReplayBody ===
+
+impl<B: Body> ReplayBody<B> {
+    /// Wraps `body`, buffering at most `max_bytes` of its data.
+    ///
+    /// If the body's size hint indicates that it is larger than `max_bytes`,
+    /// the body is capped immediately and no data is buffered.
+    pub fn new(body: B, max_bytes: usize) -> Self {
+        let size_hint = body.size_hint();
+        let is_empty = body.is_end_stream();
+        let is_capped = size_hint.lower() > max_bytes as u64;
+        Self {
+            state: Some(State {
+                body: Some(Box::pin(body)),
+                is_data_done: is_empty,
+                trailers: None,
+                chunks: 0,
+                buf: Vec::new(),
+                buf_bytes: 0,
+                max_bytes,
+            }),
+            shared: Arc::new(Shared {
+                state: Mutex::new(None),
+                complete: Mutex::new(if is_empty {
+                    Some(Arc::new(Complete {
+                        buf: Vec::new(),
+                        trailers: None,
+                    }))
+                } else {
+                    None
+                }),
+                is_capped: AtomicBool::new(is_capped),
+                is_empty,
+                size_hint,
+            }),
+            pos: 0,
+        }
+    }
+}
+
+impl<B> ReplayBody<B> {
+    /// Returns true if the body has exceeded its buffer limit and can no longer
+    /// be replayed.
+    pub fn is_capped(&self) -> bool {
+        self.shared.is_capped.load(Ordering::Acquire)
+    }
+
+    /// Returns true if the body has been read completely and buffered, so that
+    /// this clone may be replayed even while other clones are being read.
+    pub fn is_complete(&self) -> bool {
+        self.shared.complete.lock().is_some()
+    }
+
+    /// Returns the completely buffered body, unless this clone is the one
+    /// reading it.
+    fn completed(&self) -> Option<Arc<Complete>> {
+        if self.state.is_some() {
+            return None;
+        }
+        self.shared.complete.lock().clone()
+    }
+
+    /// Publishes the body once it has been read completely.
+    fn complete(state: &State<B>, shared: &Shared<B>) {
+        if shared.is_capped.load(Ordering::Acquire) {
+            return;
+        }
+        *shared.complete.lock() = Some(Arc::new(Complete {
+            buf: state.buf.clone(),
+            trailers: state.trailers.clone(),
+        }));
+    }
+
+    /// Takes ownership of the shared state if this clone does not already own
+    /// it.
+    fn acquire_state<'a>(
+        state: &'a mut Option<State<B>>,
+        shared: &Shared<B>,
+    ) -> Result<&'a mut State<B>, Busy> {
+        if state.is_none() {
+            *state = shared.state.lock().take();
+        }
+        state.as_mut().ok_or(Busy(()))
+    }
+}
+
+impl<B> Body for ReplayBody<B>
+where
+    B: Body,
+    B::Error: Into<Error>,
+{
+    type Data = Bytes;
+    type Error = Error;
+
+    fn poll_data(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Error>>> {
+        let this = self.get_mut();
+        if let Some(complete) = this.completed() {
+            let chunk = complete.buf.get(this.pos).cloned();
+            this.pos += 1;
+            return Poll::Ready(chunk.map(Ok));
+        }
+
+        let is_capped = this.shared.is_capped.load(Ordering::Acquire);
+        let state = Self::acquire_state(&mut this.state, &this.shared)?;
+
+        // Replay data that was buffered by a prior clone.
+        if this.pos < state.chunks {
+            if is_capped {
+                return Poll::Ready(Some(Err(Capped(()).into())));
+            }
+            let chunk = state.buf[this.pos].clone();
+            this.pos += 1;
+            return Poll::Ready(Some(Ok(chunk)));
+        }
+
+        let body = match state.body.as_mut() {
+            Some(body) if !state.is_data_done => body,
+            _ => return Poll::Ready(None),
+        };
+        let chunk = match ready!(body.as_mut().poll_data(cx)) {
+            Some(Ok(mut data)) => data.to_bytes(),
+            Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+            None => {
+                state.is_data_done = true;
+                // If no trailers are expected, the body is complete. Otherwise,
+                // read the trailers if they are already available, since some
+                // readers (e.g. HTTP/1 clients) never poll for them.
+                if body.is_end_stream() {
+                    state.body = None;
+                    Self::complete(state, &this.shared);
+                } else if let Poll::Ready(trailers) = body.as_mut().poll_trailers(cx) {
+                    state.body = None;
+                    state.trailers = trailers.map_err(Into::into)?;
+                    Self::complete(state, &this.shared);
+                }
+                return Poll::Ready(None);
+            }
+        };
+
+        state.chunks += 1;
+        if !is_capped {
+            if state.buf_bytes + chunk.len() > state.max_bytes {
+                tracing::debug!(max_bytes = state.max_bytes, "Body exceeds buffer limit");
+                state.buf = Vec::new();
+                state.buf_bytes = 0;
+                this.shared.is_capped.store(true, Ordering::Release);
+            } else {
+                state.buf_bytes += chunk.len();
+                state.buf.push(chunk.clone());
+            }
+        }
+        this.pos += 1;
+
+        Poll::Ready(Some(Ok(chunk)))
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Error>> {
+        let this = self.get_mut();
+        if let Some(complete) = this.completed() {
+            return Poll::Ready(Ok(complete.trailers.clone()));
+        }
+
+        let state = Self::acquire_state(&mut this.state, &this.shared)?;
+
+        let body = match state.body.as_mut() {
+            Some(body) => body,
+            None => return Poll::Ready(Ok(state.trailers.clone())),
+        };
+        let trailers = ready!(body.as_mut().poll_trailers(cx)).map_err(Into::into)?;
+        state.body = None;
+        state.is_data_done = true;
+        state.trailers = trailers.clone();
+        Self::complete(state, &this.shared);
+
+        Poll::Ready(Ok(trailers))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // Clones may need to replay data even after the inner body has
+        // completed, so only bodies that were empty to begin with are known to
+        // be complete.
+        self.shared.is_empty
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.shared.size_hint.clone()
+    }
+}
+
+impl<B> Clone for ReplayBody<B> {
+    fn clone(&self) -> Self {
+        Self {
+            state: None,
+            shared: self.shared.clone(),
+            pos: 0,
+        }
+    }
+}
+
+impl<B> Drop for ReplayBody<B> {
+    fn drop(&mut self) {
+        // Return the shared state so that it may be used by other clones.
+        if let Some(state) = self.state.take() {
+            *self.shared.state.lock() = Some(state);
+        }
+    }
+}
+
+impl<B> fmt::Debug for ReplayBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayBody")
+            .field("is_capped", &self.is_capped())
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+// === impl Capped ===
+
+impl fmt::Display for Capped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "body exceeded the replay buffer limit")
+    }
+}
+
+impl std::error::Error for Capped {}
+
+// === impl Busy ===
+
+impl fmt::Display for Busy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "body is being read by another request")
+    }
+}
+
+impl std::error::Error for Busy {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[derive(Default)]
+    struct TestBody {
+        data: VecDeque<&'static str>,
+        trailers: Option<HeaderMap>,
+    }
+
+    impl TestBody {
+        fn new(data: &[&'static str]) -> Self {
+            Self {
+                data: data.iter().copied().collect(),
+                trailers: None,
+            }
+        }
+    }
+
+    impl Body for TestBody {
+        type Data = Bytes;
+        type Error = Error;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Bytes, Error>>> {
+            Poll::Ready(self.data.pop_front().map(|s| Ok(Bytes::from(s))))
+        }
+
+        fn poll_trailers(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Error>> {
+            Poll::Ready(Ok(self.trailers.take()))
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.data.is_empty() && self.trailers.is_none()
+        }
+    }
+
+    async fn read_to_string<B: Body<Data = Bytes, Error = Error> + Unpin>(body: &mut B) -> String {
+        let mut s = String::new();
+        while let Some(chunk) = body.data().await {
+            s.push_str(std::str::from_utf8(&chunk.unwrap()[..]).unwrap());
+        }
+        s
+    }
+
+    #[tokio::test]
+    async fn replays_data_and_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let body = TestBody {
+            trailers: Some(trailers),
+            ..TestBody::new(&["hello", " ", "world"])
+        };
+
+        let mut initial = ReplayBody::new(body, 64 * 1024);
+        let mut replay = initial.clone();
+
+        assert_eq!(read_to_string(&mut initial).await, "hello world");
+        let t = initial.trailers().await.unwrap().expect("trailers");
+        assert_eq!(t.get("grpc-status").unwrap(), "0");
+        drop(initial);
+
+        assert!(!replay.is_capped());
+        assert_eq!(read_to_string(&mut replay).await, "hello world");
+        let t = replay.trailers().await.unwrap().expect("trailers");
+        assert_eq!(t.get("grpc-status").unwrap(), "0");
+    }
+
+    #[tokio::test]
+    async fn replays_partially_read_body() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", " ", "world"]), 64 * 1024);
+        let mut replay = initial.clone();
+
+        let chunk = initial.data().await.unwrap().unwrap();
+        assert_eq!(&chunk[..], b"hello");
+        drop(initial);
+
+        // The replay yields the buffered data and then reads the rest of the
+        // inner body.
+        assert_eq!(read_to_string(&mut replay).await, "hello world");
+    }
+
+    #[tokio::test]
+    async fn caps_large_bodies() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", " ", "world"]), 8);
+        let mut replay = initial.clone();
+
+        // The body is streamed normally even after exceeding the limit.
+        assert_eq!(read_to_string(&mut initial).await, "hello world");
+        assert!(initial.is_capped());
+        drop(initial);
+
+        assert!(replay.is_capped());
+        let err = replay.data().await.unwrap().unwrap_err();
+        assert!(err.is::<Capped>());
+    }
+
+    #[tokio::test]
+    async fn busy_while_read_by_another_clone() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", "world"]), 64 * 1024);
+        let mut replay = initial.clone();
+
+        let _ = initial.data().await.unwrap().unwrap();
+        let err = replay.data().await.unwrap().unwrap_err();
+        assert!(err.is::<Busy>());
+    }
+
+    #[tokio::test]
+    async fn replays_complete_body_concurrently() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        let body = TestBody {
+            trailers: Some(trailers),
+            ..TestBody::new(&["hello", " ", "world"])
+        };
+
+        let mut initial = ReplayBody::new(body, 64 * 1024);
+        let mut replay = initial.clone();
+        assert!(!replay.is_complete());
+
+        // The trailers are read as soon as the data ends, even if they are
+        // never polled.
+        assert_eq!(read_to_string(&mut initial).await, "hello world");
+        assert!(replay.is_complete());
+        let t = initial.trailers().await.unwrap().expect("trailers");
+        assert_eq!(t.get("grpc-status").unwrap(), "0");
+
+        // The initial body has not been dropped, but the replay may still be
+        // read.
+        assert_eq!(read_to_string(&mut replay).await, "hello world");
+        let t = replay.trailers().await.unwrap().expect("trailers");
+        assert_eq!(t.get("grpc-status").unwrap(), "0");
+        drop(initial);
+    }
+
+    #[tokio::test]
+    async fn capped_body_is_never_complete() {
+        let mut initial = ReplayBody::new(TestBody::new(&["hello", " ", "world"]), 8);
+        let replay = initial.clone();
+        assert_eq!(read_to_string(&mut initial).await, "hello world");
+        assert!(initial.trailers().await.unwrap().is_none());
+        assert!(!replay.is_complete());
+    }
+
+    #[test]
+    fn empty_body() {
+        let body = ReplayBody::new(TestBody::default(), 64 * 1024);
+        assert!(body.is_end_stream());
+        assert!(body.is_complete());
+        assert!(body.clone().is_end_stream());
+    }
+}
@@ -0,0 +1,290 @@
+use bytes::Buf;
+use futures::ready;
+use http::HeaderMap;
+use http_body::{Body, SizeHint};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Wraps an HTTP response body so that its trailers may be inspected before
+/// the body is read, e.g. so that a retry policy may classify a gRPC response
+/// by the `grpc-status` in its trailers.
+///
+/// Only the body's first data frame is read ahead of time. Trailers may only be
+/// read once the body's data has ended, so they are awaited if the body has no
+/// data; otherwise, they are only available if the first frame was the body's
+/// last and the trailers have already been received.
+pub struct PeekTrailersBody<B: Body> {
+    inner: Pin<Box<B>>,
+    is_data_peeked: bool,
+    first_data: Option<Result<B::Data, B::Error>>,
+    /// A frame that was already available when checking whether the first
+    /// frame was the body's last.
+    second_data: Option<Result<B::Data, B::Error>>,
+    /// Set once the inner body's data has ended, after which its trailers may
+    /// be read.
+    is_data_ended: bool,
+    trailers: Option<Result<Option<HeaderMap>, B::Error>>,
+}
+
+/// Reads ahead in a response's body, returning the response once its
+/// trailers may be inspected.
+pub struct PeekTrailers<B: Body> {
+    rsp: Option<http::Response<PeekTrailersBody<B>>>,
+}
+
+// === impl PeekTrailersBody ===
+
+impl<B: Body> PeekTrailersBody<B> {
+    /// Wraps a response body without reading ahead, so its trailers may not
+    /// be inspected.
+    pub fn new(body: B) -> Self {
+        Self {
+            inner: Box::pin(body),
+            is_data_peeked: true,
+            first_data: None,
+            second_data: None,
+            is_data_ended: false,
+            trailers: None,
+        }
+    }
+
+    /// Reads ahead in `rsp`'s body so that its trailers may be inspected.
+    pub fn peek_response(rsp: http::Response<B>) -> PeekTrailers<B> {
+        let rsp = rsp.map(|body| Self {
+            is_data_peeked: false,
+            ..Self::new(body)
+        });
+        PeekTrailers { rsp: Some(rsp) }
+    }
+
+    /// Returns the body's trailers, if they were read ahead of its data.
+    pub fn peek_trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()?.as_ref().ok()?.as_ref()
+    }
+
+    fn poll_peek(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.is_data_peeked {
+            if !self.inner.is_end_stream() {
+                self.first_data = ready!(self.inner.as_mut().poll_data(cx));
+            }
+            self.is_data_ended = self.first_data.is_none();
+            if let Some(Ok(_)) = self.first_data {
+                // Don't wait for the rest of the body, but check whether the
+                // first frame was its last.
+                match self.inner.as_mut().poll_data(cx) {
+                    Poll::Ready(Some(data)) => self.second_data = Some(data),
+                    Poll::Ready(None) => self.is_data_ended = true,
+                    Poll::Pending => {}
+                }
+            }
+            self.is_data_peeked = true;
+        }
+
+        if self.is_data_ended && self.trailers.is_none() {
+            if self.first_data.is_none() {
+                // The body has no data, so its trailers should follow
+                // immediately.
+                self.trailers = Some(ready!(self.inner.as_mut().poll_trailers(cx)));
+            } else if let Poll::Ready(trailers) = self.inner.as_mut().poll_trailers(cx) {
+                // Use the trailers if they are already available.
+                self.trailers = Some(trailers);
+            }
+        }
+
+        Poll::Ready(())
+    }
+}
+
+// The inner body is boxed and the buffered frames are never pinned.
+impl<B: Body> Unpin for PeekTrailersBody<B> {}
+
+impl<B: Body> Body for PeekTrailersBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.get_mut();
+        if let Some(data) = this.first_data.take() {
+            return Poll::Ready(Some(data));
+        }
+        if let Some(data) = this.second_data.take() {
+            return Poll::Ready(Some(data));
+        }
+        if this.is_data_ended {
+            return Poll::Ready(None);
+        }
+        this.inner.as_mut().poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let this = self.get_mut();
+        match this.trailers.take() {
+            Some(trailers) => Poll::Ready(trailers),
+            None => this.inner.as_mut().poll_trailers(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.first_data.is_none()
+            && self.second_data.is_none()
+            && self.trailers.is_none()
+            && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let mut hint = self.inner.size_hint();
+        let peeked = [&self.first_data, &self.second_data];
+        for data in peeked.iter().filter_map(|d| d.as_ref()?.as_ref().ok()) {
+            let len = data.remaining() as u64;
+            if let Some(upper) = hint.upper() {
+                hint.set_upper(upper + len);
+            }
+            hint.set_lower(hint.lower() + len);
+        }
+        hint
+    }
+}
+
+// === impl PeekTrailers ===
+
+impl<B: Body> Future for PeekTrailers<B> {
+    type Output = http::Response<PeekTrailersBody<B>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let rsp = this.rsp.as_mut().expect("polled after ready");
+        ready!(rsp.body_mut().poll_peek(cx));
+        Poll::Ready(this.rsp.take().expect("polled after ready"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use linkerd2_error::Error;
+    use std::collections::VecDeque;
+
+    #[derive(Default)]
+    struct TestBody {
+        data: VecDeque<&'static str>,
+        trailers: Option<HeaderMap>,
+    }
+
+    impl Body for TestBody {
+        type Data = Bytes;
+        type Error = Error;
+
+        fn poll_data(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Bytes, Error>>> {
+            Poll::Ready(self.data.pop_front().map(|s| Ok(Bytes::from(s))))
+        }
+
+        fn poll_trailers(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Result<Option<HeaderMap>, Error>> {
+            Poll::Ready(Ok(self.trailers.take()))
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.data.is_empty() && self.trailers.is_none()
+        }
+    }
+
+    fn grpc_status(code: &'static str) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", code.parse().unwrap());
+        trailers
+    }
+
+    async fn peek(body: TestBody) -> PeekTrailersBody<TestBody> {
+        PeekTrailersBody::peek_response(http::Response::new(body))
+            .await
+            .into_body()
+    }
+
+    #[tokio::test]
+    async fn peeks_trailers_only() {
+        let mut body = peek(TestBody {
+            trailers: Some(grpc_status("14")),
+            ..TestBody::default()
+        })
+        .await;
+
+        assert_eq!(
+            body.peek_trailers().and_then(|t| t.get("grpc-status")),
+            Some(&"14".parse().unwrap())
+        );
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().expect("trailers");
+        assert_eq!(trailers.get("grpc-status").unwrap(), "14");
+    }
+
+    #[tokio::test]
+    async fn peeks_trailers_after_data() {
+        let mut body = peek(TestBody {
+            data: vec!["hello"].into(),
+            trailers: Some(grpc_status("0")),
+        })
+        .await;
+
+        assert_eq!(
+            body.peek_trailers().and_then(|t| t.get("grpc-status")),
+            Some(&"0".parse().unwrap())
+        );
+        assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello");
+        assert!(body.data().await.is_none());
+        let trailers = body.trailers().await.unwrap().expect("trailers");
+        assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+    }
+
+    #[tokio::test]
+    async fn does_not_peek_unless_asked() {
+        let mut body = PeekTrailersBody::new(TestBody {
+            data: vec!["hello"].into(),
+            trailers: Some(grpc_status("0")),
+        });
+
+        assert!(body.peek_trailers().is_none());
+        assert_eq!(&body.data().await.unwrap().unwrap()[..], b"hello");
+        let trailers = body.trailers().await.unwrap().expect("trailers");
+        assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+    }
+
+    #[tokio::test]
+    async fn reads_every_chunk_of_http1_bodies() {
+        // HTTP/1 bodies have no trailers, so they must not be read until the
+        // body's data has ended.
+        let (mut tx, body) = hyper::Body::channel();
+        let send = tokio::spawn(async move {
+            for chunk in vec!["hello", " ", "world"] {
+                tx.send_data(Bytes::from(chunk)).await.unwrap();
+            }
+        });
+
+        let mut body = PeekTrailersBody::peek_response(http::Response::new(body))
+            .await
+            .into_body();
+        assert!(body.peek_trailers().is_none());
+
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap()[..]);
+        }
+        assert_eq!(&data[..], b"hello world");
+        send.await.unwrap();
+        assert!(body.trailers().await.unwrap().is_none());
+    }
+}
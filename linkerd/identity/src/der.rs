@@ -0,0 +1,203 @@
+//! Just enough DER to read an X.509 certificate's expiry and URI subject
+//! alternative names, which webpki validates and ignores, respectively.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use untrusted::{Input, Reader};
+
+const SEQUENCE: u8 = 0x30;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const OID: u8 = 0x06;
+const BOOLEAN: u8 = 0x01;
+const OCTET_STRING: u8 = 0x04;
+const EXPLICIT_VERSION: u8 = 0xa0;
+const EXPLICIT_EXTENSIONS: u8 = 0xa3;
+const URI_NAME: u8 = 0x86;
+
+// id-ce-subjectAltName (2.5.29.17)
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// Returns the time after which a DER-encoded certificate is no longer valid.
+pub(crate) fn not_after(crt: &[u8]) -> Option<SystemTime> {
+    // TBSCertificate ::= SEQUENCE { version, serialNumber, signature, issuer,
+    //     validity, ... }
+    let validity = tbs_certificate(crt)?
+        .read_all((), |r| {
+            if r.peek(EXPLICIT_VERSION) {
+                read_tlv(r)?;
+            }
+            for _ in 0..3 {
+                read_tlv(r)?;
+            }
+            let validity = expect(r, SEQUENCE)?;
+            r.skip_to_end();
+            Ok(validity)
+        })
+        .ok()?;
+
+    // Validity ::= SEQUENCE { notBefore Time, notAfter Time }
+    let (tag, time) = validity
+        .read_all((), |r| {
+            read_tlv(r)?;
+            read_tlv(r)
+        })
+        .ok()?;
+    parse_time(tag, time.as_slice_less_safe())
+}
+
+/// Returns the URIs among a DER-encoded certificate's subject alternative
+/// names.
+pub(crate) fn uri_sans(crt: &[u8]) -> Option<Vec<&[u8]>> {
+    // TBSCertificate ::= SEQUENCE { version, serialNumber, signature, issuer,
+    //     validity, subject, subjectPublicKeyInfo, issuerUniqueID,
+    //     subjectUniqueID, extensions [3] EXPLICIT Extensions }
+    let extensions = tbs_certificate(crt)?
+        .read_all((), |r| {
+            if r.peek(EXPLICIT_VERSION) {
+                read_tlv(r)?;
+            }
+            for _ in 0..6 {
+                read_tlv(r)?;
+            }
+            while !r.at_end() {
+                if let (EXPLICIT_EXTENSIONS, exts) = read_tlv(r)? {
+                    r.skip_to_end();
+                    return exts.read_all((), |r| expect(r, SEQUENCE)).map(Some);
+                }
+            }
+            Ok(None)
+        })
+        .ok()?;
+    let extensions = match extensions {
+        Some(extensions) => extensions,
+        None => return Some(Vec::new()),
+    };
+
+    // Extension ::= SEQUENCE { extnID OID, critical BOOLEAN DEFAULT FALSE,
+    //     extnValue OCTET STRING }
+    //
+    // GeneralNames ::= SEQUENCE OF GeneralName, where a URI is
+    // [6] IMPLICIT IA5String.
+    extensions
+        .read_all((), |r| {
+            let mut uris = Vec::new();
+            while !r.at_end() {
+                let (id, value) = expect(r, SEQUENCE)?.read_all((), |r| {
+                    let id = expect(r, OID)?;
+                    if r.peek(BOOLEAN) {
+                        read_tlv(r)?;
+                    }
+                    let value = expect(r, OCTET_STRING)?;
+                    Ok((id, value))
+                })?;
+                if id.as_slice_less_safe() != SUBJECT_ALT_NAME {
+                    continue;
+                }
+                let names = value.read_all((), |r| expect(r, SEQUENCE))?;
+                names.read_all((), |r| {
+                    while !r.at_end() {
+                        if let (URI_NAME, uri) = read_tlv(r)? {
+                            uris.push(uri.as_slice_less_safe());
+                        }
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(uris)
+        })
+        .ok()
+}
+
+// Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+fn tbs_certificate(crt: &[u8]) -> Option<Input<'_>> {
+    let crt = Input::from(crt)
+        .read_all((), |r| expect(r, SEQUENCE))
+        .ok()?;
+    crt.read_all((), |r| {
+        let tbs = expect(r, SEQUENCE)?;
+        r.skip_to_end();
+        Ok(tbs)
+    })
+    .ok()
+}
+
+fn read_tlv<'a>(r: &mut Reader<'a>) -> Result<(u8, Input<'a>), ()> {
+    let tag = r.read_byte().map_err(|_| ())?;
+    let len = match r.read_byte().map_err(|_| ())? {
+        n if n < 0x80 => usize::from(n),
+        n @ 0x81..=0x83 => {
+            let mut len = 0;
+            for _ in 0..(n & 0x7f) {
+                len = (len << 8) | usize::from(r.read_byte().map_err(|_| ())?);
+            }
+            len
+        }
+        _ => return Err(()),
+    };
+    let value = r.read_bytes(len).map_err(|_| ())?;
+    Ok((tag, value))
+}
+
+fn expect<'a>(r: &mut Reader<'a>, tag: u8) -> Result<Input<'a>, ()> {
+    match read_tlv(r)? {
+        (t, value) if t == tag => Ok(value),
+        _ => Err(()),
+    }
+}
+
+/// Parses a `UTCTime` (`YYMMDDHHMMSSZ`) or `GeneralizedTime`
+/// (`YYYYMMDDHHMMSSZ`), which RFC 5280 requires to be in UTC and to include
+/// seconds.
+fn parse_time(tag: u8, time: &[u8]) -> Option<SystemTime> {
+    let (year, time) = match (tag, time.len()) {
+        (UTC_TIME, 13) => {
+            let yy = digits(&time[..2])?;
+            let year = if yy < 50 { 2000 + yy } else { 1900 + yy };
+            (year, &time[2..])
+        }
+        (GENERALIZED_TIME, 15) => (digits(&time[..4])?, &time[4..]),
+        _ => return None,
+    };
+    if time[10] != b'Z' {
+        return None;
+    }
+
+    let month = digits(&time[0..2])?;
+    let day = digits(&time[2..4])?;
+    let hour = digits(&time[4..6])?;
+    let min = digits(&time[6..8])?;
+    let sec = digits(&time[8..10])?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || min > 59 || sec > 59 {
+        return None;
+    }
+
+    let days = days_since_epoch(year as i64, month as i64, day as i64);
+    let secs = days * 86_400 + (hour * 3_600 + min * 60 + sec) as i64;
+    if secs < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+fn digits(ds: &[u8]) -> Option<u64> {
+    ds.iter().try_fold(0, |n, d| {
+        if d.is_ascii_digit() {
+            Some(n * 10 + u64::from(d - b'0'))
+        } else {
+            None
+        }
+    })
+}
+
+/// Counts the days between the UNIX epoch and a date in the proleptic
+/// Gregorian calendar.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#days_from_civil.
+fn days_since_epoch(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
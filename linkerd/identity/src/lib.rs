@@ -2,10 +2,20 @@
 
 pub use ring::error::KeyRejected;
 use ring::rand;
-use ring::signature::EcdsaKeyPair;
-use std::{convert::TryFrom, error::Error, fmt, fs, io, str::FromStr, sync::Arc, time::SystemTime};
+use ring::signature::{self as sig, EcdsaKeyPair, Ed25519KeyPair};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    error::Error,
+    fmt, fs, io,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 use tracing::{debug, warn};
 
+mod der;
+pub mod spiffe;
 #[cfg(any(test, feature = "test-util"))]
 pub mod test_util;
 
@@ -15,18 +25,34 @@ pub use linkerd2_dns_name::InvalidName;
 #[derive(Clone, Debug)]
 pub struct Csr(Arc<Vec<u8>>);
 
-/// An endpoint's identity.
+/// An endpoint's identity: either a DNS-like name or a SPIFFE ID.
 #[derive(Clone, Eq, PartialEq, Hash)]
-pub struct Name(Arc<linkerd2_dns_name::Name>);
+pub struct Name(Arc<NameKind>);
+
+#[derive(Eq, PartialEq, Hash)]
+enum NameKind {
+    Dns(linkerd2_dns_name::Name),
+    Spiffe(spiffe::Id),
+}
 
 #[derive(Clone, Debug)]
-pub struct Key(Arc<EcdsaKeyPair>);
+pub struct Key(Arc<KeyPair>);
 
-struct SigningKey(Arc<EcdsaKeyPair>);
-struct Signer(Arc<EcdsaKeyPair>);
+/// A private key, which determines the algorithm used to sign handshakes.
+enum KeyPair {
+    Ecdsa(EcdsaKeyPair, &'static EcdsaAlg),
+    Ed25519(Ed25519KeyPair),
+}
+
+struct SigningKey(Arc<KeyPair>);
+struct Signer(Arc<KeyPair>);
 
 #[derive(Clone)]
-pub struct TrustAnchors(Arc<rustls::ClientConfig>);
+pub struct TrustAnchors {
+    config: Arc<rustls::ClientConfig>,
+    fingerprint: Arc<str>,
+    spiffe_configs: SpiffeConfigs,
+}
 
 #[derive(Clone, Debug)]
 pub struct TokenSource(Arc<String>);
@@ -44,21 +70,71 @@ pub struct CrtKey {
     expiry: SystemTime,
     client_config: Arc<rustls::ClientConfig>,
     server_config: Arc<rustls::ServerConfig>,
+    spiffe_configs: SpiffeConfigs,
 }
 
-struct CertResolver(rustls::sign::CertifiedKey);
+/// Caches the client configurations that verify servers' SPIFFE IDs, by ID,
+/// so that a configuration is not cloned for each connection.
+///
+/// The cache is held by the `CrtKey` or `TrustAnchors` whose configuration it
+/// extends, so it is discarded when they are replaced.
+#[derive(Clone, Default)]
+struct SpiffeConfigs(Arc<Mutex<HashMap<spiffe::Id, Arc<rustls::ClientConfig>>>>);
+
+struct CertResolver {
+    name: Name,
+    key: rustls::sign::CertifiedKey,
+    scheme: rustls::SignatureScheme,
+}
 
 #[derive(Clone, Debug)]
 pub struct InvalidCrt(rustls::TLSError);
 
-// These must be kept in sync:
-static SIGNATURE_ALG_RING_SIGNING: &ring::signature::EcdsaSigningAlgorithm =
-    &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING;
-const SIGNATURE_ALG_RUSTLS_SCHEME: rustls::SignatureScheme =
-    rustls::SignatureScheme::ECDSA_NISTP256_SHA256;
-const SIGNATURE_ALG_RUSTLS_ALGORITHM: rustls::internal::msgs::enums::SignatureAlgorithm =
-    rustls::internal::msgs::enums::SignatureAlgorithm::ECDSA;
-const TLS_VERSIONS: &[rustls::ProtocolVersion] = &[rustls::ProtocolVersion::TLSv1_2];
+/// An ECDSA key's signing algorithm, along with the corresponding TLS scheme
+/// and verification algorithm.
+type EcdsaAlg = (
+    &'static sig::EcdsaSigningAlgorithm,
+    rustls::SignatureScheme,
+    &'static webpki::SignatureAlgorithm,
+);
+
+// Supported ECDSA keys, in order of preference.
+static ECDSA_ALGS: &[EcdsaAlg] = &[
+    (
+        &sig::ECDSA_P256_SHA256_ASN1_SIGNING,
+        rustls::SignatureScheme::ECDSA_NISTP256_SHA256,
+        &webpki::ECDSA_P256_SHA256,
+    ),
+    (
+        &sig::ECDSA_P384_SHA384_ASN1_SIGNING,
+        rustls::SignatureScheme::ECDSA_NISTP384_SHA384,
+        &webpki::ECDSA_P384_SHA384,
+    ),
+];
+
+// Supported certificate signature algorithms, as accepted by Rustls's own
+// verifier.
+static SIG_ALGS: &[&webpki::SignatureAlgorithm] = &[
+    &webpki::ECDSA_P256_SHA256,
+    &webpki::ECDSA_P256_SHA384,
+    &webpki::ECDSA_P384_SHA256,
+    &webpki::ECDSA_P384_SHA384,
+    &webpki::ED25519,
+    &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+    &webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+    &webpki::RSA_PKCS1_2048_8192_SHA256,
+    &webpki::RSA_PKCS1_2048_8192_SHA384,
+    &webpki::RSA_PKCS1_2048_8192_SHA512,
+    &webpki::RSA_PKCS1_3072_8192_SHA384,
+];
+// TLS 1.3 is preferred, but TLS 1.2 is negotiated with peers that don't support
+// it unless TLS 1.3 is required.
+const TLS_VERSIONS: &[rustls::ProtocolVersion] = &[
+    rustls::ProtocolVersion::TLSv1_3,
+    rustls::ProtocolVersion::TLSv1_2,
+];
+const TLS13_VERSIONS: &[rustls::ProtocolVersion] = &[rustls::ProtocolVersion::TLSv1_3];
 
 // === impl Csr ===
 
@@ -79,9 +155,85 @@ impl Csr {
 // === impl Key ===
 
 impl Key {
+    /// Loads an ECDSA P-256, ECDSA P-384, or Ed25519 private key.
     pub fn from_pkcs8(b: &[u8]) -> Result<Self, KeyRejected> {
-        let k = EcdsaKeyPair::from_pkcs8(SIGNATURE_ALG_RING_SIGNING, b)?;
-        Ok(Key(Arc::new(k)))
+        let mut rejected = None;
+        for alg in ECDSA_ALGS {
+            match EcdsaKeyPair::from_pkcs8(alg.0, b) {
+                Ok(k) => return Ok(Key(Arc::new(KeyPair::Ecdsa(k, alg)))),
+                Err(e) => rejected = rejected.or(Some(e)),
+            }
+        }
+
+        // Keys generated by OpenSSL omit the public key, so ring's stricter
+        // PKCS#8 v2 parser can't be used.
+        if let Ok(k) = Ed25519KeyPair::from_pkcs8_maybe_unchecked(b) {
+            return Ok(Key(Arc::new(KeyPair::Ed25519(k))));
+        }
+
+        Err(rejected.expect("ECDSA algorithms must be configured"))
+    }
+
+    /// Loads a PEM-encoded PKCS#8 private key, as written by cert-manager or
+    /// Vault Agent.
+    pub fn from_pem(pem: &[u8]) -> io::Result<Self> {
+        let keys = rustls::internal::pemfile::pkcs8_private_keys(&mut io::Cursor::new(pem))
+            .map_err(|()| invalid_data("invalid PEM"))?;
+        let key = keys
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid_data("no PKCS#8 private key found"))?;
+        Self::from_pkcs8(&key.0).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    fn scheme(&self) -> rustls::SignatureScheme {
+        self.0.scheme()
+    }
+}
+
+// === impl KeyPair ===
+
+impl KeyPair {
+    fn scheme(&self) -> rustls::SignatureScheme {
+        match self {
+            KeyPair::Ecdsa(_, (_, scheme, _)) => *scheme,
+            KeyPair::Ed25519(_) => rustls::SignatureScheme::ED25519,
+        }
+    }
+
+    /// Returns whether the certificate was issued for this key, by verifying
+    /// a signature made with this key against the certificate's public key.
+    fn is_key_for(&self, crt: &rustls::Certificate) -> bool {
+        const MSG: &[u8] = b"linkerd2-identity";
+
+        let alg = match self {
+            KeyPair::Ecdsa(_, (_, _, alg)) => *alg,
+            KeyPair::Ed25519(_) => &webpki::ED25519,
+        };
+        let crt = match webpki::EndEntityCert::from(&crt.0) {
+            Ok(crt) => crt,
+            Err(_) => return false,
+        };
+        match self.sign(MSG) {
+            Ok(sig) => crt.verify_signature(alg, MSG, &sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, ring::error::Unspecified> {
+        match self {
+            KeyPair::Ecdsa(k, _) => {
+                let rng = rand::SystemRandom::new();
+                k.sign(&rng, message).map(|sig| sig.as_ref().to_owned())
+            }
+            KeyPair::Ed25519(k) => Ok(k.sign(message).as_ref().to_owned()),
+        }
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeyPair").field(&self.scheme()).finish()
     }
 }
 
@@ -90,7 +242,7 @@ impl rustls::sign::SigningKey for SigningKey {
         &self,
         offered: &[rustls::SignatureScheme],
     ) -> Option<Box<dyn rustls::sign::Signer>> {
-        if offered.contains(&SIGNATURE_ALG_RUSTLS_SCHEME) {
+        if offered.contains(&self.0.scheme()) {
             Some(Box::new(Signer(self.0.clone())))
         } else {
             None
@@ -98,23 +250,22 @@ impl rustls::sign::SigningKey for SigningKey {
     }
 
     fn algorithm(&self) -> rustls::internal::msgs::enums::SignatureAlgorithm {
-        SIGNATURE_ALG_RUSTLS_ALGORITHM
+        // This determines the TLS 1.2 cipher suites that may be negotiated.
+        // Ed25519 keys are used with the ECDSA suites (RFC 8422), which Rustls
+        // only selects for keys that report the ECDSA algorithm.
+        rustls::internal::msgs::enums::SignatureAlgorithm::ECDSA
     }
 }
 
 impl rustls::sign::Signer for Signer {
     fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::TLSError> {
-        let rng = rand::SystemRandom::new();
-        self.0
-            .sign(&rng, message)
-            .map(|signature| signature.as_ref().to_owned())
-            .map_err(|ring::error::Unspecified| {
-                rustls::TLSError::General("Signing Failed".to_owned())
-            })
+        self.0.sign(message).map_err(|ring::error::Unspecified| {
+            rustls::TLSError::General("Signing Failed".to_owned())
+        })
     }
 
     fn get_scheme(&self) -> rustls::SignatureScheme {
-        SIGNATURE_ALG_RUSTLS_SCHEME
+        self.0.scheme()
     }
 }
 
@@ -122,13 +273,57 @@ impl rustls::sign::Signer for Signer {
 
 impl From<linkerd2_dns_name::Name> for Name {
     fn from(n: linkerd2_dns_name::Name) -> Self {
-        Name(Arc::new(n))
+        Name(Arc::new(NameKind::Dns(n)))
+    }
+}
+
+impl From<spiffe::Id> for Name {
+    fn from(id: spiffe::Id) -> Self {
+        Name(Arc::new(NameKind::Spiffe(id)))
     }
 }
 
 impl Name {
+    /// Returns the name that TLS clients indicate (via SNI) when connecting
+    /// to this identity. SPIFFE IDs are reached via their trust domain.
+    pub fn server_name(&self) -> &linkerd2_dns_name::Name {
+        match *self.0 {
+            NameKind::Dns(ref n) => n,
+            NameKind::Spiffe(ref id) => id.trust_domain(),
+        }
+    }
+
     pub fn as_dns_name_ref(&self) -> webpki::DNSNameRef<'_> {
-        self.0.as_dns_name_ref()
+        self.server_name().as_dns_name_ref()
+    }
+
+    pub fn as_spiffe_id(&self) -> Option<&spiffe::Id> {
+        match *self.0 {
+            NameKind::Dns(_) => None,
+            NameKind::Spiffe(ref id) => Some(id),
+        }
+    }
+
+    /// Configures a TLS client to verify that servers are identified by this
+    /// name.
+    ///
+    /// Rustls verifies DNS-like names against the SNI name, so `config` is
+    /// used as-is. SPIFFE IDs are verified against the server's URI SAN, so
+    /// `config` is cloned; `CrtKey::tls_client_config_for` and
+    /// `TrustAnchors::tls_client_config_for` cache these configurations.
+    pub fn tls_client_config(
+        &self,
+        config: Arc<rustls::ClientConfig>,
+    ) -> Arc<rustls::ClientConfig> {
+        match *self.0 {
+            NameKind::Dns(_) => config,
+            NameKind::Spiffe(ref id) => {
+                let mut c = config.as_ref().clone();
+                c.dangerous()
+                    .set_certificate_verifier(Arc::new(spiffe::Verifier::new(id.clone())));
+                Arc::new(c)
+            }
+        }
     }
 }
 
@@ -136,11 +331,7 @@ impl FromStr for Name {
     type Err = InvalidName;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.as_bytes().last() == Some(&b'.') {
-            return Err(InvalidName); // SNI hostnames are implicitly absolute.
-        }
-
-        linkerd2_dns_name::Name::from_str(s).map(|n| Name(Arc::new(n)))
+        Self::try_from(s.as_bytes())
     }
 }
 
@@ -148,29 +339,41 @@ impl TryFrom<&[u8]> for Name {
     type Error = InvalidName;
 
     fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        if s.starts_with(b"spiffe:") {
+            return spiffe::Id::try_from(s)
+                .map(Name::from)
+                .map_err(|_| InvalidName);
+        }
+
         if s.last() == Some(&b'.') {
             return Err(InvalidName); // SNI hostnames are implicitly absolute.
         }
 
-        linkerd2_dns_name::Name::try_from(s).map(|n| Name(Arc::new(n)))
+        linkerd2_dns_name::Name::try_from(s).map(Name::from)
     }
 }
 
 impl AsRef<str> for Name {
     fn as_ref(&self) -> &str {
-        (*self.0).as_ref()
+        match *self.0 {
+            NameKind::Dns(ref n) => n.as_ref(),
+            NameKind::Spiffe(ref id) => id.as_ref(),
+        }
     }
 }
 
 impl fmt::Debug for Name {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        fmt::Debug::fmt(&self.0, f)
+        match *self.0 {
+            NameKind::Dns(ref n) => fmt::Debug::fmt(n, f),
+            NameKind::Spiffe(ref id) => fmt::Debug::fmt(id, f),
+        }
     }
 }
 
 impl fmt::Display for Name {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        fmt::Display::fmt(&self.0, f)
+        fmt::Display::fmt(self.as_ref(), f)
     }
 }
 
@@ -198,18 +401,32 @@ impl TokenSource {
 impl TrustAnchors {
     #[cfg(any(test, feature = "test-util"))]
     fn empty() -> Self {
-        TrustAnchors(Arc::new(rustls::ClientConfig::new()))
+        TrustAnchors {
+            config: Arc::new(rustls::ClientConfig::new()),
+            fingerprint: fingerprint(&[]).into(),
+            spiffe_configs: SpiffeConfigs::default(),
+        }
     }
 
     pub fn from_pem(s: &str) -> Option<Self> {
         use std::io::Cursor;
 
+        // Only the roots that are added to the store are fingerprinted, so
+        // that skipping an invalid root doesn't change the fingerprint.
+        let crts = rustls::internal::pemfile::certs(&mut Cursor::new(s)).ok()?;
         let mut roots = rustls::RootCertStore::empty();
-        let (added, skipped) = roots.add_pem_file(&mut Cursor::new(s)).ok()?;
+        let mut added = Vec::with_capacity(crts.len());
+        let mut skipped = 0;
+        for crt in crts.into_iter() {
+            match roots.add(&crt) {
+                Ok(()) => added.push(crt),
+                Err(_) => skipped += 1,
+            }
+        }
         if skipped != 0 {
             warn!("skipped {} trust anchors in trust anchors file", skipped);
         }
-        if added == 0 {
+        if added.is_empty() {
             return None;
         }
 
@@ -226,11 +443,29 @@ impl TrustAnchors {
         // more tested.
         c.enable_tickets = false;
 
-        Some(TrustAnchors(Arc::new(c)))
+        c.versions = TLS_VERSIONS.to_vec();
+
+        Some(TrustAnchors {
+            config: Arc::new(c),
+            fingerprint: fingerprint(&added).into(),
+            spiffe_configs: SpiffeConfigs::default(),
+        })
+    }
+
+    /// Refuses connections, both to and from peers, that cannot negotiate
+    /// TLS 1.3.
+    pub fn require_tls13(self) -> Self {
+        let mut c = self.config.as_ref().clone();
+        c.versions = TLS13_VERSIONS.to_vec();
+        TrustAnchors {
+            config: Arc::new(c),
+            fingerprint: self.fingerprint,
+            spiffe_configs: SpiffeConfigs::default(),
+        }
     }
 
     pub fn certify(&self, key: Key, crt: Crt) -> Result<CrtKey, InvalidCrt> {
-        let mut client = self.0.as_ref().clone();
+        let mut client = self.config.as_ref().clone();
 
         // Ensure the certificate is valid for the services we terminate for
         // TLS. This assumes that server cert validation does the same or
@@ -244,21 +479,45 @@ impl TrustAnchors {
         // safe API, use it to pass proof to CertCertResolver::new....
         //
         // TODO: Restrict accepted signatutre algorithms.
+        //
+        // X.509-SVIDs are verified by their URI SAN rather than a DNS name.
         static NO_OCSP: &[u8] = &[];
-        client
-            .get_verifier()
-            .verify_server_cert(
+        match crt.name.as_spiffe_id() {
+            None => client.get_verifier().verify_server_cert(
                 &client.root_store,
                 &crt.chain,
                 crt.name.as_dns_name_ref(),
                 NO_OCSP,
-            )
-            .map_err(InvalidCrt)?;
+            ),
+            Some(id) => {
+                use rustls::ServerCertVerifier;
+                spiffe::Verifier::new(id.clone()).verify_server_cert(
+                    &client.root_store,
+                    &crt.chain,
+                    crt.name.as_dns_name_ref(),
+                    NO_OCSP,
+                )
+            }
+        }
+        .map_err(InvalidCrt)?;
+
+        // Ensure the certificate was issued for our key, which may not be the
+        // case if the two are read from disk while they are being rotated.
+        if !key.0.is_key_for(&crt.chain[0]) {
+            return Err(InvalidCrt(rustls::TLSError::General(
+                "certificate was not issued for the private key".to_string(),
+            )));
+        }
         debug!("certified {}", crt.name.as_ref());
 
+        let scheme = key.scheme();
         let k = SigningKey(key.0);
         let key = rustls::sign::CertifiedKey::new(crt.chain, Arc::new(Box::new(k)));
-        let resolver = Arc::new(CertResolver(key));
+        let resolver = Arc::new(CertResolver {
+            name: crt.name.clone(),
+            key,
+            scheme,
+        });
 
         // Enable client authentication.
         client.client_auth_cert_resolver = resolver.clone();
@@ -273,9 +532,10 @@ impl TrustAnchors {
         //
         // TODO: Change Rustls's API to Avoid needing to clone `root_cert_store`.
         let mut server = rustls::ServerConfig::new(
-            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.0.root_store.clone()),
+            rustls::AllowAnyAnonymousOrAuthenticatedClient::new(self.config.root_store.clone()),
         );
-        server.versions = TLS_VERSIONS.to_vec();
+        // Servers support the same TLS versions as clients.
+        server.versions = self.config.versions.clone();
         server.cert_resolver = resolver;
 
         Ok(CrtKey {
@@ -283,20 +543,47 @@ impl TrustAnchors {
             expiry: crt.expiry,
             client_config: Arc::new(client),
             server_config: Arc::new(server),
+            spiffe_configs: SpiffeConfigs::default(),
         })
     }
 
     pub fn tls_client_config(&self) -> Arc<rustls::ClientConfig> {
-        self.0.clone()
+        self.config.clone()
+    }
+
+    /// Configures a TLS client to verify that servers are identified by
+    /// `peer`.
+    pub fn tls_client_config_for(&self, peer: &Name) -> Arc<rustls::ClientConfig> {
+        self.spiffe_configs.get(peer, &self.config)
+    }
+
+    /// Identifies the trust anchors by the hex-encoded SHA-256 digest of
+    /// their DER-encoded certificates, in order.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
     }
 }
 
 impl fmt::Debug for TrustAnchors {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("TrustAnchors").finish()
+        f.debug_struct("TrustAnchors")
+            .field("fingerprint", &self.fingerprint)
+            .finish()
     }
 }
 
+fn fingerprint(crts: &[rustls::Certificate]) -> String {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    for crt in crts {
+        ctx.update(&crt.0);
+    }
+    ctx.finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 // === Crt ===
 
 impl Crt {
@@ -312,6 +599,26 @@ impl Crt {
         }
     }
 
+    /// Parses a PEM-encoded certificate chain, leaf first, as written by
+    /// cert-manager or Vault Agent.
+    ///
+    /// The certificate expires when its leaf does.
+    pub fn from_pem(name: Name, pem: &[u8]) -> io::Result<Self> {
+        let chain = rustls::internal::pemfile::certs(&mut io::Cursor::new(pem))
+            .map_err(|()| invalid_data("invalid PEM"))?;
+        let leaf = chain
+            .first()
+            .ok_or_else(|| invalid_data("no certificates found"))?;
+        let expiry = der::not_after(&leaf.0)
+            .ok_or_else(|| invalid_data("certificate has an invalid expiry"))?;
+
+        Ok(Self {
+            name,
+            chain,
+            expiry,
+        })
+    }
+
     pub fn name(&self) -> &Name {
         &self.name
     }
@@ -324,6 +631,12 @@ impl CrtKey {
         self.client_config.clone()
     }
 
+    /// Configures a TLS client to verify that servers are identified by
+    /// `peer`.
+    pub fn tls_client_config_for(&self, peer: &Name) -> Arc<rustls::ClientConfig> {
+        self.spiffe_configs.get(peer, &self.client_config)
+    }
+
     pub fn tls_server_name(&self) -> Name {
         self.name.clone()
     }
@@ -346,6 +659,24 @@ impl fmt::Debug for CrtKey {
     }
 }
 
+// === impl SpiffeConfigs ===
+
+impl SpiffeConfigs {
+    /// Returns a configuration, based on `config`, that verifies that servers
+    /// are identified by `peer`.
+    fn get(&self, peer: &Name, config: &Arc<rustls::ClientConfig>) -> Arc<rustls::ClientConfig> {
+        let id = match peer.as_spiffe_id() {
+            Some(id) => id,
+            None => return config.clone(),
+        };
+        let mk = || peer.tls_client_config(config.clone());
+        match self.0.lock() {
+            Ok(mut configs) => configs.entry(id.clone()).or_insert_with(mk).clone(),
+            Err(_) => mk(),
+        }
+    }
+}
+
 // === impl CertResolver ===
 
 impl rustls::ResolvesClientCert for CertResolver {
@@ -369,11 +700,11 @@ impl CertResolver {
         &self,
         sigschemes: &[rustls::SignatureScheme],
     ) -> Option<rustls::sign::CertifiedKey> {
-        if !sigschemes.contains(&SIGNATURE_ALG_RUSTLS_SCHEME) {
+        if !sigschemes.contains(&self.scheme) {
             debug!("signature scheme not supported -> no certificate");
             return None;
         }
-        Some(self.0.clone())
+        Some(self.key.clone())
     }
 }
 
@@ -386,8 +717,18 @@ impl rustls::ResolvesServerCert for CertResolver {
             return None;
         };
 
+        // X.509-SVIDs aren't valid for any DNS name, so clients need only
+        // indicate our trust domain.
+        if let Some(id) = self.name.as_spiffe_id() {
+            if linkerd2_dns_name::Name::from(server_name.to_owned()) != *id.trust_domain() {
+                debug!("the SNI name is not our trust domain -> no certificate");
+                return None;
+            }
+            return self.resolve_(hello.sigschemes());
+        }
+
         // Verify that our certificate is valid for the given SNI name.
-        let c = (&self.0.cert)
+        let c = (&self.key.cert)
             .first()
             .map(rustls::Certificate::as_ref)
             .unwrap_or(&[]); // An empty input will fail to parse.
@@ -419,6 +760,13 @@ impl Error for InvalidCrt {
     }
 }
 
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
 #[cfg(test)]
 mod tests {
     use super::test_util::*;
@@ -428,6 +776,88 @@ mod tests {
         FOO_NS1.validate().expect("foo.ns1 must be valid");
     }
 
+    #[test]
+    fn can_construct_client_and_server_config_from_p384_key() {
+        FOO_NS1_CA3.validate().expect("foo.ns1 must be valid");
+    }
+
+    #[test]
+    fn can_construct_client_and_server_config_from_ed25519_key() {
+        BAR_NS1_CA3.validate().expect("bar.ns1 must be valid");
+    }
+
+    #[test]
+    fn can_construct_client_and_server_config_from_svid() {
+        FOO_NS1_SVID.validate().expect("foo.ns1 SVID must be valid");
+    }
+
+    #[test]
+    fn rejects_unsupported_key() {
+        assert!(super::Key::from_pkcs8(b"not a key").is_err());
+    }
+
+    #[test]
+    fn can_construct_client_and_server_config_from_pem() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let name = FOO_NS1_CA3.name.parse().unwrap();
+        let crt = super::Crt::from_pem(name, include_bytes!("testdata/foo-ns1-ca3/crt.pem"))
+            .expect("chain must be valid");
+        // notAfter=Oct 14 01:35:50 2036 GMT
+        assert_eq!(crt.expiry, UNIX_EPOCH + Duration::from_secs(2_107_560_950));
+
+        let key = super::Key::from_pem(include_bytes!("testdata/foo-ns1-ca3/key.pem"))
+            .expect("key must be valid");
+        FOO_NS1_CA3
+            .trust_anchors()
+            .certify(key, crt)
+            .expect("foo.ns1 must be valid");
+    }
+
+    #[test]
+    fn rejects_pem_without_a_key_or_certificate() {
+        let name = FOO_NS1_CA3.name.parse().unwrap();
+        assert!(
+            super::Crt::from_pem(name, include_bytes!("testdata/foo-ns1-ca3/key.pem")).is_err()
+        );
+        assert!(super::Key::from_pem(include_bytes!("testdata/foo-ns1-ca3/crt.pem")).is_err());
+    }
+
+    #[test]
+    fn trust_anchors_are_fingerprinted() {
+        fn pem(bytes: &[u8]) -> &str {
+            std::str::from_utf8(bytes).unwrap()
+        }
+        let ca1 = super::TrustAnchors::from_pem(pem(include_bytes!("testdata/ca1.pem"))).unwrap();
+        let ca2 = super::TrustAnchors::from_pem(pem(include_bytes!("testdata/ca2.pem"))).unwrap();
+        assert_eq!(ca1.fingerprint().len(), 64);
+        assert_ne!(ca1.fingerprint(), ca2.fingerprint());
+        assert_eq!(ca1.fingerprint(), ca1.clone().require_tls13().fingerprint());
+
+        // Invalid roots are skipped and do not change the fingerprint.
+        let bundle = [
+            pem(include_bytes!("testdata/ca1.pem")),
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n",
+        ]
+        .concat();
+        let skipped = super::TrustAnchors::from_pem(&bundle).unwrap();
+        assert_eq!(skipped.fingerprint(), ca1.fingerprint());
+    }
+
+    #[test]
+    fn trust_anchor_bundles_accept_all_roots() {
+        let bundle = [
+            std::str::from_utf8(include_bytes!("testdata/ca1.pem")).unwrap(),
+            std::str::from_utf8(include_bytes!("testdata/ca3.pem")).unwrap(),
+        ]
+        .concat();
+        let ta = super::TrustAnchors::from_pem(&bundle).unwrap();
+        ta.certify(FOO_NS1.key(), FOO_NS1.crt())
+            .expect("foo.ns1 must be valid");
+        ta.certify(FOO_NS1_CA3.key(), FOO_NS1_CA3.crt())
+            .expect("foo.ns1 must be valid");
+    }
+
     #[test]
     fn recognize_ca_did_not_issue_cert() {
         let s = Identity {
@@ -448,7 +878,75 @@ mod tests {
     }
 
     #[test]
-    #[ignore] // XXX this doesn't fail because we don't actually check the key against the cert...
+    fn recognize_svid_is_not_valid_for_spiffe_id() {
+        let s = Identity {
+            crt: BAR_NS1_SVID.crt,
+            key: BAR_NS1_SVID.key,
+            ..FOO_NS1_SVID
+        };
+        assert!(s.validate().is_err(), "identity should not be valid");
+
+        let s = Identity {
+            name: "spiffe://cluster.local/ns/ns1/sa/foo",
+            ..FOO_NS1_CA3
+        };
+        assert!(s.validate().is_err(), "DNS SAN should not be a SPIFFE ID");
+    }
+
+    #[test]
+    fn parses_spiffe_ids_and_dns_names() {
+        let id = "spiffe://cluster.local/ns/ns1/sa/foo"
+            .parse::<super::Name>()
+            .unwrap();
+        assert_eq!(id.as_ref(), "spiffe://cluster.local/ns/ns1/sa/foo");
+        assert_eq!(id.server_name().as_ref(), "cluster.local");
+        assert!(id.as_spiffe_id().is_some());
+
+        let dns = FOO_NS1.name.parse::<super::Name>().unwrap();
+        assert_eq!(dns.server_name().as_ref(), FOO_NS1.name);
+        assert!(dns.as_spiffe_id().is_none());
+
+        assert!("spiffe://cluster.local/ns/ns1/"
+            .parse::<super::Name>()
+            .is_err());
+    }
+
+    #[test]
+    fn spiffe_client_configs_are_cached() {
+        use std::sync::Arc;
+
+        let foo = FOO_NS1_SVID.name.parse::<super::Name>().unwrap();
+        let bar = BAR_NS1_SVID.name.parse::<super::Name>().unwrap();
+        let dns = FOO_NS1.name.parse::<super::Name>().unwrap();
+
+        let crt_key = FOO_NS1_SVID.validate().expect("foo.ns1 SVID must be valid");
+        let config = crt_key.tls_client_config_for(&foo);
+        assert!(!Arc::ptr_eq(&config, &crt_key.tls_client_config()));
+        assert!(Arc::ptr_eq(&config, &crt_key.tls_client_config_for(&foo)));
+        assert!(Arc::ptr_eq(
+            &config,
+            &crt_key.clone().tls_client_config_for(&foo)
+        ));
+        assert!(!Arc::ptr_eq(&config, &crt_key.tls_client_config_for(&bar)));
+        assert!(Arc::ptr_eq(
+            &crt_key.tls_client_config(),
+            &crt_key.tls_client_config_for(&dns)
+        ));
+
+        // A new certificate discards the cached configurations.
+        let crt_key = FOO_NS1_SVID.validate().expect("foo.ns1 SVID must be valid");
+        assert!(!Arc::ptr_eq(&config, &crt_key.tls_client_config_for(&foo)));
+
+        let anchors = FOO_NS1_SVID.trust_anchors();
+        let config = anchors.tls_client_config_for(&foo);
+        assert!(Arc::ptr_eq(&config, &anchors.tls_client_config_for(&foo)));
+        assert!(!Arc::ptr_eq(
+            &config,
+            &FOO_NS1_SVID.trust_anchors().tls_client_config_for(&foo)
+        ));
+    }
+
+    #[test]
     fn recognize_private_key_is_not_valid_for_cert() {
         let s = Identity {
             key: BAR_NS1.key,
@@ -0,0 +1,257 @@
+//! SPIFFE IDs, which identify workloads by URI rather than by DNS name.
+//!
+//! A SPIFFE ID has the form `spiffe://<trust domain>/<path>`, e.g.
+//! `spiffe://cluster.local/ns/emojivoto/sa/web`. Workloads prove their SPIFFE
+//! ID with an X.509-SVID: a certificate that holds the ID as its URI subject
+//! alternative name.
+//!
+//! See https://github.com/spiffe/spiffe/blob/main/standards/SPIFFE-ID.md.
+
+use crate::{der, SIG_ALGS};
+use linkerd2_dns_name as dns;
+use std::{convert::TryFrom, fmt, str::FromStr, time::SystemTime};
+
+const SCHEME: &str = "spiffe://";
+const MAX_LEN: usize = 2048;
+
+/// A SPIFFE ID.
+///
+/// The trust domain must also be a valid DNS name, since TLS clients indicate
+/// it (via SNI) when connecting to the workload.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct Id {
+    uri: String,
+    trust_domain: dns::Name,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct InvalidId;
+
+/// Verifies that servers present an X.509-SVID for a SPIFFE ID.
+pub(crate) struct Verifier(Id);
+
+// === impl Id ===
+
+impl Id {
+    /// Reads the SPIFFE ID from a DER-encoded X.509-SVID's URI SAN.
+    ///
+    /// Returns `None` if the certificate does not have exactly one URI SAN or
+    /// if it is not a valid SPIFFE ID.
+    pub fn from_crt(crt: &[u8]) -> Option<Self> {
+        let uris = der::uri_sans(crt)?;
+        match uris.as_slice() {
+            [uri] => Self::try_from(*uri).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn trust_domain(&self) -> &dns::Name {
+        &self.trust_domain
+    }
+
+    /// The ID's path, e.g. `/ns/emojivoto/sa/web`, which may be empty.
+    pub fn path(&self) -> &str {
+        &self.uri[SCHEME.len() + self.trust_domain.as_ref().len()..]
+    }
+}
+
+impl FromStr for Id {
+    type Err = InvalidId;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > MAX_LEN || !s.starts_with(SCHEME) {
+            return Err(InvalidId);
+        }
+        let rest = &s[SCHEME.len()..];
+        let (td, path) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+
+        // Trust domains are lowercase and may not have a port or user info.
+        let td_valid = !td.is_empty()
+            && td
+                .bytes()
+                .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_'));
+        if !td_valid {
+            return Err(InvalidId);
+        }
+        let trust_domain = dns::Name::from_str(td).map_err(|_| InvalidId)?;
+
+        // Each path segment is non-empty and relative segments are not
+        // allowed, so neither is a trailing slash. Query strings and
+        // fragments are not allowed.
+        if !path.is_empty() {
+            let segments_valid = path[1..].split('/').all(|seg| {
+                !seg.is_empty()
+                    && seg != "."
+                    && seg != ".."
+                    && seg
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+            });
+            if !segments_valid {
+                return Err(InvalidId);
+            }
+        }
+
+        Ok(Self {
+            uri: s.to_string(),
+            trust_domain,
+        })
+    }
+}
+
+impl TryFrom<&[u8]> for Id {
+    type Error = InvalidId;
+
+    fn try_from(s: &[u8]) -> Result<Self, Self::Error> {
+        std::str::from_utf8(s)
+            .map_err(|_| InvalidId)
+            .and_then(Self::from_str)
+    }
+}
+
+impl AsRef<str> for Id {
+    fn as_ref(&self) -> &str {
+        &self.uri
+    }
+}
+
+impl fmt::Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.uri, f)
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.uri, f)
+    }
+}
+
+// === impl InvalidId ===
+
+impl fmt::Display for InvalidId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid SPIFFE ID")
+    }
+}
+
+impl std::error::Error for InvalidId {}
+
+// === impl Verifier ===
+
+impl Verifier {
+    pub(crate) fn new(id: Id) -> Self {
+        Verifier(id)
+    }
+}
+
+impl rustls::ServerCertVerifier for Verifier {
+    /// Verifies the certificate chain like Rustls's own verifier, except that
+    /// the leaf must be an X.509-SVID for the expected SPIFFE ID rather than
+    /// be valid for the SNI name (the ID's trust domain).
+    fn verify_server_cert(
+        &self,
+        roots: &rustls::RootCertStore,
+        presented_certs: &[rustls::Certificate],
+        _: webpki::DNSNameRef<'_>,
+        _: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        let (leaf, intermediates) = match presented_certs.split_first() {
+            Some(chain) => chain,
+            None => return Err(rustls::TLSError::NoCertificatesPresented),
+        };
+        let cert = webpki::EndEntityCert::from(&leaf.0).map_err(rustls::TLSError::WebPKIError)?;
+        let intermediates = intermediates
+            .iter()
+            .map(|c| c.0.as_ref())
+            .collect::<Vec<_>>();
+        let anchors = roots
+            .roots
+            .iter()
+            .map(|ta| ta.to_trust_anchor())
+            .collect::<Vec<_>>();
+        let now = webpki::Time::try_from(SystemTime::now())
+            .map_err(|_| rustls::TLSError::FailedToGetCurrentTime)?;
+        cert.verify_is_valid_tls_server_cert(
+            SIG_ALGS,
+            &webpki::TLSServerTrustAnchors(&anchors),
+            &intermediates,
+            now,
+        )
+        .map_err(rustls::TLSError::WebPKIError)?;
+
+        match Id::from_crt(&leaf.0) {
+            Some(id) if id == self.0 => Ok(rustls::ServerCertVerified::assertion()),
+            _ => Err(rustls::TLSError::WebPKIError(
+                webpki::Error::CertNotValidForName,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_valid_ids() {
+        let cases = &[
+            ("spiffe://cluster.local", "cluster.local", ""),
+            (
+                "spiffe://cluster.local/ns/emojivoto/sa/web",
+                "cluster.local",
+                "/ns/emojivoto/sa/web",
+            ),
+            ("spiffe://example_org/A-b.c_D", "example_org", "/A-b.c_D"),
+        ];
+        for (s, td, path) in cases {
+            let id = Id::from_str(s).unwrap_or_else(|_| panic!("'{}' was invalid", s));
+            assert_eq!(id.as_ref(), *s);
+            assert_eq!(id.trust_domain().as_ref(), *td);
+            assert_eq!(id.path(), *path);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        let cases = &[
+            "cluster.local/ns/emojivoto",
+            "https://cluster.local/ns/emojivoto",
+            "SPIFFE://cluster.local/ns/emojivoto",
+            "spiffe://",
+            "spiffe:///ns/emojivoto",
+            "spiffe://Cluster.local/ns/emojivoto",
+            "spiffe://cluster.local:8443/ns/emojivoto",
+            "spiffe://user@cluster.local/ns/emojivoto",
+            "spiffe://cluster.local/",
+            "spiffe://cluster.local/ns//emojivoto",
+            "spiffe://cluster.local/ns/emojivoto/",
+            "spiffe://cluster.local/ns/./emojivoto",
+            "spiffe://cluster.local/ns/../emojivoto",
+            "spiffe://cluster.local/ns/emojivoto?sa=web",
+            "spiffe://cluster.local/ns/emojivoto#web",
+            "spiffe://cluster.local/ns/emoji%20voto",
+        ];
+        for s in cases {
+            assert_eq!(Id::from_str(s), Err(InvalidId), "{}", s);
+        }
+    }
+
+    #[test]
+    fn reads_id_from_svid() {
+        let id = Id::from_crt(include_bytes!("testdata/foo-ns1-svid/crt.der"));
+        assert_eq!(
+            id.map(|id| id.to_string()),
+            Some("spiffe://cluster.local/ns/ns1/sa/foo".to_string())
+        );
+
+        // Certificates that only have DNS SANs do not have SPIFFE IDs.
+        assert_eq!(
+            Id::from_crt(include_bytes!("testdata/foo-ns1-ca1/crt.der")),
+            None
+        );
+    }
+}
@@ -5,6 +5,7 @@ pub struct Identity {
     pub name: &'static str,
     pub trust_anchors: &'static [u8],
     pub crt: &'static [u8],
+    pub intermediates: &'static [&'static [u8]],
     pub key: &'static [u8],
 }
 
@@ -12,6 +13,7 @@ pub static FOO_NS1: Identity = Identity {
     name: "foo.ns1.serviceaccount.identity.linkerd.cluster.local",
     trust_anchors: include_bytes!("testdata/ca1.pem"),
     crt: include_bytes!("testdata/foo-ns1-ca1/crt.der"),
+    intermediates: &[],
     key: include_bytes!("testdata/foo-ns1-ca1/key.p8"),
 };
 
@@ -19,9 +21,48 @@ pub static BAR_NS1: Identity = Identity {
     name: "bar.ns1.serviceaccount.identity.linkerd.cluster.local",
     trust_anchors: include_bytes!("testdata/ca1.pem"),
     crt: include_bytes!("testdata/bar-ns1-ca1/crt.der"),
+    intermediates: &[],
     key: include_bytes!("testdata/bar-ns1-ca1/key.p8"),
 };
 
+/// Has a P-384 key and is issued by an intermediate that is signed by an RSA
+/// root.
+pub static FOO_NS1_CA3: Identity = Identity {
+    name: "foo.ns1.serviceaccount.identity.linkerd.cluster.local",
+    trust_anchors: include_bytes!("testdata/ca3.pem"),
+    crt: include_bytes!("testdata/foo-ns1-ca3/crt.der"),
+    intermediates: &[include_bytes!("testdata/ca3-int.der")],
+    key: include_bytes!("testdata/foo-ns1-ca3/key.p8"),
+};
+
+/// Has an Ed25519 key and is issued by an intermediate that is signed by an
+/// RSA root.
+pub static BAR_NS1_CA3: Identity = Identity {
+    name: "bar.ns1.serviceaccount.identity.linkerd.cluster.local",
+    trust_anchors: include_bytes!("testdata/ca3.pem"),
+    crt: include_bytes!("testdata/bar-ns1-ca3/crt.der"),
+    intermediates: &[include_bytes!("testdata/ca3-int.der")],
+    key: include_bytes!("testdata/bar-ns1-ca3/key.p8"),
+};
+
+/// A SPIFFE X.509-SVID, which is identified only by its URI SAN.
+pub static FOO_NS1_SVID: Identity = Identity {
+    name: "spiffe://cluster.local/ns/ns1/sa/foo",
+    trust_anchors: include_bytes!("testdata/ca3.pem"),
+    crt: include_bytes!("testdata/foo-ns1-svid/crt.der"),
+    intermediates: &[include_bytes!("testdata/ca3-int.der")],
+    key: include_bytes!("testdata/foo-ns1-svid/key.p8"),
+};
+
+/// A SPIFFE X.509-SVID, which is identified only by its URI SAN.
+pub static BAR_NS1_SVID: Identity = Identity {
+    name: "spiffe://cluster.local/ns/ns1/sa/bar",
+    trust_anchors: include_bytes!("testdata/ca3.pem"),
+    crt: include_bytes!("testdata/bar-ns1-svid/crt.der"),
+    intermediates: &[include_bytes!("testdata/ca3-int.der")],
+    key: include_bytes!("testdata/bar-ns1-svid/key.p8"),
+};
+
 impl Identity {
     pub fn trust_anchors(&self) -> TrustAnchors {
         let pem = ::std::str::from_utf8(self.trust_anchors).expect("utf-8");
@@ -37,7 +78,8 @@ impl Identity {
 
         let n = Name::from_str(&self.name).expect("name must be valid");
         let der = self.crt.iter().copied().collect();
-        Crt::new(n, der, vec![], SystemTime::now() + HOUR)
+        let intermediates = self.intermediates.iter().map(|i| i.to_vec()).collect();
+        Crt::new(n, der, intermediates, SystemTime::now() + HOUR)
     }
 
     pub fn validate(&self) -> Result<CrtKey, InvalidCrt> {
@@ -34,12 +34,15 @@ ee() {
   openssl pkcs8 -topk8 -nocrypt -inform pem -outform der \
     -in "${ee}-key.pem" \
     -out "${ee}/key.p8"
+  openssl pkcs8 -topk8 -nocrypt -inform pem -outform pem \
+    -in "${ee}-key.pem" \
+    -out "${ee}/key.pem"
   rm "${ee}-key.pem"
 
   openssl x509 -inform pem -outform der \
     -in "${ee}.pem" \
     -out "${ee}/crt.der"
-  rm "${ee}.pem"
+  mv "${ee}.pem" "${ee}/crt.pem"
 
   ## TODO DER-encode?
   #openssl x509 -inform pem -outform der \
@@ -57,3 +60,79 @@ ee ca1 controller linkerd linkerd
 ee ca1 foo ns1 linkerd
 ee ca2 foo ns1 linkerd # Same, but different CA
 ee ca1 bar ns1 linkerd # Different service.
+
+# cfssl doesn't support Ed25519 keys, so the CA 3 hierarchy is generated with
+# OpenSSL: an RSA root, a P-384 intermediate, and end entities with P-384 and
+# Ed25519 keys.
+openssl req -x509 -newkey rsa:2048 -nodes -sha256 -days 3650 \
+  -subj "/CN=Cluster-local CA 3" \
+  -addext "basicConstraints=critical,CA:TRUE" \
+  -addext "keyUsage=critical,keyCertSign,cRLSign" \
+  -keyout ca3-key.pem -out ca3.pem
+
+openssl ecparam -name secp384r1 -genkey -noout -out ca3-int-key.pem
+openssl req -new -key ca3-int-key.pem -subj "/CN=Cluster-local CA 3 Intermediate" \
+  | openssl x509 -req -CA ca3.pem -CAkey ca3-key.pem -CAcreateserial -sha256 -days 3650 \
+    -extfile <(printf "basicConstraints=critical,CA:TRUE,pathlen:0\nkeyUsage=critical,keyCertSign,cRLSign\n") \
+    -out ca3-int.pem
+openssl x509 -inform pem -outform der -in ca3-int.pem -out ca3-int.der
+
+ee_openssl() {
+  ee_name=$1
+  ee_ns=$2
+  cp_ns=$3
+  shift 3
+
+  hostname="${ee_name}.${ee_ns}.serviceaccount.identity.${cp_ns}.cluster.local"
+
+  ee="${ee_name}-${ee_ns}-ca3"
+  mkdir -p "${ee}"
+
+  openssl genpkey "$@" -out "${ee}-key.pem"
+  openssl pkcs8 -topk8 -nocrypt -inform pem -outform der \
+    -in "${ee}-key.pem" \
+    -out "${ee}/key.p8"
+
+  openssl req -new -key "${ee}-key.pem" -subj "/CN=${hostname}" \
+    | openssl x509 -req -CA ca3-int.pem -CAkey ca3-int-key.pem \
+      -CAcreateserial -sha384 -days 3650 \
+      -extfile <(printf "subjectAltName=DNS:%s\nkeyUsage=critical,digitalSignature,keyEncipherment\nextendedKeyUsage=serverAuth,clientAuth\nbasicConstraints=critical,CA:FALSE\n" "${hostname}") \
+      -outform der -out "${ee}/crt.der"
+  mv "${ee}-key.pem" "${ee}/key.pem"
+
+  # The PEM-encoded chain, as written by cert-manager or Vault Agent.
+  openssl x509 -inform der -outform pem -in "${ee}/crt.der" \
+    | cat - ca3-int.pem > "${ee}/crt.pem"
+}
+
+ee_openssl foo ns1 linkerd -algorithm EC -pkeyopt ec_paramgen_curve:P-384
+ee_openssl bar ns1 linkerd -algorithm ED25519
+
+# SPIFFE X.509-SVIDs, like those issued by SPIRE, are identified only by a URI
+# SAN.
+svid_openssl() {
+  ee_name=$1
+  ee_ns=$2
+
+  id="spiffe://cluster.local/ns/${ee_ns}/sa/${ee_name}"
+
+  ee="${ee_name}-${ee_ns}-svid"
+  mkdir -p "${ee}"
+
+  openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "${ee}-key.pem"
+  openssl pkcs8 -topk8 -nocrypt -inform pem -outform der \
+    -in "${ee}-key.pem" \
+    -out "${ee}/key.p8"
+
+  openssl req -new -key "${ee}-key.pem" -subj "/O=SPIRE" \
+    | openssl x509 -req -CA ca3-int.pem -CAkey ca3-int-key.pem \
+      -CAcreateserial -sha256 -days 3650 \
+      -extfile <(printf "subjectAltName=URI:%s\nkeyUsage=critical,digitalSignature,keyEncipherment,keyAgreement\nextendedKeyUsage=serverAuth,clientAuth\nbasicConstraints=critical,CA:FALSE\n" "${id}") \
+      -outform der -out "${ee}/crt.der"
+  rm "${ee}-key.pem"
+}
+
+svid_openssl foo ns1
+svid_openssl bar ns1
+
+rm ca3.srl ca3-int.srl ca3-int.pem
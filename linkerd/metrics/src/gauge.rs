@@ -17,6 +17,11 @@ impl Gauge {
         self.0.fetch_sub(1, Ordering::Release);
     }
 
+    /// Set the gauge to the given value.
+    pub fn set(&self, n: u64) {
+        self.0.store(n, Ordering::Release);
+    }
+
     pub fn value(&self) -> u64 {
         self.0
             .load(Ordering::Acquire)
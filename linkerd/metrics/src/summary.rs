@@ -150,6 +150,20 @@ impl<F> Summary<F> {
         self.count.add(n as u64);
     }
 
+    /// Returns the value at quantile `q` over the active windows.
+    ///
+    /// Returns `None` if fewer than `min_len` values are included in the
+    /// active windows.
+    pub fn quantile(&self, q: f64, min_len: u64) -> Option<u64> {
+        // Rotate windows so that expired values are not included.
+        drop(self.rotated_window_mut());
+        let report = self.lock_report();
+        if report.len() < min_len.max(1) {
+            return None;
+        }
+        Some(report.value_at_quantile(q))
+    }
+
     /// Get a mutable reference to the current histogram, rotating windows as
     /// necessary.
     #[inline]
@@ -383,4 +397,24 @@ mod tests {
         assert_eq!(s.count.value(), 40000.0);
         assert_eq!(s.sum.value(), 85_002.0);
     }
+
+    #[tokio::test]
+    async fn quantile() {
+        time::pause();
+
+        const ROTATE_INTERVAL: time::Duration = time::Duration::from_secs(10);
+        let s = Summary::<()>::new_resizable(2, 2 * ROTATE_INTERVAL, 5).unwrap();
+        assert_eq!(s.quantile(0.5, 0), None);
+
+        s.record_n(1, 5).unwrap();
+        s.record_n(2, 5).unwrap();
+        assert_eq!(s.quantile(0.5, 11), None);
+        assert_eq!(s.quantile(0.5, 10), Some(1));
+        assert_eq!(s.quantile(0.9, 10), Some(2));
+
+        // Values expire once all windows have rotated, even if nothing new
+        // has been recorded.
+        time::sleep(2 * ROTATE_INTERVAL).await;
+        assert_eq!(s.quantile(0.5, 0), None);
+    }
 }
@@ -8,6 +8,7 @@ use linkerd2_proxy_core as core;
 mod metadata;
 pub mod pb;
 mod resolve;
+pub mod snapshot;
 
-pub use self::metadata::{Metadata, ProtocolHint};
+pub use self::metadata::{Metadata, ProtocolHint, DEFAULT_WEIGHT};
 pub use self::resolve::Resolve;
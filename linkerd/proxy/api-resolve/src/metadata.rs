@@ -19,8 +19,16 @@ pub struct Metadata {
 
     /// Used to override the the authority if needed
     authority_override: Option<Authority>,
+
+    /// The endpoint's weight relative to other endpoints of the same
+    /// destination.
+    weight: u32,
 }
 
+/// The weight of endpoints for which the destination service does not provide
+/// a weight.
+pub const DEFAULT_WEIGHT: u32 = 10_000;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ProtocolHint {
     /// We don't what the destination understands, so forward messages in the
@@ -40,6 +48,7 @@ impl Default for Metadata {
             authority_override: None,
             opaque_transport_port: None,
             protocol_hint: ProtocolHint::Unknown,
+            weight: DEFAULT_WEIGHT,
         }
     }
 }
@@ -58,9 +67,15 @@ impl Metadata {
             opaque_transport_port,
             identity,
             authority_override,
+            weight: DEFAULT_WEIGHT,
         }
     }
 
+    /// Sets the endpoint's weight.
+    pub fn with_weight(self, weight: u32) -> Self {
+        Self { weight, ..self }
+    }
+
     /// Returns the endpoint's labels from the destination service, if it has them.
     pub fn labels(&self) -> &IndexMap<String, String> {
         &self.labels
@@ -81,4 +96,8 @@ impl Metadata {
     pub fn authority_override(&self) -> Option<&Authority> {
         self.authority_override.as_ref()
     }
+
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
 }
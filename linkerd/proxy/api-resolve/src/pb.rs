@@ -51,13 +51,17 @@ pub fn to_addr_meta(
     }
 
     let tls_id = pb.tls_identity.and_then(to_id);
-    let meta = Metadata::new(
+    let mut meta = Metadata::new(
         meta,
         proto_hint,
         opaque_transport_port,
         tls_id,
         authority_override,
     );
+    // Controllers that do not weight endpoints set a weight of zero.
+    if pb.weight > 0 {
+        meta = meta.with_weight(pb.weight);
+    }
     Some((addr, meta))
 }
 
@@ -2,6 +2,7 @@ use crate::api::destination as api;
 use crate::core::resolve::{self, Update};
 use crate::metadata::Metadata;
 use crate::pb;
+use crate::snapshot::{Record, Snapshots};
 use api::destination_client::DestinationClient;
 use async_stream::try_stream;
 use futures::prelude::*;
@@ -21,6 +22,7 @@ use tracing::{debug, info, trace};
 pub struct Resolve<S> {
     service: DestinationClient<S>,
     context_token: String,
+    snapshots: Option<Snapshots>,
 }
 
 // === impl Resolve ===
@@ -38,8 +40,14 @@ where
         Self {
             service: DestinationClient::new(svc),
             context_token,
+            snapshots: None,
         }
     }
+
+    /// Writes each destination's endpoints to `snapshots` as they change.
+    pub fn with_snapshots(self, snapshots: Option<Snapshots>) -> Self {
+        Self { snapshots, ..self }
+    }
 }
 
 type UpdatesStream =
@@ -70,6 +78,10 @@ where
         let path = target.to_string();
         debug!(dst = %path, context = %self.context_token, "Resolving");
 
+        let record = self
+            .snapshots
+            .clone()
+            .map(|snapshots| Record::new(snapshots, path.clone()));
         let req = api::GetDestination {
             path,
             context_token: self.context_token.clone(),
@@ -81,7 +93,7 @@ where
             // detect errors (like InvalidArgument).
             let rsp = client.get(grpc::Request::new(req)).await?;
             trace!(metadata = ?rsp.metadata());
-            let stream: UpdatesStream = Box::pin(resolution(rsp.into_inner()));
+            let stream: UpdatesStream = Box::pin(resolution(rsp.into_inner(), record));
             Ok(stream)
         })
     }
@@ -89,14 +101,16 @@ where
 
 fn resolution(
     mut stream: tonic::Streaming<api::Update>,
+    mut record: Option<Record>,
 ) -> impl Stream<Item = Result<resolve::Update<Metadata>, grpc::Status>> {
     try_stream! {
         while let Some(update) = stream.next().await {
             match update?.update {
-                Some(api::update::Update::Add(api::WeightedAddrSet {
-                    addrs,
-                    metric_labels,
-                })) => {
+                Some(api::update::Update::Add(set)) => {
+                    if let Some(record) = record.as_mut() {
+                        record.add(&set);
+                    }
+                    let api::WeightedAddrSet { addrs, metric_labels } = set;
                     let addr_metas = addrs
                         .into_iter()
                         .filter_map(|addr| pb::to_addr_meta(addr, &metric_labels))
@@ -107,7 +121,11 @@ fn resolution(
                     }
                 }
 
-                Some(api::update::Update::Remove(api::AddrSet { addrs })) => {
+                Some(api::update::Update::Remove(set)) => {
+                    if let Some(record) = record.as_mut() {
+                        record.remove(&set);
+                    }
+                    let api::AddrSet { addrs } = set;
                     let sock_addrs = addrs
                         .into_iter()
                         .filter_map(pb::to_sock_addr)
@@ -120,6 +138,9 @@ fn resolution(
 
                 Some(api::update::Update::NoEndpoints(api::NoEndpoints { exists })) => {
                     info!("No endpoints");
+                    if let Some(record) = record.as_mut() {
+                        record.no_endpoints(exists);
+                    }
                     let update = if exists {
                         Update::Reset(Vec::new())
                     } else {
@@ -0,0 +1,703 @@
+//! Last-known-good snapshots of discovery results.
+//!
+//! When enabled, the endpoints and profiles received from the destination
+//! service are written to a local directory as they change. If the
+//! destination service doesn't respond before a destination's snapshot
+//! timeout elapses--e.g. when the proxy restarts during a control plane
+//! outage--the destination's last snapshot is served until the destination
+//! service responds. Destinations that are served from snapshots are reported
+//! as stale in metrics.
+//!
+//! Snapshots are written in the background, at most once per
+//! `WRITE_DEBOUNCE` for each destination, so that churning destinations do
+//! not block the proxy on disk I/O. Snapshots that have not been written
+//! within a maximum age are removed when the directory is opened.
+
+use crate::api::destination as api;
+use crate::core::resolve::{self, Update};
+use crate::metadata::Metadata;
+use crate::pb;
+use async_stream::try_stream;
+use futures::{future, prelude::*};
+use indexmap::IndexMap;
+use linkerd2_error::Error;
+use linkerd2_metrics::{metrics, FmtLabels, FmtMetrics, Gauge};
+use prost::Message;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    ffi::OsStr,
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::{task, time};
+use tracing::{debug, info, warn};
+
+/// How long a destination's snapshot write is delayed, so that all of the
+/// destination's changes in that time are written at once.
+const WRITE_DEBOUNCE: Duration = Duration::from_secs(1);
+
+metrics! {
+    destination_snapshot_stale: Gauge {
+        "Indicates that a destination is being served from a last-known-good snapshot because the destination service has not responded."
+    }
+}
+
+/// A directory of snapshots.
+#[derive(Clone, Debug)]
+pub struct Snapshots(Arc<Inner>);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    Endpoints,
+    Profile,
+}
+
+/// Marks a destination as stale until it is dropped.
+#[derive(Debug)]
+pub struct Stale {
+    snapshots: Snapshots,
+    key: (Kind, String),
+}
+
+/// Formats metrics describing the destinations served from snapshots.
+#[derive(Clone, Debug)]
+pub struct Report(Option<Snapshots>);
+
+/// Serves endpoints from snapshots when the inner resolver doesn't resolve a
+/// destination before a timeout.
+#[derive(Clone, Debug)]
+pub struct Resolve<R> {
+    snapshots: Option<Snapshots>,
+    timeout: Duration,
+    inner: R,
+}
+
+pub type Resolution = Pin<Box<dyn Stream<Item = Result<Update<Metadata>, Error>> + Send>>;
+
+pub type ResolveFuture = Pin<Box<dyn Future<Output = Result<Resolution, Error>> + Send>>;
+
+/// Tracks a resolution's endpoints so that they may be written as they change.
+pub(crate) struct Record {
+    snapshots: Snapshots,
+    dst: String,
+    endpoints: IndexMap<SocketAddr, api::WeightedAddr>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    dir: PathBuf,
+    stale: Mutex<BTreeMap<(Kind, String), usize>>,
+
+    /// Snapshots that are being written, by path. A path is present while a
+    /// task writes its snapshots, and holds the snapshot that has yet to be
+    /// written, if any.
+    writes: Mutex<HashMap<PathBuf, Option<Pending>>>,
+}
+
+/// A snapshot that has yet to be written.
+#[derive(Clone, Debug)]
+enum Pending {
+    Write(Vec<u8>),
+    Remove,
+}
+
+struct StaleLabels<'a>(&'a (Kind, String));
+
+// === impl Snapshots ===
+
+impl Snapshots {
+    /// Stores snapshots in `dir`, creating it if necessary.
+    ///
+    /// Snapshots that have not been written within `max_age` are removed.
+    pub fn new(dir: PathBuf, max_age: Duration) -> io::Result<Self> {
+        for kind in &[Kind::Endpoints, Kind::Profile] {
+            let dir = dir.join(kind.as_str());
+            fs::create_dir_all(&dir)?;
+            gc(&dir, max_age)?;
+        }
+        Ok(Snapshots(Arc::new(Inner {
+            dir,
+            stale: Mutex::new(BTreeMap::new()),
+            writes: Mutex::new(HashMap::new()),
+        })))
+    }
+
+    pub fn report(&self) -> Report {
+        Report(Some(self.clone()))
+    }
+
+    /// Marks `dst` as being served from a snapshot.
+    pub fn stale(&self, kind: Kind, dst: impl Into<String>) -> Stale {
+        let key = (kind, dst.into());
+        if let Ok(mut stale) = self.0.stale.lock() {
+            *stale.entry(key.clone()).or_insert(0) += 1;
+        }
+        Stale {
+            snapshots: self.clone(),
+            key,
+        }
+    }
+
+    /// Loads the last-known-good endpoints for `dst`, if any were stored.
+    pub async fn load_endpoints(&self, dst: &str) -> Option<Vec<(SocketAddr, Metadata)>> {
+        let buf = self.read(Kind::Endpoints, dst).await?;
+        let mut buf = &buf[..];
+        let mut endpoints = Vec::new();
+        while !buf.is_empty() {
+            match api::WeightedAddr::decode_length_delimited(&mut buf) {
+                Ok(addr) => endpoints.extend(pb::to_addr_meta(addr, &HashMap::new())),
+                Err(error) => {
+                    warn!(%dst, %error, "Ignoring invalid endpoints snapshot");
+                    return None;
+                }
+            }
+        }
+        Some(endpoints)
+    }
+
+    /// Loads the last-known-good profile for `dst`, if one was stored.
+    pub async fn load_profile(&self, dst: &str) -> Option<api::DestinationProfile> {
+        let buf = self.read(Kind::Profile, dst).await?;
+        match api::DestinationProfile::decode(&buf[..]) {
+            Ok(profile) => Some(profile),
+            Err(error) => {
+                warn!(%dst, %error, "Ignoring invalid profile snapshot");
+                None
+            }
+        }
+    }
+
+    pub fn store_profile(&self, dst: &str, profile: &api::DestinationProfile) {
+        let mut buf = Vec::with_capacity(profile.encoded_len());
+        if profile.encode(&mut buf).is_ok() {
+            self.write(Kind::Profile, dst, Pending::Write(buf));
+        }
+    }
+
+    fn path(&self, kind: Kind, dst: &str) -> PathBuf {
+        // Destinations are names or addresses with ports, so all but a few
+        // characters are replaced to produce a valid file name.
+        let name = dst
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+                _ => '_',
+            })
+            .collect::<String>();
+        self.0.dir.join(kind.as_str()).join(name)
+    }
+
+    /// Reads the snapshot for `dst` on the blocking pool, as it is written.
+    async fn read(&self, kind: Kind, dst: &str) -> Option<Vec<u8>> {
+        let path = self.path(kind, dst);
+
+        // A snapshot that has yet to be written supersedes the file.
+        match self.pending(&path) {
+            Some(Pending::Write(buf)) => return Some(buf),
+            Some(Pending::Remove) => return None,
+            None => {}
+        }
+
+        let p = path.clone();
+        match task::spawn_blocking(move || fs::read(&p)).await {
+            Ok(Ok(buf)) => Some(buf),
+            Ok(Err(e)) if e.kind() == io::ErrorKind::NotFound => None,
+            Ok(Err(error)) => {
+                warn!(path = %path.display(), %error, "Failed to read snapshot");
+                None
+            }
+            Err(error) => {
+                warn!(path = %path.display(), %error, "Snapshot read failed");
+                None
+            }
+        }
+    }
+
+    fn pending(&self, path: &Path) -> Option<Pending> {
+        let writes = self.0.writes.lock().ok()?;
+        writes.get(path)?.clone()
+    }
+
+    /// Replaces the snapshot for `dst` in the background.
+    ///
+    /// The write is delayed by `WRITE_DEBOUNCE` so that only the latest of the
+    /// destination's snapshots in that time is written.
+    fn write(&self, kind: Kind, dst: &str, pending: Pending) {
+        let path = self.path(kind, dst);
+        let mut writes = match self.0.writes.lock() {
+            Ok(writes) => writes,
+            Err(_) => return,
+        };
+        match writes.entry(path.clone()) {
+            Entry::Occupied(mut e) => {
+                e.insert(Some(pending));
+            }
+            Entry::Vacant(e) => {
+                e.insert(Some(pending));
+                tokio::spawn(self.clone().flush(path));
+            }
+        }
+    }
+
+    /// Writes the pending snapshots for `path` until there are none.
+    async fn flush(self, path: PathBuf) {
+        loop {
+            time::sleep(WRITE_DEBOUNCE).await;
+
+            let pending = {
+                let mut writes = match self.0.writes.lock() {
+                    Ok(writes) => writes,
+                    Err(_) => return,
+                };
+                match writes.get_mut(&path).and_then(Option::take) {
+                    Some(pending) => pending,
+                    None => {
+                        writes.remove(&path);
+                        return;
+                    }
+                }
+            };
+
+            let p = path.clone();
+            match task::spawn_blocking(move || pending.write(&p)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => {
+                    warn!(path = %path.display(), %error, "Failed to write snapshot")
+                }
+                Err(error) => warn!(path = %path.display(), %error, "Snapshot write failed"),
+            }
+        }
+    }
+
+    /// Completes once all pending snapshots are written.
+    #[cfg(test)]
+    async fn flushed(&self) {
+        while !self.0.writes.lock().unwrap().is_empty() {
+            time::sleep(WRITE_DEBOUNCE).await;
+        }
+    }
+}
+
+/// Removes the snapshots in `dir` that have not been written within `max_age`,
+/// as well as any partially-written snapshots.
+fn gc(dir: &Path, max_age: Duration) -> io::Result<()> {
+    let now = SystemTime::now();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let expired = path.extension() == Some(OsStr::new("tmp"))
+            || fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .map(|modified| now.duration_since(modified).unwrap_or_default() > max_age)
+                .unwrap_or(false);
+        if expired {
+            debug!(path = %path.display(), "Removing stale snapshot");
+            if let Err(error) = fs::remove_file(&path) {
+                warn!(path = %path.display(), %error, "Failed to remove snapshot");
+            }
+        }
+    }
+    Ok(())
+}
+
+// === impl Pending ===
+
+impl Pending {
+    /// Snapshots are written to a temporary file and then renamed so that a
+    /// partially-written snapshot is never read.
+    fn write(self, path: &Path) -> io::Result<()> {
+        match self {
+            Pending::Write(buf) => {
+                let mut tmp = path.as_os_str().to_owned();
+                tmp.push(".tmp");
+                fs::write(&tmp, buf).and_then(|()| fs::rename(&tmp, path))
+            }
+            Pending::Remove => match fs::remove_file(path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                res => res,
+            },
+        }
+    }
+}
+
+// === impl Kind ===
+
+impl Kind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Endpoints => "endpoints",
+            Kind::Profile => "profile",
+        }
+    }
+}
+
+// === impl Stale ===
+
+impl Drop for Stale {
+    fn drop(&mut self) {
+        if let Ok(mut stale) = self.snapshots.0.stale.lock() {
+            if let Some(n) = stale.get_mut(&self.key) {
+                *n -= 1;
+                if *n == 0 {
+                    stale.remove(&self.key);
+                }
+            }
+        }
+    }
+}
+
+// === impl Record ===
+
+impl Record {
+    pub(crate) fn new(snapshots: Snapshots, dst: String) -> Self {
+        Self {
+            snapshots,
+            dst,
+            endpoints: IndexMap::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, set: &api::WeightedAddrSet) {
+        for addr in set.addrs.iter() {
+            if let Some(sa) = addr.addr.clone().and_then(pb::to_sock_addr) {
+                // Each endpoint is stored with its set's labels so that it may
+                // be loaded independently.
+                let mut addr = addr.clone();
+                for (k, v) in set.metric_labels.iter() {
+                    addr.metric_labels
+                        .entry(k.clone())
+                        .or_insert_with(|| v.clone());
+                }
+                self.endpoints.insert(sa, addr);
+            }
+        }
+        self.write();
+    }
+
+    pub(crate) fn remove(&mut self, set: &api::AddrSet) {
+        for addr in set.addrs.iter() {
+            if let Some(sa) = pb::to_sock_addr(addr.clone()) {
+                self.endpoints.remove(&sa);
+            }
+        }
+        self.write();
+    }
+
+    pub(crate) fn no_endpoints(&mut self, exists: bool) {
+        self.endpoints.clear();
+        if exists {
+            self.write();
+        } else {
+            self.snapshots
+                .write(Kind::Endpoints, &self.dst, Pending::Remove);
+        }
+    }
+
+    fn write(&self) {
+        let mut buf = Vec::new();
+        for addr in self.endpoints.values() {
+            if addr.encode_length_delimited(&mut buf).is_err() {
+                return;
+            }
+        }
+        self.snapshots
+            .write(Kind::Endpoints, &self.dst, Pending::Write(buf));
+    }
+}
+
+// === impl Resolve ===
+
+impl<R> Resolve<R> {
+    /// When `snapshots` is `None`, destinations are resolved by `inner` alone.
+    pub fn new(snapshots: Option<Snapshots>, timeout: Duration, inner: R) -> Self {
+        Self {
+            snapshots,
+            timeout,
+            inner,
+        }
+    }
+}
+
+impl<T, R> tower::Service<T> for Resolve<R>
+where
+    T: ToString,
+    R: resolve::Resolve<T, Endpoint = Metadata>,
+    R::Future: Send + 'static,
+    R::Resolution: Send + 'static,
+{
+    type Response = Resolution;
+    type Error = Error;
+    type Future = ResolveFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let dst = target.to_string();
+        let resolve = self
+            .inner
+            .resolve(target)
+            .err_into::<Error>()
+            .map_ok(|res| {
+                let res: Resolution = Box::pin(res.err_into::<Error>());
+                res
+            });
+        let snapshots = match self.snapshots.clone() {
+            Some(snapshots) => snapshots,
+            None => return Box::pin(resolve),
+        };
+
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let sleep = time::sleep(timeout);
+            let resolve = match future::select(Box::pin(resolve), Box::pin(sleep)).await {
+                future::Either::Left((res, _)) => return res,
+                future::Either::Right(((), resolve)) => resolve,
+            };
+
+            let endpoints = match snapshots.load_endpoints(&dst).await {
+                Some(endpoints) => endpoints,
+                None => return resolve.await,
+            };
+            info!(%dst, endpoints = %endpoints.len(), "Destination service did not respond; using snapshot");
+            let stale = snapshots.stale(Kind::Endpoints, dst);
+            let res: Resolution = Box::pin(try_stream! {
+                yield Update::Reset(endpoints);
+
+                // The destination service's first update replaces the
+                // snapshot's endpoints.
+                let mut res = resolve.await?;
+                let mut stale = Some(stale);
+                while let Some(update) = res.next().await {
+                    if let Some(stale) = stale.take() {
+                        debug!(dst = %stale.key.1, "Destination service responded");
+                    }
+                    let update = update?;
+                    yield update;
+                }
+            });
+            Ok(res)
+        })
+    }
+}
+
+// === impl Report ===
+
+impl Report {
+    pub fn disabled() -> Self {
+        Report(None)
+    }
+}
+
+impl FmtMetrics for Report {
+    fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let snapshots = match self.0.as_ref() {
+            Some(snapshots) => snapshots,
+            None => return Ok(()),
+        };
+        let stale = match snapshots.0.stale.lock() {
+            Ok(stale) => stale,
+            Err(_) => return Ok(()),
+        };
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        destination_snapshot_stale.fmt_help(f)?;
+        for key in stale.keys() {
+            destination_snapshot_stale.fmt_metric_labeled(f, &Gauge::from(1), &StaleLabels(key))?;
+        }
+        Ok(())
+    }
+}
+
+impl FmtLabels for StaleLabels<'_> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, dst) = self.0;
+        write!(f, "kind=\"{}\",dst=\"{}\"", kind.as_str(), dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::net;
+    use linkerd2_test_util::TempDir;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn tcp_addr(addr: SocketAddr) -> net::TcpAddress {
+        let ip = match addr {
+            SocketAddr::V4(a) => net::ip_address::Ip::Ipv4((*a.ip()).into()),
+            SocketAddr::V6(a) => {
+                let ip = u128::from(*a.ip());
+                net::ip_address::Ip::Ipv6(net::IPv6 {
+                    first: (ip >> 64) as u64,
+                    last: ip as u64,
+                })
+            }
+        };
+        net::TcpAddress {
+            ip: Some(net::IpAddress { ip: Some(ip) }),
+            port: addr.port().into(),
+        }
+    }
+
+    fn snapshots(dir: &TempDir) -> Snapshots {
+        Snapshots::new(dir.path().to_owned(), Duration::from_secs(60)).unwrap()
+    }
+
+    /// A resolver that never responds.
+    #[derive(Clone)]
+    struct Unavailable;
+
+    impl tower::Service<String> for Unavailable {
+        type Response = Resolution;
+        type Error = Error;
+        type Future = future::Pending<Result<Resolution, Error>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: String) -> Self::Future {
+            future::pending()
+        }
+    }
+
+    #[tokio::test]
+    async fn records_endpoints() {
+        time::pause();
+        let dir = TempDir::new();
+        let snapshots = snapshots(&dir);
+        let dst = "web.default.svc.cluster.local:8080";
+        assert!(snapshots.load_endpoints(dst).await.is_none());
+
+        let ep0 = SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 8080));
+        let ep1 = SocketAddr::from((Ipv6Addr::LOCALHOST, 8080));
+        let mut record = Record::new(snapshots.clone(), dst.to_string());
+        let mut metric_labels = HashMap::new();
+        metric_labels.insert("zone".to_string(), "east".to_string());
+        record.add(&api::WeightedAddrSet {
+            addrs: vec![
+                api::WeightedAddr {
+                    addr: Some(tcp_addr(ep0)),
+                    ..Default::default()
+                },
+                api::WeightedAddr {
+                    addr: Some(tcp_addr(ep1)),
+                    ..Default::default()
+                },
+            ],
+            metric_labels,
+        });
+        record.remove(&api::AddrSet {
+            addrs: vec![tcp_addr(ep0)],
+        });
+
+        let endpoints = snapshots.load_endpoints(dst).await.expect("must be stored");
+        assert_eq!(endpoints.len(), 1);
+        assert_eq!(endpoints[0].0, ep1);
+        assert_eq!(endpoints[0].1.labels()["zone"], "east");
+
+        // Only the latest of the destination's snapshots is written.
+        snapshots.flushed().await;
+        let reopened = Snapshots::new(snapshots.0.dir.clone(), Duration::from_secs(60)).unwrap();
+        let endpoints = reopened.load_endpoints(dst).await.expect("must be written");
+        assert_eq!(endpoints.len(), 1);
+        assert_eq!(endpoints[0].0, ep1);
+
+        record.no_endpoints(true);
+        assert_eq!(
+            snapshots.load_endpoints(dst).await.map(|eps| eps.len()),
+            Some(0)
+        );
+
+        record.no_endpoints(false);
+        assert!(snapshots.load_endpoints(dst).await.is_none());
+        snapshots.flushed().await;
+        assert!(reopened.load_endpoints(dst).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn replays_profiles() {
+        time::pause();
+        let dir = TempDir::new();
+        let snapshots = snapshots(&dir);
+        let dst = "web.default.svc.cluster.local:8080";
+        let profile = api::DestinationProfile {
+            fully_qualified_name: "web.default.svc.cluster.local".to_string(),
+            opaque_protocol: true,
+            ..Default::default()
+        };
+        snapshots.store_profile(dst, &profile);
+        snapshots.flushed().await;
+
+        // The profile is served after the proxy restarts.
+        let reopened = Snapshots::new(snapshots.0.dir.clone(), Duration::from_secs(60)).unwrap();
+        assert_eq!(reopened.load_profile(dst).await, Some(profile));
+        assert!(reopened
+            .load_profile("other.default.svc.cluster.local:8080")
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn removes_expired_snapshots() {
+        let dir = TempDir::new();
+        let snapshots = snapshots(&dir);
+        let path = snapshots.path(Kind::Profile, "web.default.svc.cluster.local:8080");
+        fs::write(&path, b"").unwrap();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, b"").unwrap();
+
+        Snapshots::new(snapshots.0.dir.clone(), Duration::from_secs(60)).unwrap();
+        assert!(path.exists(), "recent snapshots must be retained");
+        assert!(
+            !Path::new(&tmp).exists(),
+            "partial snapshots must be removed"
+        );
+
+        Snapshots::new(snapshots.0.dir.clone(), Duration::from_secs(0)).unwrap();
+        assert!(!path.exists(), "expired snapshots must be removed");
+    }
+
+    #[tokio::test]
+    async fn serves_snapshot_when_unavailable() {
+        time::pause();
+        let dir = TempDir::new();
+        let snapshots = snapshots(&dir);
+        let dst = "web.default.svc.cluster.local:8080";
+        let ep = SocketAddr::from((Ipv4Addr::new(10, 0, 0, 1), 8080));
+        Record::new(snapshots.clone(), dst.to_string()).add(&api::WeightedAddrSet {
+            addrs: vec![api::WeightedAddr {
+                addr: Some(tcp_addr(ep)),
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
+        snapshots.flushed().await;
+
+        let mut resolve =
+            Resolve::new(Some(snapshots.clone()), Duration::from_secs(3), Unavailable);
+        let mut resolution = tower::Service::call(&mut resolve, dst.to_string())
+            .await
+            .expect("snapshot must be served");
+        match resolution.next().await {
+            Some(Ok(Update::Reset(eps))) => assert_eq!(eps[0].0, ep),
+            _ => panic!("expected a reset"),
+        }
+
+        let metrics = snapshots.report().as_display().to_string();
+        assert!(metrics.contains(
+            "destination_snapshot_stale{kind=\"endpoints\",dst=\"web.default.svc.cluster.local:8080\"} 1"
+        ));
+        drop(resolution);
+        assert_eq!(snapshots.report().as_display().to_string(), "");
+    }
+}
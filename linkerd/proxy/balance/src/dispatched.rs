@@ -0,0 +1,50 @@
+use crate::hash::hash;
+use std::{
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The number of endpoints a `Dispatched` records. A hedged request is only
+/// dispatched twice, so later copies of a request are not tracked.
+const MAX_DISPATCHED: usize = 4;
+
+/// A request extension that records the endpoints to which copies of a
+/// request have been dispatched.
+///
+/// Balancers dispatch a request that carries this extension to an endpoint
+/// that has not yet received a copy of it, if one is ready. For example, a
+/// hedged request shares its original request's `Dispatched` so that the two
+/// are not sent to the same endpoint.
+#[derive(Clone, Debug, Default)]
+pub struct Dispatched(Arc<Keys>);
+
+/// The hashes of the dispatched endpoints' keys. Slots are claimed by
+/// incrementing `len` so that copies may be dispatched concurrently without
+/// locking.
+#[derive(Debug, Default)]
+struct Keys {
+    len: AtomicUsize,
+    hashes: [AtomicU64; MAX_DISPATCHED],
+}
+
+// === impl Dispatched ===
+
+impl Dispatched {
+    pub(crate) fn contains<K: Hash>(&self, key: &K) -> bool {
+        let key = hash(key);
+        let len = self.0.len.load(Ordering::Acquire).min(MAX_DISPATCHED);
+        self.0.hashes[..len]
+            .iter()
+            .any(|h| h.load(Ordering::Acquire) == key)
+    }
+
+    pub(crate) fn insert<K: Hash>(&self, key: &K) {
+        let i = self.0.len.fetch_add(1, Ordering::AcqRel);
+        if let Some(slot) = self.0.hashes.get(i) {
+            slot.store(hash(key), Ordering::Release);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use fnv::FnvHasher;
+use std::hash::{Hash, Hasher};
+
+/// The number of points each endpoint occupies on the hash ring. More points
+/// spread keys more evenly over endpoints at the cost of memory and slower
+/// updates.
+const POINTS_PER_ENDPOINT: u32 = 160;
+
+/// A request extension that carries the hash of a request's key.
+///
+/// Balancers dispatch requests with the same hash to the same endpoint,
+/// unless it is not ready. Requests without a hash are balanced by load.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RequestHash(pub u64);
+
+/// Places endpoints on a hash ring so that each hash is owned by the first
+/// endpoint at or after it on the ring.
+///
+/// When an endpoint is added or removed, only the hashes adjacent to that
+/// endpoint's points move to another endpoint.
+///
+/// The ring's points are only computed when the ring is first used after its
+/// endpoints change, so that balancers that never dispatch hashed requests do
+/// not maintain a ring.
+#[derive(Debug)]
+pub(crate) struct Ring<K> {
+    keys: Vec<K>,
+    /// Sorted hashes of each endpoint's points, with the index of the
+    /// endpoint's key.
+    points: Vec<(u64, usize)>,
+    /// Marks the endpoints that `iter_from` has returned. This is reused so
+    /// that iterating does not allocate.
+    visited: Vec<bool>,
+    stale: bool,
+}
+
+/// Hashes a request key.
+///
+/// Keys are hashed with FNV, which, unlike the standard library's hasher, is
+/// not randomized and does not change between releases, so that all proxies
+/// route a key to the same endpoint.
+pub fn hash<T: Hash + ?Sized>(key: &T) -> u64 {
+    let mut hasher = FnvHasher::default();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+// === impl Ring ===
+
+impl<K> Default for Ring<K> {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            points: Vec::new(),
+            visited: Vec::new(),
+            stale: false,
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Ring<K> {
+    pub(crate) fn insert(&mut self, key: K) {
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+            self.stale = true;
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) {
+        let len = self.keys.len();
+        self.keys.retain(|k| k != key);
+        self.stale |= self.keys.len() != len;
+    }
+
+    /// Iterates over the ring's endpoints, starting with the owner of `hash`
+    /// and continuing around the ring. Each endpoint is returned once.
+    pub(crate) fn iter_from(&mut self, hash: u64) -> impl Iterator<Item = &K> + '_ {
+        if self.stale {
+            self.rebuild();
+        }
+
+        let start = match self.points.binary_search_by_key(&hash, |&(point, _)| point) {
+            Ok(i) | Err(i) => i,
+        };
+        let Self {
+            keys,
+            points,
+            visited,
+            ..
+        } = self;
+        visited.clear();
+        visited.resize(keys.len(), false);
+        let keys = &*keys;
+        points[start..]
+            .iter()
+            .chain(points[..start].iter())
+            .filter_map(move |&(_, i)| {
+                if std::mem::replace(&mut visited[i], true) {
+                    return None;
+                }
+                Some(&keys[i])
+            })
+            .take(keys.len())
+    }
+
+    fn rebuild(&mut self) {
+        self.points.clear();
+        self.points
+            .reserve(self.keys.len() * POINTS_PER_ENDPOINT as usize);
+        for (idx, key) in self.keys.iter().enumerate() {
+            for i in 0..POINTS_PER_ENDPOINT {
+                self.points.push((hash(&(key, i)), idx));
+            }
+        }
+        self.points.sort_unstable_by_key(|&(point, _)| point);
+        self.stale = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owners(ring: &mut Ring<usize>) -> Vec<usize> {
+        (0..1000u64)
+            .map(|k| *ring.iter_from(hash(&k)).next().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ring_moves_minimal_keys() {
+        let mut ring = Ring::default();
+        for ep in 0..4 {
+            ring.insert(ep);
+        }
+        let before = owners(&mut ring);
+        for ep in 0..4 {
+            assert!(
+                before.iter().filter(|&&o| o == ep).count() > 100,
+                "keys must be spread over all endpoints"
+            );
+        }
+
+        // Adding an endpoint only moves keys to the new endpoint.
+        ring.insert(4);
+        let added = owners(&mut ring);
+        for (b, a) in before.iter().zip(added.iter()) {
+            assert!(a == b || *a == 4);
+        }
+
+        // Removing an endpoint only moves that endpoint's keys.
+        ring.remove(&1);
+        let removed = owners(&mut ring);
+        for (a, r) in added.iter().zip(removed.iter()) {
+            assert!(a == r || *a == 1);
+            assert_ne!(*r, 1);
+        }
+    }
+
+    #[test]
+    fn iterates_each_endpoint_once() {
+        let mut ring = Ring::default();
+        for ep in 0..4 {
+            ring.insert(ep);
+        }
+        let mut eps = ring.iter_from(hash(&0u64)).copied().collect::<Vec<_>>();
+        eps.sort_unstable();
+        assert_eq!(eps, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn hash_is_stable() {
+        // Proxies must agree on the owner of a key, regardless of version.
+        assert_eq!(hash(&b"a"[..]), 0x529a_4ddc_8ff5_6bbf);
+    }
+}
@@ -0,0 +1,17 @@
+#![deny(warnings, rust_2018_idioms)]
+
+mod dispatched;
+pub mod hash;
+pub mod p2c;
+pub mod peak_ewma;
+pub mod slow_start;
+pub mod weight;
+
+pub use self::{
+    dispatched::Dispatched,
+    hash::{hash, RequestHash},
+    p2c::P2c,
+    peak_ewma::{PeakEwma, PeakEwmaDiscover},
+    slow_start::{SlowStart, SlowStartDiscover},
+    weight::{NewWeighted, Weight, Weighted},
+};
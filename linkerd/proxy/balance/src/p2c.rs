@@ -0,0 +1,400 @@
+use crate::{hash::Ring, Dispatched};
+use futures::{prelude::*, ready};
+use linkerd2_error::Error;
+use rand::{thread_rng, Rng};
+use std::{
+    fmt,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::{
+    discover::{Change, Discover},
+    load::Load,
+    ready_cache::{error::Failed, ReadyCache},
+};
+use tracing::{debug, trace};
+
+/// Balances requests over a `Discover`ed set of endpoints by choosing the
+/// less-loaded of two random ready endpoints.
+///
+/// Requests that are dispatched with a hash are instead dispatched by
+/// consistent hashing: endpoints are placed on a hash ring and each request is
+/// dispatched to the first ready endpoint at or after its hash on the ring.
+///
+/// Unlike `tower::balance::p2c::Balance`, endpoints are chosen as each request
+/// is dispatched, so that a request may avoid the endpoints to which copies of
+/// it were already `Dispatched`.
+pub struct P2c<D, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    discover: D,
+    ring: Ring<D::Key>,
+    services: ReadyCache<D::Key, D::Service, Req>,
+}
+
+// === impl P2c ===
+
+impl<D, Req> P2c<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Eq + Clone,
+    D::Error: Into<Error>,
+    D::Service: tower::Service<Req> + Load,
+    <D::Service as Load>::Metric: PartialOrd + fmt::Debug,
+    <D::Service as tower::Service<Req>>::Error: Into<Error>,
+{
+    pub fn new(discover: D) -> Self {
+        Self {
+            discover,
+            ring: Ring::default(),
+            services: ReadyCache::default(),
+        }
+    }
+
+    /// Returns ready when at least one endpoint is ready.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let _ = self.update_from_discover(cx)?;
+        self.promote_pending_to_ready(cx);
+
+        if self.services.ready_len() == 0 {
+            // We have previously registered interest in updates from discover
+            // and pending services.
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Dispatches a request to the less-loaded of two random ready endpoints
+    /// or, if the request has a `hash`, to the endpoint that owns it.
+    ///
+    /// If the owning endpoint is not ready, the request is dispatched to the
+    /// next ready endpoint on the ring. Endpoints in `dispatched` are only
+    /// chosen if no other endpoint is ready. The chosen endpoint is added to
+    /// `dispatched`.
+    ///
+    /// # Panics
+    ///
+    /// If `poll_ready` has not returned ready.
+    pub fn call(
+        &mut self,
+        hash: Option<u64>,
+        dispatched: Option<&Dispatched>,
+        req: Req,
+    ) -> future::ErrInto<<D::Service as tower::Service<Req>>::Future, Error> {
+        let index = match hash {
+            Some(hash) => self.hash_ready_index(hash, dispatched),
+            None => self.p2c_ready_index(dispatched),
+        }
+        .expect("called before ready");
+        if let Some(dispatched) = dispatched {
+            let (key, _) = self.services.get_ready_index(index).expect("invalid index");
+            dispatched.insert(key);
+        }
+        self.services.call_ready_index(index, req).err_into()
+    }
+
+    fn hash_ready_index(&mut self, hash: u64, dispatched: Option<&Dispatched>) -> Option<usize> {
+        let services = &self.services;
+        let mut skipped = None;
+        for key in self.ring.iter_from(hash) {
+            if let Some((index, _, _)) = services.get_ready(key) {
+                if !dispatched.map(|d| d.contains(key)).unwrap_or(false) {
+                    return Some(index);
+                }
+                skipped = skipped.or(Some(index));
+            }
+        }
+        if skipped.is_some() {
+            trace!("All ready endpoints were dispatched to");
+        }
+        skipped
+    }
+
+    fn p2c_ready_index(&self, dispatched: Option<&Dispatched>) -> Option<usize> {
+        let services = &self.services;
+        let is_undispatched = |i: usize| match (dispatched, services.get_ready_index(i)) {
+            (Some(dispatched), Some((key, _))) => !dispatched.contains(key),
+            _ => true,
+        };
+
+        // Only endpoints that have not received a copy of the request are
+        // candidates, unless every ready endpoint has.
+        let ready = services.ready_len();
+        let mut candidates = match dispatched {
+            Some(_) => (0..ready).filter(|&i| is_undispatched(i)).count(),
+            None => ready,
+        };
+        // Candidates are only looked up by filtering when some, but not all,
+        // ready endpoints are excluded.
+        let filter = candidates != 0 && candidates != ready;
+        if candidates == 0 && ready != 0 {
+            trace!("All ready endpoints were dispatched to");
+            candidates = ready;
+        }
+        // Returns the index of the `n`th candidate.
+        let nth = |n: usize| {
+            if filter {
+                (0..ready).filter(|&i| is_undispatched(i)).nth(n)
+            } else {
+                Some(n)
+            }
+        };
+
+        match candidates {
+            0 => None,
+            1 => nth(0),
+            len => {
+                // Compare the loads of two distinct random endpoints.
+                let (a, b) = sample2(len);
+                let aidx = nth(a)?;
+                let bidx = nth(b)?;
+                let aload = self.ready_index_load(aidx);
+                let bload = self.ready_index_load(bidx);
+                let chosen = if aload <= bload { aidx } else { bidx };
+                trace!(
+                    a.index = aidx,
+                    a.load = ?aload,
+                    b.index = bidx,
+                    b.load = ?bload,
+                    chosen = if chosen == aidx { "a" } else { "b" },
+                    "p2c",
+                );
+                Some(chosen)
+            }
+        }
+    }
+
+    fn ready_index_load(&self, index: usize) -> <D::Service as Load>::Metric {
+        let (_, svc) = self.services.get_ready_index(index).expect("invalid index");
+        svc.load()
+    }
+
+    fn update_from_discover(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<(), Error>>> {
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(Into::into)?
+            {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    trace!("remove");
+                    self.ring.remove(&key);
+                    self.services.evict(&key);
+                }
+                Some(Change::Insert(key, svc)) => {
+                    trace!("insert");
+                    self.ring.insert(key.clone());
+                    self.services.push(key, svc);
+                }
+            }
+        }
+    }
+
+    fn promote_pending_to_ready(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.services.poll_pending(cx) {
+                Poll::Ready(Ok(())) | Poll::Pending => break,
+                Poll::Ready(Err(Failed(key, error))) => {
+                    // The endpoint was lost, so its hashes move to its
+                    // neighbors.
+                    debug!(%error, "dropping failed endpoint");
+                    self.ring.remove(&key);
+                }
+            }
+        }
+        trace!(
+            ready = %self.services.ready_len(),
+            pending = %self.services.pending_len(),
+            "poll_unready"
+        );
+    }
+}
+
+/// Samples two distinct indices in `0..len` without allocating.
+///
+/// # Panics
+///
+/// If `len` is less than 2.
+fn sample2(len: usize) -> (usize, usize) {
+    debug_assert!(len >= 2);
+    let mut rng = thread_rng();
+    let a = rng.gen_range(0, len);
+    let b = rng.gen_range(0, len - 1);
+    // Skip over `a`, so that every other index is equally likely.
+    (a, if b >= a { b + 1 } else { b })
+}
+
+impl<D, Req> tower::Service<Req> for P2c<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Eq + Clone,
+    D::Error: Into<Error>,
+    D::Service: tower::Service<Req> + Load,
+    <D::Service as Load>::Metric: PartialOrd + fmt::Debug,
+    <D::Service as tower::Service<Req>>::Error: Into<Error>,
+{
+    type Response = <D::Service as tower::Service<Req>>::Response;
+    type Error = Error;
+    type Future = future::ErrInto<<D::Service as tower::Service<Req>>::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        P2c::poll_ready(self, cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        P2c::call(self, None, None, req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::hash;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use tower::{discover::ServiceList, load::Constant};
+
+    #[tokio::test]
+    async fn avoids_dispatched_endpoints() {
+        let mut balance = P2c::new(ServiceList::new(endpoints(3)));
+
+        for _ in 0..20 {
+            let dispatched = Dispatched::default();
+            let mut endpoints = Vec::new();
+            for _ in 0..3 {
+                future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+                endpoints.push(balance.call(None, Some(&dispatched), ()).await.unwrap());
+            }
+            endpoints.sort_unstable();
+            assert_eq!(
+                endpoints,
+                vec![0, 1, 2],
+                "each copy must use a new endpoint"
+            );
+
+            // Once every endpoint has been dispatched to, any may be chosen.
+            future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+            assert!(balance.call(None, Some(&dispatched), ()).await.is_ok());
+        }
+    }
+
+    #[test]
+    fn samples_distinct_indices() {
+        let mut sampled = [0; 3];
+        for _ in 0..1000 {
+            let (a, b) = sample2(3);
+            assert_ne!(a, b);
+            sampled[a] += 1;
+            sampled[b] += 1;
+        }
+        assert!(sampled.iter().all(|&n| n > 0), "{:?}", sampled);
+    }
+
+    #[tokio::test]
+    async fn dispatches_by_hash() {
+        let mut balance = P2c::new(ServiceList::new(endpoints(4)));
+
+        for key in 0..100u64 {
+            let mut endpoints = Vec::new();
+            for _ in 0..3 {
+                future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+                endpoints.push(balance.call(Some(hash(&key)), None, ()).await.unwrap());
+            }
+            assert!(
+                endpoints.iter().all(|&ep| ep == endpoints[0]),
+                "key {} must be dispatched to a single endpoint: {:?}",
+                key,
+                endpoints
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn hashed_requests_skip_dispatched_endpoints() {
+        let mut balance = P2c::new(ServiceList::new(endpoints(4)));
+
+        for key in 0..100u64 {
+            future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+            let owner = balance.call(Some(hash(&key)), None, ()).await.unwrap();
+
+            // A copy of the request moves to the next endpoint on the ring.
+            let dispatched = Dispatched::default();
+            future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+            let first = balance.call(Some(hash(&key)), Some(&dispatched), ());
+            assert_eq!(first.await.unwrap(), owner);
+            future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+            let second = balance.call(Some(hash(&key)), Some(&dispatched), ());
+            assert_ne!(second.await.unwrap(), owner);
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_along_ring() {
+        let mut ring = Ring::default();
+        for i in 0..3usize {
+            ring.insert(i);
+        }
+
+        for key in 0..100u64 {
+            let order = ring.iter_from(hash(&key)).copied().collect::<Vec<_>>();
+            let ready = (0..3)
+                .map(|_| Arc::new(AtomicBool::new(true)))
+                .collect::<Vec<_>>();
+            let svcs = ready.iter().enumerate().map(|(id, ready)| {
+                let ready = ready.clone();
+                Constant::new(Toggle { ready, id }, 0)
+            });
+            let mut balance = P2c::new(ServiceList::new(svcs.collect::<Vec<_>>()));
+
+            // While the owner is not ready, its requests are dispatched to the
+            // next endpoint on the ring.
+            ready[order[0]].store(false, Ordering::SeqCst);
+            future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+            let ep = balance.call(Some(hash(&key)), None, ()).await.unwrap();
+            assert_eq!(ep, order[1]);
+
+            ready[order[0]].store(true, Ordering::SeqCst);
+            future::poll_fn(|cx| balance.poll_ready(cx)).await.unwrap();
+            let ep = balance.call(Some(hash(&key)), None, ()).await.unwrap();
+            assert_eq!(ep, order[0]);
+        }
+    }
+
+    fn endpoints(
+        n: usize,
+    ) -> Vec<Constant<impl tower::Service<(), Response = usize, Error = Error>, usize>> {
+        (0..n)
+            .map(|i| Constant::new(tower::service_fn(move |()| future::ok::<_, Error>(i)), 0))
+            .collect()
+    }
+
+    /// An endpoint that is ready only while its flag is set.
+    struct Toggle {
+        ready: Arc<AtomicBool>,
+        id: usize,
+    }
+
+    impl tower::Service<()> for Toggle {
+        type Response = usize;
+        type Error = Error;
+        type Future = future::Ready<Result<usize, Error>>;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            if self.ready.load(Ordering::SeqCst) {
+                return Poll::Ready(Ok(()));
+            }
+            // Check again on the next poll.
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+
+        fn call(&mut self, (): ()) -> Self::Future {
+            future::ok(self.id)
+        }
+    }
+}
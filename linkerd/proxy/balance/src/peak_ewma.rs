@@ -0,0 +1,286 @@
+//! A PeakEWMA load metric that is scaled by each endpoint's weight.
+//!
+//! Modified from tower's `PeakEwma`, whose `Cost` does not expose its value,
+//! so it cannot be scaled.
+
+use crate::weight::{Weight, Weighted};
+use futures::{ready, Stream};
+use pin_project::pin_project;
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower::{
+    discover::{Change, Discover},
+    load::{
+        completion::{TrackCompletion, TrackCompletionFuture},
+        Load,
+    },
+};
+use tracing::trace;
+
+const NANOS_PER_MILLI: f64 = 1_000_000.0;
+
+/// Wraps `Weighted` endpoints with `PeakEwma`.
+#[pin_project]
+#[derive(Debug)]
+pub struct PeakEwmaDiscover<D, C> {
+    #[pin]
+    discover: D,
+    decay_ns: f64,
+    default_rtt: Duration,
+    completion: C,
+}
+
+/// Measures an endpoint's load as its peak-sensitive EWMA of response
+/// latencies, multiplied by its number of pending requests plus one and
+/// divided by its weight.
+///
+/// An endpoint with twice the weight of another may therefore have twice as
+/// many pending requests (or twice the latency) before it is considered as
+/// loaded.
+#[derive(Debug)]
+pub struct PeakEwma<S, C> {
+    service: S,
+    weight: Weight,
+    decay_ns: f64,
+    rtt_estimate: Arc<Mutex<RttEstimate>>,
+    completion: C,
+}
+
+/// The load of a `PeakEwma` endpoint.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost(f64);
+
+/// Updates an endpoint's RTT estimate when a request completes.
+#[derive(Debug)]
+pub struct Handle {
+    sent_at: Instant,
+    decay_ns: f64,
+    rtt_estimate: Arc<Mutex<RttEstimate>>,
+}
+
+#[derive(Debug)]
+struct RttEstimate {
+    update_at: Instant,
+    rtt_ns: f64,
+}
+
+// === impl PeakEwmaDiscover ===
+
+impl<D, C> PeakEwmaDiscover<D, C> {
+    pub fn new(discover: D, default_rtt: Duration, decay: Duration, completion: C) -> Self {
+        Self {
+            discover,
+            decay_ns: nanos(decay),
+            default_rtt,
+            completion,
+        }
+    }
+}
+
+impl<D, S, C> Stream for PeakEwmaDiscover<D, C>
+where
+    D: Discover<Service = Weighted<S>>,
+    C: Clone,
+{
+    type Item = Result<Change<D::Key, PeakEwma<S, C>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)) {
+            None => return Poll::Ready(None),
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            Some(Ok(change)) => change,
+        };
+        let change = match change {
+            Change::Insert(key, svc) => {
+                let (service, weight) = svc.into_parts();
+                let svc = PeakEwma::new(
+                    service,
+                    weight,
+                    *this.default_rtt,
+                    *this.decay_ns,
+                    this.completion.clone(),
+                );
+                Change::Insert(key, svc)
+            }
+            Change::Remove(key) => Change::Remove(key),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// === impl PeakEwma ===
+
+impl<S, C> PeakEwma<S, C> {
+    fn new(
+        service: S,
+        weight: Weight,
+        default_rtt: Duration,
+        decay_ns: f64,
+        completion: C,
+    ) -> Self {
+        debug_assert!(decay_ns > 0.0, "decay_ns must be positive");
+        Self {
+            service,
+            weight,
+            decay_ns,
+            rtt_estimate: Arc::new(Mutex::new(RttEstimate::new(nanos(default_rtt)))),
+            completion,
+        }
+    }
+
+    fn handle(&self) -> Handle {
+        Handle {
+            decay_ns: self.decay_ns,
+            sent_at: Instant::now(),
+            rtt_estimate: self.rtt_estimate.clone(),
+        }
+    }
+}
+
+impl<S, C, Req> tower::Service<Req> for PeakEwma<S, C>
+where
+    S: tower::Service<Req>,
+    C: TrackCompletion<Handle, S::Response>,
+{
+    type Response = C::Output;
+    type Error = S::Error;
+    type Future = TrackCompletionFuture<S::Future, C, Handle>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Req) -> Self::Future {
+        TrackCompletionFuture::new(
+            self.completion.clone(),
+            self.handle(),
+            self.service.call(req),
+        )
+    }
+}
+
+impl<S, C> Load for PeakEwma<S, C> {
+    type Metric = Cost;
+
+    fn load(&self) -> Self::Metric {
+        // Each pending request holds a reference to the estimate.
+        let pending = Arc::strong_count(&self.rtt_estimate) as u32 - 1;
+        let estimate = match self.rtt_estimate.lock() {
+            Ok(mut rtt) => rtt.decay(self.decay_ns),
+            Err(poisoned) => poisoned.into_inner().decay(self.decay_ns),
+        };
+
+        let cost = Cost(estimate * f64::from(pending + 1) / self.weight.factor());
+        trace!(
+            estimate.ms = estimate / NANOS_PER_MILLI,
+            pending,
+            weight = self.weight.0,
+            ?cost,
+            "load"
+        );
+        cost
+    }
+}
+
+// === impl RttEstimate ===
+
+impl RttEstimate {
+    fn new(rtt_ns: f64) -> Self {
+        debug_assert!(0.0 < rtt_ns, "rtt must be positive");
+        Self {
+            rtt_ns,
+            update_at: Instant::now(),
+        }
+    }
+
+    /// Decays the estimate with the time elapsed since it was last updated.
+    fn decay(&mut self, decay_ns: f64) -> f64 {
+        let now = Instant::now();
+        self.update(now, now, decay_ns)
+    }
+
+    /// Updates the estimate with an observed RTT.
+    ///
+    /// The estimate jumps to any RTT above it and otherwise decays towards
+    /// the observed RTT.
+    fn update(&mut self, sent_at: Instant, recv_at: Instant, decay_ns: f64) -> f64 {
+        let rtt = nanos(recv_at.saturating_duration_since(sent_at));
+        let now = Instant::now();
+
+        self.rtt_ns = if self.rtt_ns < rtt {
+            trace!(rtt.ms = rtt / NANOS_PER_MILLI, "update peak");
+            rtt
+        } else {
+            let elapsed = nanos(now.saturating_duration_since(self.update_at));
+            let decay = (-elapsed / decay_ns).exp();
+            let recency = 1.0 - decay;
+            (self.rtt_ns * decay) + (rtt * recency)
+        };
+        self.update_at = now;
+
+        self.rtt_ns
+    }
+}
+
+// === impl Handle ===
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let recv_at = Instant::now();
+        if let Ok(mut rtt) = self.rtt_estimate.lock() {
+            rtt.update(self.sent_at, recv_at, self.decay_ns);
+        }
+    }
+}
+
+fn nanos(d: Duration) -> f64 {
+    const NANOS_PER_SEC: u64 = 1_000_000_000;
+    let n = f64::from(d.subsec_nanos());
+    let s = d.as_secs().saturating_mul(NANOS_PER_SEC) as f64;
+    n + s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::load::CompleteOnResponse;
+
+    fn svc(weight: u32) -> PeakEwma<(), CompleteOnResponse> {
+        PeakEwma::new(
+            (),
+            Weight(weight),
+            Duration::from_millis(10),
+            nanos(Duration::from_secs(10)),
+            CompleteOnResponse::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn scales_cost_by_weight() {
+        tokio::time::pause();
+        let light = svc(1);
+        let heavy = svc(2);
+        assert!(heavy.load() < light.load());
+
+        // The heavier endpoint is as loaded as the lighter one when it has
+        // twice as many requests in flight.
+        let _h0 = heavy.handle();
+        let _h1 = heavy.handle();
+        let _h2 = heavy.handle();
+        let _l0 = light.handle();
+        assert_eq!(heavy.load(), light.load());
+    }
+
+    #[tokio::test]
+    async fn zero_weight_is_minimal() {
+        tokio::time::pause();
+        assert_eq!(svc(0).load(), svc(1).load());
+    }
+}
@@ -0,0 +1,163 @@
+use futures::{ready, Stream};
+use pin_project::pin_project;
+use rand::{thread_rng, Rng};
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower::{
+    discover::{Change, Discover},
+    load::Load,
+};
+
+/// The weight of an endpoint when it is first discovered.
+const MIN_WEIGHT: f64 = 0.1;
+
+/// Wraps newly discovered endpoints with `SlowStart`.
+#[pin_project]
+#[derive(Debug)]
+pub struct SlowStartDiscover<D> {
+    #[pin]
+    discover: D,
+    window: Duration,
+}
+
+/// Ramps an endpoint's weight from `MIN_WEIGHT` to 1 over the slow-start
+/// window, starting when the endpoint is discovered.
+///
+/// While an endpoint's weight is below 1, each time its load is compared, the
+/// endpoint is marked as warming with a probability of `1 - weight`. Warming
+/// endpoints compare as more loaded than any warm endpoint, so a power of two
+/// choices balancer dispatches a fraction of the requests it otherwise would
+/// to the endpoint.
+#[derive(Debug)]
+pub struct SlowStart<S> {
+    inner: S,
+    start: Instant,
+    window: Duration,
+}
+
+/// An endpoint's load, adjusted for slow-start.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct SlowStartLoad<M> {
+    warming: bool,
+    load: M,
+}
+
+// === impl SlowStartDiscover ===
+
+impl<D> SlowStartDiscover<D> {
+    pub fn new(discover: D, window: Duration) -> Self {
+        Self { discover, window }
+    }
+}
+
+impl<D: Discover> Stream for SlowStartDiscover<D> {
+    type Item = Result<Change<D::Key, SlowStart<D::Service>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)) {
+            None => return Poll::Ready(None),
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            Some(Ok(change)) => change,
+        };
+        let change = match change {
+            Change::Insert(key, svc) => Change::Insert(key, SlowStart::new(svc, *this.window)),
+            Change::Remove(key) => Change::Remove(key),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// === impl SlowStart ===
+
+impl<S> SlowStart<S> {
+    fn new(inner: S, window: Duration) -> Self {
+        Self {
+            inner,
+            start: Instant::now(),
+            window,
+        }
+    }
+
+    fn weight(&self) -> f64 {
+        let elapsed = self.start.elapsed();
+        if elapsed >= self.window {
+            return 1.0;
+        }
+        let ramp = elapsed.as_secs_f64() / self.window.as_secs_f64();
+        MIN_WEIGHT + (1.0 - MIN_WEIGHT) * ramp
+    }
+}
+
+impl<S: Load> Load for SlowStart<S> {
+    type Metric = SlowStartLoad<S::Metric>;
+
+    fn load(&self) -> Self::Metric {
+        let weight = self.weight();
+        SlowStartLoad {
+            warming: weight < 1.0 && !thread_rng().gen_bool(weight),
+            load: self.inner.load(),
+        }
+    }
+}
+
+impl<S, Req> tower::Service<Req> for SlowStart<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn ramps_weight() {
+        time::pause();
+        let svc = SlowStart::new((), Duration::from_secs(10));
+        assert!((svc.weight() - MIN_WEIGHT).abs() < 0.001);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert!((svc.weight() - 0.55).abs() < 0.001);
+
+        time::advance(Duration::from_secs(5)).await;
+        assert!((svc.weight() - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn warming_endpoints_compare_as_loaded() {
+        let warming = SlowStartLoad {
+            warming: true,
+            load: 1,
+        };
+        let warm = SlowStartLoad {
+            warming: false,
+            load: 10,
+        };
+        assert!(warming > warm);
+    }
+
+    #[test]
+    fn zero_window_is_warm() {
+        let svc = SlowStart::new((), Duration::from_secs(0));
+        assert!((svc.weight() - 1.0).abs() < f64::EPSILON);
+    }
+}
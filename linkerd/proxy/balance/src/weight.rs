@@ -0,0 +1,92 @@
+use linkerd2_stack::{layer, NewService};
+use std::task::{Context, Poll};
+
+/// An endpoint's weight, relative to the other endpoints in its balancer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Weight(pub u32);
+
+/// Builds `Weighted` endpoint services so that balancers can scale each
+/// endpoint's load by its weight.
+#[derive(Clone, Debug)]
+pub struct NewWeighted<N> {
+    inner: N,
+}
+
+/// An endpoint service annotated with its `Weight`.
+#[derive(Debug)]
+pub struct Weighted<S> {
+    inner: S,
+    weight: Weight,
+}
+
+// === impl Weight ===
+
+impl Default for Weight {
+    fn default() -> Self {
+        Weight(1)
+    }
+}
+
+impl Weight {
+    /// Returns the weight as a non-zero factor by which an endpoint's load
+    /// is divided.
+    pub(crate) fn factor(self) -> f64 {
+        f64::from(self.0.max(1))
+    }
+}
+
+// === impl NewWeighted ===
+
+impl<N> NewWeighted<N> {
+    pub fn layer() -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(|inner| Self { inner })
+    }
+}
+
+impl<T, N> NewService<T> for NewWeighted<N>
+where
+    for<'t> &'t T: Into<Weight>,
+    N: NewService<T>,
+{
+    type Service = Weighted<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        let weight = (&target).into();
+        Weighted::new(self.inner.new_service(target), weight)
+    }
+}
+
+// === impl Weighted ===
+
+impl<S> Weighted<S> {
+    pub fn new(inner: S, weight: Weight) -> Self {
+        Self { inner, weight }
+    }
+
+    pub fn weight(&self) -> Weight {
+        self.weight
+    }
+
+    pub fn into_parts(self) -> (S, Weight) {
+        (self.inner, self.weight)
+    }
+}
+
+impl<S, Req> tower::Service<Req> for Weighted<S>
+where
+    S: tower::Service<Req>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: Req) -> Self::Future {
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,462 @@
+//! Resolves endpoints from a local file rather than from the destination
+//! service.
+//!
+//! The file maps each destination's authority to its endpoints, encoded as
+//! YAML or, if the file's extension is `.json`, as JSON:
+//!
+//! ```yaml
+//! web.default.svc.cluster.local:8080:
+//! - addr: 10.1.0.1:8080
+//!   labels:
+//!     pod: web-5f7d8c-x2xbk
+//!   identity: web.default.serviceaccount.identity.linkerd.cluster.local
+//!   protocolHint: h2
+//! - addr: 10.1.0.2:8080
+//!   weight: 5000
+//! ```
+//!
+//! The file is polled for changes, and each resolution is updated as its
+//! destination's endpoints change. The file is only parsed again when its
+//! modification time or length changes. If the file cannot be read or parsed,
+//! the last valid endpoints are retained.
+
+#![deny(warnings, rust_2018_idioms)]
+
+use futures::{future, prelude::*, select_biased, stream};
+use indexmap::IndexMap;
+use linkerd2_addr::{Addr, NameAddr};
+use linkerd2_error::Error;
+use linkerd2_identity as identity;
+use linkerd2_proxy_api_resolve::{Metadata, ProtocolHint};
+use linkerd2_proxy_core::resolve::{Resolve, Update};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::{sync::watch, task, time};
+use tracing::{debug, trace, warn};
+use tracing_futures::Instrument;
+
+/// Resolves destinations from a watched file.
+///
+/// Destinations that are not in the file do not exist until they are added.
+#[derive(Clone, Debug)]
+pub struct FileResolve {
+    endpoints: watch::Receiver<Arc<Endpoints>>,
+}
+
+/// Resolves destinations from a watched file in place of an inner `Resolve`.
+///
+/// Whether a destination is resolved from the file is determined when its
+/// resolution starts, so adding or removing a destination from the file
+/// does not affect resolutions that are already in progress.
+#[derive(Clone, Debug)]
+pub struct Overrides<R> {
+    local: Option<FileResolve>,
+    inner: R,
+}
+
+pub type UpdateStream = Pin<Box<dyn Stream<Item = Result<Update<Metadata>, Error>> + Send>>;
+
+pub type ResolveFuture = Pin<Box<dyn Future<Output = Result<UpdateStream, Error>> + Send>>;
+
+/// Endpoints, indexed by their destination's authority.
+type Endpoints = HashMap<String, IndexMap<SocketAddr, Metadata>>;
+
+/// The file's modification time and length when it was last read, if its
+/// modification time is known.
+type Stamp = Option<(SystemTime, u64)>;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct EndpointSpec {
+    addr: SocketAddr,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    identity: Option<String>,
+    protocol_hint: Option<String>,
+    weight: Option<u32>,
+}
+
+// === impl FileResolve ===
+
+impl FileResolve {
+    /// Loads endpoints from `path`, polling it for changes every `interval`.
+    ///
+    /// Fails if the file cannot be read or parsed. Must be called on a Tokio
+    /// runtime, since the file is polled on a background task. The file is
+    /// read on the runtime's blocking threads.
+    pub fn watch(path: PathBuf, interval: Duration) -> Result<Self, Error> {
+        let mut stamp = None;
+        let mut published = read_changed(&path, &mut stamp)?.unwrap_or_default();
+        let (tx, endpoints) = watch::channel(Arc::new(published.clone()));
+
+        let poll = async move {
+            let path = Arc::new(path);
+            loop {
+                select_biased! {
+                    _ = tx.closed().fuse() => {
+                        trace!("Resolver dropped");
+                        return;
+                    },
+                    _ = time::sleep(interval).fuse() => {},
+                }
+
+                let read = {
+                    let path = path.clone();
+                    task::spawn_blocking(move || {
+                        let res = read_changed(&path, &mut stamp);
+                        (stamp, res)
+                    })
+                };
+                let res = match read.await {
+                    Ok((read, res)) => {
+                        stamp = read;
+                        res
+                    }
+                    Err(error) => {
+                        warn!(%error, "Endpoints file watch failed");
+                        return;
+                    }
+                };
+                let endpoints = match res {
+                    Ok(Some(endpoints)) => endpoints,
+                    Ok(None) => {
+                        trace!("Endpoints file unchanged");
+                        continue;
+                    }
+                    Err(error) => {
+                        warn!(%error, file = %path.display(), "Failed to load endpoints");
+                        continue;
+                    }
+                };
+                if endpoints != published {
+                    debug!(destinations = endpoints.len(), "Endpoints changed");
+                    if tx.send(Arc::new(endpoints.clone())).is_err() {
+                        return;
+                    }
+                    published = endpoints;
+                }
+            }
+        };
+        tokio::spawn(poll.in_current_span());
+
+        Ok(Self { endpoints })
+    }
+
+    /// Resolves this file's destinations in place of those from `inner`.
+    pub fn with_overrides_of<R>(self, inner: R) -> Overrides<R> {
+        Overrides::new(Some(self), inner)
+    }
+
+    fn contains(&self, authority: &str) -> bool {
+        self.endpoints.borrow().contains_key(authority)
+    }
+
+    /// Streams updates to a destination's endpoints.
+    fn resolution(&self, authority: String) -> UpdateStream {
+        let endpoints = self.endpoints.clone();
+        let init = endpoints.borrow().get(&authority).cloned();
+        let updates = stream::unfold(
+            (endpoints, init, true),
+            move |(mut endpoints, current, first)| {
+                let authority = authority.clone();
+                async move {
+                    if first {
+                        let update = match current.as_ref() {
+                            Some(eps) => Update::Reset(eps.clone().into_iter().collect()),
+                            None => Update::DoesNotExist,
+                        };
+                        return Some((vec![update], (endpoints, current, false)));
+                    }
+
+                    loop {
+                        if endpoints.changed().await.is_err() {
+                            trace!("Resolver dropped");
+                            return None;
+                        }
+                        let next = endpoints.borrow().get(&authority).cloned();
+                        let updates = diff(current.as_ref(), next.as_ref());
+                        if !updates.is_empty() {
+                            debug!(%authority, ?updates, "Endpoints changed");
+                            return Some((updates, (endpoints, next, false)));
+                        }
+                    }
+                }
+            },
+        );
+        Box::pin(updates.flat_map(|updates| stream::iter(updates.into_iter().map(Ok))))
+    }
+}
+
+impl<T: Into<Addr>> tower::Service<T> for FileResolve {
+    type Response = UpdateStream;
+    type Error = Error;
+    type Future = future::Ready<Result<UpdateStream, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        match target.into() {
+            Addr::Name(na) => future::ok(self.resolution(authority(&na))),
+            Addr::Socket(sa) => {
+                let eps = vec![(sa, Metadata::default())];
+                let updates: UpdateStream = Box::pin(stream::iter(Some(Ok(Update::Reset(eps)))));
+                future::ok(updates)
+            }
+        }
+    }
+}
+
+// === impl Overrides ===
+
+impl<R> Overrides<R> {
+    /// When `local` is `None`, destinations are resolved by `inner` alone.
+    pub fn new(local: Option<FileResolve>, inner: R) -> Self {
+        Self { local, inner }
+    }
+}
+
+impl<T, R> tower::Service<T> for Overrides<R>
+where
+    T: Into<Addr> + Clone,
+    R: Resolve<T, Endpoint = Metadata>,
+    R::Future: Send + 'static,
+    R::Resolution: Send + 'static,
+{
+    type Response = UpdateStream;
+    type Error = Error;
+    type Future = ResolveFuture;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        if let Some(local) = self.local.as_ref() {
+            if let Addr::Name(na) = target.clone().into() {
+                let authority = authority(&na);
+                if local.contains(&authority) {
+                    debug!(%authority, "Resolving from file");
+                    return Box::pin(future::ok(local.resolution(authority)));
+                }
+            }
+        }
+
+        Box::pin(
+            self.inner
+                .resolve(target)
+                .err_into::<Error>()
+                .map_ok(|res| {
+                    let res: UpdateStream = Box::pin(res.err_into::<Error>());
+                    res
+                }),
+        )
+    }
+}
+
+/// Returns the key under which a destination's endpoints are configured.
+fn authority(na: &NameAddr) -> String {
+    format!("{}:{}", na.name().without_trailing_dot(), na.port())
+}
+
+/// Computes the updates that transform one set of endpoints into another.
+fn diff(
+    prev: Option<&IndexMap<SocketAddr, Metadata>>,
+    next: Option<&IndexMap<SocketAddr, Metadata>>,
+) -> Vec<Update<Metadata>> {
+    match (prev, next) {
+        (None, None) => vec![],
+        (Some(_), None) => vec![Update::DoesNotExist],
+        (None, Some(next)) => vec![Update::Reset(next.clone().into_iter().collect())],
+        (Some(prev), Some(next)) => {
+            let mut updates = Vec::new();
+            let removed = prev
+                .keys()
+                .filter(|addr| !next.contains_key(*addr))
+                .copied()
+                .collect::<Vec<_>>();
+            if !removed.is_empty() {
+                updates.push(Update::Remove(removed));
+            }
+            let added = next
+                .iter()
+                .filter(|(addr, meta)| prev.get(*addr) != Some(*meta))
+                .map(|(addr, meta)| (*addr, meta.clone()))
+                .collect::<Vec<_>>();
+            if !added.is_empty() {
+                updates.push(Update::Add(added));
+            }
+            updates
+        }
+    }
+}
+
+/// Reads the file unless its modification time and length match `stamp`,
+/// which is updated to the file's current stamp.
+fn read_changed(path: &Path, stamp: &mut Stamp) -> Result<Option<Endpoints>, Error> {
+    let meta = fs::metadata(path)?;
+    let next = meta.modified().ok().map(|modified| (modified, meta.len()));
+    if next.is_some() && next == *stamp {
+        return Ok(None);
+    }
+    *stamp = next;
+    read_file(path).map(Some)
+}
+
+fn read_file(path: &Path) -> Result<Endpoints, Error> {
+    let bytes = fs::read(path)?;
+    let specs: HashMap<String, Vec<EndpointSpec>> =
+        if path.extension().and_then(|e| e.to_str()) == Some("json") {
+            serde_json::from_slice(&bytes)?
+        } else {
+            serde_yaml::from_slice(&bytes)?
+        };
+
+    let mut endpoints = HashMap::with_capacity(specs.len());
+    for (name, eps) in specs.into_iter() {
+        let na = NameAddr::from_str(&name)
+            .map_err(|_| format!("invalid destination authority: {}", name))?;
+        let eps = eps
+            .into_iter()
+            .map(|ep| Ok((ep.addr, ep.into_metadata()?)))
+            .collect::<Result<IndexMap<_, _>, Error>>()?;
+        endpoints.insert(authority(&na), eps);
+    }
+    Ok(endpoints)
+}
+
+// === impl EndpointSpec ===
+
+impl EndpointSpec {
+    fn into_metadata(self) -> Result<Metadata, Error> {
+        let identity = match self.identity {
+            Some(id) => Some(
+                identity::Name::from_str(&id)
+                    .map_err(|_| format!("invalid endpoint identity: {}", id))?,
+            ),
+            None => None,
+        };
+        let protocol_hint = match self.protocol_hint.as_deref() {
+            None | Some("unknown") => ProtocolHint::Unknown,
+            Some("h2") | Some("http2") => ProtocolHint::Http2,
+            Some(hint) => return Err(format!("invalid protocol hint: {}", hint).into()),
+        };
+        let meta = Metadata::new(
+            self.labels.into_iter().collect(),
+            protocol_hint,
+            None,
+            identity,
+            None,
+        );
+        Ok(match self.weight {
+            Some(weight) => meta.with_weight(weight),
+            None => meta,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_test_util::TempDir;
+
+    fn endpoints(eps: &[(&str, &str)]) -> IndexMap<SocketAddr, Metadata> {
+        eps.iter()
+            .map(|(addr, pod)| {
+                let labels = Some(("pod".to_string(), pod.to_string()))
+                    .into_iter()
+                    .collect();
+                let meta = Metadata::new(labels, ProtocolHint::Unknown, None, None, None);
+                (addr.parse().unwrap(), meta)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn diffs_endpoints() {
+        let a = endpoints(&[("10.0.0.1:80", "a"), ("10.0.0.2:80", "b")]);
+        let b = endpoints(&[("10.0.0.2:80", "c"), ("10.0.0.3:80", "d")]);
+
+        assert_eq!(diff(Some(&a), Some(&a)), vec![]);
+        assert_eq!(
+            diff(Some(&a), Some(&b)),
+            vec![
+                Update::Remove(vec!["10.0.0.1:80".parse().unwrap()]),
+                Update::Add(b.clone().into_iter().collect()),
+            ],
+            "changed metadata is re-added"
+        );
+        assert_eq!(diff(Some(&a), None), vec![Update::DoesNotExist]);
+        assert_eq!(
+            diff(None, Some(&b)),
+            vec![Update::Reset(b.into_iter().collect())]
+        );
+    }
+
+    #[test]
+    fn skips_unchanged_file() {
+        let dir = TempDir::new();
+        let path = dir.write("endpoints.json", r#"{"web.ns.svc.cluster.local:80": []}"#);
+
+        let mut stamp = None;
+        assert!(read_changed(&path, &mut stamp).unwrap().is_some());
+        assert!(read_changed(&path, &mut stamp).unwrap().is_none());
+
+        dir.write("endpoints.json", r#"{"api.ns.svc.cluster.local:8080": []}"#);
+        let endpoints = read_changed(&path, &mut stamp)
+            .unwrap()
+            .expect("file changed");
+        assert!(endpoints.contains_key("api.ns.svc.cluster.local:8080"));
+    }
+
+    #[tokio::test]
+    async fn watches_file() {
+        time::pause();
+        let dir = TempDir::new();
+        let path = dir.write(
+            "endpoints.yaml",
+            "web.ns.svc.cluster.local:80:\n- addr: 10.0.0.1:80\n",
+        );
+
+        let mut resolve = FileResolve::watch(path, Duration::from_millis(10)).unwrap();
+        let web = NameAddr::from_str("web.ns.svc.cluster.local.:80").unwrap();
+        let mut updates = resolve.resolve(web).await.unwrap();
+        let mut unknown = resolve
+            .resolve(NameAddr::from_str("api.ns.svc.cluster.local:80").unwrap())
+            .await
+            .unwrap();
+
+        let ep1 = "10.0.0.1:80".parse().unwrap();
+        let ep2 = "10.0.0.2:80".parse().unwrap();
+        assert_eq!(
+            updates.next().await.unwrap().unwrap(),
+            Update::Reset(vec![(ep1, Metadata::default())])
+        );
+        assert_eq!(unknown.next().await.unwrap().unwrap(), Update::DoesNotExist);
+
+        dir.write(
+            "endpoints.yaml",
+            "web.ns.svc.cluster.local:80:\n- addr: 10.0.0.2:80\n  protocolHint: h2\n",
+        );
+        assert_eq!(
+            updates.next().await.unwrap().unwrap(),
+            Update::Remove(vec![ep1])
+        );
+        let meta = Metadata::new(Default::default(), ProtocolHint::Http2, None, None, None);
+        assert_eq!(
+            updates.next().await.unwrap().unwrap(),
+            Update::Add(vec![(ep2, meta)])
+        );
+    }
+}
@@ -1,13 +1,23 @@
 use crate::Error;
+use futures::prelude::*;
 use hyper::body::HttpBody;
 pub use hyper_balance::{PendingUntilFirstData, PendingUntilFirstDataBody};
-use rand::thread_rng;
-use std::{hash::Hash, marker::PhantomData, time::Duration};
-use tower::discover::Discover;
-pub use tower::{
-    balance::p2c::Balance,
-    load::{Load, PeakEwmaDiscover},
+pub use linkerd2_proxy_balance::{
+    hash, peak_ewma::Handle, Dispatched, NewWeighted, RequestHash, Weight, Weighted,
+};
+use linkerd2_proxy_balance::{P2c, PeakEwmaDiscover, SlowStartDiscover};
+use std::{
+    hash::Hash,
+    marker::PhantomData,
+    task::{Context, Poll},
+    time::Duration,
 };
+use tower::discover::Discover;
+pub use tower::load::Load;
+
+pub type Loaded<D> = PeakEwmaDiscover<D, PendingUntilFirstData>;
+
+pub type PeakEwmaBalance<D, A> = P2cBalance<SlowStartDiscover<Loaded<D>>, A>;
 
 /// Configures a stack to resolve `T` typed targets to balance requests over
 /// `M`-typed endpoint stacks.
@@ -15,15 +25,44 @@ pub use tower::{
 pub struct Layer<A, B> {
     decay: Duration,
     default_rtt: Duration,
+    slow_start: Duration,
     _marker: PhantomData<fn(A) -> B>,
 }
 
+/// Balances requests over endpoints by load.
+///
+/// Requests that carry a `RequestHash` extension are instead dispatched by
+/// consistent hashing, so that requests with the same hash are dispatched to
+/// the same endpoint.
+///
+/// Requests are not dispatched to endpoints that have already received a
+/// copy of the request, as recorded by its `Dispatched` extension, unless no
+/// other endpoint is ready.
+pub struct P2cBalance<D, A>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    balance: P2c<D, http::Request<A>>,
+}
+
 // === impl Layer ===
 
-pub fn layer<A, B>(default_rtt: Duration, decay: Duration) -> Layer<A, B> {
+/// Balances requests by PeakEWMA load, or by consistent hashing for requests
+/// with a `RequestHash`.
+///
+/// Each endpoint's PeakEWMA load is divided by its `Weight`, so endpoints
+/// with greater weights receive a greater share of requests. Weights do not
+/// apply to consistent hashing.
+///
+/// The share of requests dispatched to a newly discovered endpoint ramps up
+/// over the `slow_start` window. Slow-start does not apply to consistent
+/// hashing.
+pub fn layer<A, B>(default_rtt: Duration, decay: Duration, slow_start: Duration) -> Layer<A, B> {
     Layer {
         decay,
         default_rtt,
+        slow_start,
         _marker: PhantomData,
     }
 }
@@ -33,6 +72,7 @@ impl<A, B> Clone for Layer<A, B> {
         Self {
             decay: self.decay,
             default_rtt: self.default_rtt,
+            slow_start: self.slow_start,
             _marker: PhantomData,
         }
     }
@@ -42,18 +82,47 @@ impl<D, S, A, B> tower::layer::Layer<D> for Layer<A, B>
 where
     A: HttpBody,
     B: HttpBody,
-    D: Discover<Service = S>,
-    D::Key: Hash,
+    D: Discover<Service = Weighted<S>> + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
     S: tower::Service<http::Request<A>, Response = http::Response<B>>,
     S::Error: Into<Error>,
-    Balance<PeakEwmaDiscover<D, PendingUntilFirstData>, http::Request<A>>:
-        tower::Service<http::Request<A>>,
+    PeakEwmaBalance<D, A>: tower::Service<http::Request<A>>,
 {
-    type Service = Balance<PeakEwmaDiscover<D, PendingUntilFirstData>, http::Request<A>>;
+    type Service = PeakEwmaBalance<D, A>;
 
     fn layer(&self, discover: D) -> Self::Service {
         let instrument = PendingUntilFirstData::default();
         let loaded = PeakEwmaDiscover::new(discover, self.default_rtt, self.decay, instrument);
-        Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid")
+        let ramped = SlowStartDiscover::new(loaded, self.slow_start);
+        P2cBalance {
+            balance: P2c::new(ramped),
+        }
+    }
+}
+
+// === impl P2cBalance ===
+
+impl<D, S, A, B> tower::Service<http::Request<A>> for P2cBalance<D, A>
+where
+    D: Discover<Service = S> + Unpin,
+    D::Key: Hash + Eq + Clone,
+    D::Error: Into<Error>,
+    S: tower::Service<http::Request<A>, Response = http::Response<B>> + Load,
+    S::Metric: PartialOrd + std::fmt::Debug,
+    S::Error: Into<Error>,
+{
+    type Response = http::Response<B>;
+    type Error = Error;
+    type Future = future::ErrInto<S::Future, Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.balance.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: http::Request<A>) -> Self::Future {
+        let hash = req.extensions().get::<RequestHash>().map(|h| h.0);
+        let dispatched = req.extensions().get::<Dispatched>().cloned();
+        self.balance.call(hash, dispatched.as_ref(), req)
     }
 }
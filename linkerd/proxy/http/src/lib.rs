@@ -33,7 +33,7 @@ pub use self::{
     override_authority::{CanOverrideAuthority, NewOverrideAuthority},
     retain::Retain,
     server::NewServeHttp,
-    timeout::MakeTimeoutLayer,
+    timeout::{MakeTimeoutLayer, NewDeadlineHeaders},
     version::Version,
 };
 pub use http::{header, uri, Request, Response, StatusCode};
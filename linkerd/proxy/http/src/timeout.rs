@@ -1,11 +1,19 @@
 use futures::{ready, TryFuture};
-use linkerd2_stack::NewService;
-use linkerd2_timeout::Timeout;
+use http::header::{HeaderName, HeaderValue};
+use linkerd2_error::Error;
+use linkerd2_stack::{layer, NewService, Proxy};
+use linkerd2_timeout::TimeoutFuture;
 use pin_project::pin_project;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::trace;
+
+/// The gRPC header that carries the time remaining before the caller gives
+/// up on a request.
+pub const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
 
 /// Implement on targets to determine if a service has a timeout.
 pub trait HasTimeout {
@@ -17,13 +25,24 @@ pub trait HasTimeout {
 /// The stack target must implement `HasTimeout`, and if a duration is
 /// specified for the target, a timeout is applied waiting for HTTP responses.
 ///
+/// Requests may also carry a deadline in a `grpc-timeout` header or, when
+/// configured, in a deadline header whose value is a number of milliseconds.
+/// The smaller of the target's timeout and the request's deadline is
+/// enforced, and the request's deadline headers are rewritten with the time
+/// remaining so that downstream services don't continue processing requests
+/// that have already been abandoned. The request's `Deadline` is set so that
+/// `DeadlineHeaders` can rewrite the headers of each retried or hedged attempt.
+///
 /// Timeout errors are translated into `http::Response`s with appropiate
 /// status codes.
 #[derive(Clone, Debug, Default)]
-pub struct MakeTimeoutLayer(());
+pub struct MakeTimeoutLayer {
+    deadline_header: Option<HeaderName>,
+}
 
 #[derive(Clone, Debug)]
 pub struct MakeTimeout<M> {
+    deadline_header: Option<HeaderName>,
     inner: M,
 }
 
@@ -32,27 +51,72 @@ pub struct MakeFuture<F> {
     #[pin]
     inner: F,
     timeout: Option<Duration>,
+    deadline_header: Option<HeaderName>,
+}
+
+/// Applies the smaller of a target's timeout and each request's deadline.
+#[derive(Clone, Debug)]
+pub struct RequestTimeout<S> {
+    timeout: Option<Duration>,
+    deadline_header: Option<HeaderName>,
+    inner: S,
+}
+
+/// A request extension that records when the request's timeout elapses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Deadline(pub Instant);
+
+/// Rewrites the deadline headers of each attempt of a request with the time
+/// remaining before its `Deadline`.
+///
+/// Retried and hedged attempts are dispatched after the request's deadline
+/// headers were first rewritten, so this is applied to each attempt.
+#[derive(Clone, Debug)]
+pub struct NewDeadlineHeaders<N> {
+    deadline_header: Option<HeaderName>,
+    inner: N,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeadlineHeaders<P> {
+    deadline_header: Option<HeaderName>,
+    inner: P,
+}
+
+// === impl MakeTimeoutLayer ===
+
+impl MakeTimeoutLayer {
+    /// Also reads (and rewrites) request deadlines from the given header.
+    pub fn with_deadline_header(deadline_header: Option<HeaderName>) -> Self {
+        Self { deadline_header }
+    }
 }
 
 impl<M> tower::layer::Layer<M> for MakeTimeoutLayer {
     type Service = MakeTimeout<M>;
 
     fn layer(&self, inner: M) -> Self::Service {
-        MakeTimeout { inner }
+        MakeTimeout {
+            deadline_header: self.deadline_header.clone(),
+            inner,
+        }
     }
 }
 
+// === impl MakeTimeout ===
+
 impl<T, M> NewService<T> for MakeTimeout<M>
 where
     M: NewService<T>,
     T: HasTimeout,
 {
-    type Service = Timeout<M::Service>;
+    type Service = RequestTimeout<M::Service>;
 
     fn new_service(&mut self, target: T) -> Self::Service {
-        match target.timeout() {
-            Some(t) => Timeout::new(self.inner.new_service(target), t),
-            None => Timeout::passthru(self.inner.new_service(target)),
+        RequestTimeout {
+            timeout: target.timeout(),
+            deadline_header: self.deadline_header.clone(),
+            inner: self.inner.new_service(target),
         }
     }
 }
@@ -62,7 +126,7 @@ where
     M: tower::Service<T>,
     T: HasTimeout,
 {
-    type Response = Timeout<M::Response>;
+    type Response = RequestTimeout<M::Response>;
     type Error = M::Error;
     type Future = MakeFuture<M::Future>;
 
@@ -74,22 +138,296 @@ where
         let timeout = target.timeout();
         let inner = self.inner.call(target);
 
-        MakeFuture { inner, timeout }
+        MakeFuture {
+            inner,
+            timeout,
+            deadline_header: self.deadline_header.clone(),
+        }
     }
 }
 
 impl<F: TryFuture> Future for MakeFuture<F> {
-    type Output = Result<Timeout<F::Ok>, F::Error>;
+    type Output = Result<RequestTimeout<F::Ok>, F::Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
         let inner = ready!(this.inner.try_poll(cx))?;
 
-        let svc = match this.timeout {
-            Some(t) => Timeout::new(inner, *t),
-            None => Timeout::passthru(inner),
-        };
+        Poll::Ready(Ok(RequestTimeout {
+            timeout: *this.timeout,
+            deadline_header: this.deadline_header.take(),
+            inner,
+        }))
+    }
+}
+
+// === impl RequestTimeout ===
+
+impl<S> RequestTimeout<S> {
+    /// Determines the request's timeout, rewriting its deadline headers with
+    /// the time remaining.
+    fn deadline<B>(&self, req: &mut http::Request<B>) -> Option<Duration> {
+        let grpc = req
+            .headers()
+            .get(GRPC_TIMEOUT_HEADER)
+            .and_then(parse_grpc_timeout);
+        let header = self.deadline_header.as_ref().and_then(|h| {
+            let ms = req.headers().get(h)?.to_str().ok()?.parse().ok()?;
+            Some(Duration::from_millis(ms))
+        });
+
+        let timeout = [self.timeout, grpc, header]
+            .iter()
+            .flatten()
+            .min()
+            .copied()?;
+        trace!(?timeout, route = ?self.timeout, ?grpc, ?header, "Request deadline");
+
+        set_deadline_headers(req, self.deadline_header.as_ref(), timeout);
+        req.extensions_mut()
+            .insert(Deadline(Instant::now() + timeout));
+
+        Some(timeout)
+    }
+}
+
+impl<B, P, S> Proxy<http::Request<B>, S> for RequestTimeout<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = TimeoutFuture<P::Future>;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        let deadline = self.deadline(&mut req);
+        let inner = self.inner.proxy(svc, req);
+        match deadline {
+            None => TimeoutFuture::Passthru(inner),
+            Some(t) => TimeoutFuture::Timeout(time::timeout(t, inner), t),
+        }
+    }
+}
+
+impl<B, S> tower::Service<http::Request<B>> for RequestTimeout<S>
+where
+    S: tower::Service<http::Request<B>>,
+    S::Error: Into<Error>,
+{
+    type Response = S::Response;
+    type Error = Error;
+    type Future = TimeoutFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        let deadline = self.deadline(&mut req);
+        let inner = self.inner.call(req);
+        match deadline {
+            None => TimeoutFuture::Passthru(inner),
+            Some(t) => TimeoutFuture::Timeout(time::timeout(t, inner), t),
+        }
+    }
+}
+
+// === impl NewDeadlineHeaders ===
+
+impl<N> NewDeadlineHeaders<N> {
+    pub fn layer(
+        deadline_header: Option<HeaderName>,
+    ) -> impl tower::layer::Layer<N, Service = Self> + Clone {
+        layer::mk(move |inner| Self {
+            deadline_header: deadline_header.clone(),
+            inner,
+        })
+    }
+}
+
+impl<T, N: NewService<T>> NewService<T> for NewDeadlineHeaders<N> {
+    type Service = DeadlineHeaders<N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        DeadlineHeaders {
+            deadline_header: self.deadline_header.clone(),
+            inner: self.inner.new_service(target),
+        }
+    }
+}
+
+// === impl DeadlineHeaders ===
+
+impl<B, P, S> Proxy<http::Request<B>, S> for DeadlineHeaders<P>
+where
+    P: Proxy<http::Request<B>, S>,
+    S: tower::Service<P::Request>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = P::Error;
+    type Future = P::Future;
+
+    fn proxy(&self, svc: &mut S, mut req: http::Request<B>) -> Self::Future {
+        if let Some(Deadline(deadline)) = req.extensions().get::<Deadline>().copied() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            trace!(?remaining, "Attempt deadline");
+            set_deadline_headers(&mut req, self.deadline_header.as_ref(), remaining);
+        }
+        self.inner.proxy(svc, req)
+    }
+}
+
+/// Rewrites the deadline headers that are present on the request with the
+/// time remaining.
+fn set_deadline_headers<B>(
+    req: &mut http::Request<B>,
+    deadline_header: Option<&HeaderName>,
+    remaining: Duration,
+) {
+    if req.headers().contains_key(GRPC_TIMEOUT_HEADER) {
+        req.headers_mut()
+            .insert(GRPC_TIMEOUT_HEADER, encode_grpc_timeout(remaining));
+    }
+    if let Some(name) = deadline_header {
+        if req.headers().contains_key(name) {
+            req.headers_mut()
+                .insert(name, HeaderValue::from(remaining.as_millis() as u64));
+        }
+    }
+}
+
+/// Parses a `grpc-timeout` header value: at most 8 digits followed by a unit.
+fn parse_grpc_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?;
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n = digits.parse::<u64>().ok()?;
+    let timeout = match unit {
+        "H" => Duration::from_secs(n * 60 * 60),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" => Duration::from_millis(n),
+        "u" => Duration::from_micros(n),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    };
+    Some(timeout)
+}
+
+/// Encodes a `grpc-timeout` header value in the most precise unit that fits
+/// in 8 digits.
+fn encode_grpc_timeout(timeout: Duration) -> HeaderValue {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    let value = if nanos <= MAX {
+        format!("{}n", nanos)
+    } else if nanos / 1_000 <= MAX {
+        format!("{}u", nanos / 1_000)
+    } else if nanos / 1_000_000 <= MAX {
+        format!("{}m", nanos / 1_000_000)
+    } else if timeout.as_secs() as u128 <= MAX {
+        format!("{}S", timeout.as_secs())
+    } else if timeout.as_secs() as u128 / 60 <= MAX {
+        format!("{}M", timeout.as_secs() / 60)
+    } else {
+        format!("{}H", (timeout.as_secs() / 60 / 60).min(MAX as u64))
+    };
+    HeaderValue::from_str(&value).expect("grpc-timeout must be a valid header value")
+}
 
-        Poll::Ready(Ok(svc))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout(route: Option<Duration>, header: Option<&str>) -> RequestTimeout<()> {
+        RequestTimeout {
+            timeout: route,
+            deadline_header: header.map(|h| HeaderName::from_bytes(h.as_bytes()).unwrap()),
+            inner: (),
+        }
+    }
+
+    #[test]
+    fn parses_grpc_timeout() {
+        let parse = |s: &'static str| parse_grpc_timeout(&HeaderValue::from_static(s));
+        assert_eq!(parse("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse("2M"), Some(Duration::from_secs(120)));
+        assert_eq!(parse("3S"), Some(Duration::from_secs(3)));
+        assert_eq!(parse("40m"), Some(Duration::from_millis(40)));
+        assert_eq!(parse("50u"), Some(Duration::from_micros(50)));
+        assert_eq!(parse("99999999n"), Some(Duration::from_nanos(99_999_999)));
+        assert_eq!(parse("123456789n"), None, "at most 8 digits");
+        assert_eq!(parse("S"), None);
+        assert_eq!(parse("-1S"), None);
+        assert_eq!(parse("10s"), None);
+    }
+
+    #[test]
+    fn encodes_grpc_timeout() {
+        let encode = |d| encode_grpc_timeout(d).to_str().unwrap().to_string();
+        assert_eq!(encode(Duration::from_nanos(1_500)), "1500n");
+        assert_eq!(encode(Duration::from_millis(250)), "250000u");
+        assert_eq!(encode(Duration::from_secs(300)), "300000m");
+        assert_eq!(encode(Duration::from_secs(200_000)), "200000S");
+    }
+
+    #[test]
+    fn enforces_smallest_deadline() {
+        let mut req = http::Request::builder()
+            .header(GRPC_TIMEOUT_HEADER, "2S")
+            .header("x-deadline-ms", "3000")
+            .body(())
+            .unwrap();
+        let t = timeout(Some(Duration::from_secs(1)), Some("x-deadline-ms"));
+        assert_eq!(t.deadline(&mut req), Some(Duration::from_secs(1)));
+        assert_eq!(req.headers()[GRPC_TIMEOUT_HEADER], "1000000u");
+        assert_eq!(req.headers()["x-deadline-ms"], "1000");
+
+        // Headers are only set when they were present on the request.
+        let mut req = http::Request::builder()
+            .header(GRPC_TIMEOUT_HEADER, "500m")
+            .body(())
+            .unwrap();
+        let t = timeout(Some(Duration::from_secs(1)), Some("x-deadline-ms"));
+        assert_eq!(t.deadline(&mut req), Some(Duration::from_millis(500)));
+        assert_eq!(req.headers()[GRPC_TIMEOUT_HEADER], "500000u");
+        assert!(req.headers().get("x-deadline-ms").is_none());
+
+        let mut req = http::Request::new(());
+        assert_eq!(timeout(None, None).deadline(&mut req), None);
+        assert!(req.extensions().get::<Deadline>().is_none());
+    }
+
+    #[tokio::test]
+    async fn rewrites_headers_per_attempt() {
+        time::pause();
+        let mut req = http::Request::builder()
+            .header(GRPC_TIMEOUT_HEADER, "2S")
+            .header("x-deadline-ms", "2000")
+            .body(())
+            .unwrap();
+        let t = timeout(None, Some("x-deadline-ms"));
+        assert_eq!(t.deadline(&mut req), Some(Duration::from_secs(2)));
+
+        // A later attempt carries the time remaining.
+        time::advance(Duration::from_millis(500)).await;
+        let headers = DeadlineHeaders {
+            deadline_header: Some(HeaderName::from_static("x-deadline-ms")),
+            inner: (),
+        };
+        let mut svc = tower::service_fn(|req: http::Request<()>| {
+            futures::future::ok::<_, Error>(req.headers().clone())
+        });
+        let headers = headers.proxy(&mut svc, req).await.unwrap();
+        assert_eq!(headers[GRPC_TIMEOUT_HEADER], "1500000u");
+        assert_eq!(headers["x-deadline-ms"], "1500");
     }
 }
@@ -1,4 +1,5 @@
-use crate::{Crt, CrtKey, Csr, Key, Name, TokenSource, TrustAnchors};
+use crate::{file, trust_anchors, Crt, CrtKey, Csr, Key, Name, TokenSource, TrustAnchors};
+use futures::{prelude::*, select_biased};
 use http_body::Body as HttpBody;
 use linkerd2_error::Error;
 use linkerd2_metrics::Counter;
@@ -15,12 +16,13 @@ use tonic::{
     body::{Body, BoxBody},
     client::GrpcService,
 };
-use tracing::{debug, error, trace};
+use tracing::{debug, error, trace, warn};
 
 /// Configures the Identity service and local identity.
 #[derive(Clone, Debug)]
 pub struct Config {
     pub trust_anchors: TrustAnchors,
+    pub trust_anchors_file: Option<trust_anchors::File>,
     pub key: Key,
     pub csr: Csr,
     pub token: TokenSource,
@@ -31,11 +33,12 @@ pub struct Config {
 
 /// Holds the process's local TLS identity state.
 ///
-/// Updates dynamically as certificates are provisioned from the Identity service.
+/// Updates dynamically as certificates are provisioned from the Identity service
+/// or read from files.
 #[pin_project]
 #[derive(Clone, Debug)]
 pub struct Local {
-    trust_anchors: TrustAnchors,
+    trust_anchors: trust_anchors::Watch,
     name: Name,
     crt_key: watch::Receiver<Option<CrtKey>>,
     refreshes: Arc<Counter>,
@@ -54,6 +57,8 @@ pub type CrtKeySender = watch::Sender<Option<CrtKey>>;
 pub struct Daemon {
     crt_key_watch: CrtKeySender,
     refreshes: Arc<linkerd2_metrics::Counter>,
+    trust_anchors: trust_anchors::Watch,
+    reload: trust_anchors::Daemon,
     config: Config,
 }
 
@@ -94,8 +99,14 @@ impl Daemon {
             config,
             crt_key_watch,
             refreshes,
+            mut trust_anchors,
+            reload,
         } = self;
+        // Trust anchors are reloaded for as long as they are observed.
+        tokio::spawn(reload.run());
+
         let mut curr_expiry = UNIX_EPOCH;
+        let mut curr_crt = None;
         let mut client = api::identity_client::IdentityClient::new(client);
 
         loop {
@@ -129,7 +140,7 @@ impl Daemon {
                                         expiry,
                                     );
 
-                                    match config.trust_anchors.certify(key, crt) {
+                                    match trust_anchors.current().certify(key, crt.clone()) {
                                         Err(e) => {
                                             error!("Received invalid ceritficate: {}", e);
                                         }
@@ -143,6 +154,7 @@ impl Daemon {
 
                                             refreshes.incr();
                                             curr_expiry = expiry;
+                                            curr_crt = Some(crt);
                                         }
                                     }
                                 }
@@ -152,7 +164,34 @@ impl Daemon {
                 }
                 Err(e) => error!("Failed to read authentication token: {}", e),
             }
-            config.refresh(curr_expiry).await;
+
+            // If the trust anchors change before the refresh, the current
+            // certificate is certified with the new trust anchors. If they don't
+            // trust it, a new certificate is requested immediately.
+            let refresh = config.refresh(curr_expiry).fuse();
+            futures::pin_mut!(refresh);
+            loop {
+                select_biased! {
+                    _ = refresh => break,
+                    _ = trust_anchors.changed().fuse() => {},
+                }
+                let crt = match curr_crt.clone() {
+                    Some(crt) => crt,
+                    None => break,
+                };
+                match trust_anchors.current().certify(config.key.clone(), crt) {
+                    Err(error) => {
+                        warn!(%error, "Certificate is not trusted by the new trust anchors");
+                        break;
+                    }
+                    Ok(crt_key) => {
+                        debug!("daemon certified with new trust anchors");
+                        if crt_key_watch.send(Some(crt_key)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
         }
     }
 }
@@ -161,26 +200,54 @@ impl Daemon {
 
 impl Local {
     pub fn new(config: &Config) -> (Self, Daemon) {
-        let (s, w) = watch::channel(None);
-        let refreshes = Arc::new(Counter::new());
-        let l = Local {
-            name: config.local_name.clone(),
-            trust_anchors: config.trust_anchors.clone(),
-            crt_key: w,
-            refreshes: refreshes.clone(),
-        };
+        let (trust_anchors, reload) = trust_anchors::channel(
+            config.trust_anchors.clone(),
+            config.trust_anchors_file.clone(),
+        );
+        let (l, s, refreshes) = Self::channel(&config.local_name, &trust_anchors);
         let daemon = Daemon {
             config: config.clone(),
             refreshes,
             crt_key_watch: s,
+            trust_anchors,
+            reload,
         };
         (l, daemon)
     }
 
+    pub fn from_files(config: &file::Config) -> (Self, file::Daemon) {
+        let (trust_anchors, reload) = trust_anchors::channel(
+            config.trust_anchors.clone(),
+            config.trust_anchors_file.clone(),
+        );
+        let (l, s, refreshes) = Self::channel(&config.local_name, &trust_anchors);
+        let daemon = file::Daemon::new(config.clone(), s, refreshes, trust_anchors, reload);
+        (l, daemon)
+    }
+
+    fn channel(
+        name: &Name,
+        trust_anchors: &trust_anchors::Watch,
+    ) -> (Self, CrtKeySender, Arc<Counter>) {
+        let (s, w) = watch::channel(None);
+        let refreshes = Arc::new(Counter::new());
+        let l = Local {
+            name: name.clone(),
+            trust_anchors: trust_anchors.clone(),
+            crt_key: w,
+            refreshes: refreshes.clone(),
+        };
+        (l, s, refreshes)
+    }
+
     pub fn name(&self) -> &Name {
         &self.name
     }
 
+    pub fn trust_anchors(&self) -> trust_anchors::Watch {
+        self.trust_anchors.clone()
+    }
+
     pub async fn await_crt(mut self) -> Result<Self, LostDaemon> {
         while self.crt_key.borrow().is_none() {
             // If the sender is dropped, the daemon task has ended.
@@ -192,7 +259,11 @@ impl Local {
     }
 
     pub fn metrics(&self) -> crate::metrics::Report {
-        crate::metrics::Report::new(self.crt_key.clone(), self.refreshes.clone())
+        crate::metrics::Report::new(
+            self.crt_key.clone(),
+            self.refreshes.clone(),
+            self.trust_anchors.clone(),
+        )
     }
 }
 
@@ -202,7 +273,15 @@ impl tls::client::HasConfig for Local {
             return c.tls_client_config();
         }
 
-        self.trust_anchors.tls_client_config()
+        self.trust_anchors.current().tls_client_config()
+    }
+
+    fn tls_client_config_for(&self, peer: &Name) -> Arc<tls::client::Config> {
+        if let Some(ref c) = *self.crt_key.borrow() {
+            return c.tls_client_config_for(peer);
+        }
+
+        self.trust_anchors.current().tls_client_config_for(peer)
     }
 }
 
@@ -0,0 +1,215 @@
+//! Reads the local identity's certificate and key from files, rather than
+//! obtaining certificates from the Identity service.
+//!
+//! This supports hosts on which an external agent, like cert-manager or Vault
+//! Agent, issues certificates and rotates them on disk. The certificate file
+//! holds a PEM-encoded chain, leaf first (e.g. cert-manager's `tls.crt`), and
+//! the key file holds a PEM-encoded PKCS#8 private key (e.g. `tls.key`, when
+//! the certificate's `privateKey.encoding` is `PKCS8`).
+
+use crate::{trust_anchors, Crt, CrtKey, CrtKeySender, Key, Name, TrustAnchors};
+use futures::{prelude::*, select_biased};
+use linkerd2_error::Error;
+use linkerd2_metrics::Counter;
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::{fs, time};
+use tracing::{debug, trace, warn};
+
+/// Configures a local identity that is read from files.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub trust_anchors: TrustAnchors,
+    pub trust_anchors_file: Option<trust_anchors::File>,
+    pub local_name: Name,
+    pub crt: PathBuf,
+    pub key: PathBuf,
+    pub interval: Duration,
+}
+
+/// Publishes the local identity each time its files change.
+#[derive(Debug)]
+pub struct Daemon {
+    crt_key_watch: CrtKeySender,
+    refreshes: Arc<Counter>,
+    trust_anchors: trust_anchors::Watch,
+    reload: trust_anchors::Daemon,
+    config: Config,
+}
+
+// === impl Config ===
+
+impl Config {
+    fn load(&self, trust_anchors: &TrustAnchors, crt: &[u8], key: &[u8]) -> Result<CrtKey, Error> {
+        let crt = Crt::from_pem(self.local_name.clone(), crt)?;
+        let key = Key::from_pem(key)?;
+        let crt_key = trust_anchors.certify(key, crt)?;
+        Ok(crt_key)
+    }
+}
+
+// === impl Daemon ===
+
+impl Daemon {
+    pub(crate) fn new(
+        config: Config,
+        crt_key_watch: CrtKeySender,
+        refreshes: Arc<Counter>,
+        trust_anchors: trust_anchors::Watch,
+        reload: trust_anchors::Daemon,
+    ) -> Self {
+        Self {
+            crt_key_watch,
+            refreshes,
+            trust_anchors,
+            reload,
+            config,
+        }
+    }
+
+    /// Polls the certificate and key files, publishing a new identity
+    /// whenever either changes.
+    ///
+    /// Files that cannot be read or that do not form a valid identity are
+    /// logged and retried, and the last valid identity continues to be used.
+    /// This tolerates an agent replacing the certificate and key separately.
+    ///
+    /// When the trust anchors change, the files are loaded again so that the
+    /// identity is certified with the new trust anchors.
+    pub async fn run(self) {
+        let Self {
+            config,
+            crt_key_watch,
+            refreshes,
+            mut trust_anchors,
+            reload,
+        } = self;
+        // Trust anchors are reloaded for as long as they are observed.
+        tokio::spawn(reload.run());
+
+        let mut loaded = None;
+        let mut recertify = false;
+
+        loop {
+            match (fs::read(&config.crt).await, fs::read(&config.key).await) {
+                (Err(error), _) => {
+                    warn!(%error, file = %config.crt.display(), "Failed to read certificate")
+                }
+                (_, Err(error)) => {
+                    warn!(%error, file = %config.key.display(), "Failed to read key")
+                }
+                (Ok(crt), Ok(key)) => {
+                    let files = (crt, key);
+                    if !recertify && loaded.as_ref() == Some(&files) {
+                        trace!("Identity files unchanged");
+                    } else {
+                        match config.load(&trust_anchors.current(), &files.0, &files.1) {
+                            Err(error) => warn!(%error, "Failed to load identity"),
+                            Ok(crt_key) => {
+                                debug!(expiry = ?crt_key.expiry(), "Loaded identity");
+                                if crt_key_watch.send(Some(crt_key)).is_err() {
+                                    // If we can't store a value, than all observations
+                                    // have been dropped and we can stop polling.
+                                    return;
+                                }
+
+                                if loaded.as_ref() != Some(&files) {
+                                    refreshes.incr();
+                                    loaded = Some(files);
+                                }
+                                recertify = false;
+                            }
+                        }
+                    }
+                }
+            }
+
+            select_biased! {
+                _ = crt_key_watch.closed().fuse() => {
+                    trace!("Identity dropped");
+                    return;
+                },
+                _ = time::sleep(config.interval).fuse() => {},
+                _ = trust_anchors.changed().fuse() => {
+                    recertify = true;
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_test_util::TempDir;
+    use std::str::FromStr;
+    use tokio::sync::watch;
+
+    const NAME: &str = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+
+    #[tokio::test]
+    async fn reloads_rotated_identity() {
+        time::pause();
+        let dir = TempDir::new();
+        let crt = dir.write(
+            "tls.crt",
+            &include_bytes!("../../../identity/src/testdata/foo-ns1-ca3/crt.pem")[..],
+        );
+        let key = dir.write(
+            "tls.key",
+            &include_bytes!("../../../identity/src/testdata/foo-ns1-ca3/key.pem")[..],
+        );
+
+        // Roots are rotated from CA 3 to CA 1.
+        let roots = [
+            include_str!("../../../identity/src/testdata/ca3.pem"),
+            include_str!("../../../identity/src/testdata/ca1.pem"),
+        ]
+        .concat();
+        let config = Config {
+            trust_anchors: TrustAnchors::from_pem(&roots).unwrap(),
+            trust_anchors_file: None,
+            local_name: Name::from_str(NAME).unwrap(),
+            crt,
+            key,
+            interval: Duration::from_millis(10),
+        };
+        let (tx, mut rx) = watch::channel(None);
+        let refreshes = Arc::new(Counter::new());
+        let (trust_anchors, reload) = trust_anchors::channel(config.trust_anchors.clone(), None);
+        tokio::spawn(Daemon::new(config, tx, refreshes.clone(), trust_anchors, reload).run());
+
+        rx.changed().await.unwrap();
+        let expiry = rx
+            .borrow()
+            .as_ref()
+            .expect("identity must be loaded")
+            .expiry();
+        assert_eq!(refreshes.value(), 1.0);
+
+        // A key that was not issued the certificate is ignored.
+        dir.write(
+            "tls.key",
+            &include_bytes!("../../../identity/src/testdata/bar-ns1-ca3/key.pem")[..],
+        );
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.changed().now_or_never().is_none());
+        assert_eq!(refreshes.value(), 1.0);
+
+        dir.write(
+            "tls.crt",
+            &include_bytes!("../../../identity/src/testdata/foo-ns1-ca1/crt.pem")[..],
+        );
+        dir.write(
+            "tls.key",
+            &include_bytes!("../../../identity/src/testdata/foo-ns1-ca1/key.pem")[..],
+        );
+        rx.changed().await.unwrap();
+        let rotated = rx
+            .borrow()
+            .as_ref()
+            .expect("identity must be loaded")
+            .expiry();
+        assert_ne!(rotated, expiry);
+        assert_eq!(refreshes.value(), 2.0);
+    }
+}
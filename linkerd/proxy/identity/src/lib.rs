@@ -1,7 +1,9 @@
 #![deny(warnings, rust_2018_idioms)]
 
 pub mod certify;
+pub mod file;
 pub mod metrics;
+pub mod trust_anchors;
 
 pub use self::certify::{AwaitCrt, CrtKeySender, Local};
 pub use linkerd2_identity::{Crt, CrtKey, Csr, InvalidName, Key, Name, TokenSource, TrustAnchors};
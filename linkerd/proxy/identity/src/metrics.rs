@@ -1,5 +1,5 @@
-use crate::CrtKey;
-use linkerd2_metrics::{metrics, Counter, FmtMetrics, Gauge};
+use crate::{trust_anchors, CrtKey};
+use linkerd2_metrics::{metrics, Counter, FmtLabels, FmtMetrics, Gauge};
 use std::{fmt, sync::Arc, time::UNIX_EPOCH};
 use tokio::sync::watch;
 
@@ -14,7 +14,11 @@ metrics! {
     },
 
     identity_cert_refresh_count: Counter {
-        "The total number of times this proxy's mTLS identity certificate has been refreshed by the Identity service."
+        "The total number of times this proxy's mTLS identity certificate has been refreshed by the Identity service or reloaded from files."
+    },
+
+    identity_trust_anchors_fingerprint: Gauge {
+        "Identifies the trust anchors with which this proxy verifies peers' certificates by the SHA-256 digest of the trust anchors' certificates."
     }
 }
 
@@ -22,11 +26,13 @@ impl Report {
     pub(crate) fn new(
         crt_key_watch: watch::Receiver<Option<CrtKey>>,
         refreshes: Arc<Counter>,
+        trust_anchors: trust_anchors::Watch,
     ) -> Self {
         Self {
             inner: Some(Inner {
                 crt_key_watch,
                 refreshes,
+                trust_anchors,
             }),
         }
     }
@@ -40,8 +46,11 @@ impl Report {
 struct Inner {
     crt_key_watch: watch::Receiver<Option<CrtKey>>,
     refreshes: Arc<Counter>,
+    trust_anchors: trust_anchors::Watch,
 }
 
+struct Fingerprint<'a>(&'a str);
+
 impl FmtMetrics for Report {
     fn fmt_metrics(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let this = match self.inner.as_ref() {
@@ -65,6 +74,20 @@ impl FmtMetrics for Report {
         identity_cert_refresh_count.fmt_help(f)?;
         identity_cert_refresh_count.fmt_metric(f, &this.refreshes)?;
 
+        let trust_anchors = this.trust_anchors.current();
+        identity_trust_anchors_fingerprint.fmt_help(f)?;
+        identity_trust_anchors_fingerprint.fmt_metric_labeled(
+            f,
+            &Gauge::from(1),
+            &Fingerprint(trust_anchors.fingerprint()),
+        )?;
+
         Ok(())
     }
 }
+
+impl FmtLabels for Fingerprint<'_> {
+    fn fmt_labels(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sha256=\"{}\"", self.0)
+    }
+}
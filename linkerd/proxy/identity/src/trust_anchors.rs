@@ -0,0 +1,210 @@
+//! Reloads the trust anchors with which peers' certificates are verified, so
+//! that roots may be rotated without restarting the proxy.
+//!
+//! Trust anchors are read from a file holding a PEM-encoded bundle of root
+//! certificates. Certificates issued by any root in the bundle are accepted,
+//! so a root is rotated by first adding the new root to the bundle alongside
+//! the old one and, once all certificates have been reissued by the new root,
+//! removing the old root.
+
+use crate::{Name, TrustAnchors};
+use futures::{future, prelude::*, select_biased};
+use linkerd2_proxy_transport::tls;
+use std::{fs, io, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{sync::watch, time};
+use tracing::{info, trace, warn};
+
+/// Configures trust anchors that are reloaded as their file changes.
+#[derive(Clone, Debug)]
+pub struct File {
+    pub path: PathBuf,
+    pub interval: Duration,
+    pub require_tls13: bool,
+}
+
+/// Observes the current trust anchors.
+#[derive(Clone, Debug)]
+pub struct Watch(watch::Receiver<TrustAnchors>);
+
+/// Publishes trust anchors as their file changes.
+#[derive(Debug)]
+pub struct Daemon {
+    file: Option<File>,
+    fingerprint: String,
+    tx: watch::Sender<TrustAnchors>,
+}
+
+/// Publishes `trust_anchors` until they are reloaded from `file`, if one is
+/// configured.
+pub(crate) fn channel(trust_anchors: TrustAnchors, file: Option<File>) -> (Watch, Daemon) {
+    let fingerprint = trust_anchors.fingerprint().to_string();
+    let (tx, rx) = watch::channel(trust_anchors);
+    let daemon = Daemon {
+        file,
+        fingerprint,
+        tx,
+    };
+    (Watch(rx), daemon)
+}
+
+// === impl File ===
+
+impl File {
+    pub fn load(&self) -> io::Result<TrustAnchors> {
+        let pem = fs::read_to_string(&self.path)?;
+        self.parse(&pem)
+    }
+
+    /// Like `load`, but reads the file without blocking the runtime.
+    async fn reload(&self) -> io::Result<TrustAnchors> {
+        let pem = tokio::fs::read_to_string(&self.path).await?;
+        self.parse(&pem)
+    }
+
+    fn parse(&self, pem: &str) -> io::Result<TrustAnchors> {
+        let ta = TrustAnchors::from_pem(pem).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "no valid trust anchors found")
+        })?;
+        Ok(if self.require_tls13 {
+            ta.require_tls13()
+        } else {
+            ta
+        })
+    }
+}
+
+// === impl Watch ===
+
+impl Watch {
+    pub fn current(&self) -> TrustAnchors {
+        self.0.borrow().clone()
+    }
+
+    /// Completes when the trust anchors change, which never happens if they
+    /// are not reloaded from a file.
+    pub(crate) async fn changed(&mut self) {
+        if self.0.changed().await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+}
+
+impl tls::client::HasConfig for Watch {
+    fn tls_client_config(&self) -> Arc<tls::client::Config> {
+        self.0.borrow().tls_client_config()
+    }
+
+    fn tls_client_config_for(&self, peer: &Name) -> Arc<tls::client::Config> {
+        self.0.borrow().tls_client_config_for(peer)
+    }
+}
+
+// === impl Daemon ===
+
+impl Daemon {
+    /// Polls the trust anchors' file, publishing the trust anchors whenever
+    /// they change.
+    ///
+    /// A file that cannot be read or that holds no valid trust anchors is
+    /// logged, and the last valid trust anchors continue to be used. Completes
+    /// immediately when trust anchors are not reloaded from a file.
+    pub async fn run(self) {
+        let Self {
+            file,
+            mut fingerprint,
+            tx,
+        } = self;
+        let file = match file {
+            Some(file) => file,
+            None => return,
+        };
+
+        loop {
+            select_biased! {
+                _ = tx.closed().fuse() => {
+                    trace!("Trust anchors dropped");
+                    return;
+                },
+                _ = time::sleep(file.interval).fuse() => {},
+            }
+
+            match file.reload().await {
+                Err(error) => {
+                    warn!(%error, file = %file.path.display(), "Failed to load trust anchors")
+                }
+                Ok(ta) if ta.fingerprint() == fingerprint => trace!("Trust anchors unchanged"),
+                Ok(ta) => {
+                    info!(fingerprint = %ta.fingerprint(), "Trust anchors changed");
+                    fingerprint = ta.fingerprint().to_string();
+                    if tx.send(ta).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{file, tls::client::HasConfig, Local};
+    use linkerd2_metrics::FmtMetrics;
+    use linkerd2_test_util::TempDir;
+    use std::str::FromStr;
+
+    const NAME: &str = "foo.ns1.serviceaccount.identity.linkerd.cluster.local";
+    const CA1: &str = include_str!("../../../identity/src/testdata/ca1.pem");
+    const CA3: &str = include_str!("../../../identity/src/testdata/ca3.pem");
+
+    #[tokio::test]
+    async fn reloads_bundles() {
+        time::pause();
+        let dir = TempDir::new();
+        let bundle = dir.write("ca.pem", CA3);
+        let crt = dir.write(
+            "tls.crt",
+            &include_bytes!("../../../identity/src/testdata/foo-ns1-ca3/crt.pem")[..],
+        );
+        let key = dir.write(
+            "tls.key",
+            &include_bytes!("../../../identity/src/testdata/foo-ns1-ca3/key.pem")[..],
+        );
+
+        let trust_anchors_file = File {
+            path: bundle,
+            interval: Duration::from_millis(10),
+            require_tls13: false,
+        };
+        let config = file::Config {
+            trust_anchors: trust_anchors_file.load().unwrap(),
+            trust_anchors_file: Some(trust_anchors_file),
+            local_name: Name::from_str(NAME).unwrap(),
+            crt,
+            key,
+            interval: Duration::from_millis(10),
+        };
+        let (local, daemon) = Local::from_files(&config);
+        tokio::spawn(daemon.run());
+        let local = local.await_crt().await.expect("identity must be loaded");
+        let mut watch = local.trust_anchors();
+        let old = watch.current().fingerprint().to_string();
+        assert_eq!(local.tls_client_config().root_store.len(), 1);
+
+        // A new root is added to the bundle.
+        dir.write("ca.pem", [CA3, CA1].concat());
+        watch.changed().await;
+        let new = watch.current().fingerprint().to_string();
+        assert_ne!(new, old);
+        let metrics = local.metrics().as_display().to_string();
+        assert!(metrics.contains(&format!("sha256=\"{}\"", new)));
+        assert!(!metrics.contains(&old));
+
+        // The identity is certified with the new bundle, so that its client
+        // verifies peers issued by the new root. The paused clock advances
+        // while the files are read, so the wait is not bounded by a timeout.
+        while local.tls_client_config().root_store.len() != 2 {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -1,28 +1,108 @@
+use futures::prelude::*;
+use linkerd2_duplex::Duplex;
 use linkerd2_error::Error;
+use linkerd2_io::PeerAddr;
+use linkerd2_proxy_balance::{hash, P2c, PeakEwmaDiscover, SlowStartDiscover};
+pub use linkerd2_proxy_balance::{NewWeighted, Weight, Weighted};
 use linkerd2_stack::layer;
-use rand::thread_rng;
-use std::{hash::Hash, time::Duration};
-pub use tower::{
-    balance::p2c::Balance,
-    load::{Load, PeakEwmaDiscover},
+use std::{
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
 };
+use tokio::io::{AsyncRead, AsyncWrite};
+pub use tower::load::Load;
 use tower::{discover::Discover, load::CompleteOnResponse};
 
+pub type PeakEwmaBalance<D> = P2c<SlowStartDiscover<PeakEwmaDiscover<D, CompleteOnResponse>>, ()>;
+
+/// Forwards connections to endpoints chosen by consistent hashing on the
+/// client's IP address, so that a client's connections are all forwarded to
+/// the same endpoint.
+///
+/// If the client's endpoint is not ready, its connections are forwarded to the
+/// next ready endpoint on the hash ring.
+pub struct HashForward<D>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    balance: PeakEwmaBalance<D>,
+}
+
 /// Produces a PeakEWMA balancer that uses connect latency (and pending
-/// connections) as its load metric.
-pub fn layer<T, D>(
+/// connections), divided by each endpoint's `Weight`, as its load metric.
+///
+/// The share of connections dispatched to a newly discovered endpoint ramps
+/// up over the `slow_start` window.
+pub fn layer<D, S>(
     default_rtt: Duration,
     decay: Duration,
-) -> impl tower::layer::Layer<D, Service = Balance<PeakEwmaDiscover<D, CompleteOnResponse>, T>> + Clone
+    slow_start: Duration,
+) -> impl tower::layer::Layer<D, Service = PeakEwmaBalance<D>> + Clone
 where
-    D: Discover,
-    D::Key: Hash,
-    D::Service: tower::Service<T>,
-    <D::Service as tower::Service<T>>::Error: Into<Error>,
+    D: Discover<Service = Weighted<S>> + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
+    S: tower::Service<()>,
+    S::Error: Into<Error>,
 {
     layer::mk(move |discover| {
         let loaded =
             PeakEwmaDiscover::new(discover, default_rtt, decay, CompleteOnResponse::default());
-        Balance::from_rng(loaded, &mut thread_rng()).expect("RNG must be valid")
+        P2c::new(SlowStartDiscover::new(loaded, slow_start))
     })
 }
+
+/// Produces a service that forwards connections to endpoints chosen by
+/// consistent hashing on the client's IP address.
+pub fn hash_forward_layer<D, S>(
+    default_rtt: Duration,
+    decay: Duration,
+    slow_start: Duration,
+) -> impl tower::layer::Layer<D, Service = HashForward<D>> + Clone
+where
+    D: Discover<Service = Weighted<S>> + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
+    S: tower::Service<()>,
+    S::Error: Into<Error>,
+{
+    let balance = layer(default_rtt, decay, slow_start);
+    layer::mk(move |discover| HashForward {
+        balance: tower::layer::Layer::layer(&balance, discover),
+    })
+}
+
+// === impl HashForward ===
+
+impl<D, S, I> tower::Service<I> for HashForward<D>
+where
+    I: AsyncRead + AsyncWrite + PeerAddr + Send + Unpin + 'static,
+    D: Discover<Service = Weighted<S>> + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<Error>,
+    S: tower::Service<()>,
+    S::Error: Into<Error>,
+    S::Future: Send + 'static,
+    S::Response: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Response = ();
+    type Error = Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.balance.poll_ready(cx)
+    }
+
+    fn call(&mut self, src_io: I) -> Self::Future {
+        let hash = src_io.peer_addr().ok().map(|addr| hash(&addr.ip()));
+        Box::pin(
+            self.balance
+                .call(hash, None, ())
+                .and_then(|dst_io| Duplex::new(src_io, dst_io).err_into::<Error>()),
+        )
+    }
+}
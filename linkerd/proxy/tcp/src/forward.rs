@@ -16,7 +16,7 @@ pub struct Forward<C> {
 }
 
 impl<C> Forward<C> {
-    fn new(connect: C) -> Self {
+    pub fn new(connect: C) -> Self {
         Self { connect }
     }
 
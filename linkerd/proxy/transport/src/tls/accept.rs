@@ -284,6 +284,13 @@ fn client_identity<S>(tls: &tokio_rustls::server::TlsStream<S>) -> Option<identi
     let (_io, session) = tls.get_ref();
     let certs = session.get_peer_certificates()?;
     let c = certs.first().map(rustls::Certificate::as_ref)?;
+
+    // X.509-SVIDs are identified by their SPIFFE ID, even if they also have
+    // DNS names.
+    if let Some(id) = identity::spiffe::Id::from_crt(c) {
+        return Some(identity::Name::from(id));
+    }
+
     let end_cert = webpki::EndEntityCert::from(c).ok()?;
     let dns_names = end_cert.dns_names().ok()?;
 
@@ -18,6 +18,12 @@ use tracing::{debug, trace};
 
 pub trait HasConfig {
     fn tls_client_config(&self) -> Arc<Config>;
+
+    /// Returns a configuration that verifies that servers are identified by
+    /// `peer`.
+    fn tls_client_config_for(&self, peer: &identity::Name) -> Arc<Config> {
+        peer.tls_client_config(self.tls_client_config())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -61,8 +67,8 @@ where
     }
 
     fn call(&mut self, target: T) -> Self::Future {
-        let tls = match self.local.clone() {
-            Conditional::Some(l) => tokio_rustls::TlsConnector::from(l.tls_client_config()),
+        let local = match self.local.as_ref() {
+            Conditional::Some(l) => l,
             Conditional::None(reason) => {
                 trace!(%reason, "Local identity disabled");
                 return Either::Left(self.inner.call(target).map_ok(io::EitherIo::Left));
@@ -76,6 +82,10 @@ where
             }
         };
 
+        // SPIFFE IDs are verified against the server's URI SAN, rather than
+        // its SNI name.
+        let tls = tokio_rustls::TlsConnector::from(local.tls_client_config_for(&peer_identity));
+
         debug!(peer.identity = ?peer_identity, "Initiating TLS connection");
         let connect = self.inner.call(target);
         Either::Right(Box::pin(async move {
@@ -90,10 +100,18 @@ impl HasConfig for identity::CrtKey {
     fn tls_client_config(&self) -> Arc<Config> {
         identity::CrtKey::tls_client_config(self)
     }
+
+    fn tls_client_config_for(&self, peer: &identity::Name) -> Arc<Config> {
+        identity::CrtKey::tls_client_config_for(self, peer)
+    }
 }
 
 impl HasConfig for identity::TrustAnchors {
     fn tls_client_config(&self) -> Arc<Config> {
         identity::TrustAnchors::tls_client_config(self)
     }
+
+    fn tls_client_config_for(&self, peer: &identity::Name) -> Arc<Config> {
+        identity::TrustAnchors::tls_client_config_for(self, peer)
+    }
 }
@@ -1,3 +1,4 @@
+use linkerd2_dns_name as dns;
 use linkerd2_identity as identity;
 use std::convert::TryFrom;
 use tracing::trace;
@@ -14,7 +15,8 @@ pub enum Match {
 ///
 /// The determination is made based on whether the input looks like (the start
 /// of) a valid ClientHello that a reasonable TLS client might send, and the
-/// SNI matches the given identity.
+/// SNI matches the given identity's server name (the trust domain, for SPIFFE
+/// IDs).
 ///
 /// XXX: Once the TLS record header is matched, the determination won't be
 /// made until the entire TLS record including the entire ClientHello handshake
@@ -31,9 +33,9 @@ pub fn match_client_hello(input: &[u8], identity: &identity::Name) -> Match {
     });
     match r {
         Ok(Some(sni)) => {
-            let m = identity::Name::try_from(sni.as_slice_less_safe())
+            let m = dns::Name::try_from(sni.as_slice_less_safe())
                 .map(|sni| {
-                    if sni == *identity {
+                    if sni == *identity.server_name() {
                         Match::Matched
                     } else {
                         Match::NotMatched
@@ -229,6 +231,20 @@ mod tests {
         check_all_prefixes(Match::NotMatched, "aexample.com", VALID_EXAMPLE_COM);
     }
 
+    #[test]
+    fn matches_spiffe_trust_domain() {
+        check_all_prefixes(
+            Match::Matched,
+            "spiffe://example.com/ns/default/sa/web",
+            VALID_EXAMPLE_COM,
+        );
+        check_all_prefixes(
+            Match::NotMatched,
+            "spiffe://example.org/ns/default/sa/web",
+            VALID_EXAMPLE_COM,
+        );
+    }
+
     #[test]
     fn mismatch_http_1_0_request() {
         check_all_prefixes(
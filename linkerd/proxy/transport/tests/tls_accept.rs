@@ -15,7 +15,10 @@ use linkerd2_proxy_transport::{
 };
 use linkerd2_stack::NewService;
 use std::future::Future;
-use std::{net::SocketAddr, sync::mpsc};
+use std::{
+    net::SocketAddr,
+    sync::{mpsc, Arc},
+};
 use tokio::net::TcpStream;
 use tower::{
     layer::Layer,
@@ -53,6 +56,112 @@ fn proxy_to_proxy_tls_works() {
     assert_eq!(&server_result.result.expect("ping")[..], PING);
 }
 
+#[test]
+fn proxy_to_proxy_tls_works_with_p384_and_ed25519_keys() {
+    let server_tls = test_util::FOO_NS1_CA3.validate().unwrap();
+    let client_tls = test_util::BAR_NS1_CA3.validate().unwrap();
+    let (client_result, server_result) = run_test(
+        Conditional::Some((client_tls, server_tls.tls_server_name())),
+        |conn| write_then_read(conn, PING),
+        Conditional::Some(server_tls),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    );
+    assert_eq!(client_result.is_tls(), true);
+    assert_eq!(&client_result.result.expect("pong")[..], PONG);
+    assert_eq!(server_result.is_tls(), true);
+    assert_eq!(&server_result.result.expect("ping")[..], PING);
+}
+
+#[test]
+fn proxy_to_proxy_tls_works_with_spiffe_ids() {
+    let server_tls = test_util::FOO_NS1_SVID.validate().unwrap();
+    let client_tls = test_util::BAR_NS1_SVID.validate().unwrap();
+    let (client_result, server_result) = run_test(
+        Conditional::Some((client_tls, server_tls.tls_server_name())),
+        |conn| write_then_read(conn, PING),
+        Conditional::Some(server_tls),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    );
+    assert_eq!(client_result.is_tls(), true);
+    assert_eq!(&client_result.result.expect("pong")[..], PONG);
+    assert_eq!(
+        server_result.peer_identity,
+        Some(Conditional::Some(
+            test_util::BAR_NS1_SVID.crt().name().clone()
+        ))
+    );
+    assert_eq!(&server_result.result.expect("ping")[..], PING);
+}
+
+#[test]
+fn proxy_to_proxy_tls_negotiates_tls13() {
+    let server_tls = test_util::FOO_NS1.validate().unwrap();
+    let client_tls = test_util::BAR_NS1.validate().unwrap();
+    let (client_result, server_result) = run_test(
+        Conditional::Some((client_tls, server_tls.tls_server_name())),
+        |conn| write_then_read_tls_version(conn, PING),
+        Conditional::Some(server_tls),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    );
+    assert_eq!(client_result.is_tls(), true);
+    let (version, pong) = client_result.result.expect("pong");
+    assert_eq!(version, Some(rustls::ProtocolVersion::TLSv1_3));
+    assert_eq!(&pong[..], PONG);
+    assert_eq!(server_result.is_tls(), true);
+    assert_eq!(&server_result.result.expect("ping")[..], PING);
+}
+
+#[test]
+fn proxy_to_proxy_tls_works_when_tls13_required() {
+    let server_tls = require_tls13(&test_util::FOO_NS1);
+    let client_tls = require_tls13(&test_util::BAR_NS1);
+    let (client_result, server_result) = run_test(
+        Conditional::Some((client_tls, server_tls.tls_server_name())),
+        |conn| write_then_read_tls_version(conn, PING),
+        Conditional::Some(server_tls),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    );
+    assert_eq!(client_result.is_tls(), true);
+    let (version, pong) = client_result.result.expect("pong");
+    assert_eq!(version, Some(rustls::ProtocolVersion::TLSv1_3));
+    assert_eq!(&pong[..], PONG);
+    assert_eq!(server_result.is_tls(), true);
+    assert_eq!(&server_result.result.expect("ping")[..], PING);
+}
+
+#[test]
+fn proxy_to_proxy_tls_negotiates_tls12_with_tls12_only_peers() {
+    let server_tls = test_util::FOO_NS1.validate().unwrap();
+    let client_tls = tls12_only(&test_util::BAR_NS1.validate().unwrap());
+    let (client_result, server_result) = run_test_with_client_config(
+        Conditional::Some((client_tls, server_tls.tls_server_name())),
+        |conn| write_then_read_tls_version(conn, PING),
+        Conditional::Some(server_tls),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    );
+    assert_eq!(client_result.is_tls(), true);
+    let (version, pong) = client_result.result.expect("pong");
+    assert_eq!(version, Some(rustls::ProtocolVersion::TLSv1_2));
+    assert_eq!(&pong[..], PONG);
+    let server_result = server_result.expect("server complete");
+    assert_eq!(server_result.is_tls(), true);
+    assert_eq!(&server_result.result.expect("ping")[..], PING);
+}
+
+#[test]
+fn proxy_to_proxy_tls_refuses_tls12_only_peers_when_tls13_required() {
+    let server_tls = require_tls13(&test_util::FOO_NS1);
+    let client_tls = tls12_only(&test_util::BAR_NS1.validate().unwrap());
+    let (client_result, server_result) = run_test_with_client_config(
+        Conditional::Some((client_tls, server_tls.tls_server_name())),
+        |conn| write_then_read(conn, PING),
+        Conditional::Some(server_tls),
+        |(_, conn)| read_then_write(conn, PING.len(), PONG),
+    );
+    assert!(client_result.result.is_err(), "handshake must fail");
+    assert!(server_result.is_none(), "connection must not be accepted");
+}
+
 #[test]
 fn proxy_to_proxy_tls_pass_through_when_identity_does_not_match() {
     let server_tls = test_util::FOO_NS1.validate().unwrap();
@@ -105,6 +214,30 @@ fn run_test<C, CF, CR, S, SF, SR>(
     server_tls: tls::Conditional<CrtKey>,
     server: S,
 ) -> (Transported<CR>, Transported<SR>)
+where
+    // Client
+    C: FnOnce(tls::client::Io<TcpStream>) -> CF + Clone + Send + 'static,
+    CF: Future<Output = Result<CR, io::Error>> + Send + 'static,
+    CR: Send + 'static,
+    // Server
+    S: Fn(tls::accept::Connection<TcpStream>) -> SF + Clone + Send + 'static,
+    SF: Future<Output = Result<SR, io::Error>> + Send + 'static,
+    SR: Send + 'static,
+{
+    let client_tls = client_tls.map(|(crtkey, name)| (crtkey.tls_client_config(), name));
+    let (client_result, server_result) =
+        run_test_with_client_config(client_tls, client, server_tls, server);
+    (client_result, server_result.expect("server complete"))
+}
+
+/// Like `run_test`, but connects with the given client configuration. The
+/// server's result is `None` when the connection isn't accepted.
+fn run_test_with_client_config<C, CF, CR, S, SF, SR>(
+    client_tls: tls::Conditional<(Arc<tls::client::Config>, Name)>,
+    client: C,
+    server_tls: tls::Conditional<CrtKey>,
+    server: S,
+) -> (Transported<CR>, Option<Transported<SR>>)
 where
     // Client
     C: FnOnce(tls::client::Io<TcpStream>) -> CF + Clone + Send + 'static,
@@ -124,8 +257,8 @@ where
     }
 
     let (client_tls, client_target_name) = match client_tls {
-        Conditional::Some((crtkey, name)) => (
-            Conditional::Some(ClientTls(crtkey)),
+        Conditional::Some((config, name)) => (
+            Conditional::Some(ClientTls(config)),
             Conditional::Some(name),
         ),
         Conditional::None(reason) => (Conditional::None(reason), Conditional::None(reason)),
@@ -182,8 +315,10 @@ where
                 .expect("listener closed");
             tracing::debug!("incoming connection");
             let accept = detect.new_service(meta);
-            accept.oneshot(io).await.expect("connection failed");
-            tracing::debug!("done");
+            match accept.oneshot(io).await {
+                Ok(()) => tracing::debug!("done"),
+                Err(error) => tracing::debug!(%error, "connection failed"),
+            }
         }
         .instrument(tracing::info_span!("run_server", %listen_addr));
 
@@ -237,7 +372,7 @@ where
     // XXX: This assumes that only one connection is accepted. TODO: allow the
     // caller to observe the results for every connection, once we have tests
     // that allow accepting multiple connections.
-    let server_result = server_result.try_recv().expect("server complete");
+    let server_result = server_result.try_recv().ok();
 
     (client_result, server_result)
 }
@@ -261,6 +396,21 @@ async fn write_then_read(
     Ok(vec)
 }
 
+/// Like `write_then_read`, but also returns the TLS version negotiated by the
+/// client, if any.
+async fn write_then_read_tls_version(
+    conn: tls::client::Io<TcpStream>,
+    to_write: &'static [u8],
+) -> Result<(Option<rustls::ProtocolVersion>, Vec<u8>), io::Error> {
+    use rustls::Session;
+    let version = match conn {
+        io::EitherIo::Left(_) => None,
+        io::EitherIo::Right(ref tls) => tls.get_ref().1.get_protocol_version(),
+    };
+    let read = write_then_read(conn, to_write).await?;
+    Ok((version, read))
+}
+
 /// Reads until EOF then writes `to_write` and shuts down the write side,
 /// returning the bytes read.
 async fn read_then_write(
@@ -293,11 +443,26 @@ const PING: &[u8] = b"ping";
 const PONG: &[u8] = b"pong";
 const START_OF_TLS: &[u8] = &[22, 3, 1]; // ContentType::handshake version 3.1
 
+fn require_tls13(id: &test_util::Identity) -> CrtKey {
+    id.trust_anchors()
+        .require_tls13()
+        .certify(id.key(), id.crt())
+        .expect("identity must be valid")
+}
+
+/// Builds a client configuration that only supports TLS 1.2, like a peer that
+/// predates TLS 1.3 support.
+fn tls12_only(crtkey: &CrtKey) -> Arc<tls::client::Config> {
+    let mut config = crtkey.tls_client_config().as_ref().clone();
+    config.versions = vec![rustls::ProtocolVersion::TLSv1_2];
+    Arc::new(config)
+}
+
 #[derive(Clone)]
 struct Target(SocketAddr, Conditional<Name>);
 
 #[derive(Clone)]
-struct ClientTls(CrtKey);
+struct ClientTls(Arc<tls::client::Config>);
 
 impl Into<SocketAddr> for Target {
     fn into(self) -> SocketAddr {
@@ -312,7 +477,7 @@ impl tls::HasPeerIdentity for Target {
 }
 
 impl tls::client::HasConfig for ClientTls {
-    fn tls_client_config(&self) -> std::sync::Arc<tls::client::Config> {
-        self.0.tls_client_config()
+    fn tls_client_config(&self) -> Arc<tls::client::Config> {
+        self.0.clone()
     }
 }
@@ -0,0 +1,376 @@
+use crate::NewPolicy;
+use linkerd2_error::Error;
+use linkerd2_stack::{NewService, Proxy};
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{debug, trace};
+
+/// Determines whether and when a request is hedged.
+pub trait HedgePolicy<Req> {
+    /// Returns how long to wait for a response before a hedged request is
+    /// sent, or `None` if requests should not be hedged at all (e.g. because
+    /// too few latencies have been observed).
+    fn delay(&self) -> Option<Duration>;
+
+    /// Clones a request so that it may be hedged.
+    ///
+    /// The original request may be modified, e.g. so that the two copies are
+    /// not dispatched to the same endpoint. Returns `None` if the request
+    /// cannot be cloned.
+    fn clone_request(&self, req: &mut Req) -> Option<Req>;
+
+    /// Called once the delay has elapsed to determine whether the hedged
+    /// request may be sent.
+    fn can_hedge(&self, req: &Req) -> bool;
+
+    /// Records the latency of a response.
+    fn record_latency(&self, latency: Duration);
+}
+
+/// A layer that applies per-target hedge polcies.
+///
+/// Composes `NewService`s that produce a `Proxy`.
+#[derive(Clone, Debug)]
+pub struct NewHedgeLayer<P> {
+    new_policy: P,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewHedge<P, N> {
+    new_policy: P,
+    inner: N,
+}
+
+/// Sends a second copy of a request if no response has been received after
+/// the policy's delay. Whichever response is received first is returned.
+#[derive(Clone, Debug)]
+pub struct Hedge<P, S> {
+    policy: Option<P>,
+    inner: S,
+}
+
+#[pin_project(project = ResponseFutureProj)]
+pub enum ResponseFuture<R, P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+{
+    Disabled(#[pin] P::Future),
+    Hedge(#[pin] Hedging<R, P, S, Req>),
+}
+
+#[pin_project]
+pub struct Hedging<R, P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+{
+    policy: R,
+    /// The original request, until it completes.
+    #[pin]
+    primary: Option<P::Future>,
+    primary_start: Instant,
+    #[pin]
+    hedge: HedgeState<P, S, Req>,
+}
+
+#[pin_project(project = HedgeStateProj)]
+enum HedgeState<P, S, Req>
+where
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+{
+    Waiting {
+        #[pin]
+        sleep: time::Sleep,
+        proxy: P,
+        svc: S,
+        req: Option<Req>,
+    },
+    Pending {
+        #[pin]
+        future: P::Future,
+        start: Instant,
+    },
+    Done,
+}
+
+// === impl NewHedgeLayer ===
+
+impl<P> NewHedgeLayer<P> {
+    pub fn new(new_policy: P) -> Self {
+        Self { new_policy }
+    }
+}
+
+impl<P: Clone, N> tower::layer::Layer<N> for NewHedgeLayer<P> {
+    type Service = NewHedge<P, N>;
+
+    fn layer(&self, inner: N) -> Self::Service {
+        Self::Service {
+            inner,
+            new_policy: self.new_policy.clone(),
+        }
+    }
+}
+
+// === impl NewHedge ===
+
+impl<T, N, P> NewService<T> for NewHedge<P, N>
+where
+    N: NewService<T>,
+    P: NewPolicy<T>,
+{
+    type Service = Hedge<P::Policy, N::Service>;
+
+    fn new_service(&mut self, target: T) -> Self::Service {
+        // Determine if there is a hedge policy for the given target.
+        let policy = self.new_policy.new_policy(&target);
+
+        let inner = self.inner.new_service(target);
+        Hedge { policy, inner }
+    }
+}
+
+// === impl Hedge ===
+
+impl<R, P, Req, S> Proxy<Req, S> for Hedge<R, P>
+where
+    R: HedgePolicy<Req> + Clone,
+    P: Proxy<Req, S> + Clone,
+    S: tower::Service<P::Request> + Clone,
+    S::Error: Into<Error>,
+{
+    type Request = P::Request;
+    type Response = P::Response;
+    type Error = Error;
+    type Future = ResponseFuture<R, P, S, Req>;
+
+    fn proxy(&self, svc: &mut S, mut req: Req) -> Self::Future {
+        let policy = match self.policy.as_ref() {
+            Some(policy) => policy,
+            None => return ResponseFuture::Disabled(self.inner.proxy(svc, req)),
+        };
+
+        // The hedged request must be cloned before the original request is
+        // dispatched.
+        let hedge = match policy
+            .delay()
+            .and_then(|delay| Some((delay, policy.clone_request(&mut req)?)))
+        {
+            Some((delay, hedge_req)) => {
+                trace!(?delay, "Hedging");
+                HedgeState::Waiting {
+                    sleep: time::sleep(delay),
+                    proxy: self.inner.clone(),
+                    svc: svc.clone(),
+                    req: Some(hedge_req),
+                }
+            }
+            None => HedgeState::Done,
+        };
+
+        ResponseFuture::Hedge(Hedging {
+            policy: policy.clone(),
+            primary: Some(self.inner.proxy(svc, req)),
+            primary_start: Instant::now(),
+            hedge,
+        })
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<R, P, S, Req> Future for ResponseFuture<R, P, S, Req>
+where
+    R: HedgePolicy<Req>,
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            ResponseFutureProj::Disabled(f) => f.poll(cx).map_err(Into::into),
+            ResponseFutureProj::Hedge(f) => f.poll(cx),
+        }
+    }
+}
+
+// === impl Hedging ===
+
+impl<R, P, S, Req> Future for Hedging<R, P, S, Req>
+where
+    R: HedgePolicy<Req>,
+    P: Proxy<Req, S>,
+    S: tower::Service<P::Request>,
+    S::Error: Into<Error>,
+{
+    type Output = Result<P::Response, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        if let Some(primary) = this.primary.as_mut().as_pin_mut() {
+            if let Poll::Ready(res) = primary.poll(cx) {
+                this.primary.set(None);
+                match res {
+                    Ok(rsp) => {
+                        this.policy.record_latency(this.primary_start.elapsed());
+                        return Poll::Ready(Ok(rsp));
+                    }
+                    // If a hedged request is in flight, it may still succeed.
+                    Err(e) => match this.hedge.as_mut().project() {
+                        HedgeStateProj::Pending { .. } => {
+                            debug!("Original request failed; waiting for hedged request");
+                        }
+                        _ => return Poll::Ready(Err(e.into())),
+                    },
+                }
+            }
+        }
+
+        loop {
+            let future = match this.hedge.as_mut().project() {
+                HedgeStateProj::Done => return Poll::Pending,
+                HedgeStateProj::Pending { future, start } => {
+                    let res = match future.poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(res) => res,
+                    };
+                    match res {
+                        Ok(rsp) => {
+                            this.policy.record_latency(start.elapsed());
+                            return Poll::Ready(Ok(rsp));
+                        }
+                        Err(e) => {
+                            this.hedge.set(HedgeState::Done);
+                            if this.primary.is_none() {
+                                return Poll::Ready(Err(e.into()));
+                            }
+                            debug!("Hedged request failed; waiting for original request");
+                            return Poll::Pending;
+                        }
+                    }
+                }
+                HedgeStateProj::Waiting {
+                    sleep,
+                    proxy,
+                    svc,
+                    req,
+                } => {
+                    if sleep.poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    match svc.poll_ready(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Err(e)) => {
+                            let e: Error = e.into();
+                            debug!(error = %e, "Hedged request could not be sent");
+                            this.hedge.set(HedgeState::Done);
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Ok(())) => {}
+                    }
+                    let hedge_req = req.take().expect("polled after ready");
+                    if !this.policy.can_hedge(&hedge_req) {
+                        trace!("Request may not be hedged");
+                        this.hedge.set(HedgeState::Done);
+                        return Poll::Pending;
+                    }
+                    debug!("Sending hedged request");
+                    proxy.proxy(svc, hedge_req)
+                }
+            };
+            this.hedge.set(HedgeState::Pending {
+                future,
+                start: Instant::now(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[derive(Clone, Default)]
+    struct TestPolicy {
+        hedges: Arc<AtomicUsize>,
+    }
+
+    impl HedgePolicy<()> for TestPolicy {
+        fn delay(&self) -> Option<Duration> {
+            Some(Duration::from_millis(10))
+        }
+
+        fn clone_request(&self, _: &mut ()) -> Option<()> {
+            Some(())
+        }
+
+        fn can_hedge(&self, _: &()) -> bool {
+            self.hedges.fetch_add(1, Ordering::SeqCst);
+            true
+        }
+
+        fn record_latency(&self, _: Duration) {}
+    }
+
+    /// Returns a service that never responds to its first request and
+    /// responds to later requests with the number of prior calls.
+    fn stuck_once() -> impl tower::Service<
+        (),
+        Response = usize,
+        Error = Error,
+        Future = impl Future<Output = Result<usize, Error>>,
+    > + Clone {
+        let calls = Arc::new(AtomicUsize::new(0));
+        tower::service_fn(move |()| {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                if n == 0 {
+                    std::future::pending::<()>().await;
+                }
+                Ok::<_, Error>(n)
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn hedges_slow_requests() {
+        time::pause();
+        let policy = TestPolicy::default();
+        let hedge = Hedge {
+            policy: Some(policy.clone()),
+            inner: (),
+        };
+        let rsp = hedge.proxy(&mut stuck_once(), ()).await.unwrap();
+        assert_eq!(rsp, 1, "the hedged request must win");
+        assert_eq!(policy.hedges.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_hedge_fast_requests() {
+        time::pause();
+        let policy = TestPolicy::default();
+        let hedge = Hedge {
+            policy: Some(policy.clone()),
+            inner: (),
+        };
+        let mut svc = stuck_once();
+        // Use up the stuck call so that the original request completes.
+        drop(tower::Service::call(&mut svc, ()));
+        let rsp = hedge.proxy(&mut svc, ()).await.unwrap();
+        assert_eq!(rsp, 1);
+        assert_eq!(policy.hedges.load(Ordering::SeqCst), 0);
+    }
+}
@@ -10,7 +10,11 @@ pub use tower::retry::{budget::Budget, Policy};
 use tower::util::{Oneshot, ServiceExt};
 use tracing::trace;
 
-/// A strategy for obtaining per-target retry polices.
+mod hedge;
+
+pub use self::hedge::{HedgePolicy, NewHedge, NewHedgeLayer};
+
+/// A strategy for obtaining per-target retry (or hedge) polices.
 pub trait NewPolicy<T> {
     type Policy;
 
@@ -1,4 +1,4 @@
-use crate::{http, Profile, Receiver, Target};
+use crate::{anchored_regex, http, Profile, Receiver, Target};
 use api::destination_client::DestinationClient;
 use futures::{future, prelude::*, ready, select_biased};
 use http_body::Body as HttpBody;
@@ -6,9 +6,11 @@ use linkerd2_addr::Addr;
 use linkerd2_dns_name::Name;
 use linkerd2_error::{Error, Recover};
 use linkerd2_proxy_api::destination as api;
-use linkerd2_proxy_api_resolve::pb as resolve;
+use linkerd2_proxy_api_resolve::{
+    pb as resolve,
+    snapshot::{Kind, Snapshots, Stale},
+};
 use pin_project::pin_project;
-use regex::Regex;
 use std::{
     convert::TryInto,
     future::Future,
@@ -18,14 +20,14 @@ use std::{
     task::{Context, Poll},
     time::Duration,
 };
-use tokio::sync::watch;
+use tokio::{sync::watch, time};
 use tonic::{
     self as grpc,
     body::{Body, BoxBody},
     client::GrpcService,
 };
 use tower::retry::budget::Budget;
-use tracing::{debug, debug_span, error, trace, warn};
+use tracing::{debug, debug_span, error, info, trace, warn};
 use tracing_futures::Instrument;
 
 #[derive(Clone, Debug)]
@@ -33,6 +35,8 @@ pub struct Client<S, R> {
     service: DestinationClient<S>,
     recover: R,
     context_token: String,
+    snapshots: Option<Snapshots>,
+    snapshot_timeout: Duration,
 }
 
 #[pin_project]
@@ -43,6 +47,7 @@ where
 {
     #[pin]
     inner: Option<Inner<S, R>>,
+    snapshot: Option<SnapshotFuture>,
 }
 
 #[pin_project]
@@ -56,6 +61,7 @@ where
     #[pin]
     state: State<R::Backoff>,
     request: api::GetDestination,
+    snapshots: Option<Snapshots>,
 }
 
 #[pin_project(project = StateProj)]
@@ -83,6 +89,8 @@ type RspFuture = Pin<
     >,
 >;
 
+type SnapshotFuture = Pin<Box<dyn Future<Output = Option<(Profile, Stale)>> + Send + 'static>>;
+
 // === impl Client ===
 
 impl<S, R> Client<S, R>
@@ -104,6 +112,19 @@ where
             service: DestinationClient::new(service),
             recover,
             context_token,
+            snapshots: None,
+            snapshot_timeout: Duration::from_secs(0),
+        }
+    }
+
+    /// Writes each destination's profile to `snapshots` as it changes, serving
+    /// the last snapshot when the destination service doesn't respond within
+    /// `timeout`.
+    pub fn with_snapshots(self, snapshots: Option<Snapshots>, timeout: Duration) -> Self {
+        Self {
+            snapshots,
+            snapshot_timeout: timeout,
+            ..self
         }
     }
 }
@@ -141,8 +162,17 @@ where
             service: self.service.clone(),
             recover: self.recover.clone(),
             state: State::Disconnected { backoff: None },
+            snapshots: self.snapshots.clone(),
         };
-        ProfileFuture { inner: Some(inner) }
+        let timeout = self.snapshot_timeout;
+        let snapshot = self.snapshots.clone().map(|snapshots| {
+            let dst = inner.request.path.clone();
+            Box::pin(load_snapshot(snapshots, dst, timeout)) as SnapshotFuture
+        });
+        ProfileFuture {
+            inner: Some(inner),
+            snapshot,
+        }
     }
 }
 
@@ -161,25 +191,38 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let mut this = self.project();
-        let profile = match this
+        let (profile, stale) = match this
             .inner
             .as_mut()
             .as_pin_mut()
             .expect("polled after ready")
             .poll_profile(cx)
         {
-            Poll::Pending => return Poll::Pending,
+            Poll::Pending => {
+                // If the destination service doesn't respond before the
+                // snapshot timeout, serve the last-known-good profile.
+                let snapshot = match this.snapshot.as_mut() {
+                    Some(snapshot) => ready!(snapshot.as_mut().poll(cx)),
+                    None => return Poll::Pending,
+                };
+                *this.snapshot = None;
+                match snapshot {
+                    Some((profile, stale)) => (profile, Some(stale)),
+                    None => return Poll::Pending,
+                }
+            }
             Poll::Ready(Err(error)) => {
                 trace!(%error, "failed to fetch profile");
                 return Poll::Ready(Err(error));
             }
-            Poll::Ready(Ok(profile)) => profile,
+            Poll::Ready(Ok(profile)) => (profile, None),
         };
 
         trace!("daemonizing");
         let (tx, rx) = watch::channel(profile);
         let inner = this.inner.take().expect("polled after ready");
         let daemon = async move {
+            let mut stale = stale;
             tokio::pin!(inner);
             loop {
                 select_biased! {
@@ -196,6 +239,9 @@ where
                                 return;
                             }
                             Ok(profile) => {
+                                if stale.take().is_some() {
+                                    debug!("Destination service responded");
+                                }
                                 trace!(?profile, "publishing");
                                 if tx.send(profile).is_err() {
                                     trace!("failed to publish profile");
@@ -213,6 +259,19 @@ where
     }
 }
 
+/// Loads the last-known-good profile for `dst` once `timeout` elapses.
+async fn load_snapshot(
+    snapshots: Snapshots,
+    dst: String,
+    timeout: Duration,
+) -> Option<(Profile, Stale)> {
+    time::sleep(timeout).await;
+    let proto = snapshots.load_profile(&dst).await?;
+    info!(%dst, "Destination service did not respond; using snapshot");
+    let stale = snapshots.stale(Kind::Profile, dst);
+    Some((convert_profile(proto), stale))
+}
+
 // === impl Inner ===
 
 impl<S, R> Inner<S, R>
@@ -227,35 +286,18 @@ where
 {
     fn poll_rx(
         rx: Pin<&mut grpc::Streaming<api::DestinationProfile>>,
+        snapshots: Option<&Snapshots>,
+        dst: &str,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Profile, grpc::Status>>> {
         trace!("poll");
         let profile = ready!(rx.poll_next(cx)).map(|res| {
             res.map(|proto| {
                 debug!("profile received: {:?}", proto);
-                let name = Name::from_str(&proto.fully_qualified_name).ok();
-                let retry_budget = proto.retry_budget.and_then(convert_retry_budget);
-                let http_routes = proto
-                    .routes
-                    .into_iter()
-                    .filter_map(move |orig| convert_route(orig, retry_budget.as_ref()))
-                    .collect();
-                let targets = proto
-                    .dst_overrides
-                    .into_iter()
-                    .filter_map(convert_dst_override)
-                    .collect();
-                let endpoint = proto.endpoint.and_then(|e| {
-                    let labels = std::collections::HashMap::new();
-                    resolve::to_addr_meta(e, &labels)
-                });
-                Profile {
-                    name,
-                    http_routes,
-                    targets,
-                    opaque_protocol: proto.opaque_protocol,
-                    endpoint,
+                if let Some(snapshots) = snapshots {
+                    snapshots.store_profile(dst, &proto);
                 }
+                convert_profile(proto)
             })
         });
         Poll::Ready(profile)
@@ -295,7 +337,8 @@ where
                 }
                 StateProj::Streaming(s) => {
                     trace!("streaming");
-                    let status = match ready!(Self::poll_rx(s, cx)) {
+                    let snapshots = this.snapshots.as_ref();
+                    let status = match ready!(Self::poll_rx(s, snapshots, &this.request.path, cx)) {
                         Some(Ok(profile)) => return Poll::Ready(Ok(profile)),
                         None => grpc::Status::new(grpc::Code::Ok, ""),
                         Some(Err(status)) => status,
@@ -317,6 +360,33 @@ where
     }
 }
 
+fn convert_profile(proto: api::DestinationProfile) -> Profile {
+    let name = Name::from_str(&proto.fully_qualified_name).ok();
+    let retry_budget = proto.retry_budget.and_then(convert_retry_budget);
+    let http_routes = proto
+        .routes
+        .into_iter()
+        .filter_map(move |orig| convert_route(orig, retry_budget.as_ref()))
+        .collect();
+    let targets = proto
+        .dst_overrides
+        .into_iter()
+        .filter_map(convert_dst_override)
+        .collect();
+    let endpoint = proto.endpoint.and_then(|e| {
+        let labels = std::collections::HashMap::new();
+        resolve::to_addr_meta(e, &labels)
+    });
+    Profile {
+        name,
+        http_routes,
+        targets,
+        opaque_protocol: proto.opaque_protocol,
+        endpoint,
+        hash_key: None,
+    }
+}
+
 fn convert_route(
     orig: api::Route,
     retry_budget: Option<&Arc<Budget>>,
@@ -345,6 +415,7 @@ fn convert_dst_override(orig: api::WeightedDst) -> Option<Target> {
     Some(Target {
         addr,
         weight: orig.weight,
+        request_match: None,
     })
 }
 
@@ -386,17 +457,7 @@ fn convert_req_match(orig: api::RequestMatch) -> Option<http::RequestMatch> {
             http::RequestMatch::Not(Box::new(m))
         }
         api::request_match::Match::Path(api::PathMatch { regex }) => {
-            let regex = regex.trim();
-            let re = match (regex.starts_with('^'), regex.ends_with('$')) {
-                (true, true) => Regex::new(regex).ok()?,
-                (hd_anchor, tl_anchor) => {
-                    let hd = if hd_anchor { "" } else { "^" };
-                    let tl = if tl_anchor { "" } else { "$" };
-                    let re = format!("{}{}{}", hd, regex, tl);
-                    Regex::new(&re).ok()?
-                }
-            };
-            http::RequestMatch::Path(re)
+            http::RequestMatch::Path(anchored_regex(&regex)?)
         }
         api::request_match::Match::Method(mm) => {
             let m = mm.r#type.and_then(|m| (&m).try_into().ok())?;
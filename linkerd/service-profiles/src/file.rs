@@ -0,0 +1,1007 @@
+//! Loads service profiles from a directory of files rather than from the
+//! destination service.
+//!
+//! Each file holds a single profile in the shape of a Kubernetes
+//! `ServiceProfile` resource, encoded as YAML (`.yaml`, `.yml`) or JSON
+//! (`.json`). A profile applies to targets whose host matches its
+//! `metadata.name`, regardless of the target's port:
+//!
+//! ```yaml
+//! metadata:
+//!   name: web.default.svc.cluster.local
+//! spec:
+//!   routes:
+//!   - name: GET /books
+//!     condition:
+//!       method: GET
+//!       pathRegex: /books/[^/]*
+//!     responseClasses:
+//!     - condition:
+//!         status: { min: 500, max: 599 }
+//!       isFailure: true
+//!     isRetryable: true
+//!     timeout: 300ms
+//!   retryBudget:
+//!     retryRatio: 0.2
+//!     minRetriesPerSecond: 10
+//!     ttl: 10s
+//!   dstOverrides:
+//!   - authority: web-v2.default.svc.cluster.local:8080
+//!     weight: 100
+//! ```
+//!
+//! In addition to the fields supported by the `ServiceProfile` resource,
+//! routes may match on an `authority`, `header`, `queryParam`, or `cookie`;
+//! response classes may match a `grpcStatus` range; routes may set
+//! `isIdempotent`, `mirror`, and `fault`; and `dstOverrides` may set a
+//! `condition` that always routes matching requests to that target.
+//!
+//! Profiles and routes may also set a `hashKey` (`header:<name>`,
+//! `cookie:<name>`, or `source-ip`) so that requests are balanced by
+//! consistent hashing rather than by load. A profile's key applies to its
+//! routes that do not set one and, when it is `source-ip`, to connections.
+//!
+//! The directory is polled for changes, and each target's profile is updated
+//! as its file is created, changed, or removed. Files that cannot be read or
+//! parsed are logged and their last valid profile is retained.
+
+use crate::{anchored_regex, http, parse_duration, GetProfile, Profile, Receiver, Target};
+use futures::{future, prelude::*, select_biased};
+use linkerd2_addr::Addr;
+use linkerd2_dns_name::Name;
+use linkerd2_error::Error;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
+use tokio::{sync::watch, task, time};
+use tower::retry::budget::Budget;
+use tracing::{debug, trace, warn};
+use tracing_futures::Instrument;
+
+/// Serves profiles from a watched directory.
+///
+/// Every target is given a profile, which is the default profile until a
+/// file configures the target's host.
+#[derive(Clone, Debug)]
+pub struct Profiles {
+    specs: watch::Receiver<Arc<Specs>>,
+}
+
+/// Serves profiles from a watched directory in place of those from an inner
+/// `GetProfile`.
+///
+/// A target's file-based profile, when one exists, takes precedence over the
+/// inner profile. When the file is removed, the inner profile is used again.
+///
+/// Targets configured by a file are served their file-based profile without
+/// waiting for the inner profile, which is used once it is resolved.
+#[derive(Clone, Debug)]
+pub struct Overrides<P> {
+    local: Option<Profiles>,
+    inner: P,
+}
+
+pub type ProfileFuture = Pin<Box<dyn Future<Output = Result<Option<Receiver>, Error>> + Send>>;
+
+/// Resolves a target's inner profile after its file-based profile is served.
+type InnerFuture = Pin<Box<dyn Future<Output = Option<Receiver>> + Send>>;
+
+/// Profile specs, indexed by host.
+type Specs = HashMap<String, ServiceProfile>;
+
+/// Profile files, indexed by path.
+type Files = HashMap<PathBuf, File>;
+
+/// A profile file, as of when it was last read.
+#[derive(Debug)]
+struct File {
+    modified: Option<SystemTime>,
+    len: u64,
+    /// The last valid spec read from the file, if any.
+    spec: Option<ServiceProfile>,
+}
+
+/// Wakes a target's profile task.
+enum Update {
+    Closed,
+    Specs,
+    Inner,
+    InnerClosed,
+    InnerResolved(Option<Receiver>),
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct ServiceProfile {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: Spec,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct Metadata {
+    name: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Spec {
+    #[serde(default)]
+    routes: Vec<RouteSpec>,
+    retry_budget: Option<RetryBudgetSpec>,
+    #[serde(default)]
+    dst_overrides: Vec<DstOverrideSpec>,
+    #[serde(default)]
+    opaque_ports: Vec<u16>,
+    hash_key: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RouteSpec {
+    name: String,
+    condition: RequestMatchSpec,
+    #[serde(default)]
+    response_classes: Vec<ResponseClassSpec>,
+    #[serde(default)]
+    is_retryable: bool,
+    #[serde(default)]
+    is_idempotent: bool,
+    timeout: Option<String>,
+    mirror: Option<String>,
+    fault: Option<FaultSpec>,
+    hash_key: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestMatchSpec {
+    #[serde(default)]
+    all: Vec<RequestMatchSpec>,
+    #[serde(default)]
+    any: Vec<RequestMatchSpec>,
+    not: Option<Box<RequestMatchSpec>>,
+    path_regex: Option<String>,
+    method: Option<String>,
+    authority: Option<ValueMatchSpec>,
+    header: Option<NamedValueMatchSpec>,
+    query_param: Option<NamedValueMatchSpec>,
+    cookie: Option<NamedValueMatchSpec>,
+}
+
+/// Matches a value exactly, by regular expression, or, when neither is set,
+/// so long as it is present.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+struct ValueMatchSpec {
+    value: Option<String>,
+    regex: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct NamedValueMatchSpec {
+    name: String,
+    #[serde(flatten)]
+    value: ValueMatchSpec,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseClassSpec {
+    condition: ResponseMatchSpec,
+    #[serde(default)]
+    is_failure: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseMatchSpec {
+    #[serde(default)]
+    all: Vec<ResponseMatchSpec>,
+    #[serde(default)]
+    any: Vec<ResponseMatchSpec>,
+    not: Option<Box<ResponseMatchSpec>>,
+    status: Option<RangeSpec>,
+    grpc_status: Option<RangeSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct RangeSpec {
+    min: u32,
+    max: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RetryBudgetSpec {
+    retry_ratio: f32,
+    min_retries_per_second: u32,
+    ttl: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct DstOverrideSpec {
+    authority: String,
+    weight: u32,
+    condition: Option<RequestMatchSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FaultSpec {
+    delay: Option<FaultDelaySpec>,
+    abort: Option<FaultAbortSpec>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+struct FaultDelaySpec {
+    percent: u8,
+    min: String,
+    max: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FaultAbortSpec {
+    percent: u8,
+    #[serde(default = "FaultAbortSpec::default_http_status")]
+    http_status: u16,
+    #[serde(default = "FaultAbortSpec::default_grpc_status")]
+    grpc_status: u32,
+}
+
+// === impl Profiles ===
+
+impl Profiles {
+    /// Loads profiles from `dir`, polling it for changes every `interval`.
+    ///
+    /// Fails if the directory cannot be read. Must be called on a Tokio
+    /// runtime, since the directory is polled on a background task. The
+    /// directory is read on the runtime's blocking threads and only files
+    /// whose modification time or length changed are parsed again.
+    pub fn watch(dir: PathBuf, interval: Duration) -> io::Result<Self> {
+        let mut files = Files::new();
+        load_dir(&dir, &mut files)?;
+        let mut published = index(&files);
+        let (tx, specs) = watch::channel(Arc::new(published.clone()));
+
+        let poll = async move {
+            let dir = Arc::new(dir);
+            loop {
+                select_biased! {
+                    _ = tx.closed().fuse() => {
+                        trace!("Profiles dropped");
+                        return;
+                    },
+                    _ = time::sleep(interval).fuse() => {},
+                }
+
+                let loaded = {
+                    let dir = dir.clone();
+                    task::spawn_blocking(move || {
+                        let res = load_dir(&dir, &mut files);
+                        (files, res)
+                    })
+                };
+                let res = match loaded.await {
+                    Ok((loaded, res)) => {
+                        files = loaded;
+                        res
+                    }
+                    Err(error) => {
+                        warn!(%error, "Profile directory watch failed");
+                        return;
+                    }
+                };
+                if let Err(error) = res {
+                    warn!(%error, dir = %dir.display(), "Failed to read profile directory");
+                    continue;
+                }
+                let specs = index(&files);
+                if specs != published {
+                    debug!(profiles = specs.len(), "Profiles changed");
+                    if tx.send(Arc::new(specs.clone())).is_err() {
+                        return;
+                    }
+                    published = specs;
+                }
+            }
+        };
+        tokio::spawn(poll.in_current_span());
+
+        Ok(Self { specs })
+    }
+
+    /// Serves these profiles in place of those from `inner`.
+    pub fn with_overrides_of<P>(self, inner: P) -> Overrides<P> {
+        Overrides::new(Some(self), inner)
+    }
+
+    /// Returns whether a file configures the target's host.
+    fn contains(&self, host: &str) -> bool {
+        self.specs.borrow().contains_key(host)
+    }
+
+    /// Publishes the target's profile, falling back to the profiles published
+    /// by `inner`, if any, when no file configures the target.
+    ///
+    /// When `pending` is set, it resolves the inner profile, which is used once
+    /// it is resolved.
+    fn spawn(
+        &self,
+        addr: Addr,
+        mut inner: Option<Receiver>,
+        mut pending: Option<InnerFuture>,
+    ) -> Receiver {
+        let host = host(&addr);
+        let mut specs = self.specs.clone();
+        let mut spec = specs.borrow().get(&host).cloned();
+        let (tx, rx) = watch::channel(profile(&addr, spec.as_ref(), inner.as_ref()));
+
+        let publish = async move {
+            loop {
+                let update = {
+                    let inner_changed = match inner.as_mut() {
+                        Some(rx) => future::Either::Left(rx.changed()),
+                        None => future::Either::Right(future::pending()),
+                    };
+                    let inner_resolved = match pending.as_mut() {
+                        Some(f) => future::Either::Left(f),
+                        None => future::Either::Right(future::pending()),
+                    };
+                    select_biased! {
+                        _ = tx.closed().fuse() => Update::Closed,
+                        res = specs.changed().fuse() => match res {
+                            Ok(()) => Update::Specs,
+                            Err(_) => Update::Closed,
+                        },
+                        res = inner_changed.fuse() => match res {
+                            Ok(()) => Update::Inner,
+                            Err(_) => Update::InnerClosed,
+                        },
+                        rx = inner_resolved.fuse() => Update::InnerResolved(rx),
+                    }
+                };
+
+                match update {
+                    Update::Closed => {
+                        trace!("Profile dropped");
+                        return;
+                    }
+                    Update::Specs => {
+                        let s = specs.borrow().get(&host).cloned();
+                        if s == spec {
+                            continue;
+                        }
+                        debug!(%addr, "Profile file changed");
+                        spec = s;
+                    }
+                    Update::InnerClosed => {
+                        // The last inner profile continues to be served.
+                        trace!("Inner profile dropped");
+                        inner = None;
+                        continue;
+                    }
+                    Update::InnerResolved(rx) => {
+                        trace!(resolved = rx.is_some(), "Inner profile resolved");
+                        pending = None;
+                        inner = rx;
+                        if spec.is_some() || inner.is_none() {
+                            continue;
+                        }
+                    }
+                    // Files take precedence over the inner profile.
+                    Update::Inner if spec.is_some() => continue,
+                    Update::Inner => {}
+                }
+
+                if tx
+                    .send(profile(&addr, spec.as_ref(), inner.as_ref()))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        };
+        tokio::spawn(publish.in_current_span());
+
+        rx
+    }
+}
+
+impl<T: Into<Addr>> tower::Service<T> for Profiles {
+    type Response = Option<Receiver>;
+    type Error = Error;
+    type Future = future::Ready<Result<Option<Receiver>, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        future::ok(Some(self.spawn(target.into(), None, None)))
+    }
+}
+
+// === impl Overrides ===
+
+impl<P> Overrides<P> {
+    /// When `local` is `None`, profiles are served by `inner` alone.
+    pub fn new(local: Option<Profiles>, inner: P) -> Self {
+        Self { local, inner }
+    }
+}
+
+impl<T, P> tower::Service<T> for Overrides<P>
+where
+    T: Into<Addr> + Clone,
+    P: GetProfile<T>,
+    P::Future: Send + 'static,
+{
+    type Response = Option<Receiver>;
+    type Error = Error;
+    type Future = ProfileFuture;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, target: T) -> Self::Future {
+        let local = match self.local.clone() {
+            Some(local) => local,
+            None => return Box::pin(self.inner.get_profile(target).err_into::<Error>()),
+        };
+
+        let addr: Addr = target.clone().into();
+        let inner = self.inner.get_profile(target).err_into::<Error>();
+        if !local.contains(&host(&addr)) {
+            return Box::pin(inner.map_ok(move |inner| Some(local.spawn(addr, inner, None))));
+        }
+
+        // The destination service may be unavailable, so its errors are not
+        // surfaced when a file configures the target.
+        let pending = {
+            let addr = addr.clone();
+            inner.map(move |res| match res {
+                Ok(inner) => inner,
+                Err(error) => {
+                    debug!(%error, %addr, "Using profile file");
+                    None
+                }
+            })
+        };
+        Box::pin(future::ok(Some(local.spawn(
+            addr,
+            None,
+            Some(Box::pin(pending)),
+        ))))
+    }
+}
+
+// === impl FaultAbortSpec ===
+
+impl FaultAbortSpec {
+    fn default_http_status() -> u16 {
+        500
+    }
+
+    fn default_grpc_status() -> u32 {
+        // UNAVAILABLE
+        14
+    }
+}
+
+/// Returns the host of a target, to which profiles are keyed.
+fn host(addr: &Addr) -> String {
+    match addr {
+        Addr::Name(n) => n.name().without_trailing_dot().to_string(),
+        Addr::Socket(sa) => sa.ip().to_string(),
+    }
+}
+
+/// Builds a target's profile from its file, falling back to the inner profile
+/// or, if there is none, the default profile.
+fn profile(addr: &Addr, spec: Option<&ServiceProfile>, inner: Option<&Receiver>) -> Profile {
+    match spec {
+        Some(spec) => convert_profile(spec, addr.port()),
+        None => inner.map(|rx| rx.borrow().clone()).unwrap_or_default(),
+    }
+}
+
+/// Reads every profile file in `dir` into `files`.
+///
+/// Files whose modification time and length are unchanged are not read
+/// again. Files that fail to parse retain their previously loaded spec.
+fn load_dir(dir: &Path, files: &mut Files) -> io::Result<()> {
+    let mut paths = HashSet::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let ext = path.extension().and_then(|e| e.to_str());
+        if !matches!(ext, Some("yaml") | Some("yml") | Some("json")) {
+            continue;
+        }
+        let meta = match fs::metadata(&path) {
+            Ok(meta) if meta.is_file() => meta,
+            _ => continue,
+        };
+        paths.insert(path.clone());
+
+        let modified = meta.modified().ok();
+        if let Some(file) = files.get(&path) {
+            if modified.is_some() && file.modified == modified && file.len == meta.len() {
+                continue;
+            }
+        }
+        let spec = match read_file(&path) {
+            Ok(spec) => Some(spec),
+            Err(error) => {
+                warn!(%error, file = %path.display(), "Failed to load profile");
+                files.get_mut(&path).and_then(|f| f.spec.take())
+            }
+        };
+        let file = File {
+            modified,
+            len: meta.len(),
+            spec,
+        };
+        files.insert(path, file);
+    }
+
+    files.retain(|path, _| paths.contains(path));
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<ServiceProfile, Error> {
+    let bytes = fs::read(path)?;
+    let spec = if path.extension().and_then(|e| e.to_str()) == Some("json") {
+        serde_json::from_slice(&bytes)?
+    } else {
+        serde_yaml::from_slice(&bytes)?
+    };
+    Ok(spec)
+}
+
+/// Indexes specs by host. When several files configure the same host, the
+/// file whose path sorts last wins.
+fn index(files: &Files) -> Specs {
+    let mut specs = files
+        .iter()
+        .filter_map(|(path, file)| Some((path, file.spec.as_ref()?)))
+        .collect::<Vec<_>>();
+    specs.sort_by_key(|&(path, _)| path);
+    specs
+        .into_iter()
+        .map(|(_, spec)| {
+            let name = spec.metadata.name.trim_end_matches('.').to_string();
+            (name, spec.clone())
+        })
+        .collect()
+}
+
+fn convert_profile(orig: &ServiceProfile, port: u16) -> Profile {
+    let spec = &orig.spec;
+    let name = Name::from_str(&orig.metadata.name).ok();
+    let retry_budget = convert_retry_budget(spec.retry_budget.as_ref());
+    let hash_key = spec.hash_key.as_ref().and_then(|key| match key.parse() {
+        Ok(key) => Some(key),
+        Err(_) => {
+            warn!(%key, "Invalid hash key");
+            None
+        }
+    });
+    let http_routes = spec
+        .routes
+        .iter()
+        .filter_map(|r| convert_route(r, &retry_budget, hash_key.as_ref()))
+        .collect();
+    let targets = spec
+        .dst_overrides
+        .iter()
+        .filter_map(convert_dst_override)
+        .collect();
+    Profile {
+        name,
+        http_routes,
+        targets,
+        opaque_protocol: spec.opaque_ports.contains(&port),
+        endpoint: None,
+        hash_key,
+    }
+}
+
+fn convert_route(
+    orig: &RouteSpec,
+    retry_budget: &Arc<Budget>,
+    hash_key: Option<&http::HashKey>,
+) -> Option<(http::RequestMatch, http::Route)> {
+    let req_match = match convert_req_match(&orig.condition) {
+        Some(m) => m,
+        None => {
+            warn!(route = %orig.name, "Route has an invalid condition");
+            return None;
+        }
+    };
+    let rsp_classes = orig
+        .response_classes
+        .iter()
+        .filter_map(|c| {
+            let m = convert_rsp_match(&c.condition)?;
+            Some(http::ResponseClass::new(c.is_failure, m))
+        })
+        .collect();
+    let labels = std::iter::once(("route".to_string(), orig.name.clone()));
+    let mut route = http::Route::new(labels, rsp_classes);
+    if orig.is_retryable {
+        route.set_retries(retry_budget.clone());
+    }
+    if orig.is_idempotent {
+        route.set_hedges(retry_budget.clone());
+    }
+    if let Some(timeout) = orig.timeout.as_ref() {
+        match parse_duration(timeout) {
+            Ok(timeout) => route.set_timeout(timeout),
+            Err(_) => warn!(route = %orig.name, %timeout, "Invalid route timeout"),
+        }
+    }
+    if let Some(mirror) = orig.mirror.as_ref() {
+        match Addr::from_str(mirror) {
+            Ok(addr) => route.set_mirror(addr),
+            Err(_) => warn!(route = %orig.name, %mirror, "Invalid mirror address"),
+        }
+    }
+    if let Some(fault) = orig.fault.as_ref() {
+        match convert_fault(fault) {
+            Some(fault) => route.set_fault(fault),
+            None => warn!(route = %orig.name, "Invalid fault"),
+        }
+    }
+    match orig.hash_key.as_ref() {
+        Some(key) => match key.parse() {
+            Ok(key) => route.set_hash_key(key),
+            Err(_) => warn!(route = %orig.name, %key, "Invalid hash key"),
+        },
+        None => {
+            if let Some(key) = hash_key {
+                route.set_hash_key(key.clone());
+            }
+        }
+    }
+    Some((req_match, route))
+}
+
+/// Converts a request match. When a condition sets several fields, all of
+/// them must match.
+fn convert_req_match(orig: &RequestMatchSpec) -> Option<http::RequestMatch> {
+    let mut ms = Vec::new();
+    if !orig.all.is_empty() {
+        let all = orig
+            .all
+            .iter()
+            .map(convert_req_match)
+            .collect::<Option<_>>()?;
+        ms.push(http::RequestMatch::All(all));
+    }
+    if !orig.any.is_empty() {
+        let any = orig
+            .any
+            .iter()
+            .map(convert_req_match)
+            .collect::<Option<_>>()?;
+        ms.push(http::RequestMatch::Any(any));
+    }
+    if let Some(not) = orig.not.as_ref() {
+        ms.push(http::RequestMatch::Not(Box::new(convert_req_match(not)?)));
+    }
+    if let Some(path) = orig.path_regex.as_ref() {
+        ms.push(http::RequestMatch::Path(anchored_regex(path)?));
+    }
+    if let Some(method) = orig.method.as_ref() {
+        let method = ::http::Method::from_bytes(method.as_bytes()).ok()?;
+        ms.push(http::RequestMatch::Method(method));
+    }
+    if let Some(authority) = orig.authority.as_ref() {
+        ms.push(http::RequestMatch::Authority(convert_value_match(
+            authority,
+        )?));
+    }
+    if let Some(header) = orig.header.as_ref() {
+        let name = ::http::header::HeaderName::from_bytes(header.name.as_bytes()).ok()?;
+        let m = convert_value_match(&header.value)?;
+        ms.push(http::RequestMatch::Header(name, m));
+    }
+    if let Some(param) = orig.query_param.as_ref() {
+        let m = convert_value_match(&param.value)?;
+        ms.push(http::RequestMatch::QueryParam(param.name.clone(), m));
+    }
+    if let Some(cookie) = orig.cookie.as_ref() {
+        let m = convert_value_match(&cookie.value)?;
+        ms.push(http::RequestMatch::Cookie(cookie.name.clone(), m));
+    }
+
+    if ms.len() > 1 {
+        Some(http::RequestMatch::All(ms))
+    } else {
+        ms.pop()
+    }
+}
+
+fn convert_value_match(orig: &ValueMatchSpec) -> Option<http::ValueMatch> {
+    match (orig.value.as_ref(), orig.regex.as_ref()) {
+        (None, None) => Some(http::ValueMatch::Present),
+        (Some(v), None) => Some(http::ValueMatch::Exact(v.clone())),
+        (None, Some(re)) => Some(http::ValueMatch::Regex(anchored_regex(re)?)),
+        (Some(_), Some(_)) => None,
+    }
+}
+
+/// Converts a response match. When a condition sets several fields, all of
+/// them must match.
+fn convert_rsp_match(orig: &ResponseMatchSpec) -> Option<http::ResponseMatch> {
+    let mut ms = Vec::new();
+    if !orig.all.is_empty() {
+        let all = orig
+            .all
+            .iter()
+            .map(convert_rsp_match)
+            .collect::<Option<_>>()?;
+        ms.push(http::ResponseMatch::All(all));
+    }
+    if !orig.any.is_empty() {
+        let any = orig
+            .any
+            .iter()
+            .map(convert_rsp_match)
+            .collect::<Option<_>>()?;
+        ms.push(http::ResponseMatch::Any(any));
+    }
+    if let Some(not) = orig.not.as_ref() {
+        ms.push(http::ResponseMatch::Not(Box::new(convert_rsp_match(not)?)));
+    }
+    if let Some(RangeSpec { min, max }) = orig.status {
+        let min = ::http::StatusCode::from_u16(min as u16).ok()?;
+        let max = ::http::StatusCode::from_u16(max as u16).ok()?;
+        ms.push(http::ResponseMatch::Status { min, max });
+    }
+    if let Some(RangeSpec { min, max }) = orig.grpc_status {
+        ms.push(http::ResponseMatch::GrpcStatus { min, max });
+    }
+
+    if ms.len() > 1 {
+        Some(http::ResponseMatch::All(ms))
+    } else {
+        ms.pop()
+    }
+}
+
+/// Uses the same defaults as the destination service when a profile does not
+/// configure a retry budget.
+fn convert_retry_budget(orig: Option<&RetryBudgetSpec>) -> Arc<Budget> {
+    let default = || Arc::new(Budget::new(Duration::from_secs(10), 10, 0.2));
+    let orig = match orig {
+        Some(orig) => orig,
+        None => return default(),
+    };
+    let ttl = match parse_duration(&orig.ttl) {
+        Ok(ttl) if ttl >= Duration::from_secs(1) && ttl <= Duration::from_secs(60) => ttl,
+        _ => {
+            warn!(ttl = %orig.ttl, "Invalid retry budget TTL");
+            return default();
+        }
+    };
+    if !(0.0..=1000.0).contains(&orig.retry_ratio) || orig.min_retries_per_second > i32::MAX as u32
+    {
+        warn!(?orig, "Invalid retry budget");
+        return default();
+    }
+    Arc::new(Budget::new(
+        ttl,
+        orig.min_retries_per_second,
+        orig.retry_ratio,
+    ))
+}
+
+/// Overrides with a weight of zero are retained, so that a backend may be
+/// drained without removing it from the split and so that requests matching
+/// its condition are still routed to it.
+fn convert_dst_override(orig: &DstOverrideSpec) -> Option<Target> {
+    let addr = Addr::from_str(&orig.authority).ok()?;
+    let request_match = match orig.condition.as_ref() {
+        Some(c) => Some(convert_req_match(c)?),
+        None => None,
+    };
+    Some(Target {
+        addr,
+        weight: orig.weight,
+        request_match,
+    })
+}
+
+fn convert_fault(orig: &FaultSpec) -> Option<http::Fault> {
+    let delay = match orig.delay.as_ref() {
+        Some(d) => {
+            let min = parse_duration(&d.min).ok()?;
+            let max = match d.max.as_ref() {
+                Some(max) => parse_duration(max).ok()?,
+                None => min,
+            };
+            if d.percent > 100 || max < min {
+                return None;
+            }
+            Some(http::FaultDelay {
+                percent: d.percent,
+                min,
+                max,
+            })
+        }
+        None => None,
+    };
+    let abort = match orig.abort.as_ref() {
+        Some(a) => {
+            if a.percent > 100 {
+                return None;
+            }
+            Some(http::FaultAbort {
+                percent: a.percent,
+                http_status: ::http::StatusCode::from_u16(a.http_status).ok()?,
+                grpc_status: a.grpc_status,
+            })
+        }
+        None => None,
+    };
+    Some(http::Fault { delay, abort })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linkerd2_test_util::TempDir;
+
+    const WEB: &str = r#"
+metadata:
+  name: web.default.svc.cluster.local.
+spec:
+  routes:
+  - name: GET /books
+    condition:
+      method: GET
+      pathRegex: /books/[^/]*
+    responseClasses:
+    - condition:
+        status: { min: 500, max: 599 }
+      isFailure: true
+    isRetryable: true
+    timeout: 300ms
+  - name: canary
+    condition:
+      header: { name: x-canary, value: "true" }
+    fault:
+      abort: { percent: 10, httpStatus: 503 }
+    hashKey: cookie:session
+  - name: invalid
+    condition:
+      pathRegex: "("
+  dstOverrides:
+  - authority: web-v2.default.svc.cluster.local:8080
+    weight: 100
+    condition:
+      cookie: { name: canary }
+  - authority: web-v1.default.svc.cluster.local:8080
+    weight: 0
+  opaquePorts: [3306]
+  hashKey: source-ip
+"#;
+
+    #[test]
+    fn converts_service_profile() {
+        let spec = serde_yaml::from_str::<ServiceProfile>(WEB).unwrap();
+        let profile = convert_profile(&spec, 8080);
+
+        assert_eq!(
+            profile.name.as_ref().map(|n| n.without_trailing_dot()),
+            Some("web.default.svc.cluster.local")
+        );
+        assert!(!profile.opaque_protocol);
+        assert!(convert_profile(&spec, 3306).opaque_protocol);
+
+        assert_eq!(profile.http_routes.len(), 2, "invalid routes are skipped");
+        let (m, books) = &profile.http_routes[0];
+        let req = ::http::Request::get("/books/1").body(()).unwrap();
+        assert!(m.is_match(&req));
+        let req = ::http::Request::post("/books/1").body(()).unwrap();
+        assert!(!m.is_match(&req));
+        assert_eq!(books.labels()["route"], "GET /books");
+        assert_eq!(books.timeout(), Some(Duration::from_millis(300)));
+        assert!(books.retries().is_some());
+        assert!(books.hedges().is_none());
+        assert!(books.response_classes()[0].is_failure());
+        assert_eq!(books.hash_key(), Some(&http::HashKey::SourceIp));
+
+        let (m, canary) = &profile.http_routes[1];
+        let req = ::http::Request::get("/")
+            .header("x-canary", "true")
+            .body(())
+            .unwrap();
+        assert!(m.is_match(&req));
+        let abort = canary.fault().unwrap().abort.as_ref().unwrap();
+        assert_eq!(abort.http_status, ::http::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(abort.grpc_status, 14);
+        assert_eq!(
+            canary.hash_key(),
+            Some(&http::HashKey::Cookie("session".to_string()))
+        );
+        assert_eq!(profile.hash_key, Some(http::HashKey::SourceIp));
+
+        assert_eq!(profile.targets.len(), 2, "drained targets are retained");
+        assert_eq!(profile.targets[0].weight, 100);
+        assert!(profile.targets[0].request_match.is_some());
+        assert_eq!(profile.targets[1].weight, 0);
+    }
+
+    #[test]
+    fn skips_unchanged_files() {
+        let dir = TempDir::new();
+        let file = dir.write("web.yaml", WEB);
+
+        let mut files = Files::new();
+        load_dir(dir.path(), &mut files).unwrap();
+        assert!(files[&file].spec.is_some());
+
+        // The file is not read again until it changes.
+        files.get_mut(&file).unwrap().spec = None;
+        load_dir(dir.path(), &mut files).unwrap();
+        assert!(files[&file].spec.is_none());
+
+        dir.write("web.yaml", format!("{}\n", WEB));
+        load_dir(dir.path(), &mut files).unwrap();
+        assert!(files[&file].spec.is_some());
+    }
+
+    #[tokio::test]
+    async fn serves_files_before_inner_profiles() {
+        time::pause();
+        let dir = TempDir::new();
+        dir.write("web.yaml", WEB);
+
+        // The inner profile is never resolved.
+        let inner =
+            tower::service_fn(|_: Addr| future::pending::<Result<Option<Receiver>, Error>>());
+        let mut overrides = Profiles::watch(dir.path().to_owned(), Duration::from_millis(10))
+            .unwrap()
+            .with_overrides_of(inner);
+        let rx = overrides
+            .get_profile(Addr::from_str("web.default.svc.cluster.local:8080").unwrap())
+            .await
+            .unwrap()
+            .expect("profile files are served");
+        assert_eq!(rx.borrow().http_routes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn watches_directory() {
+        time::pause();
+        let dir = TempDir::new();
+
+        let mut profiles =
+            Profiles::watch(dir.path().to_owned(), Duration::from_millis(10)).unwrap();
+        let mut rx = profiles
+            .get_profile(Addr::from_str("web.default.svc.cluster.local:8080").unwrap())
+            .await
+            .unwrap()
+            .expect("profiles are always served");
+        assert!(rx.borrow().http_routes.is_empty());
+
+        dir.write("web.yaml", WEB);
+        rx.changed().await.unwrap();
+        assert_eq!(rx.borrow().http_routes.len(), 2);
+
+        dir.remove("web.yaml");
+        rx.changed().await.unwrap();
+        assert!(rx.borrow().http_routes.is_empty());
+    }
+}
@@ -1,11 +1,14 @@
 use crate::Receiver;
 use indexmap::IndexMap;
+use linkerd2_addr::Addr;
 use regex::Regex;
 use std::{
+    borrow::Cow,
     fmt,
     hash::{Hash, Hasher},
     iter::FromIterator,
     ops::Deref,
+    str::FromStr,
     sync::Arc,
     time::Duration,
 };
@@ -18,7 +21,11 @@ pub struct Route {
     labels: Labels,
     response_classes: ResponseClasses,
     retries: Option<Retries>,
+    hedges: Option<Hedges>,
     timeout: Option<Duration>,
+    mirror: Option<Addr>,
+    fault: Option<Fault>,
+    hash_key: Option<HashKey>,
 }
 
 #[derive(Clone, Debug)]
@@ -28,6 +35,20 @@ pub enum RequestMatch {
     Not(Box<RequestMatch>),
     Path(Regex),
     Method(http::Method),
+    Authority(ValueMatch),
+    Header(http::header::HeaderName, ValueMatch),
+    QueryParam(String, ValueMatch),
+    Cookie(String, ValueMatch),
+}
+
+/// Matches a string value in a request, e.g. a header value, a query
+/// parameter, a cookie, or the request's authority.
+#[derive(Clone, Debug)]
+pub enum ValueMatch {
+    /// Matches any value, so long as it is present.
+    Present,
+    Exact(String),
+    Regex(Regex),
 }
 
 #[derive(Clone, Debug)]
@@ -48,6 +69,15 @@ pub enum ResponseMatch {
         min: http::StatusCode,
         max: http::StatusCode,
     },
+    /// Matches a gRPC status code in the given inclusive range.
+    ///
+    /// gRPC responses typically carry their status in trailers, so this may
+    /// only be evaluated once the response stream has ended (unless the
+    /// response is trailers-only).
+    GrpcStatus {
+        min: u32,
+        max: u32,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -55,6 +85,62 @@ pub struct Retries {
     budget: Arc<Budget>,
 }
 
+/// Configures hedged requests on idempotent routes.
+///
+/// Hedged requests are capped by the same sort of budget as retries.
+#[derive(Clone, Debug)]
+pub struct Hedges {
+    budget: Arc<Budget>,
+}
+
+/// Faults that are injected into a percentage of a route's requests.
+///
+/// When both are configured, a request may be delayed and then aborted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Fault {
+    pub delay: Option<FaultDelay>,
+    pub abort: Option<FaultAbort>,
+}
+
+/// Delays requests before they are dispatched.
+///
+/// Each delayed request waits for a random duration between `min` and `max`;
+/// when they are equal, the delay is fixed.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FaultDelay {
+    pub percent: u8,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+/// Fails requests with a synthetic response without dispatching them.
+///
+/// gRPC requests fail with `grpc_status`; all other requests fail with
+/// `http_status`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FaultAbort {
+    pub percent: u8,
+    pub http_status: http::StatusCode,
+    pub grpc_status: u32,
+}
+
+/// Determines which part of a request is hashed to choose an endpoint when
+/// balancing by consistent hashing.
+///
+/// Parsed from `header:<name>`, `cookie:<name>`, or `source-ip`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    /// Hashes the value of the named header.
+    Header(http::header::HeaderName),
+    /// Hashes the value of the named cookie.
+    Cookie(String),
+    /// Hashes the client's IP address.
+    SourceIp,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidHashKey(());
+
 #[derive(Clone, Default)]
 struct Labels(Arc<IndexMap<String, String>>);
 
@@ -75,7 +161,11 @@ impl Route {
             labels,
             response_classes: ResponseClasses(response_classes.into()),
             retries: None,
+            hedges: None,
             timeout: None,
+            mirror: None,
+            fault: None,
+            hash_key: None,
         }
     }
 
@@ -91,31 +181,182 @@ impl Route {
         self.retries.as_ref()
     }
 
+    /// Returns the route's hedge configuration, if the route is marked
+    /// idempotent.
+    pub fn hedges(&self) -> Option<&Hedges> {
+        self.hedges.as_ref()
+    }
+
     pub fn timeout(&self) -> Option<Duration> {
         self.timeout
     }
 
+    /// Returns the address to which a copy of each request on this route is
+    /// sent, if any.
+    pub fn mirror(&self) -> Option<&Addr> {
+        self.mirror.as_ref()
+    }
+
+    pub fn fault(&self) -> Option<&Fault> {
+        self.fault.as_ref()
+    }
+
+    /// Returns the key by which the route's requests are balanced, if they
+    /// are balanced by consistent hashing rather than by load.
+    pub fn hash_key(&self) -> Option<&HashKey> {
+        self.hash_key.as_ref()
+    }
+
     pub fn set_retries(&mut self, budget: Arc<Budget>) {
         self.retries = Some(Retries { budget });
     }
 
+    /// Marks the route as idempotent so that its requests may be hedged.
+    pub fn set_hedges(&mut self, budget: Arc<Budget>) {
+        self.hedges = Some(Hedges { budget });
+    }
+
     pub fn set_timeout(&mut self, timeout: Duration) {
         self.timeout = Some(timeout);
     }
+
+    pub fn set_mirror(&mut self, addr: Addr) {
+        self.mirror = Some(addr);
+    }
+
+    pub fn set_fault(&mut self, fault: Fault) {
+        self.fault = Some(fault);
+    }
+
+    pub fn set_hash_key(&mut self, hash_key: HashKey) {
+        self.hash_key = Some(hash_key);
+    }
 }
 
 // === impl RequestMatch ===
 
 impl RequestMatch {
-    fn is_match<B>(&self, req: &http::Request<B>) -> bool {
+    pub fn is_match<B>(&self, req: &http::Request<B>) -> bool {
         match self {
             RequestMatch::Method(ref method) => req.method() == *method,
             RequestMatch::Path(ref re) => re.is_match(req.uri().path()),
+            RequestMatch::Authority(ref m) => {
+                Self::authority(req).map(|a| m.is_match(a)).unwrap_or(false)
+            }
+            RequestMatch::Header(ref name, ref m) => {
+                req.headers()
+                    .get_all(name)
+                    .iter()
+                    .any(|v| match v.to_str() {
+                        Ok(v) => m.is_match(v),
+                        // Values that are not valid strings can only satisfy a
+                        // presence match.
+                        Err(_) => m.is_present(),
+                    })
+            }
+            RequestMatch::QueryParam(ref name, ref m) => req
+                .uri()
+                .query()
+                .map(|q| Self::query_params(q).any(|(k, v)| k == *name && m.is_match(&v)))
+                .unwrap_or(false),
+            RequestMatch::Cookie(ref name, ref m) => req
+                .headers()
+                .get_all(http::header::COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(cookies)
+                .any(|(k, v)| k == name && m.is_match(v)),
             RequestMatch::Not(ref m) => !m.is_match(req),
             RequestMatch::All(ref ms) => ms.iter().all(|m| m.is_match(req)),
             RequestMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(req)),
         }
     }
+
+    /// Returns the request's authority, preferring the URI's authority over
+    /// the `Host` header.
+    fn authority<B>(req: &http::Request<B>) -> Option<&str> {
+        req.uri().authority().map(|a| a.as_str()).or_else(|| {
+            req.headers()
+                .get(http::header::HOST)
+                .and_then(|h| h.to_str().ok())
+        })
+    }
+
+    /// Splits a query string into key-value pairs. Parameters without a value
+    /// (e.g. `?debug`) have an empty value.
+    ///
+    /// Keys and values are percent-decoded, and `+` is decoded as a space, as
+    /// in `application/x-www-form-urlencoded` data.
+    fn query_params(query: &str) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        query
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|p| match p.find('=') {
+                Some(i) => (Self::decode(&p[..i]), Self::decode(&p[i + 1..])),
+                None => (Self::decode(p), Cow::Borrowed("")),
+            })
+    }
+
+    /// Percent-decodes a query string component. Malformed escapes are left
+    /// as-is and invalid UTF-8 is replaced.
+    fn decode(s: &str) -> Cow<'_, str> {
+        if !s.bytes().any(|b| b == b'%' || b == b'+') {
+            return Cow::Borrowed(s);
+        }
+
+        fn hex(b: u8) -> Option<u8> {
+            match b {
+                b'0'..=b'9' => Some(b - b'0'),
+                b'a'..=b'f' => Some(b - b'a' + 10),
+                b'A'..=b'F' => Some(b - b'A' + 10),
+                _ => None,
+            }
+        }
+
+        let bytes = s.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'+' => out.push(b' '),
+                b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push(hi << 4 | lo);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                },
+                b => out.push(b),
+            }
+            i += 1;
+        }
+        Cow::Owned(String::from_utf8_lossy(&out).into_owned())
+    }
+}
+
+/// Splits a `Cookie` header value into name-value pairs.
+fn cookies(header: &str) -> impl Iterator<Item = (&str, &str)> {
+    header.split(';').filter_map(|c| {
+        let c = c.trim();
+        let i = c.find('=')?;
+        Some((&c[..i], &c[i + 1..]))
+    })
+}
+
+// === impl ValueMatch ===
+
+impl ValueMatch {
+    pub fn is_match(&self, value: &str) -> bool {
+        match self {
+            ValueMatch::Present => true,
+            ValueMatch::Exact(ref v) => v == value,
+            ValueMatch::Regex(ref re) => re.is_match(value),
+        }
+    }
+
+    fn is_present(&self) -> bool {
+        matches!(self, ValueMatch::Present)
+    }
 }
 
 // === impl ResponseClass ===
@@ -132,6 +373,17 @@ impl ResponseClass {
     pub fn is_match<B>(&self, req: &http::Response<B>) -> bool {
         self.match_.is_match(req)
     }
+
+    /// Matches a response given its HTTP status and, if it is known, its gRPC
+    /// status, e.g. as read from the response's trailers.
+    pub fn is_match_status(&self, status: http::StatusCode, grpc_status: Option<u32>) -> bool {
+        self.match_.is_match_status(status, grpc_status)
+    }
+
+    /// Returns true if this class matches on the gRPC status.
+    pub fn is_grpc(&self) -> bool {
+        self.match_.is_grpc()
+    }
 }
 
 // === impl ResponseClasses ===
@@ -167,14 +419,60 @@ impl fmt::Debug for ResponseClasses {
 // === impl ResponseMatch ===
 
 impl ResponseMatch {
-    fn is_match<B>(&self, req: &http::Response<B>) -> bool {
+    fn is_match<B>(&self, rsp: &http::Response<B>) -> bool {
+        let grpc_status = rsp
+            .headers()
+            .get("grpc-status")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|s| s.parse::<u32>().ok());
+        self.is_match_status(rsp.status(), grpc_status)
+    }
+
+    fn is_match_status(&self, status: http::StatusCode, grpc_status: Option<u32>) -> bool {
+        self.eval(status, grpc_status).unwrap_or(false)
+    }
+
+    /// Evaluates the match, returning `None` if it depends on a gRPC status
+    /// that is not known, so that e.g. a negated gRPC status match does not
+    /// match responses without a gRPC status.
+    fn eval(&self, status: http::StatusCode, grpc_status: Option<u32>) -> Option<bool> {
+        match self {
+            ResponseMatch::Status { ref min, ref max } => Some(*min <= status && status <= *max),
+            ResponseMatch::GrpcStatus { min, max } => grpc_status.map(|s| *min <= s && s <= *max),
+            ResponseMatch::Not(ref m) => m.eval(status, grpc_status).map(|m| !m),
+            ResponseMatch::All(ref ms) => {
+                let mut result = Some(true);
+                for m in ms {
+                    match m.eval(status, grpc_status) {
+                        Some(false) => return Some(false),
+                        Some(true) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+            ResponseMatch::Any(ref ms) => {
+                let mut result = Some(false);
+                for m in ms {
+                    match m.eval(status, grpc_status) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    fn is_grpc(&self) -> bool {
         match self {
-            ResponseMatch::Status { ref min, ref max } => {
-                *min <= req.status() && req.status() <= *max
+            ResponseMatch::GrpcStatus { .. } => true,
+            ResponseMatch::Status { .. } => false,
+            ResponseMatch::Not(ref m) => m.is_grpc(),
+            ResponseMatch::All(ref ms) | ResponseMatch::Any(ref ms) => {
+                ms.iter().any(|m| m.is_grpc())
             }
-            ResponseMatch::Not(ref m) => !m.is_match(req),
-            ResponseMatch::All(ref ms) => ms.iter().all(|m| m.is_match(req)),
-            ResponseMatch::Any(ref ms) => ms.iter().any(|m| m.is_match(req)),
         }
     }
 }
@@ -201,6 +499,83 @@ impl Hash for Retries {
     }
 }
 
+// === impl Hedges ===
+
+impl Hedges {
+    pub fn budget(&self) -> &Arc<Budget> {
+        &self.budget
+    }
+}
+
+impl PartialEq for Hedges {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.budget, &other.budget)
+    }
+}
+
+impl Eq for Hedges {}
+
+impl Hash for Hedges {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(Arc::as_ref(&self.budget) as *const _ as usize);
+    }
+}
+
+// === impl HashKey ===
+
+impl HashKey {
+    /// Returns the value of the key in a request, if it is set.
+    ///
+    /// The client's IP address is not part of the request, so `SourceIp`
+    /// keys have no value.
+    pub fn value<'r, B>(&self, req: &'r http::Request<B>) -> Option<&'r [u8]> {
+        match self {
+            HashKey::Header(name) => req.headers().get(name).map(|v| v.as_bytes()),
+            HashKey::Cookie(name) => req
+                .headers()
+                .get_all(http::header::COOKIE)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(cookies)
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_bytes()),
+            HashKey::SourceIp => None,
+        }
+    }
+}
+
+impl FromStr for HashKey {
+    type Err = InvalidHashKey;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "source-ip" {
+            return Ok(HashKey::SourceIp);
+        }
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("header"), Some(name)) => name
+                .parse()
+                .map(HashKey::Header)
+                .map_err(|_| InvalidHashKey(())),
+            (Some("cookie"), Some(name)) if !name.is_empty() => {
+                Ok(HashKey::Cookie(name.to_string()))
+            }
+            _ => Err(InvalidHashKey(())),
+        }
+    }
+}
+
+impl fmt::Display for InvalidHashKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hash keys must be one of header:<name>, cookie:<name>, or source-ip"
+        )
+    }
+}
+
+impl std::error::Error for InvalidHashKey {}
+
 // === impl Labels ===
 
 impl PartialEq for Labels {
@@ -222,3 +597,199 @@ impl fmt::Debug for Labels {
         self.0.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(uri: &str) -> http::Request<()> {
+        http::Request::get(uri).body(()).unwrap()
+    }
+
+    #[test]
+    fn header_match() {
+        let exact = RequestMatch::Header(
+            http::header::HeaderName::from_static("x-tenant"),
+            ValueMatch::Exact("blue".into()),
+        );
+        let present = RequestMatch::Header(
+            http::header::HeaderName::from_static("x-tenant"),
+            ValueMatch::Present,
+        );
+        let re = RequestMatch::Header(
+            http::header::CONTENT_TYPE,
+            ValueMatch::Regex(Regex::new("^application/grpc").unwrap()),
+        );
+
+        let mut r = req("http://foo.svc.cluster.local/");
+        assert!(!exact.is_match(&r));
+        assert!(!present.is_match(&r));
+        assert!(!re.is_match(&r));
+
+        r.headers_mut().append("x-tenant", "green".parse().unwrap());
+        r.headers_mut().insert(
+            http::header::CONTENT_TYPE,
+            "application/grpc+proto".parse().unwrap(),
+        );
+        assert!(!exact.is_match(&r));
+        assert!(present.is_match(&r));
+        assert!(re.is_match(&r));
+
+        // Any of a header's values may match.
+        r.headers_mut().append("x-tenant", "blue".parse().unwrap());
+        assert!(exact.is_match(&r));
+    }
+
+    #[test]
+    fn query_param_match() {
+        let version = RequestMatch::QueryParam("version".into(), ValueMatch::Exact("2".into()));
+        let debug = RequestMatch::QueryParam("debug".into(), ValueMatch::Present);
+
+        assert!(!version.is_match(&req("http://foo.svc.cluster.local/books")));
+        assert!(!version.is_match(&req("http://foo.svc.cluster.local/books?version=1")));
+        assert!(!version.is_match(&req("http://foo.svc.cluster.local/books?myversion=2")));
+        assert!(version.is_match(&req("http://foo.svc.cluster.local/books?version=2")));
+        assert!(version.is_match(&req("http://foo.svc.cluster.local/books?a=b&version=2")));
+
+        assert!(!debug.is_match(&req("http://foo.svc.cluster.local/books?version=2")));
+        assert!(debug.is_match(&req("http://foo.svc.cluster.local/books?debug")));
+        assert!(debug.is_match(&req("http://foo.svc.cluster.local/books?debug=1&version=2")));
+
+        // Keys and values are percent-decoded before they are matched.
+        let q = RequestMatch::QueryParam("q x".into(), ValueMatch::Exact("a b".into()));
+        assert!(q.is_match(&req("http://foo.svc.cluster.local/books?q%20x=a%20b")));
+        assert!(q.is_match(&req("http://foo.svc.cluster.local/books?q+x=a+b")));
+        assert!(!q.is_match(&req("http://foo.svc.cluster.local/books?q%20x=a%2")));
+        let pct = RequestMatch::QueryParam("q".into(), ValueMatch::Exact("100%".into()));
+        assert!(pct.is_match(&req("http://foo.svc.cluster.local/books?q=100%25")));
+        assert!(pct.is_match(&req("http://foo.svc.cluster.local/books?q=100%")));
+    }
+
+    #[test]
+    fn authority_match() {
+        let m = RequestMatch::Authority(ValueMatch::Exact("foo.svc.cluster.local:8080".into()));
+        assert!(m.is_match(&req("http://foo.svc.cluster.local:8080/")));
+        assert!(!m.is_match(&req("http://bar.svc.cluster.local:8080/")));
+
+        let mut r = req("/");
+        assert!(!m.is_match(&r));
+        r.headers_mut().insert(
+            http::header::HOST,
+            "foo.svc.cluster.local:8080".parse().unwrap(),
+        );
+        assert!(m.is_match(&r));
+    }
+
+    #[test]
+    fn grpc_status_match() {
+        // UNAVAILABLE
+        let class = ResponseClass::new(true, ResponseMatch::GrpcStatus { min: 14, max: 14 });
+        assert!(class.is_grpc());
+        assert!(class.is_match_status(http::StatusCode::OK, Some(14)));
+        assert!(!class.is_match_status(http::StatusCode::OK, Some(5)));
+        assert!(!class.is_match_status(http::StatusCode::OK, None));
+
+        // Trailers-only responses carry the status in their headers.
+        let rsp = http::Response::builder()
+            .header("grpc-status", "14")
+            .body(())
+            .unwrap();
+        assert!(class.is_match(&rsp));
+
+        let status = ResponseClass::new(
+            true,
+            ResponseMatch::Status {
+                min: http::StatusCode::INTERNAL_SERVER_ERROR,
+                max: http::StatusCode::from_u16(599).unwrap(),
+            },
+        );
+        assert!(!status.is_grpc());
+        let not = ResponseClass::new(
+            false,
+            ResponseMatch::Not(Box::new(ResponseMatch::GrpcStatus { min: 0, max: 0 })),
+        );
+        assert!(not.is_grpc());
+        assert!(not.is_match_status(http::StatusCode::OK, Some(2)));
+        assert!(!not.is_match_status(http::StatusCode::OK, Some(0)));
+        // Responses without a gRPC status don't match negated gRPC matches.
+        assert!(!not.is_match_status(http::StatusCode::OK, None));
+
+        let any = ResponseClass::new(
+            true,
+            ResponseMatch::Any(vec![
+                ResponseMatch::Status {
+                    min: http::StatusCode::INTERNAL_SERVER_ERROR,
+                    max: http::StatusCode::from_u16(599).unwrap(),
+                },
+                ResponseMatch::GrpcStatus { min: 14, max: 14 },
+            ]),
+        );
+        assert!(any.is_match_status(http::StatusCode::BAD_GATEWAY, None));
+        assert!(!any.is_match_status(http::StatusCode::OK, None));
+        let not_any = ResponseClass::new(false, ResponseMatch::Not(Box::new(any.match_.clone())));
+        assert!(!not_any.is_match_status(http::StatusCode::BAD_GATEWAY, None));
+        assert!(!not_any.is_match_status(http::StatusCode::OK, None));
+        assert!(not_any.is_match_status(http::StatusCode::OK, Some(0)));
+    }
+
+    #[test]
+    fn cookie_match() {
+        let m = RequestMatch::Cookie("canary".into(), ValueMatch::Exact("true".into()));
+
+        let mut r = req("/");
+        assert!(!m.is_match(&r));
+        r.headers_mut().insert(
+            http::header::COOKIE,
+            "session=abc; canary=false".parse().unwrap(),
+        );
+        assert!(!m.is_match(&r));
+        r.headers_mut().append(
+            http::header::COOKIE,
+            "theme=dark;canary=true".parse().unwrap(),
+        );
+        assert!(m.is_match(&r));
+    }
+
+    #[test]
+    fn hash_key_value() {
+        let key = HashKey::Cookie("session".to_string());
+        let with_cookie = |cookie: &str| {
+            let mut r = req("/");
+            r.headers_mut()
+                .insert(http::header::COOKIE, cookie.parse().unwrap());
+            r
+        };
+
+        assert_eq!(key.value(&with_cookie("session=a")), Some(&b"a"[..]));
+        assert_eq!(
+            key.value(&with_cookie("other=b; session=a")),
+            Some(&b"a"[..])
+        );
+        assert_eq!(key.value(&with_cookie("sessions=a")), None);
+        assert_eq!(key.value(&req("/")), None);
+
+        let key = HashKey::Header(http::header::HeaderName::from_static("x-shard"));
+        let mut r = req("/");
+        assert_eq!(key.value(&r), None);
+        r.headers_mut().insert("x-shard", "7".parse().unwrap());
+        assert_eq!(key.value(&r), Some(&b"7"[..]));
+    }
+
+    #[test]
+    fn parse_hash_key() {
+        assert_eq!("source-ip".parse(), Ok(HashKey::SourceIp));
+        assert_eq!(
+            "header:x-shard".parse(),
+            Ok(HashKey::Header(http::header::HeaderName::from_static(
+                "x-shard"
+            )))
+        );
+        assert_eq!(
+            "cookie:session".parse(),
+            Ok(HashKey::Cookie("session".to_string()))
+        );
+        assert!("cookie:".parse::<HashKey>().is_err());
+        assert!("header:".parse::<HashKey>().is_err());
+        assert!("query:k".parse::<HashKey>().is_err());
+    }
+}
@@ -5,17 +5,20 @@ use linkerd2_addr::Addr;
 pub use linkerd2_dns_name::Name;
 use linkerd2_error::Error;
 use linkerd2_proxy_api_resolve::Metadata;
+use regex::Regex;
 use std::{
     future::Future,
     net::SocketAddr,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use tower::util::{Oneshot, ServiceExt};
 
 mod client;
 mod default;
 pub mod discover;
+pub mod file;
 pub mod http;
 pub mod split;
 
@@ -30,17 +33,31 @@ pub struct Profile {
     pub targets: Vec<Target>,
     pub opaque_protocol: bool,
     pub endpoint: Option<(SocketAddr, Metadata)>,
+    /// When set, connections are balanced by consistent hashing on this key
+    /// rather than by load. Only `SourceIp` keys apply to connections; HTTP
+    /// requests are balanced by their route's key.
+    pub hash_key: Option<self::http::HashKey>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Target {
     pub addr: Addr,
     pub weight: u32,
+    /// Requests that satisfy this match are always routed to this target,
+    /// regardless of the weighted distribution.
+    pub request_match: Option<self::http::RequestMatch>,
 }
 
 #[derive(Clone, Debug)]
 pub struct GetProfileService<P>(P);
 
+/// Describes why a duration could not be parsed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InvalidDuration {
+    NotANumber,
+    NotADuration,
+}
+
 /// Watches a destination's Profile.
 pub trait GetProfile<T> {
     type Error: Into<Error>;
@@ -86,7 +103,41 @@ where
     }
 }
 
-fn stream_profile(mut rx: Receiver) -> Pin<Box<dyn Stream<Item = Profile> + Send + Sync>> {
+/// Parses a duration like `300ms`, `10s`, `5m`, `1h`, or `1d`. A zero duration
+/// may omit its unit.
+pub fn parse_duration(s: &str) -> Result<Duration, InvalidDuration> {
+    let re = Regex::new(r"^\s*(\d+)(ms|s|m|h|d)?\s*$").expect("duration regex");
+
+    let cap = re.captures(s).ok_or(InvalidDuration::NotADuration)?;
+
+    let magnitude = cap[1].parse().map_err(|_| InvalidDuration::NotANumber)?;
+    match cap.get(2).map(|m| m.as_str()) {
+        None if magnitude == 0 => Ok(Duration::from_secs(0)),
+        Some("ms") => Ok(Duration::from_millis(magnitude)),
+        Some("s") => Ok(Duration::from_secs(magnitude)),
+        Some("m") => Ok(Duration::from_secs(magnitude * 60)),
+        Some("h") => Ok(Duration::from_secs(magnitude * 60 * 60)),
+        Some("d") => Ok(Duration::from_secs(magnitude * 60 * 60 * 24)),
+        _ => Err(InvalidDuration::NotADuration),
+    }
+}
+
+/// Compiles a regular expression that must match an entire value.
+pub(crate) fn anchored_regex(regex: &str) -> Option<Regex> {
+    let regex = regex.trim();
+    match (regex.starts_with('^'), regex.ends_with('$')) {
+        (true, true) => Regex::new(regex).ok(),
+        (hd_anchor, tl_anchor) => {
+            let hd = if hd_anchor { "" } else { "^" };
+            let tl = if tl_anchor { "" } else { "$" };
+            let re = format!("{}{}{}", hd, regex, tl);
+            Regex::new(&re).ok()
+        }
+    }
+}
+
+/// Streams a profile's updates, starting with its current value.
+pub fn stream_profile(mut rx: Receiver) -> Pin<Box<dyn Stream<Item = Profile> + Send + Sync>> {
     Box::pin(async_stream::stream! {
         loop {
             let val = rx.borrow().clone();
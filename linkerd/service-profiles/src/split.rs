@@ -1,10 +1,10 @@
-use crate::{Profile, Receiver, Target};
+use crate::{http::RequestMatch, Profile, Receiver, Target};
 use futures::{prelude::*, ready};
 use indexmap::IndexSet;
 use linkerd2_addr::Addr;
 use linkerd2_error::Error;
 use linkerd2_stack::{layer, NewService};
-use rand::distributions::{Distribution, WeightedIndex};
+use rand::distributions::{Distribution, WeightedError, WeightedIndex};
 use rand::{rngs::SmallRng, thread_rng, SeedableRng};
 use std::{
     marker::PhantomData,
@@ -12,9 +12,11 @@ use std::{
     task::{Context, Poll},
 };
 use tower::ready_cache::ReadyCache;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
-pub fn layer<N, S, Req>() -> impl layer::Layer<N, Service = NewSplit<N, S, Req>> + Clone {
+/// Splits requests over a profile's targets by weight, ignoring the targets'
+/// request matches.
+pub fn layer<N, S, Req>() -> impl layer::Layer<N, Service = NewSplit<N, S, Req, NoMatch>> + Clone {
     // This RNG doesn't need to be cryptographically secure. Small and fast is
     // preferable.
     layer::mk(move |inner| NewSplit {
@@ -23,30 +25,62 @@ pub fn layer<N, S, Req>() -> impl layer::Layer<N, Service = NewSplit<N, S, Req>>
     })
 }
 
+/// Splits HTTP requests over a profile's targets. Requests that satisfy a
+/// target's request match are always routed to that target; all other
+/// requests are distributed by weight.
+pub fn http_layer<N, S, Req>(
+) -> impl layer::Layer<N, Service = NewSplit<N, S, Req, HttpMatch>> + Clone {
+    layer::mk(move |inner| NewSplit {
+        inner,
+        _service: PhantomData,
+    })
+}
+
+/// Determines whether a request satisfies a target's request match.
+pub trait MatchRequest<Req> {
+    fn is_match(m: &RequestMatch, req: &Req) -> bool;
+}
+
+/// Never matches requests, e.g. because they are not HTTP requests.
+#[derive(Copy, Clone, Debug)]
+pub enum NoMatch {}
+
+/// Matches HTTP requests.
+#[derive(Copy, Clone, Debug)]
+pub enum HttpMatch {}
+
 #[derive(Debug)]
-pub struct NewSplit<N, S, Req> {
+pub struct NewSplit<N, S, Req, M> {
     inner: N,
-    _service: PhantomData<fn(Req) -> S>,
+    _service: PhantomData<fn(Req, M) -> S>,
 }
 
-pub enum Split<T, N, S, Req> {
+pub enum Split<T, N, S, Req, M> {
     Default(S),
-    Split(Box<Inner<T, N, S, Req>>),
+    Split(Box<Inner<T, N, S, Req, M>>),
 }
 
-pub struct Inner<T, N, S, Req> {
+pub struct Inner<T, N, S, Req, M> {
     rng: SmallRng,
     rx: Pin<Box<dyn Stream<Item = Profile> + Send + Sync>>,
     target: T,
     new_service: N,
-    distribution: WeightedIndex<u32>,
+    /// Distributes requests that don't match a target's request match. There
+    /// is no distribution when all targets have a weight of zero or the
+    /// weights are otherwise invalid.
+    distribution: Option<WeightedIndex<u64>>,
+    /// The index of the target that receives unmatched requests when there is
+    /// no distribution: the first target without a request match, if any.
+    fallback: usize,
     addrs: IndexSet<Addr>,
+    overrides: Vec<(RequestMatch, Addr)>,
     services: ReadyCache<Addr, S, Req>,
+    _match: PhantomData<fn(M)>,
 }
 
 // === impl NewSplit ===
 
-impl<N: Clone, S, Req> Clone for NewSplit<N, S, Req> {
+impl<N: Clone, S, Req, M> Clone for NewSplit<N, S, Req, M> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
@@ -55,7 +89,7 @@ impl<N: Clone, S, Req> Clone for NewSplit<N, S, Req> {
     }
 }
 
-impl<T, N, S, Req> NewService<T> for NewSplit<N, S, Req>
+impl<T, N, S, Req, M> NewService<T> for NewSplit<N, S, Req, M>
 where
     T: Clone,
     for<'t> &'t T: Into<Addr> + Into<Option<Receiver>>,
@@ -63,7 +97,7 @@ where
     S: tower::Service<Req>,
     S::Error: Into<Error>,
 {
-    type Service = Split<T, N, S, Req>;
+    type Service = Split<T, N, S, Req, M>;
 
     fn new_service(&mut self, target: T) -> Self::Service {
         // If there is a profile, it is used to configure one or more inner
@@ -83,20 +117,35 @@ where
                     targets.push(Target {
                         addr: (&target).into(),
                         weight: 1,
+                        request_match: None,
                     })
                 }
                 trace!(?targets, "Building split service");
 
                 let mut addrs = IndexSet::with_capacity(targets.len());
                 let mut weights = Vec::with_capacity(targets.len());
+                let mut overrides = Vec::new();
+                let mut fallback = None;
                 let mut services = ReadyCache::default();
                 let mut new_service = self.inner.clone();
-                for Target { weight, addr } in targets.into_iter() {
+                for Target {
+                    weight,
+                    addr,
+                    request_match,
+                } in targets.into_iter()
+                {
                     services.push(
                         addr.clone(),
                         new_service.new_service((Some(addr.clone()), target.clone())),
                     );
-                    addrs.insert(addr);
+                    let is_unconditional = request_match.is_none();
+                    if let Some(m) = request_match {
+                        overrides.push((m, addr.clone()));
+                    }
+                    let (idx, _) = addrs.insert_full(addr);
+                    if is_unconditional && fallback.is_none() {
+                        fallback = Some(idx);
+                    }
                     weights.push(weight);
                 }
 
@@ -106,19 +155,58 @@ where
                     new_service,
                     services,
                     addrs,
-                    distribution: WeightedIndex::new(weights).unwrap(),
+                    overrides,
+                    distribution: mk_distribution(weights),
+                    fallback: fallback.unwrap_or(0),
                     rng: SmallRng::from_rng(&mut thread_rng()).expect("RNG must initialize"),
+                    _match: PhantomData,
                 }))
             }
         }
     }
 }
 
+/// Weights are summed as `u64`s so that large weights cannot overflow.
+///
+/// When there is no distribution, unmatched requests are sent to the fallback
+/// target.
+fn mk_distribution(weights: Vec<u32>) -> Option<WeightedIndex<u64>> {
+    match WeightedIndex::new(weights.into_iter().map(u64::from)) {
+        Ok(distribution) => Some(distribution),
+        // E.g. when the only targets are match-only canaries.
+        Err(WeightedError::AllWeightsZero) => {
+            debug!("All targets have a weight of zero");
+            None
+        }
+        Err(error) => {
+            warn!(%error, "Invalid target weights; using the fallback target");
+            None
+        }
+    }
+}
+
+// === impl NoMatch ===
+
+impl<Req> MatchRequest<Req> for NoMatch {
+    fn is_match(_: &RequestMatch, _: &Req) -> bool {
+        false
+    }
+}
+
+// === impl HttpMatch ===
+
+impl<B> MatchRequest<http::Request<B>> for HttpMatch {
+    fn is_match(m: &RequestMatch, req: &http::Request<B>) -> bool {
+        m.is_match(req)
+    }
+}
+
 // === impl Split ===
 
-impl<T, N, S, Req> tower::Service<Req> for Split<T, N, S, Req>
+impl<T, N, S, Req, M> tower::Service<Req> for Split<T, N, S, Req, M>
 where
     Req: Send + 'static,
+    M: MatchRequest<Req>,
     T: Clone,
     for<'t> &'t T: Into<Addr>,
     N: NewService<(Option<Addr>, T), Service = S> + Clone,
@@ -147,6 +235,7 @@ where
                         targets.push(Target {
                             addr: (&inner.target).into(),
                             weight: 1,
+                            request_match: None,
                         })
                     }
                     debug!(?targets, "Updating");
@@ -158,9 +247,16 @@ where
                     let mut prior_addrs =
                         std::mem::replace(&mut inner.addrs, IndexSet::with_capacity(targets.len()));
                     let mut weights = Vec::with_capacity(targets.len());
+                    let mut fallback = None;
+                    inner.overrides.clear();
 
                     // Create an updated distribution and set of services.
-                    for Target { weight, addr } in targets.into_iter() {
+                    for Target {
+                        weight,
+                        addr,
+                        request_match,
+                    } in targets.into_iter()
+                    {
                         // Reuse the prior services whenever possible.
                         if !prior_addrs.remove(&addr) {
                             debug!(%addr, "Creating target");
@@ -171,11 +267,19 @@ where
                         } else {
                             trace!(%addr, "Target already exists");
                         }
-                        inner.addrs.insert(addr);
+                        let is_unconditional = request_match.is_none();
+                        if let Some(m) = request_match {
+                            inner.overrides.push((m, addr.clone()));
+                        }
+                        let (idx, _) = inner.addrs.insert_full(addr);
+                        if is_unconditional && fallback.is_none() {
+                            fallback = Some(idx);
+                        }
                         weights.push(weight);
                     }
 
-                    inner.distribution = WeightedIndex::new(weights).unwrap();
+                    inner.distribution = mk_distribution(weights);
+                    inner.fallback = fallback.unwrap_or(0);
 
                     // Remove all prior services that did not exist in the new
                     // set of targets.
@@ -195,15 +299,178 @@ where
         match self {
             Self::Default(ref mut svc) => Box::pin(svc.call(req).err_into::<Error>()),
             Self::Split(ref mut inner) => {
-                let idx = if inner.addrs.len() == 1 {
-                    0
-                } else {
-                    inner.distribution.sample(&mut inner.rng)
+                // Requests that match a target are routed to it directly,
+                // bypassing the weighted distribution.
+                let addr = match inner.overrides.iter().find(|(m, _)| M::is_match(m, &req)) {
+                    Some((_, addr)) => addr,
+                    None => {
+                        let idx = match inner.distribution {
+                            Some(ref d) if inner.addrs.len() > 1 => d.sample(&mut inner.rng),
+                            Some(_) => 0,
+                            None => inner.fallback,
+                        };
+                        inner.addrs.get_index(idx).expect("invalid index")
+                    }
                 };
-                let addr = inner.addrs.get_index(idx).expect("invalid index");
                 trace!(?addr, "Dispatching");
                 Box::pin(inner.services.call_ready(addr, req).err_into::<Error>())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::ValueMatch;
+    use linkerd2_stack::layer::Layer;
+    use std::str::FromStr;
+    use tower::Service;
+
+    #[derive(Clone)]
+    struct Logical(Addr, Receiver);
+
+    impl From<&'_ Logical> for Addr {
+        fn from(Logical(addr, _): &Logical) -> Self {
+            addr.clone()
+        }
+    }
+
+    impl From<&'_ Logical> for Option<Receiver> {
+        fn from(Logical(_, rx): &Logical) -> Self {
+            Some(rx.clone())
+        }
+    }
+
+    #[test]
+    fn invalid_weights_have_no_distribution() {
+        assert!(mk_distribution(vec![]).is_none());
+        assert!(mk_distribution(vec![0, 0]).is_none());
+
+        let d = mk_distribution(vec![u32::MAX, u32::MAX]).expect("weights must not overflow");
+        let mut rng = SmallRng::seed_from_u64(0);
+        assert!(d.sample(&mut rng) < 2);
+    }
+
+    #[tokio::test]
+    async fn routes_matching_requests_to_target() {
+        let logical = Addr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let stable = Addr::from_str("web-stable.ns.svc.cluster.local:8080").unwrap();
+        let canary = Addr::from_str("web-canary.ns.svc.cluster.local:8080").unwrap();
+        let canary_match = RequestMatch::Header(
+            ::http::header::HeaderName::from_static("x-canary"),
+            ValueMatch::Exact("true".into()),
+        );
+        let profile = Profile {
+            targets: vec![
+                Target {
+                    addr: stable.clone(),
+                    weight: 1,
+                    request_match: None,
+                },
+                // The canary only receives requests that match.
+                Target {
+                    addr: canary.clone(),
+                    weight: 0,
+                    request_match: Some(canary_match),
+                },
+            ],
+            ..Profile::default()
+        };
+        let (_tx, rx) = tokio::sync::watch::channel(profile);
+
+        let mut split = http_layer().layer(|(addr, _): (Option<Addr>, Logical)| {
+            tower::service_fn(move |_: ::http::Request<()>| {
+                future::ok::<_, Error>(addr.clone().expect("concrete address"))
+            })
+        });
+        let mut split = split.new_service(Logical(logical, rx));
+
+        for (header, expected) in &[
+            (Some("true"), &canary),
+            (Some("false"), &stable),
+            (None, &stable),
+        ] {
+            let mut req = ::http::Request::builder();
+            if let Some(v) = header {
+                req = req.header("x-canary", *v);
+            }
+            future::poll_fn(|cx| split.poll_ready(cx)).await.unwrap();
+            let addr = split.call(req.body(()).unwrap()).await.unwrap();
+            assert_eq!(&addr, *expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_unmatched_requests_without_weights() {
+        let logical = Addr::from_str("web.ns.svc.cluster.local:8080").unwrap();
+        let canary = Addr::from_str("web-canary.ns.svc.cluster.local:8080").unwrap();
+        let beta = Addr::from_str("web-beta.ns.svc.cluster.local:8080").unwrap();
+        let header_match = |name: &'static str| {
+            RequestMatch::Header(
+                ::http::header::HeaderName::from_static(name),
+                ValueMatch::Exact("true".into()),
+            )
+        };
+        // Every target only receives requests that match.
+        let profile = Profile {
+            targets: vec![
+                Target {
+                    addr: canary.clone(),
+                    weight: 0,
+                    request_match: Some(header_match("x-canary")),
+                },
+                Target {
+                    addr: beta.clone(),
+                    weight: 0,
+                    request_match: Some(header_match("x-beta")),
+                },
+            ],
+            ..Profile::default()
+        };
+        let (tx, rx) = tokio::sync::watch::channel(profile.clone());
+
+        let mut split = http_layer().layer(|(addr, _): (Option<Addr>, Logical)| {
+            tower::service_fn(move |_: ::http::Request<()>| {
+                future::ok::<_, Error>(addr.clone().expect("concrete address"))
+            })
+        });
+        let mut split = split.new_service(Logical(logical, rx));
+
+        // Requests that don't match are sent to the first target.
+        for (header, expected) in &[
+            (Some("x-beta"), &beta),
+            (Some("x-canary"), &canary),
+            (None, &canary),
+        ] {
+            let mut req = ::http::Request::builder();
+            if let Some(h) = header {
+                req = req.header(*h, "true");
+            }
+            future::poll_fn(|cx| split.poll_ready(cx)).await.unwrap();
+            let addr = split.call(req.body(()).unwrap()).await.unwrap();
+            assert_eq!(&addr, *expected);
+        }
+
+        // Once there is an unconditional target, unmatched requests are sent
+        // to it, even though its weight is zero.
+        let stable = Addr::from_str("web-stable.ns.svc.cluster.local:8080").unwrap();
+        let mut targets = profile.targets;
+        targets.push(Target {
+            addr: stable.clone(),
+            weight: 0,
+            request_match: None,
+        });
+        tx.send(Profile {
+            targets,
+            ..Profile::default()
+        })
+        .unwrap();
+        future::poll_fn(|cx| split.poll_ready(cx)).await.unwrap();
+        let addr = split
+            .call(::http::Request::builder().body(()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(addr, stable);
+    }
+}
@@ -0,0 +1,56 @@
+#![deny(warnings, rust_2018_idioms)]
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A directory of files that is removed when dropped, even if a test panics.
+///
+/// Files are replaced atomically so that a task that watches the directory
+/// never reads a partially-written file.
+#[derive(Debug)]
+pub struct TempDir {
+    dir: tempfile::TempDir,
+    staging: tempfile::TempDir,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        Self {
+            dir: tempfile::tempdir().expect("temporary directory must be created"),
+            staging: tempfile::tempdir().expect("temporary directory must be created"),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Replaces the contents of the file `name`, returning its path.
+    pub fn write(&self, name: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(name);
+        let tmp = self.staging.path().join("tmp");
+        fs::write(&tmp, contents)
+            .and_then(|()| fs::rename(&tmp, &path))
+            .expect("file must be written");
+        path
+    }
+
+    /// Removes the file `name`, if it exists.
+    pub fn remove(&self, name: impl AsRef<Path>) {
+        if let Err(e) = fs::remove_file(self.join(name)) {
+            assert_eq!(e.kind(), io::ErrorKind::NotFound, "file must be removed");
+        }
+    }
+}
+
+impl Default for TempDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
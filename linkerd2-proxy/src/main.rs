@@ -50,20 +50,26 @@ fn main() {
             None => warn!("Identity is DISABLED"),
             Some(identity) => {
                 info!("Local identity is {}", identity.name());
-                let addr = app.identity_addr().expect("must have identity addr");
-                match addr.identity.value() {
-                    None => info!("Identity verified via {}", addr.addr),
-                    Some(identity) => {
-                        info!("Identity verified via {} ({})", addr.addr, identity);
-                    }
+                match app.identity_addr() {
+                    None => info!("Identity read from local files"),
+                    Some(addr) => match addr.identity.value() {
+                        None => info!("Identity verified via {}", addr.addr),
+                        Some(identity) => {
+                            info!("Identity verified via {} ({})", addr.addr, identity);
+                        }
+                    },
                 }
             }
         }
 
-        let dst_addr = app.dst_addr();
-        match dst_addr.identity.value() {
-            None => info!("Destinations resolved via {}", dst_addr.addr),
-            Some(identity) => info!("Destinations resolved via {} ({})", dst_addr.addr, identity),
+        match app.dst_addr() {
+            None => info!("Destinations resolved from local files"),
+            Some(dst_addr) => match dst_addr.identity.value() {
+                None => info!("Destinations resolved via {}", dst_addr.addr),
+                Some(identity) => {
+                    info!("Destinations resolved via {} ({})", dst_addr.addr, identity)
+                }
+            },
         }
 
         if let Some(oc) = app.opencensus_addr() {